@@ -0,0 +1,3 @@
+-- Add down migration script here
+DROP TABLE IF EXISTS totp_codigos_recuperacion;
+DROP TABLE IF EXISTS usuarios_totp;
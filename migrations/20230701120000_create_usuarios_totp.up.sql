@@ -0,0 +1,21 @@
+-- Add up migration script here
+CREATE TABLE IF NOT EXISTS usuarios_totp (
+    usuario_id uuid NOT NULL PRIMARY KEY
+        REFERENCES usuarios(usuario_id) ON DELETE CASCADE,
+    secreto TEXT NOT NULL,
+    confirmado BOOLEAN NOT NULL DEFAULT FALSE,
+    -- Ultimo paso de tiempo aceptado, evita reutilizar un codigo
+    ultimo_paso BIGINT NULL,
+    creado_en TIMESTAMP NOT NULL DEFAULT NOW(),
+    confirmado_en TIMESTAMP NULL
+);
+
+CREATE TABLE IF NOT EXISTS totp_codigos_recuperacion (
+    codigo_hash TEXT NOT NULL,
+    usuario_id uuid NOT NULL
+        REFERENCES usuarios(usuario_id) ON DELETE CASCADE,
+    usado_en TIMESTAMP NULL,
+    PRIMARY KEY(codigo_hash)
+);
+
+CREATE INDEX totp_codigos_recuperacion_usuario_id_idx ON totp_codigos_recuperacion (usuario_id);
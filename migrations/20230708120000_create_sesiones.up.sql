@@ -0,0 +1,16 @@
+-- Add up migration script here
+CREATE TABLE IF NOT EXISTS sesiones (
+    sesion_id uuid NOT NULL PRIMARY KEY,
+    usuario_id uuid NOT NULL
+        REFERENCES usuarios(usuario_id) ON DELETE CASCADE,
+    dispositivo TEXT NULL,
+    ip TEXT NULL,
+    user_agent TEXT NULL,
+    creado_en TIMESTAMP NOT NULL DEFAULT NOW(),
+    -- El ultimo uso reciente se guarda en Redis
+    ultimo_uso TIMESTAMP NOT NULL DEFAULT NOW(),
+    expira_en TIMESTAMP NOT NULL,
+    revocado_en TIMESTAMP NULL
+);
+
+CREATE INDEX sesiones_usuario_id_idx ON sesiones (usuario_id);
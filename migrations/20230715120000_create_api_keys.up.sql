@@ -0,0 +1,17 @@
+-- Add up migration script here
+CREATE TABLE IF NOT EXISTS api_keys (
+    api_key_id uuid NOT NULL PRIMARY KEY,
+    -- La API key actua con la identidad del administrador que la creo
+    usuario_id uuid NOT NULL
+        REFERENCES usuarios(usuario_id) ON DELETE CASCADE,
+    nombre TEXT NOT NULL,
+    prefijo TEXT NOT NULL,
+    key_hash TEXT UNIQUE NOT NULL,
+    scopes TEXT[] NOT NULL DEFAULT '{}',
+    expira_en TIMESTAMP NULL,
+    ultimo_uso TIMESTAMP NULL,
+    creado_en TIMESTAMP NOT NULL DEFAULT NOW(),
+    revocado_en TIMESTAMP NULL
+);
+
+CREATE INDEX api_keys_usuario_id_idx ON api_keys (usuario_id);
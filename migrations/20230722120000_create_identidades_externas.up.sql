@@ -0,0 +1,15 @@
+-- Add up migration script here
+CREATE TABLE IF NOT EXISTS identidades_externas (
+    identidad_id uuid NOT NULL PRIMARY KEY,
+    usuario_id uuid NOT NULL
+        REFERENCES usuarios(usuario_id) ON DELETE CASCADE,
+    -- Nombre del proveedor en la configuracion
+    proveedor TEXT NOT NULL,
+    -- Claim sub del id token, unico por proveedor
+    sujeto TEXT NOT NULL,
+    email TEXT NULL,
+    creado_en TIMESTAMP NOT NULL DEFAULT NOW(),
+    ultimo_login TIMESTAMP NULL,
+    UNIQUE (proveedor, sujeto),
+    UNIQUE (usuario_id, proveedor)
+);
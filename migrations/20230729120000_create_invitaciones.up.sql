@@ -0,0 +1,25 @@
+-- Add up migration script here
+CREATE TABLE IF NOT EXISTS invitaciones (
+    invitacion_id uuid NOT NULL PRIMARY KEY,
+    email TEXT NOT NULL,
+    -- Datos con los que se crea la cuenta
+    departamento INTEGER NULL DEFAULT NULL
+        REFERENCES departamentos(id) ON DELETE SET NULL,
+    rol usuario_rol NOT NULL DEFAULT 'normal',
+    numero_empleado SMALLINT NULL,
+    -- sha256 del token que se envia por correo
+    token_hash TEXT UNIQUE NOT NULL,
+    invitado_por uuid NULL
+        REFERENCES usuarios(usuario_id) ON DELETE SET NULL,
+    creado_en TIMESTAMP NOT NULL DEFAULT NOW(),
+    expira_en TIMESTAMP NOT NULL,
+    aceptado_en TIMESTAMP NULL,
+    revocado_en TIMESTAMP NULL,
+    -- Cuenta creada al aceptar la invitacion
+    usuario_id uuid NULL
+        REFERENCES usuarios(usuario_id) ON DELETE SET NULL
+);
+
+-- Solo una invitacion pendiente por correo
+CREATE UNIQUE INDEX invitaciones_email_pendiente_idx ON invitaciones (lower(email))
+    WHERE aceptado_en IS NULL AND revocado_en IS NULL;
@@ -0,0 +1,8 @@
+-- Add down migration script here
+ALTER TABLE usuarios
+    DROP COLUMN IF EXISTS aprobacion,
+    DROP COLUMN IF EXISTS aprobacion_motivo,
+    DROP COLUMN IF EXISTS aprobado_por,
+    DROP COLUMN IF EXISTS aprobado_en;
+
+DROP TYPE IF EXISTS usuario_aprobacion;
@@ -0,0 +1,13 @@
+-- Add up migration script here
+CREATE TYPE usuario_aprobacion AS ENUM ('pendiente', 'aprobado', 'rechazado');
+
+-- Las cuentas existentes ya estan aprobadas
+ALTER TABLE usuarios
+    ADD COLUMN aprobacion usuario_aprobacion NOT NULL DEFAULT 'aprobado',
+    ADD COLUMN aprobacion_motivo TEXT NULL,
+    ADD COLUMN aprobado_por uuid NULL
+        REFERENCES usuarios(usuario_id) ON DELETE SET NULL,
+    ADD COLUMN aprobado_en TIMESTAMP NULL;
+
+CREATE INDEX usuarios_aprobacion_pendiente_idx ON usuarios (creado_en)
+    WHERE aprobacion = 'pendiente';
@@ -0,0 +1,17 @@
+-- Add up migration script here
+CREATE TABLE IF NOT EXISTS cambios_email (
+    cambio_id uuid NOT NULL PRIMARY KEY,
+    usuario_id uuid NOT NULL
+        REFERENCES usuarios(usuario_id) ON DELETE CASCADE,
+    email_anterior TEXT NOT NULL,
+    email_nuevo TEXT NOT NULL,
+    -- sha256 del token que se envia al email nuevo
+    token_hash TEXT UNIQUE NOT NULL,
+    creado_en TIMESTAMP NOT NULL DEFAULT NOW(),
+    expira_en TIMESTAMP NOT NULL,
+    confirmado_en TIMESTAMP NULL
+);
+
+-- Solo un cambio pendiente por usuario
+CREATE UNIQUE INDEX cambios_email_pendiente_idx ON cambios_email (usuario_id)
+    WHERE confirmado_en IS NULL;
@@ -0,0 +1,11 @@
+-- Add up migration script here
+CREATE TABLE IF NOT EXISTS historial_passwords (
+    historial_id uuid NOT NULL PRIMARY KEY,
+    usuario_id uuid NOT NULL
+        REFERENCES usuarios(usuario_id) ON DELETE CASCADE,
+    password_hash TEXT NOT NULL,
+    creado_en TIMESTAMP NOT NULL DEFAULT NOW()
+);
+
+-- Se consultan las mas recientes de cada usuario
+CREATE INDEX historial_passwords_usuario_idx ON historial_passwords (usuario_id, creado_en DESC);
@@ -0,0 +1,7 @@
+-- Add down migration script here
+DROP INDEX IF EXISTS usuarios_departamento_idx;
+DROP INDEX IF EXISTS usuarios_email_lower_idx;
+DROP INDEX IF EXISTS usuarios_apellidos_idx;
+DROP INDEX IF EXISTS usuarios_nombres_idx;
+CREATE INDEX usuarios_nombres_idx ON usuarios (nombres);
+CREATE INDEX usuarios_apellidos_idx ON usuarios (apellidos);
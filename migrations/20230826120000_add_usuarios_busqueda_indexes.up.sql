@@ -0,0 +1,11 @@
+-- Add up migration script here
+-- La busqueda del directorio compara prefijos sin distinguir mayusculas,
+-- text_pattern_ops permite usar el indice con LIKE 'texto%' en cualquier collation
+DROP INDEX IF EXISTS usuarios_nombres_idx;
+DROP INDEX IF EXISTS usuarios_apellidos_idx;
+CREATE INDEX usuarios_nombres_idx ON usuarios (lower(nombres) text_pattern_ops);
+CREATE INDEX usuarios_apellidos_idx ON usuarios (lower(apellidos) text_pattern_ops);
+CREATE INDEX usuarios_email_lower_idx ON usuarios (lower(email) text_pattern_ops);
+
+-- Filtro por departamento
+CREATE INDEX usuarios_departamento_idx ON usuarios (departamento);
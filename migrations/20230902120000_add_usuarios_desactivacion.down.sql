@@ -0,0 +1,12 @@
+-- Add down migration script here
+ALTER TABLE peticiones
+    DROP CONSTRAINT peticiones_usuario_id_fkey,
+    ADD CONSTRAINT peticiones_usuario_id_fkey
+        FOREIGN KEY (usuario_id) REFERENCES usuarios(usuario_id) ON DELETE CASCADE;
+
+DROP INDEX IF EXISTS usuarios_desactivado_idx;
+
+ALTER TABLE usuarios
+    DROP COLUMN anonimizado_en,
+    DROP COLUMN desactivado_por,
+    DROP COLUMN desactivado_en;
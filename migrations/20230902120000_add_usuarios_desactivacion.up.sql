@@ -0,0 +1,17 @@
+-- Add up migration script here
+-- Los usuarios ya no se borran, se desactivan y despues de un plazo
+-- se anonimizan conservando su usuario_id para el historial
+ALTER TABLE usuarios
+    ADD COLUMN desactivado_en TIMESTAMP NULL,
+    ADD COLUMN desactivado_por uuid NULL
+        REFERENCES usuarios(usuario_id) ON DELETE SET NULL,
+    ADD COLUMN anonimizado_en TIMESTAMP NULL;
+
+CREATE INDEX usuarios_desactivado_idx ON usuarios (desactivado_en)
+    WHERE desactivado_en IS NOT NULL AND anonimizado_en IS NULL;
+
+-- Las peticiones son el historial de viajes, no se borran con el usuario
+ALTER TABLE peticiones
+    DROP CONSTRAINT peticiones_usuario_id_fkey,
+    ADD CONSTRAINT peticiones_usuario_id_fkey
+        FOREIGN KEY (usuario_id) REFERENCES usuarios(usuario_id) ON DELETE RESTRICT;
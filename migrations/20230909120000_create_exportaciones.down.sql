@@ -0,0 +1,3 @@
+-- Add down migration script here
+DROP TABLE IF EXISTS exportaciones;
+DROP TYPE IF EXISTS estado_exportacion;
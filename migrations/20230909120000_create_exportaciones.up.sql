@@ -0,0 +1,22 @@
+-- Add up migration script here
+CREATE TYPE estado_exportacion AS ENUM ('pendiente', 'lista', 'fallida', 'expirada');
+
+CREATE TABLE IF NOT EXISTS exportaciones (
+    exportacion_id uuid NOT NULL PRIMARY KEY,
+    usuario_id uuid NOT NULL
+        REFERENCES usuarios(usuario_id) ON DELETE CASCADE,
+    estado estado_exportacion NOT NULL DEFAULT 'pendiente',
+    -- Nombre del ZIP en uploads/exports
+    archivo TEXT NULL,
+    -- sha256 del token del enlace de descarga
+    token_hash TEXT UNIQUE NULL,
+    creado_en TIMESTAMP NOT NULL DEFAULT NOW(),
+    completado_en TIMESTAMP NULL,
+    expira_en TIMESTAMP NULL
+);
+
+CREATE INDEX exportaciones_usuario_id_idx ON exportaciones (usuario_id);
+
+-- Solo una exportacion en proceso por usuario
+CREATE UNIQUE INDEX exportaciones_pendiente_idx ON exportaciones (usuario_id)
+    WHERE estado = 'pendiente';
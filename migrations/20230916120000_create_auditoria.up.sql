@@ -0,0 +1,18 @@
+-- Add up migration script here
+CREATE TABLE IF NOT EXISTS auditoria (
+    auditoria_id uuid NOT NULL PRIMARY KEY,
+    -- Quien realizo la accion, al suplantar es el administrador
+    actor_id uuid NULL
+        REFERENCES usuarios(usuario_id) ON DELETE SET NULL,
+    -- Usuario afectado por la accion o suplantado
+    usuario_id uuid NULL
+        REFERENCES usuarios(usuario_id) ON DELETE SET NULL,
+    accion TEXT NOT NULL,
+    detalle TEXT NOT NULL DEFAULT '',
+    ip TEXT NULL,
+    creado_en TIMESTAMP NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX auditoria_actor_id_idx ON auditoria (actor_id);
+CREATE INDEX auditoria_usuario_id_idx ON auditoria (usuario_id);
+CREATE INDEX auditoria_creado_en_idx ON auditoria (creado_en);
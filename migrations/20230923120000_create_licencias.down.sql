@@ -0,0 +1,4 @@
+-- Add down migration script here
+DROP TABLE IF EXISTS licencias;
+ALTER TABLE vehiculos DROP COLUMN IF EXISTS clase_licencia;
+DROP TABLE IF EXISTS clases_licencia;
@@ -0,0 +1,42 @@
+-- Add up migration script here
+-- Tipos de licencia, cubre lista las clases de vehiculo que puede manejar
+CREATE TABLE IF NOT EXISTS clases_licencia (
+    clase TEXT NOT NULL PRIMARY KEY,
+    descripcion TEXT NOT NULL,
+    cubre TEXT[] NOT NULL
+);
+
+INSERT INTO clases_licencia (clase, descripcion, cubre)
+VALUES
+('A', 'Automovilista', '{A}'),
+('B', 'Chofer', '{A,B}'),
+('C', 'Carga', '{A,B,C}'),
+('M', 'Motociclista', '{M}');
+
+-- Clase de licencia que requiere el vehiculo
+ALTER TABLE vehiculos
+    ADD COLUMN clase_licencia TEXT NOT NULL DEFAULT 'A'
+        REFERENCES clases_licencia(clase);
+
+-- Una licencia vigente por usuario, se reemplaza al registrar otra
+CREATE TABLE IF NOT EXISTS licencias (
+    licencia_id uuid NOT NULL PRIMARY KEY,
+    usuario_id uuid UNIQUE NOT NULL
+        REFERENCES usuarios(usuario_id) ON DELETE CASCADE,
+    numero TEXT NOT NULL,
+    clase TEXT NOT NULL REFERENCES clases_licencia(clase),
+    estado_emisor TEXT NOT NULL,
+    expira_en DATE NOT NULL,
+    imagen_frente TEXT NULL,
+    imagen_reverso TEXT NULL,
+    verificada_en TIMESTAMP NULL,
+    verificada_por uuid NULL
+        REFERENCES usuarios(usuario_id) ON DELETE SET NULL,
+    rechazo_motivo TEXT NULL,
+    recordatorio_enviado_en TIMESTAMP NULL,
+    creado_en TIMESTAMP NOT NULL DEFAULT NOW(),
+    modificado_en TIMESTAMP NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX licencias_expira_en_idx ON licencias (expira_en)
+    WHERE verificada_en IS NOT NULL AND recordatorio_enviado_en IS NULL;
@@ -0,0 +1,6 @@
+-- Add down migration script here
+DROP TABLE IF EXISTS departamentos_jefes;
+ALTER TABLE departamentos
+    DROP CONSTRAINT IF EXISTS departamentos_padre_distinto,
+    DROP COLUMN IF EXISTS centro_costos,
+    DROP COLUMN IF EXISTS padre_id;
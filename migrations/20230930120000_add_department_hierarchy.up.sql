@@ -0,0 +1,21 @@
+-- Add up migration script here
+-- Departamento padre, al borrarlo los hijos quedan en la raiz
+ALTER TABLE departamentos
+    ADD COLUMN padre_id INTEGER NULL DEFAULT NULL
+        REFERENCES departamentos(id) ON DELETE SET NULL,
+    ADD COLUMN centro_costos TEXT NULL DEFAULT NULL UNIQUE,
+    ADD CONSTRAINT departamentos_padre_distinto CHECK (padre_id <> id);
+
+CREATE INDEX departamentos_padre_idx ON departamentos (padre_id);
+
+-- Jefes del departamento, aprueban y reciben los reportes de sus miembros
+CREATE TABLE IF NOT EXISTS departamentos_jefes (
+    departamento_id INTEGER NOT NULL
+        REFERENCES departamentos(id) ON DELETE CASCADE,
+    usuario_id uuid NOT NULL
+        REFERENCES usuarios(usuario_id) ON DELETE CASCADE,
+    creado_en TIMESTAMP NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (departamento_id, usuario_id)
+);
+
+CREATE INDEX departamentos_jefes_usuario_idx ON departamentos_jefes (usuario_id);
@@ -0,0 +1,6 @@
+-- Add down migration script here
+ALTER TABLE vehiculos DROP COLUMN IF EXISTS departamento;
+ALTER TABLE usuarios
+    DROP CONSTRAINT usuarios_departamento_fkey,
+    ADD CONSTRAINT usuarios_departamento_fkey
+        FOREIGN KEY (departamento) REFERENCES departamentos(id) ON DELETE CASCADE;
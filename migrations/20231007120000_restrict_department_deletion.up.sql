@@ -0,0 +1,13 @@
+-- Add up migration script here
+-- Borrar un departamento ya no borra a sus usuarios, se reasignan antes
+ALTER TABLE usuarios
+    DROP CONSTRAINT usuarios_departamento_fkey,
+    ADD CONSTRAINT usuarios_departamento_fkey
+        FOREIGN KEY (departamento) REFERENCES departamentos(id) ON DELETE RESTRICT;
+
+-- Departamento al que esta asignado el vehiculo
+ALTER TABLE vehiculos
+    ADD COLUMN departamento INTEGER NULL DEFAULT NULL
+        REFERENCES departamentos(id) ON DELETE RESTRICT;
+
+CREATE INDEX vehiculos_departamento_idx ON vehiculos (departamento);
@@ -0,0 +1,10 @@
+-- Add down migration script here
+DROP TABLE IF EXISTS delegaciones;
+DROP TABLE IF EXISTS peticiones_aprobaciones;
+ALTER TABLE peticiones
+    DROP COLUMN IF EXISTS distancia_estimada_km,
+    DROP COLUMN IF EXISTS flujo_id;
+DROP TABLE IF EXISTS flujos_aprobacion_pasos;
+DROP TABLE IF EXISTS flujos_aprobacion;
+DROP TYPE IF EXISTS decision_aprobacion;
+DROP TYPE IF EXISTS tipo_aprobador;
@@ -0,0 +1,72 @@
+-- Add up migration script here
+CREATE TYPE tipo_aprobador AS ENUM ('jefe_departamento', 'usuario', 'admin');
+CREATE TYPE decision_aprobacion AS ENUM ('aprobada', 'rechazada');
+
+-- Flujo que aplica a las peticiones del departamento (o de todos si es NULL)
+-- y de sus hijos, cuando se cumplen todas sus reglas
+CREATE TABLE IF NOT EXISTS flujos_aprobacion (
+    flujo_id uuid NOT NULL PRIMARY KEY,
+    nombre TEXT NOT NULL,
+    departamento_id INTEGER NULL DEFAULT NULL
+        REFERENCES departamentos(id) ON DELETE CASCADE,
+    -- Gana el de mayor prioridad, despues el departamento mas cercano
+    prioridad INTEGER NOT NULL DEFAULT 0,
+    duracion_min_horas INTEGER NULL,
+    distancia_min_km INTEGER NULL,
+    solo_fin_de_semana BOOLEAN NOT NULL DEFAULT FALSE,
+    activo BOOLEAN NOT NULL DEFAULT TRUE,
+    creado_por uuid NULL
+        REFERENCES usuarios(usuario_id) ON DELETE SET NULL,
+    creado_en TIMESTAMP NOT NULL DEFAULT NOW()
+);
+
+-- Pasos en orden, el usuario solo aplica al tipo 'usuario'
+CREATE TABLE IF NOT EXISTS flujos_aprobacion_pasos (
+    flujo_id uuid NOT NULL
+        REFERENCES flujos_aprobacion(flujo_id) ON DELETE CASCADE,
+    orden SMALLINT NOT NULL CHECK (orden > 0),
+    tipo tipo_aprobador NOT NULL,
+    usuario_id uuid NULL
+        REFERENCES usuarios(usuario_id) ON DELETE RESTRICT,
+    PRIMARY KEY (flujo_id, orden),
+    CHECK ((tipo = 'usuario') = (usuario_id IS NOT NULL))
+);
+
+-- Sin flujo la peticion la aprueba un administrador
+ALTER TABLE peticiones
+    ADD COLUMN flujo_id uuid NULL DEFAULT NULL
+        REFERENCES flujos_aprobacion(flujo_id) ON DELETE SET NULL,
+    ADD COLUMN distancia_estimada_km INTEGER NULL DEFAULT NULL;
+
+-- Una decision por paso, con quien decidio y a nombre de quien si fue delegado
+CREATE TABLE IF NOT EXISTS peticiones_aprobaciones (
+    aprobacion_id uuid NOT NULL PRIMARY KEY,
+    peticion_id uuid NOT NULL
+        REFERENCES peticiones(peticion_id) ON DELETE CASCADE,
+    orden SMALLINT NOT NULL,
+    aprobador_id uuid NULL
+        REFERENCES usuarios(usuario_id) ON DELETE SET NULL,
+    en_nombre_de uuid NULL
+        REFERENCES usuarios(usuario_id) ON DELETE SET NULL,
+    decision decision_aprobacion NOT NULL,
+    comentario TEXT NULL,
+    decidido_en TIMESTAMP NOT NULL DEFAULT NOW(),
+    UNIQUE (peticion_id, orden)
+);
+
+-- El delegado decide por el delegante entre inicio y fin
+CREATE TABLE IF NOT EXISTS delegaciones (
+    delegacion_id uuid NOT NULL PRIMARY KEY,
+    delegante_id uuid NOT NULL
+        REFERENCES usuarios(usuario_id) ON DELETE CASCADE,
+    delegado_id uuid NOT NULL
+        REFERENCES usuarios(usuario_id) ON DELETE CASCADE,
+    inicio TIMESTAMP NOT NULL,
+    fin TIMESTAMP NOT NULL,
+    motivo TEXT NULL,
+    creado_en TIMESTAMP NOT NULL DEFAULT NOW(),
+    CHECK (fin > inicio),
+    CHECK (delegante_id <> delegado_id)
+);
+
+CREATE INDEX delegaciones_delegado_idx ON delegaciones (delegado_id, fin);
@@ -0,0 +1,6 @@
+-- Add down migration script here
+ALTER TABLE peticiones
+    DROP COLUMN IF EXISTS costo_combustible_estimado,
+    DROP COLUMN IF EXISTS departamento_id;
+DROP TABLE IF EXISTS cuotas_departamento;
+DROP TYPE IF EXISTS modo_cuota;
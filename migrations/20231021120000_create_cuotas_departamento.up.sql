@@ -0,0 +1,29 @@
+-- Add up migration script here
+CREATE TYPE modo_cuota AS ENUM ('advertir', 'bloquear');
+
+-- Limites mensuales del departamento, NULL es sin limite
+CREATE TABLE IF NOT EXISTS cuotas_departamento (
+    departamento_id INTEGER NOT NULL PRIMARY KEY
+        REFERENCES departamentos(id) ON DELETE CASCADE,
+    km_max INTEGER NULL CHECK (km_max >= 0),
+    horas_max INTEGER NULL CHECK (horas_max >= 0),
+    vehiculos_simultaneos_max INTEGER NULL CHECK (vehiculos_simultaneos_max >= 0),
+    presupuesto_combustible INTEGER NULL CHECK (presupuesto_combustible >= 0),
+    modo modo_cuota NOT NULL DEFAULT 'advertir',
+    modificado_por uuid NULL
+        REFERENCES usuarios(usuario_id) ON DELETE SET NULL,
+    modificado_en TIMESTAMP NOT NULL DEFAULT NOW()
+);
+
+-- Departamento al que se carga la peticion, el del solicitante al crearla
+ALTER TABLE peticiones
+    ADD COLUMN departamento_id INTEGER NULL DEFAULT NULL
+        REFERENCES departamentos(id) ON DELETE SET NULL,
+    ADD COLUMN costo_combustible_estimado INTEGER NULL DEFAULT NULL;
+
+UPDATE peticiones p
+SET departamento_id = u.departamento
+FROM usuarios u
+WHERE u.usuario_id = p.usuario_id;
+
+CREATE INDEX peticiones_departamento_inicio_idx ON peticiones (departamento_id, inicio);
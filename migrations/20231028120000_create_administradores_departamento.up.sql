@@ -0,0 +1,14 @@
+-- Add up migration script here
+-- Administradores limitados a un departamento y sus subdepartamentos
+CREATE TABLE IF NOT EXISTS administradores_departamento (
+    departamento_id INTEGER NOT NULL
+        REFERENCES departamentos(id) ON DELETE CASCADE,
+    usuario_id uuid NOT NULL
+        REFERENCES usuarios(usuario_id) ON DELETE CASCADE,
+    creado_por uuid
+        REFERENCES usuarios(usuario_id) ON DELETE SET NULL,
+    creado_en TIMESTAMP NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (departamento_id, usuario_id)
+);
+
+CREATE INDEX administradores_departamento_usuario_idx ON administradores_departamento (usuario_id);
@@ -0,0 +1,5 @@
+-- Add down migration script here
+ALTER TABLE flujos_aprobacion
+    DROP CONSTRAINT flujos_aprobacion_departamento_id_fkey,
+    ADD CONSTRAINT flujos_aprobacion_departamento_id_fkey
+        FOREIGN KEY (departamento_id) REFERENCES departamentos(id) ON DELETE CASCADE;
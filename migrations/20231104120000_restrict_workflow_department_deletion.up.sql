@@ -0,0 +1,7 @@
+-- Add up migration script here
+-- Borrar un departamento ya no borra sus flujos, se mueven al destino antes.
+-- Las peticiones en curso dependen de los pasos de su flujo
+ALTER TABLE flujos_aprobacion
+    DROP CONSTRAINT flujos_aprobacion_departamento_id_fkey,
+    ADD CONSTRAINT flujos_aprobacion_departamento_id_fkey
+        FOREIGN KEY (departamento_id) REFERENCES departamentos(id) ON DELETE RESTRICT;
@@ -0,0 +1,3 @@
+-- Add down migration script here
+ALTER TABLE usuarios
+    DROP COLUMN activo_al_desactivar;
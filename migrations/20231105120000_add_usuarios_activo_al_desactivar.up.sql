@@ -0,0 +1,5 @@
+-- Add up migration script here
+-- Restaurar una cuenta regresa el activo que tenia antes de desactivarla,
+-- una cuenta suspendida no se reactiva al restaurarla
+ALTER TABLE usuarios
+    ADD COLUMN activo_al_desactivar BOOLEAN NULL;
@@ -0,0 +1,54 @@
+#!/usr/bin/env bash
+set -x
+set -eo pipefail
+
+# if a keycloak container is running, print instructions to kill it and exit
+RUNNING_CONTAINER=$(docker ps --filter 'name=keycloak' --format '{{.ID}}')
+if [[ -n $RUNNING_CONTAINER ]]; then
+  echo >&2 "there is a keycloak container already running, kill it with."
+  echo >&2 "    docker kill ${RUNNING_CONTAINER}"
+  exit 1
+fi
+
+KEYCLOAK_PASSWORD="${KEYCLOAK_PASSWORD:=password}"
+KEYCLOAK_PORT="${KEYCLOAK_PORT:=8080}"
+REDIRECT_URL="${REDIRECT_URL:=http://127.0.0.1:3000/oidc/callback}"
+
+# Launch Keycloak in development mode using Docker
+CONTAINER_NAME="keycloak_cpv_$(date '+%s')"
+docker run \
+    -p "${KEYCLOAK_PORT}":8080 \
+    -e KEYCLOAK_ADMIN=admin \
+    -e KEYCLOAK_ADMIN_PASSWORD="${KEYCLOAK_PASSWORD}" \
+    -d \
+    --name "${CONTAINER_NAME}" \
+    quay.io/keycloak/keycloak:21.1 \
+    start-dev
+
+KCADM="docker exec ${CONTAINER_NAME} /opt/keycloak/bin/kcadm.sh"
+
+# Keep trying to log in until Keycloak is ready
+until ${KCADM} config credentials --server http://localhost:8080 --realm master \
+    --user admin --password "${KEYCLOAK_PASSWORD}" > /dev/null 2>&1; do
+  >&2 echo "Keycloak is still unavailable - sleeping"
+  sleep 2
+done
+
+# Realm "cpv" with a public client that requires PKCE
+${KCADM} create realms -s realm=cpv -s enabled=true
+${KCADM} create clients -r cpv \
+    -s clientId=cpv \
+    -s publicClient=true \
+    -s standardFlowEnabled=true \
+    -s "redirectUris=[\"${REDIRECT_URL}\"]" \
+    -s 'attributes."pkce.code.challenge.method"=S256'
+
+# Test account with a verified email, the password is "password"
+${KCADM} create users -r cpv \
+    -s username=ana \
+    -s email=ana@cpv.local \
+    -s emailVerified=true \
+    -s enabled=true
+${KCADM} set-password -r cpv --username ana --new-password password
+
+>&2 echo "Keycloak is ready to go, issuer http://127.0.0.1:${KEYCLOAK_PORT}/realms/cpv"
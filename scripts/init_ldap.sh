@@ -0,0 +1,74 @@
+#!/usr/bin/env bash
+set -x
+set -eo pipefail
+
+# if an ldap container is running, print instructions to kill it and exit
+RUNNING_CONTAINER=$(docker ps --filter 'name=ldap' --format '{{.ID}}')
+if [[ -n $RUNNING_CONTAINER ]]; then
+  echo >&2 "there is an ldap container already running, kill it with."
+  echo >&2 "    docker kill ${RUNNING_CONTAINER}"
+  exit 1
+fi
+
+LDAP_PASSWORD="${LDAP_PASSWORD:=password}"
+LDAP_PORT="${LDAP_PORT:=389}"
+
+# Launch OpenLDAP using Docker, the domain cpv.local becomes dc=cpv,dc=local
+CONTAINER_NAME="ldap_cpv_$(date '+%s')"
+docker run \
+    -p "${LDAP_PORT}":389 \
+    -e LDAP_DOMAIN="cpv.local" \
+    -e LDAP_ORGANISATION="Control Parque Vehicular" \
+    -e LDAP_ADMIN_PASSWORD="${LDAP_PASSWORD}" \
+    -d \
+    --name "${CONTAINER_NAME}" \
+    osixia/openldap:1.5.0
+
+# Keep pinging the directory until it's ready to accept commands
+until docker exec "${CONTAINER_NAME}" ldapsearch -x -H ldap://localhost -b "dc=cpv,dc=local" \
+    -D "cn=admin,dc=cpv,dc=local" -w "${LDAP_PASSWORD}" > /dev/null 2>&1; do
+  >&2 echo "LDAP is still unavailable - sleeping"
+  sleep 1
+done
+
+# Seed test accounts, every password is "password"
+docker exec -i "${CONTAINER_NAME}" ldapadd -x -H ldap://localhost \
+    -D "cn=admin,dc=cpv,dc=local" -w "${LDAP_PASSWORD}" <<LDIF
+dn: ou=people,dc=cpv,dc=local
+objectClass: organizationalUnit
+ou: people
+
+dn: ou=groups,dc=cpv,dc=local
+objectClass: organizationalUnit
+ou: groups
+
+dn: uid=ana,ou=people,dc=cpv,dc=local
+objectClass: inetOrgPerson
+uid: ana
+cn: Ana Torres
+givenName: Ana
+sn: Torres
+mail: ana@cpv.local
+userPassword: password
+
+dn: uid=luis,ou=people,dc=cpv,dc=local
+objectClass: inetOrgPerson
+uid: luis
+cn: Luis Medina
+givenName: Luis
+sn: Medina
+mail: luis@cpv.local
+userPassword: password
+
+dn: cn=admins,ou=groups,dc=cpv,dc=local
+objectClass: groupOfNames
+cn: admins
+member: uid=ana,ou=people,dc=cpv,dc=local
+
+dn: cn=rh,ou=groups,dc=cpv,dc=local
+objectClass: groupOfNames
+cn: rh
+member: uid=luis,ou=people,dc=cpv,dc=local
+LDIF
+
+>&2 echo "LDAP is ready to go on port ${LDAP_PORT}"
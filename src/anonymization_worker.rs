@@ -0,0 +1,244 @@
+use std::path::PathBuf;
+use std::time::Duration;
+
+use anyhow::Context;
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+use crate::configuration::{Settings, UserRetentionSettings};
+use crate::export_worker::{get_exports_path, nombre_seguro};
+use crate::startup::get_connection_pool;
+use crate::upload::image::get_uploads_path;
+
+
+const IMAGEN_DEFAULT: &str = "default-user.jpeg";
+
+
+pub async fn run_worker_until_stopped(configuration: Settings) -> Result<(), anyhow::Error> {
+    let connection_pool = get_connection_pool(&configuration.database);
+    worker_loop(connection_pool, configuration.user_retention).await
+}
+
+async fn worker_loop(pool: PgPool, settings: UserRetentionSettings) -> Result<(), anyhow::Error> {
+    loop {
+        match anonimizar_usuarios_vencidos(&pool, settings.anonymize_after_days).await {
+            Ok(0) => {},
+            Ok(anonimizados) => tracing::info!("Se anonimizaron {} usuarios", anonimizados),
+            Err(e) => tracing::error!(
+                error.cause_chain = ?e,
+                error.message = %e,
+                "Failed to anonymize deactivated users"
+            ),
+        }
+        tokio::time::sleep(Duration::from_secs(settings.worker_interval_seconds)).await;
+    }
+}
+
+
+/// Anonimiza los usuarios desactivados hace mas de `dias`, regresa cuantos
+#[tracing::instrument(skip(pool))]
+pub async fn anonimizar_usuarios_vencidos(
+    pool: &PgPool,
+    dias: i32,
+) -> Result<u64, anyhow::Error> {
+    let usuarios = sqlx::query!(
+        r#"
+        SELECT usuario_id
+        FROM usuarios
+        WHERE desactivado_en < now() - make_interval(days => $1)
+            AND anonimizado_en IS NULL
+        "#,
+        dias,
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to fetch users to anonymize")?;
+
+    let mut anonimizados = 0;
+    for row in usuarios {
+        let mut transaction = pool.begin()
+            .await
+            .context("Failed to begin transaction")?;
+
+        let archivos = anonimizar_usuario(&mut transaction, &row.usuario_id).await?;
+
+        transaction.commit()
+            .await
+            .context("Failed to commit transaction")?;
+
+        borrar_archivos(&archivos);
+        anonimizados += 1;
+    }
+
+    Ok(anonimizados)
+}
+
+/// Borra los datos personales y todo lo que permite iniciar sesion, el
+/// usuario_id se conserva como identificador seudonimo en las peticiones.
+/// Regresa la imagen, las fotos de su licencia y las exportaciones que tenia
+/// para borrar los archivos.
+#[tracing::instrument(skip(transaction))]
+async fn anonimizar_usuario(
+    transaction: &mut Transaction<'_, Postgres>,
+    usuario_id: &Uuid,
+) -> Result<Vec<PathBuf>, anyhow::Error> {
+    let row = sqlx::query!(
+        r#"
+        UPDATE usuarios u
+        SET nombres = 'Usuario',
+            apellidos = 'Anonimizado',
+            email = 'anonimo-' || u.usuario_id || '@anonimo.invalid',
+            password_hash = '',
+            numero_empleado = NULL,
+            imagen = $2,
+            activo = false,
+            verificado = false,
+            anonimizado_en = now(),
+            modificado_en = now()
+        FROM usuarios anterior
+        WHERE u.usuario_id = $1
+            AND anterior.usuario_id = u.usuario_id
+            AND u.anonimizado_en IS NULL
+        RETURNING anterior.imagen
+        "#,
+        usuario_id,
+        IMAGEN_DEFAULT,
+    )
+    .fetch_optional(&mut *transaction)
+    .await
+    .context("Failed to anonymize user")?;
+
+    // Credenciales y datos ligados a la cuenta
+    sqlx::query!("DELETE FROM sesiones WHERE usuario_id = $1", usuario_id)
+        .execute(&mut *transaction)
+        .await
+        .context("Failed to delete sessions")?;
+    sqlx::query!("DELETE FROM api_keys WHERE usuario_id = $1", usuario_id)
+        .execute(&mut *transaction)
+        .await
+        .context("Failed to delete api keys")?;
+    sqlx::query!("DELETE FROM usuarios_totp WHERE usuario_id = $1", usuario_id)
+        .execute(&mut *transaction)
+        .await
+        .context("Failed to delete totp")?;
+    sqlx::query!("DELETE FROM totp_codigos_recuperacion WHERE usuario_id = $1", usuario_id)
+        .execute(&mut *transaction)
+        .await
+        .context("Failed to delete recovery codes")?;
+    sqlx::query!("DELETE FROM identidades_externas WHERE usuario_id = $1", usuario_id)
+        .execute(&mut *transaction)
+        .await
+        .context("Failed to delete external identities")?;
+    sqlx::query!("DELETE FROM historial_passwords WHERE usuario_id = $1", usuario_id)
+        .execute(&mut *transaction)
+        .await
+        .context("Failed to delete password history")?;
+    sqlx::query!("DELETE FROM cambios_email WHERE usuario_id = $1", usuario_id)
+        .execute(&mut *transaction)
+        .await
+        .context("Failed to delete email changes")?;
+    sqlx::query!("DELETE FROM signup_tokens WHERE usuario_id = $1", usuario_id)
+        .execute(&mut *transaction)
+        .await
+        .context("Failed to delete signup tokens")?;
+    sqlx::query!("DELETE FROM departamentos_jefes WHERE usuario_id = $1", usuario_id)
+        .execute(&mut *transaction)
+        .await
+        .context("Failed to remove department heads")?;
+    sqlx::query!("DELETE FROM administradores_departamento WHERE usuario_id = $1", usuario_id)
+        .execute(&mut *transaction)
+        .await
+        .context("Failed to remove department administrators")?;
+    sqlx::query!("DELETE FROM delegaciones WHERE delegante_id = $1 OR delegado_id = $1", usuario_id)
+        .execute(&mut *transaction)
+        .await
+        .context("Failed to delete delegations")?;
+    // La invitacion aceptada guarda el email original
+    sqlx::query!(
+        r#"
+        UPDATE invitaciones i
+        SET email = u.email
+        FROM usuarios u
+        WHERE i.usuario_id = $1 AND u.usuario_id = i.usuario_id
+        "#,
+        usuario_id,
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("Failed to anonymize invitations")?;
+    // Los ZIP exportados tienen los datos personales
+    let exportaciones = sqlx::query!(
+        "DELETE FROM exportaciones WHERE usuario_id = $1 RETURNING archivo",
+        usuario_id,
+    )
+    .fetch_all(&mut *transaction)
+    .await
+    .context("Failed to delete exports")?;
+    let licencia = sqlx::query!(
+        "DELETE FROM licencias WHERE usuario_id = $1 RETURNING imagen_frente, imagen_reverso",
+        usuario_id,
+    )
+    .fetch_optional(&mut *transaction)
+    .await
+    .context("Failed to delete license")?;
+    // La foto de la licencia que se guardo en cada viaje, las peticiones se conservan.
+    // Un archivo que tambien usan peticiones de otro usuario no se borra
+    let imagenes_peticiones = sqlx::query!(
+        r#"
+        SELECT DISTINCT p.usuario_licencia_imagen as imagen
+        FROM peticiones p
+        WHERE p.usuario_id = $1
+            AND p.usuario_licencia_imagen <> ''
+            AND NOT EXISTS (
+                SELECT 1 FROM peticiones o
+                WHERE o.usuario_licencia_imagen = p.usuario_licencia_imagen AND o.usuario_id <> $1
+            )
+        "#,
+        usuario_id,
+    )
+    .fetch_all(&mut *transaction)
+    .await
+    .context("Failed to fetch request license images")?;
+    sqlx::query!(
+        "UPDATE peticiones SET usuario_licencia_imagen = '' WHERE usuario_id = $1 AND usuario_licencia_imagen <> ''",
+        usuario_id,
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("Failed to clear request license images")?;
+
+    let mut archivos = vec![];
+    if let Some(imagen) = row.map(|row| row.imagen).filter(|imagen| imagen != IMAGEN_DEFAULT) {
+        archivos.push(get_uploads_path().context("Failed to get uploads path")?.join("users").join(imagen));
+    }
+    let exports_path = get_exports_path().context("Failed to get exports path")?;
+    archivos.extend(exportaciones
+        .into_iter()
+        .filter_map(|row| row.archivo)
+        .map(|archivo| exports_path.join(archivo)));
+    let licenses_path = get_uploads_path().context("Failed to get uploads path")?.join("licenses");
+    archivos.extend(licencia
+        .into_iter()
+        .flat_map(|row| [row.imagen_frente, row.imagen_reverso])
+        .flatten()
+        .map(|imagen| licenses_path.join(imagen)));
+    // Solo el nombre del archivo, igual que en la exportacion
+    for row in imagenes_peticiones.iter() {
+        if let Some(nombre) = nombre_seguro(&row.imagen) {
+            let path = licenses_path.join(nombre);
+            if !archivos.contains(&path) {
+                archivos.push(path);
+            }
+        }
+    }
+
+    Ok(archivos)
+}
+
+fn borrar_archivos(archivos: &[PathBuf]) {
+    for path in archivos {
+        if let Err(e) = std::fs::remove_file(path) {
+            tracing::warn!("No se pudo borrar el archivo {}: {:?}", path.display(), e);
+        }
+    }
+}
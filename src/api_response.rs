@@ -127,3 +127,16 @@ pub fn e409() -> ApiError {
             .with_status("fail")
 }
 
+
+pub fn e429() -> ApiError {
+    ApiError::new()
+            .with_status_code(429)
+            .with_status("fail")
+}
+
+pub fn e503() -> ApiError {
+    ApiError::new()
+            .with_status_code(503)
+            .with_status("fail")
+            .with_message("Servicio no disponible")
+}
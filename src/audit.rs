@@ -0,0 +1,113 @@
+use anyhow::Context;
+use chrono::NaiveDateTime;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+
+const LIMITE_DEFAULT: i64 = 50;
+const LIMITE_MAXIMO: i64 = 200;
+
+
+/// Acciones que se guardan en la tabla auditoria
+#[derive(Debug, Clone, Copy)]
+pub enum AccionAuditoria {
+    SuplantacionIniciada,
+    PeticionSuplantada,
+}
+
+impl AccionAuditoria {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AccionAuditoria::SuplantacionIniciada => "suplantacion_iniciada",
+            AccionAuditoria::PeticionSuplantada => "peticion_suplantada",
+        }
+    }
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct RegistroAuditoria {
+    pub auditoria_id: Uuid,
+    pub actor_id: Option<Uuid>,
+    pub usuario_id: Option<Uuid>,
+    pub accion: String,
+    pub detalle: String,
+    pub ip: Option<String>,
+    pub creado_en: NaiveDateTime,
+}
+
+#[derive(Debug, Default, serde::Deserialize)]
+pub struct FiltroAuditoria {
+    pub actor_id: Option<Uuid>,
+    pub usuario_id: Option<Uuid>,
+    pub accion: Option<String>,
+    pub limite: Option<i64>,
+    pub pagina: Option<i64>,
+}
+
+
+#[tracing::instrument(
+    name = "Registrar auditoria",
+    skip(pool)
+)]
+pub async fn registrar_auditoria(
+    pool: &PgPool,
+    actor_id: Option<&Uuid>,
+    usuario_id: Option<&Uuid>,
+    accion: AccionAuditoria,
+    detalle: &str,
+    ip: Option<&str>,
+) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO auditoria (auditoria_id, actor_id, usuario_id, accion, detalle, ip)
+        VALUES ($1, $2, $3, $4, $5, $6)
+        "#,
+        Uuid::new_v4(),
+        actor_id,
+        usuario_id,
+        accion.as_str(),
+        detalle,
+        ip,
+    )
+    .execute(pool)
+    .await
+    .context("Failed to store audit entry")?;
+
+    Ok(())
+}
+
+/// Registros mas recientes primero
+#[tracing::instrument(
+    name = "Listar auditoria",
+    skip(pool)
+)]
+pub async fn listar_auditoria(
+    pool: &PgPool,
+    filtro: &FiltroAuditoria,
+) -> Result<Vec<RegistroAuditoria>, anyhow::Error> {
+    let limite = filtro.limite.unwrap_or(LIMITE_DEFAULT).clamp(1, LIMITE_MAXIMO);
+    let pagina = filtro.pagina.unwrap_or(1).max(1);
+
+    let registros = sqlx::query_as!(
+        RegistroAuditoria,
+        r#"
+        SELECT auditoria_id, actor_id, usuario_id, accion, detalle, ip, creado_en
+        FROM auditoria
+        WHERE ($1::uuid IS NULL OR actor_id = $1)
+            AND ($2::uuid IS NULL OR usuario_id = $2)
+            AND ($3::text IS NULL OR accion = $3)
+        ORDER BY creado_en DESC
+        LIMIT $4 OFFSET $5
+        "#,
+        filtro.actor_id,
+        filtro.usuario_id,
+        filtro.accion,
+        limite,
+        (pagina - 1) * limite,
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to fetch audit entries")?;
+
+    Ok(registros)
+}
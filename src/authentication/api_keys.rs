@@ -0,0 +1,229 @@
+use actix_web::HttpRequest;
+use anyhow::Context;
+use chrono::NaiveDateTime;
+use rand::{thread_rng, Rng, distributions::Alphanumeric};
+use sha2::{Digest, Sha256};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+
+/// Encabezado con el que se envia la API key
+pub const API_KEY_HEADER: &str = "X-Api-Key";
+
+const API_KEY_PREFIX: &str = "cpv";
+
+/// Recursos que se pueden delegar a una API key, cada uno con
+/// permiso `read` o `write`
+pub const API_KEY_RESOURCES: [&str; 4] = ["departments", "requests", "users", "vehicules"];
+
+
+#[derive(Debug, serde::Serialize)]
+pub struct ApiKey {
+    pub api_key_id: Uuid,
+    pub usuario_id: Uuid,
+    pub nombre: String,
+    pub prefijo: String,
+    pub scopes: Vec<String>,
+    pub expira_en: Option<NaiveDateTime>,
+    pub ultimo_uso: Option<NaiveDateTime>,
+    pub creado_en: NaiveDateTime,
+    pub revocado_en: Option<NaiveDateTime>,
+}
+
+#[derive(Debug, serde::Deserialize)]
+pub struct NuevaApiKey {
+    pub nombre: String,
+    pub scopes: Vec<String>,
+    pub expira_en: Option<NaiveDateTime>,
+}
+
+impl NuevaApiKey {
+    /// Regresa el primer scope que no es valido
+    pub fn scope_invalido(&self) -> Option<&str> {
+        self.scopes.iter()
+            .map(|scope| scope.as_str())
+            .find(|scope| {
+                match scope.split_once(':') {
+                    Some((recurso, permiso)) => {
+                        !API_KEY_RESOURCES.contains(&recurso) || !["read", "write"].contains(&permiso)
+                    },
+                    None => true,
+                }
+            })
+    }
+}
+
+
+fn hash_api_key(api_key: &str) -> String {
+    format!("{:x}", Sha256::digest(api_key.trim().as_bytes()))
+}
+
+fn generar_api_key() -> (String, String) {
+    let mut rng = thread_rng();
+    let mut aleatorio = || -> String {
+        std::iter::repeat_with(|| rng.sample(Alphanumeric))
+            .map(char::from)
+            .take(32)
+            .collect()
+    };
+
+    let prefijo: String = aleatorio().chars().take(8).collect();
+    let secreto = aleatorio();
+
+    (prefijo.clone(), format!("{}_{}_{}", API_KEY_PREFIX, prefijo, secreto))
+}
+
+/// Scope que requiere la peticion, `recurso:read` para GET y
+/// `recurso:write` para los demas metodos.
+/// Regresa None si la ruta no se puede usar con una API key.
+pub fn scope_de_peticion(req: &HttpRequest) -> Option<String> {
+    let mut segmentos = req.path()
+        .trim_start_matches('/')
+        .split('/')
+        .skip_while(|segmento| *segmento == "api");
+
+    let recurso = segmentos.next()?;
+    if !API_KEY_RESOURCES.contains(&recurso) {
+        return None;
+    }
+
+    // Las rutas de la cuenta propia requieren una sesion
+    if recurso == "users" && segmentos.next() == Some("me") {
+        return None;
+    }
+
+    let permiso = match *req.method() {
+        actix_web::http::Method::GET | actix_web::http::Method::HEAD => "read",
+        _ => "write",
+    };
+
+    Some(format!("{}:{}", recurso, permiso))
+}
+
+
+/// Crea la API key y regresa el valor completo, que solo se muestra una vez
+#[tracing::instrument(
+    name = "Crear API key",
+    skip(pool, nueva)
+)]
+pub async fn crear_api_key(
+    pool: &PgPool,
+    usuario_id: &Uuid,
+    nueva: &NuevaApiKey,
+) -> Result<(ApiKey, String), anyhow::Error> {
+    let (prefijo, api_key) = generar_api_key();
+
+    let row = sqlx::query_as!(
+        ApiKey,
+        r#"
+        INSERT INTO api_keys
+        (api_key_id, usuario_id, nombre, prefijo, key_hash, scopes, expira_en)
+        VALUES ($1, $2, $3, $4, $5, $6, $7)
+        RETURNING api_key_id, usuario_id, nombre, prefijo, scopes, expira_en,
+                  ultimo_uso, creado_en, revocado_en
+        "#,
+        Uuid::new_v4(),
+        usuario_id,
+        nueva.nombre,
+        prefijo,
+        hash_api_key(&api_key),
+        &nueva.scopes,
+        nueva.expira_en,
+    )
+    .fetch_one(pool)
+    .await
+    .context("Failed to store api key")?;
+
+    Ok((row, api_key))
+}
+
+/// Busca una API key vigente y registra su uso
+#[tracing::instrument(
+    name = "Validar API key",
+    skip_all
+)]
+pub async fn validar_api_key(
+    pool: &PgPool,
+    api_key: &str,
+) -> Result<Option<ApiKey>, anyhow::Error> {
+    let row = sqlx::query_as!(
+        ApiKey,
+        r#"
+        SELECT api_key_id, usuario_id, nombre, prefijo, scopes, expira_en,
+               ultimo_uso, creado_en, revocado_en
+        FROM api_keys
+        WHERE key_hash = $1
+            AND revocado_en IS NULL
+            AND (expira_en IS NULL OR expira_en > now())
+        "#,
+        hash_api_key(api_key),
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to fetch api key")?;
+
+    if let Some(row) = &row {
+        // Basta con precision de un minuto para no escribir en cada peticion
+        sqlx::query!(
+            r#"
+            UPDATE api_keys
+            SET ultimo_uso = now()
+            WHERE api_key_id = $1
+                AND (ultimo_uso IS NULL OR ultimo_uso < now() - INTERVAL '1 minute')
+            "#,
+            row.api_key_id,
+        )
+        .execute(pool)
+        .await
+        .context("Failed to update api key last use")?;
+    }
+
+    Ok(row)
+}
+
+#[tracing::instrument(
+    name = "Listar API keys",
+    skip(pool)
+)]
+pub async fn listar_api_keys(
+    pool: &PgPool,
+) -> Result<Vec<ApiKey>, anyhow::Error> {
+    let rows = sqlx::query_as!(
+        ApiKey,
+        r#"
+        SELECT api_key_id, usuario_id, nombre, prefijo, scopes, expira_en,
+               ultimo_uso, creado_en, revocado_en
+        FROM api_keys
+        ORDER BY creado_en DESC
+        "#,
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to fetch api keys")?;
+
+    Ok(rows)
+}
+
+/// Regresa false si la API key no existe o ya estaba revocada
+#[tracing::instrument(
+    name = "Revocar API key",
+    skip(pool)
+)]
+pub async fn revocar_api_key(
+    pool: &PgPool,
+    api_key_id: &Uuid,
+) -> Result<bool, anyhow::Error> {
+    let result = sqlx::query!(
+        r#"
+        UPDATE api_keys
+        SET revocado_en = now()
+        WHERE api_key_id = $1 AND revocado_en IS NULL
+        "#,
+        api_key_id,
+    )
+    .execute(pool)
+    .await
+    .context("Failed to revoke api key")?;
+
+    Ok(result.rows_affected() > 0)
+}
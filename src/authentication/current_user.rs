@@ -0,0 +1,380 @@
+use actix_web::{HttpRequest, HttpMessage, FromRequest, web};
+use actix_web::dev::Payload;
+use chrono::NaiveDateTime;
+use futures::future::LocalBoxFuture;
+use redis::AsyncCommands;
+use sqlx::PgPool;
+use std::future::ready;
+use uuid::Uuid;
+
+use common::models::user::{Usuario, UsuarioRol};
+
+use crate::api_response::{e401, e403, e500};
+use crate::redis_pool::{RedisPool, RedisPoolError};
+use crate::routes::department::sqlx::obtener_departamentos_administrados_sqlx;
+use crate::routes::users::sqlx::obtener_usuario_por_id_sqlx;
+use crate::startup::UserCacheTtl;
+
+use super::api_keys::{ApiKey, API_KEY_HEADER, scope_de_peticion, validar_api_key};
+use super::jwt_session::JwtSession;
+
+
+/// Usuario autenticado de la peticion actual.
+///
+/// Se construye a partir de `JwtSession` (reutilizando la sesion que
+/// `reject_anonymous_user` deja en las extensiones) o de una API key en el
+/// encabezado `X-Api-Key`, y carga el usuario una sola vez por peticion,
+/// opcionalmente desde el cache en Redis.
+/// Responde 401 si el usuario ya no existe o esta desactivado, y 403 si la
+/// API key no tiene el scope que requiere la ruta.
+pub struct CurrentUser {
+    pub usuario: Usuario,
+    // None cuando la peticion se autentico con una API key
+    pub session: Option<JwtSession>,
+    pub api_key: Option<ApiKey>,
+    pub redis: RedisPool,
+    pub alcance: AlcanceAdmin,
+}
+
+/// Lo que el usuario puede administrar, se guarda en el cache junto con el usuario
+#[derive(Debug, Clone, PartialEq)]
+pub enum AlcanceAdmin {
+    Global,
+    // Departamentos asignados en administradores_departamento y sus subdepartamentos
+    Departamentos(Vec<i32>),
+    Ninguno,
+}
+
+impl CurrentUser {
+    pub fn into_inner(self) -> Usuario {
+        self.usuario
+    }
+
+    pub fn session_id(&self) -> Option<Uuid> {
+        self.session.as_ref().map(|session| session.session_id)
+    }
+
+    /// Administrador que suplanta al usuario en esta peticion
+    pub fn impersonador(&self) -> Option<Uuid> {
+        self.session.as_ref().and_then(|session| session.actor)
+    }
+
+    /// Administrador global o de al menos un departamento
+    pub fn administra(&self) -> bool {
+        self.alcance != AlcanceAdmin::Ninguno
+    }
+
+    /// Departamentos a los que se limitan los queries de administracion,
+    /// None para el administrador global
+    pub fn departamentos_administrados(&self) -> Option<&[i32]> {
+        match &self.alcance {
+            AlcanceAdmin::Global => None,
+            AlcanceAdmin::Departamentos(departamentos) => Some(departamentos),
+            AlcanceAdmin::Ninguno => Some(&[]),
+        }
+    }
+
+    pub fn administra_departamento(&self, departamento: Option<i32>) -> bool {
+        match self.departamentos_administrados() {
+            None => true,
+            Some(departamentos) => departamento
+                .map(|departamento| departamentos.contains(&departamento))
+                .unwrap_or(false),
+        }
+    }
+
+    /// Responde 403 si la peticion se hace suplantando al usuario, para
+    /// acciones sensibles como cambiar la contraseña o el correo
+    pub fn rechazar_suplantacion(&self) -> Result<(), actix_web::Error> {
+        if self.impersonador().is_some() {
+            return Err(e403().with_message("Esta accion no se permite al suplantar a un usuario"))?;
+        }
+        Ok(())
+    }
+
+    /// Responde 403 si la peticion se hace con una API key, para acciones
+    /// que otorgan o quitan privilegios de administracion
+    pub fn rechazar_api_key(&self) -> Result<(), actix_web::Error> {
+        if self.api_key.is_some() {
+            return Err(e403().with_message("Esta accion no se permite con una API key"))?;
+        }
+        Ok(())
+    }
+}
+
+impl std::ops::Deref for CurrentUser {
+    type Target = Usuario;
+
+    fn deref(&self) -> &Self::Target {
+        &self.usuario
+    }
+}
+
+impl FromRequest for CurrentUser {
+    type Error = actix_web::Error;
+    type Future = LocalBoxFuture<'static, Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, payload: &mut Payload) -> Self::Future {
+        let pool = req.app_data::<web::Data<PgPool>>().cloned();
+        let redis = req.app_data::<web::Data<RedisPool>>()
+            .map(|redis| redis.get_ref().clone());
+
+        let cache_ttl = req.app_data::<web::Data<UserCacheTtl>>()
+            .map(|ttl| ttl.0)
+            .unwrap_or(0);
+
+        let api_key = req.headers()
+            .get(API_KEY_HEADER)
+            .and_then(|h| h.to_str().ok())
+            .map(|h| h.to_string());
+
+        if let Some(api_key) = api_key {
+            let scope = scope_de_peticion(req);
+
+            return Box::pin(async move {
+                let pool = pool.ok_or(e500())?;
+                let redis = redis.ok_or(e500())?;
+
+                let api_key = validar_api_key(&pool, &api_key).await
+                    .map_err(|_| e500())?
+                    .ok_or(e401().with_message("API key invalida"))?;
+
+                match scope {
+                    Some(scope) if api_key.scopes.contains(&scope) => {},
+                    Some(scope) => {
+                        return Err(e403().with_message(format!("La API key no tiene el permiso {}", scope)))?;
+                    },
+                    None => {
+                        return Err(e403().with_message("Esta ruta requiere iniciar sesion"))?;
+                    },
+                }
+
+                let (usuario, alcance) = cargar_usuario(&redis, &pool, &api_key.usuario_id, cache_ttl).await?;
+
+                Ok(CurrentUser { usuario, session: None, api_key: Some(api_key), redis, alcance })
+            });
+        }
+
+        let session: LocalBoxFuture<'static, Result<JwtSession, actix_web::Error>> =
+            match req.extensions().get::<JwtSession>() {
+                Some(session) => Box::pin(ready(Ok(session.clone()))),
+                None => JwtSession::from_request(req, payload),
+            };
+
+        Box::pin(async move {
+            let session = session.await?;
+            let pool = pool.ok_or(e500())?;
+
+            let (usuario, alcance) = cargar_usuario(&session.redis, &pool, &session.user_id, cache_ttl).await?;
+            let redis = session.redis.clone();
+
+            Ok(CurrentUser { usuario, session: Some(session), api_key: None, redis, alcance })
+        })
+    }
+}
+
+
+async fn cargar_usuario(
+    redis: &RedisPool,
+    pool: &PgPool,
+    usuario_id: &Uuid,
+    cache_ttl: u64,
+) -> Result<(Usuario, AlcanceAdmin), actix_web::Error> {
+    let (usuario, alcance) = obtener_usuario_con_cache(redis, pool, usuario_id, cache_ttl).await
+        .map_err(|_| e500())?
+        .ok_or(e401().with_message("Usuario no valido"))?;
+
+    if !usuario.activo {
+        return Err(e401().with_message("Usuario desactivado"))?;
+    }
+
+    Ok((usuario, alcance))
+}
+
+
+fn alcance_de(usuario: &Usuario, departamentos: Vec<i32>) -> AlcanceAdmin {
+    if usuario.es_admin() {
+        return AlcanceAdmin::Global;
+    }
+    if departamentos.is_empty() {
+        return AlcanceAdmin::Ninguno;
+    }
+
+    AlcanceAdmin::Departamentos(departamentos)
+}
+
+async fn obtener_usuario_y_alcance_sqlx(
+    pool: &PgPool,
+    usuario_id: &Uuid,
+) -> Result<Option<(Usuario, Vec<i32>)>, anyhow::Error> {
+    let usuario = match obtener_usuario_por_id_sqlx(pool, usuario_id).await? {
+        Some(usuario) => usuario,
+        None => return Ok(None),
+    };
+
+    // El administrador global no necesita la lista de departamentos
+    let departamentos = if usuario.es_admin() {
+        vec![]
+    } else {
+        obtener_departamentos_administrados_sqlx(pool, usuario_id).await?
+    };
+
+    Ok(Some((usuario, departamentos)))
+}
+
+
+fn get_cache_key(usuario_id: &Uuid) -> String {
+    format!("user.id:{}:cache", usuario_id)
+}
+
+#[tracing::instrument(
+    name = "Obtener usuario con cache",
+    skip(redis, pool)
+)]
+async fn obtener_usuario_con_cache(
+    redis: &RedisPool,
+    pool: &PgPool,
+    usuario_id: &Uuid,
+    cache_ttl: u64,
+) -> Result<Option<(Usuario, AlcanceAdmin)>, anyhow::Error> {
+    let sin_cache = |resultado: Option<(Usuario, Vec<i32>)>| resultado
+        .map(|(usuario, departamentos)| {
+            let alcance = alcance_de(&usuario, departamentos);
+            (usuario, alcance)
+        });
+
+    if cache_ttl == 0 {
+        return Ok(sin_cache(obtener_usuario_y_alcance_sqlx(pool, usuario_id).await?));
+    }
+
+    let key = get_cache_key(usuario_id);
+
+    // Un error del cache no debe impedir atender la peticion
+    let mut redis_con = match redis.get().await {
+        Ok(con) => con,
+        Err(e) => {
+            tracing::warn!("No se pudo conectar al cache de usuarios: {:?}", e);
+            return Ok(sin_cache(obtener_usuario_y_alcance_sqlx(pool, usuario_id).await?));
+        }
+    };
+
+    // Una entrada con otro formato se ignora y se vuelve a cargar
+    let cached: Option<String> = redis.timeout(redis_con.get(&key)).await.unwrap_or(None);
+    if let Some(json) = cached {
+        if let Ok(cache) = serde_json::from_str::<UsuarioCache>(&json) {
+            let departamentos = cache.departamentos_administrados.clone();
+            let usuario = Usuario::from(cache);
+            let alcance = alcance_de(&usuario, departamentos);
+            return Ok(Some((usuario, alcance)));
+        }
+    }
+
+    let resultado = obtener_usuario_y_alcance_sqlx(pool, usuario_id).await?;
+
+    if let Some((usuario, departamentos)) = &resultado {
+        let json = serde_json::to_string(&UsuarioCacheRef::new(usuario, departamentos))?;
+        let result: Result<(), RedisPoolError> = redis.timeout(redis_con.set_ex(&key, json, cache_ttl as usize)).await;
+        if let Err(e) = result {
+            tracing::warn!("No se pudo guardar el usuario en cache: {:?}", e);
+        }
+    }
+
+    Ok(sin_cache(resultado))
+}
+
+/// Borra el usuario del cache, se debe llamar despues de modificarlo o de
+/// cambiar los departamentos que administra.
+/// Un fallo solo se registra, el cache expira por si solo.
+#[tracing::instrument(
+    name = "Invalidar cache del usuario",
+    skip(redis)
+)]
+pub async fn invalidar_cache_usuario(
+    redis: &RedisPool,
+    usuario_id: &Uuid,
+) {
+    let result: Result<(), RedisPoolError> = async {
+        let mut redis_con = redis.get().await?;
+        redis.timeout(redis_con.del(get_cache_key(usuario_id))).await
+    }.await;
+
+    if let Err(e) = result {
+        tracing::warn!("No se pudo invalidar el cache del usuario: {:?}", e);
+    }
+}
+
+
+// El password_hash nunca se guarda en el cache
+#[derive(serde::Serialize)]
+struct UsuarioCacheRef<'a> {
+    usuario_id: &'a Uuid,
+    nombres: &'a str,
+    apellidos: &'a str,
+    email: &'a str,
+    numero_empleado: Option<i16>,
+    activo: bool,
+    verificado: bool,
+    imagen: &'a str,
+    departamento: &'a str,
+    rol: &'a UsuarioRol,
+    creado_en: &'a NaiveDateTime,
+    modificado_en: &'a NaiveDateTime,
+    departamentos_administrados: &'a [i32],
+}
+
+impl<'a> UsuarioCacheRef<'a> {
+    fn new(usuario: &'a Usuario, departamentos_administrados: &'a [i32]) -> Self {
+        Self {
+            usuario_id: &usuario.usuario_id,
+            nombres: &usuario.nombres,
+            apellidos: &usuario.apellidos,
+            email: &usuario.email,
+            numero_empleado: usuario.numero_empleado,
+            activo: usuario.activo,
+            verificado: usuario.verificado,
+            imagen: &usuario.imagen,
+            departamento: &usuario.departamento,
+            rol: &usuario.rol,
+            creado_en: &usuario.creado_en,
+            modificado_en: &usuario.modificado_en,
+            departamentos_administrados,
+        }
+    }
+}
+
+#[derive(serde::Deserialize)]
+struct UsuarioCache {
+    usuario_id: Uuid,
+    nombres: String,
+    apellidos: String,
+    email: String,
+    numero_empleado: Option<i16>,
+    activo: bool,
+    verificado: bool,
+    imagen: String,
+    departamento: String,
+    rol: UsuarioRol,
+    creado_en: NaiveDateTime,
+    modificado_en: NaiveDateTime,
+    departamentos_administrados: Vec<i32>,
+}
+
+impl From<UsuarioCache> for Usuario {
+    fn from(cache: UsuarioCache) -> Self {
+        Usuario {
+            usuario_id: cache.usuario_id,
+            nombres: cache.nombres,
+            apellidos: cache.apellidos,
+            email: cache.email,
+            password_hash: String::new(),
+            numero_empleado: cache.numero_empleado,
+            activo: cache.activo,
+            verificado: cache.verificado,
+            imagen: cache.imagen,
+            departamento: cache.departamento,
+            rol: cache.rol,
+            creado_en: cache.creado_en,
+            modificado_en: cache.modificado_en,
+        }
+    }
+}
@@ -1,18 +1,22 @@
 use chrono::Utc;
-use jsonwebtoken::{Header, Algorithm, encode, EncodingKey, decode, Validation, DecodingKey};
 use serde::{Serialize, Deserialize};
 use uuid::Uuid;
-use secrecy::{Secret, ExposeSecret};
+use secrecy::Secret;
 
-use actix_web::{HttpRequest, web, FromRequest};
+use actix_web::{HttpMessage, HttpRequest, web, FromRequest};
 use actix_web::http;
 use actix_web::dev::Payload;
-use std::future::{ready, Ready};
+use futures::future::LocalBoxFuture;
+use redis::{AsyncCommands, RedisResult};
 
-use crate::api_response::{e401, e500};
-use crate::startup::RedisUri;
+use sqlx::PgPool;
 
-use redis::Commands;
+use crate::api_response::{e401, e403, e500, e503};
+use crate::audit::{AccionAuditoria, registrar_auditoria};
+use crate::redis_pool::{RedisConnection, RedisPool};
+
+use super::key_ring::KeyRing;
+use super::sessions::verificar_sesion;
 
 
 
@@ -22,15 +26,30 @@ pub struct HmacKey(pub Secret<String>);
 #[derive(Debug, Serialize, Deserialize)]
 pub struct TokenClaims {
     pub sub: String,
+    pub iss: String,
+    pub aud: String,
+    // Sesion registrada en la tabla sesiones
+    pub sid: Uuid,
+    // Identificador unico del token para revocarlo
+    pub jti: Uuid,
     pub iat: usize,
     pub exp: usize,
+    // Administrador que actua como el usuario (RFC 8693)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub act: Option<ActorClaim>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ActorClaim {
+    pub sub: String,
 }
 
 
 impl TokenClaims {
-    const EXPIRATION_HOURS: i64 = 5;
+    pub const EXPIRATION_HOURS: i64 = 5;
+    pub const IMPERSONATION_MINUTES: i64 = 30;
 
-    pub fn new(user_id: &Uuid) -> Self {
+    pub fn new(user_id: &Uuid, session_id: &Uuid, key_ring: &KeyRing) -> Self {
         let issue_at = Utc::now().timestamp(); 
 
         let expiration = Utc::now()
@@ -40,111 +59,181 @@ impl TokenClaims {
 
         Self {
             sub: user_id.to_string(),
+            iss: key_ring.issuer().to_string(),
+            aud: key_ring.audience().to_string(),
+            sid: *session_id,
+            jti: Uuid::new_v4(),
             iat: issue_at as usize,
             exp: expiration as usize,
+            act: None,
         }
     }
 
-    
-    pub fn from_token(token: &str, hmac_key: &HmacKey) -> Result<Self, jsonwebtoken::errors::Error> {
-        let validation = Validation::new(Algorithm::HS256);
-        let key = DecodingKey::from_secret(hmac_key.0.expose_secret().as_bytes());
-        let token_data = decode::<Self>(token, &key, &validation)?;
+    /// Token de corta duracion para que un administrador actue como el usuario,
+    /// no tiene una sesion registrada en la tabla sesiones
+    pub fn impersonation(user_id: &Uuid, admin_id: &Uuid, key_ring: &KeyRing) -> Self {
+        let expiration = Utc::now()
+            .checked_add_signed(chrono::Duration::minutes(Self::IMPERSONATION_MINUTES))
+            .expect("valid timestamp")
+            .timestamp();
 
-        Ok(token_data.claims)
+        Self {
+            exp: expiration as usize,
+            act: Some(ActorClaim { sub: admin_id.to_string() }),
+            ..Self::new(user_id, &Uuid::new_v4(), key_ring)
+        }
+    }
+
+    
+    pub fn from_token(token: &str, key_ring: &KeyRing) -> Result<Self, jsonwebtoken::errors::Error> {
+        key_ring.decode(token)
     }
 
     pub fn get_user_id(&self) -> Result<Uuid, uuid::Error> {
         Uuid::parse_str(self.sub.as_str()) 
     }
+
+    pub fn get_actor_id(&self) -> Result<Option<Uuid>, uuid::Error> {
+        self.act.as_ref()
+            .map(|act| Uuid::parse_str(act.sub.as_str()))
+            .transpose()
+    }
 }
 
-#[tracing::instrument(name = "Create JWT", skip(key))]
-pub fn create_jwt(user_id: &Uuid, key: &HmacKey) -> Result<String, jsonwebtoken::errors::Error> {
-    let claims = TokenClaims::new(user_id);
-    let header = Header::new(Algorithm::HS256);
-    encode(&header,
-           &claims,
-           &EncodingKey::from_secret(key.0.expose_secret().as_bytes())
-    )
+/// Firma los claims, los tokens se crean con `sessions::crear_sesion`
+#[tracing::instrument(name = "Encode JWT", skip(claims, key_ring))]
+pub fn encode_jwt(claims: &TokenClaims, key_ring: &KeyRing) -> Result<String, jsonwebtoken::errors::Error> {
+    key_ring.encode(claims)
 }
 
 
 
 #[derive(Debug, Clone)]
-//pub struct JwtSession<'a> {
 pub struct JwtSession {
     pub user_id: Uuid,
+    pub session_id: Uuid,
+    pub jti: Uuid,
+    pub iat: usize,
+    pub exp: usize,
+    // Administrador que suplanta al usuario
+    pub actor: Option<Uuid>,
     pub token: String,
-    pub redis_client: redis::Client
-    //pub redis_client: &'a redis::Client
-    //pub redis_con: redis::aio::ConnectionManager
+    pub redis: RedisPool,
 }
 
-//impl<'a> JwtSession<'a> {
 impl JwtSession {
-    pub fn new(user_id: Uuid, token: String, redis_client: redis::Client) -> Self {
-        Self { user_id, token, redis_client}
+    pub fn new(user_id: Uuid, claims: &TokenClaims, token: String, redis: RedisPool) -> Self {
+        Self {
+            user_id,
+            session_id: claims.sid,
+            jti: claims.jti,
+            iat: claims.iat,
+            exp: claims.exp,
+            actor: claims.get_actor_id().ok().flatten(),
+            token,
+            redis,
+        }
     }
 
-    pub fn get_blacklist_key(&self) -> String {
-        format!("user.id:{}:blacklist.tokens", self.user_id)
+    pub fn is_impersonation(&self) -> bool {
+        self.actor.is_some()
+    }
+
+    /// Segundos que le quedan al token, minimo 1 para poder usarlo como TTL
+    pub fn remaining_seconds(&self) -> usize {
+        (self.exp as i64 - Utc::now().timestamp()).max(1) as usize
     }
 
     #[tracing::instrument(
     name = "Blacklist current JWT session",
     skip(self)
     )]
-    pub fn blacklist_session(&self) -> Result<(), anyhow::Error> {
-        let mut redis_con = self.redis_client.get_connection().unwrap();
-
-        let result: Result<(), redis::RedisError> = redis::cmd("SADD")
-            .arg(self.get_blacklist_key())
-            .arg(&self.token)
-            .query(&mut redis_con);
-        //map error
-        match result {
-            Ok(()) => Ok(()),
-            Err(e) => Err(e.into())
-        }
+    pub async fn blacklist_session(&self) -> Result<(), anyhow::Error> {
+        let mut redis_con = self.redis.get().await?;
+
+        // La entrada expira junto con el token
+        let _: () = self.redis.timeout(
+            redis_con.set_ex(get_revoked_jti_key(&self.jti), 1, self.remaining_seconds())
+        ).await?;
+
+        Ok(())
     }
 
     #[tracing::instrument(
     name = "Check if JWT Session is blacklisted",
-    skip(self)
+    skip(self, redis_con)
     )]
-    pub fn is_blacklisted(&self) -> Result<bool, anyhow::Error> {
-        let mut redis_con = self.redis_client.get_connection().unwrap();
-        match redis_con.sismember(self.get_blacklist_key(), &self.token) {
-            Ok(blacklisted) => { Ok(blacklisted) },
-            Err(e) => { Err(e.into()) },
+    pub async fn is_blacklisted(&self, redis_con: &mut RedisConnection) -> RedisResult<bool> {
+        let (revoked, valid_after): (bool, Option<usize>) = redis::pipe()
+            .exists(get_revoked_jti_key(&self.jti))
+            .get(get_tokens_valid_after_key(&self.user_id))
+            .query_async(redis_con)
+            .await?;
+
+        Ok(revoked || valid_after.map_or(false, |valid_after| self.iat < valid_after))
+    }
+
+    /// Regresa el motivo si el token o su sesion fueron revocados
+    async fn check_revocation(&self, pool: &PgPool) -> Result<Option<&'static str>, anyhow::Error> {
+        let mut redis_con = self.redis.get().await?;
+
+        if self.redis.timeout(self.is_blacklisted(&mut redis_con)).await? {
+            return Ok(Some("Blacklisted token"));
+        }
+
+        if verificar_sesion(pool, &self.redis, &mut redis_con, &self.session_id, self.exp).await? {
+            return Ok(Some("Session revoked"));
         }
+
+        Ok(None)
     }
 }
 
-impl FromRequest for JwtSession {
-    type Error = actix_web::Error;
-    type Future = Ready<Result<Self, Self::Error>>;
 
-    fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
-        let hmca_key = if let Some(key) = req.app_data::<web::Data<HmacKey>>() {
-            key.get_ref()
-        } else {
-            return ready(Err(e500().into()))
-        };
+fn get_revoked_jti_key(jti: &Uuid) -> String {
+    format!("token.jti:{}:revoked", jti)
+}
 
+fn get_tokens_valid_after_key(user_id: &Uuid) -> String {
+    format!("user.id:{}:tokens.valid.after", user_id)
+}
 
-        let redis_uri= if let Some(uri) =  req.app_data::<web::Data<RedisUri>>() {
-            uri.get_ref()
-        } else {
-            return ready(Err(e500().into()))
-        };
+/// Invalida todos los tokens del usuario emitidos antes de este momento.
+/// La marca dura lo mismo que el token mas largo posible.
+#[tracing::instrument(
+    name = "Revoke all user tokens",
+    skip(redis_con)
+)]
+pub async fn revoke_user_tokens(
+    redis_con: &mut RedisConnection,
+    user_id: &Uuid,
+) -> Result<(), redis::RedisError> {
+    let ttl = TokenClaims::EXPIRATION_HOURS * 60 * 60;
+
+    redis::cmd("SET")
+        .arg(get_tokens_valid_after_key(user_id))
+        .arg(Utc::now().timestamp())
+        .arg("EX")
+        .arg(ttl)
+        .query_async(redis_con)
+        .await
+}
+
+impl FromRequest for JwtSession {
+    type Error = actix_web::Error;
+    type Future = LocalBoxFuture<'static, Result<Self, Self::Error>>;
 
-        let redis_client = match redis::Client::open(redis_uri.0.clone()) {
-            Ok(client) => client,
-            Err(_) =>  return ready(Err(e500().into())),
-        };
+    fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        // Ya validada por `reject_anonymous_user`
+        if let Some(session) = req.extensions().get::<JwtSession>() {
+            let session = session.clone();
+            return Box::pin(async move { Ok(session) });
+        }
 
+        let key_ring = req.app_data::<web::Data<KeyRing>>().cloned();
+        let pool = req.app_data::<web::Data<PgPool>>().cloned();
+        let redis = req.app_data::<web::Data<RedisPool>>()
+            .map(|pool| pool.get_ref().clone());
 
         let token = req
             .headers()
@@ -152,7 +241,7 @@ impl FromRequest for JwtSession {
             .and_then(|h| h.to_str().ok())
             .and_then(|h| {
                 let words = h.split("Bearer").collect::<Vec<&str>>();
-                let token = words.get(1).map(|w| w.trim());
+                let token = words.get(1).map(|w| w.trim().to_string());
 
                 token
             });
@@ -163,33 +252,79 @@ impl FromRequest for JwtSession {
             });
             */
 
-        if token.is_none() {
-            return ready(Err(e401().with_message("Please provide a token").into()))
-        }
-        
-        let claims = match TokenClaims::from_token(token.unwrap(), hmca_key) {
-            Ok(c) => c,
-            Err(_) => {
-                return ready(Err(e401().with_message("Invalid token").into()))
+        // Cada peticion de una suplantacion queda en la auditoria
+        let peticion = format!("{} {}", req.method(), req.path());
+        let ip = req.connection_info()
+            .realip_remote_addr()
+            .map(|ip| ip.to_string());
+
+        Box::pin(async move {
+            let key_ring = key_ring.ok_or(e500())?;
+            let pool = pool.ok_or(e500())?;
+            let redis = redis.ok_or(e500())?;
+            let token = token.ok_or(e401().with_message("Please provide a token"))?;
+
+            let claims = TokenClaims::from_token(&token, &key_ring)
+                .map_err(|_| e401().with_message("Invalid token"))?;
+
+            let user_id = claims.get_user_id()
+                .map_err(|_| e401().with_message("Invalid token"))?;
+            let actor = claims.get_actor_id()
+                .map_err(|_| e401().with_message("Invalid token"))?;
+            let jwt_session = JwtSession::new(user_id, &claims, token, redis);
+
+            // Check revoked tokens and sessions
+            match jwt_session.check_revocation(&pool).await {
+                Ok(None) => {},
+                Ok(Some(reason)) => {
+                    return Err(e401().with_message(reason))?;
+                },
+                Err(e) if jwt_session.redis.fail_open() => {
+                    tracing::warn!("No se pudo verificar la revocacion del token: {:?}", e);
+                },
+                Err(e) => {
+                    tracing::error!("No se pudo verificar la revocacion del token: {:?}", e);
+                    return Err(e503())?;
+                },
             }
-        };
-
-
-        let user_id = uuid::Uuid::parse_str(claims.sub.as_str()).unwrap();
-        let jwt_session = JwtSession::new(user_id, token.unwrap().to_string(), redis_client);
-        
-        // Check blacklist tokens
-        match jwt_session.is_blacklisted() {
-            Ok(exists) => {
-                if exists {
-                    return ready(Err(e401().with_message("Blacklisted token").into()))
-                }
-            },
-            Err(_) => {
-                return ready(Err(e500().into()))
-            },
-        }
 
-        ready(Ok(jwt_session))
+            if let Some(actor) = actor {
+                auditar_suplantacion(&pool, &actor, &user_id, &peticion, ip.as_deref()).await?;
+            }
+
+            Ok(jwt_session)
+        })
+    }
+}
+
+
+/// Registra la peticion hecha al suplantar, el administrador debe seguir
+/// siendo administrador activo para que el token sea valido
+async fn auditar_suplantacion(
+    pool: &PgPool,
+    actor: &Uuid,
+    user_id: &Uuid,
+    peticion: &str,
+    ip: Option<&str>,
+) -> Result<(), actix_web::Error> {
+    let row = sqlx::query!(
+        r#"
+        SELECT COALESCE(rol = 'admin', false) AND activo AND desactivado_en IS NULL as "puede_suplantar!"
+        FROM usuarios
+        WHERE usuario_id = $1
+        "#,
+        actor,
+    )
+    .fetch_optional(pool)
+    .await
+    .map_err(|_| e500())?;
+
+    if !row.map_or(false, |row| row.puede_suplantar) {
+        return Err(e403().with_message("La suplantacion ya no es valida"))?;
     }
+
+    registrar_auditoria(pool, Some(actor), Some(user_id), AccionAuditoria::PeticionSuplantada, peticion, ip).await
+        .map_err(|_| e500())?;
+
+    Ok(())
 }
@@ -0,0 +1,220 @@
+use std::collections::HashMap;
+
+use anyhow::Context;
+use jsonwebtoken::{Algorithm, DecodingKey, EncodingKey, Header, Validation, decode, decode_header, encode};
+use jsonwebtoken::jwk::{
+    AlgorithmParameters, CommonParameters, EllipticCurve, Jwk, JwkSet, OctetKeyPairParameters,
+    OctetKeyPairType, PublicKeyUse, RSAKeyParameters, RSAKeyType,
+};
+use rsa::RsaPublicKey;
+use rsa::pkcs8::DecodePublicKey;
+use rsa::traits::PublicKeyParts;
+use secrecy::ExposeSecret;
+
+use crate::configuration::{JwtKeySettings, JwtSettings};
+
+use super::jwt_session::{HmacKey, TokenClaims};
+
+
+/// Identificador de la llave con la que se verifican los tokens sin `kid`,
+/// sin `secret` en `jwt.keys` usa `application.hmca_secret`
+pub const LEGACY_KID: &str = "legacy";
+
+// Prefijo DER de una llave publica Ed25519 (SubjectPublicKeyInfo)
+const ED25519_SPKI_PREFIX: [u8; 12] = [
+    0x30, 0x2a, 0x30, 0x05, 0x06, 0x03, 0x2b, 0x65, 0x70, 0x03, 0x21, 0x00,
+];
+
+
+struct JwtKey {
+    algorithm: Algorithm,
+    encoding: Option<EncodingKey>,
+    decoding: DecodingKey,
+    // Solo las llaves asimetricas se publican
+    jwk: Option<Jwk>,
+}
+
+
+/// Llaves para firmar y verificar los jwt.
+///
+/// Solo una llave firma, pero todas las llaves configuradas verifican,
+/// asi una llave se puede rotar sin cerrar las sesiones existentes.
+pub struct KeyRing {
+    signing_kid: String,
+    keys: HashMap<String, JwtKey>,
+    issuer: String,
+    audience: String,
+}
+
+impl KeyRing {
+    pub fn from_settings(legacy: &HmacKey, settings: &JwtSettings) -> Result<Self, anyhow::Error> {
+        let mut keys = HashMap::new();
+
+        for key_settings in settings.keys.iter() {
+            if key_settings.retired {
+                continue;
+            }
+
+            let mut key = if key_settings.kid == LEGACY_KID && key_settings.secret.is_none() {
+                let mut legacy_settings = key_settings.clone();
+                legacy_settings.secret = Some(legacy.0.clone());
+                jwt_key_from_settings(&legacy_settings)
+            } else {
+                jwt_key_from_settings(key_settings)
+            }
+            .with_context(|| format!("Invalid jwt key {}", key_settings.kid))?;
+
+            if key_settings.verify_only {
+                key.encoding = None;
+            }
+            keys.insert(key_settings.kid.clone(), key);
+        }
+
+        let signing_kid = settings.signing_kid.clone()
+            .unwrap_or_else(|| LEGACY_KID.to_string());
+
+        match keys.get(&signing_kid) {
+            Some(key) if key.encoding.is_some() => {},
+            Some(_) => anyhow::bail!("Signing key {} has no private key", signing_kid),
+            None => anyhow::bail!("Signing key {} is not configured", signing_kid),
+        }
+
+        Ok(Self {
+            signing_kid,
+            keys,
+            issuer: settings.issuer.clone(),
+            audience: settings.audience.clone(),
+        })
+    }
+
+    pub fn issuer(&self) -> &str {
+        &self.issuer
+    }
+
+    pub fn audience(&self) -> &str {
+        &self.audience
+    }
+
+    pub fn encode(&self, claims: &TokenClaims) -> Result<String, jsonwebtoken::errors::Error> {
+        let key = &self.keys[&self.signing_kid];
+
+        let mut header = Header::new(key.algorithm);
+        header.kid = Some(self.signing_kid.clone());
+
+        // La llave de firma siempre tiene parte privada, se valida al construir
+        encode(&header, claims, key.encoding.as_ref().expect("signing key"))
+    }
+
+    pub fn decode(&self, token: &str) -> Result<TokenClaims, jsonwebtoken::errors::Error> {
+        let header = decode_header(token)?;
+        let kid = header.kid.as_deref().unwrap_or(LEGACY_KID);
+
+        let key = self.keys.get(kid)
+            .ok_or(jsonwebtoken::errors::ErrorKind::InvalidToken)?;
+
+        // El algoritmo lo decide la llave, nunca el encabezado
+        let mut validation = Validation::new(key.algorithm);
+        validation.set_issuer(&[&self.issuer]);
+        validation.set_audience(&[&self.audience]);
+
+        let token_data = decode::<TokenClaims>(token, &key.decoding, &validation)?;
+
+        Ok(token_data.claims)
+    }
+
+    /// Llaves publicas para que otros servicios verifiquen los tokens
+    pub fn jwks(&self) -> JwkSet {
+        let mut keys: Vec<Jwk> = self.keys.values()
+            .filter_map(|key| key.jwk.clone())
+            .collect();
+        keys.sort_by(|a, b| a.common.key_id.cmp(&b.common.key_id));
+
+        JwkSet { keys }
+    }
+}
+
+
+fn jwt_key_from_settings(settings: &JwtKeySettings) -> Result<JwtKey, anyhow::Error> {
+    match settings.algorithm {
+        Algorithm::HS256 | Algorithm::HS384 | Algorithm::HS512 => {
+            let secret = settings.secret.as_ref()
+                .context("HMAC keys require a secret")?;
+
+            Ok(JwtKey {
+                algorithm: settings.algorithm,
+                encoding: Some(EncodingKey::from_secret(secret.expose_secret().as_bytes())),
+                decoding: DecodingKey::from_secret(secret.expose_secret().as_bytes()),
+                jwk: None,
+            })
+        },
+        Algorithm::RS256 | Algorithm::RS384 | Algorithm::RS512 => {
+            let public_key = settings.public_key.as_ref()
+                .context("RSA keys require a public key")?;
+            let encoding = settings.private_key.as_ref()
+                .map(|pem| EncodingKey::from_rsa_pem(pem.expose_secret().as_bytes()))
+                .transpose()?;
+
+            let rsa_key = RsaPublicKey::from_public_key_pem(public_key)
+                .context("Invalid RSA public key")?;
+            let parameters = AlgorithmParameters::RSA(RSAKeyParameters {
+                key_type: RSAKeyType::RSA,
+                n: base64::encode_config(rsa_key.n().to_bytes_be(), base64::URL_SAFE_NO_PAD),
+                e: base64::encode_config(rsa_key.e().to_bytes_be(), base64::URL_SAFE_NO_PAD),
+            });
+
+            Ok(JwtKey {
+                algorithm: settings.algorithm,
+                encoding,
+                decoding: DecodingKey::from_rsa_pem(public_key.as_bytes())?,
+                jwk: Some(public_jwk(settings, parameters)),
+            })
+        },
+        Algorithm::EdDSA => {
+            let public_key = settings.public_key.as_ref()
+                .context("EdDSA keys require a public key")?;
+            let encoding = settings.private_key.as_ref()
+                .map(|pem| EncodingKey::from_ed_pem(pem.expose_secret().as_bytes()))
+                .transpose()?;
+
+            let parameters = AlgorithmParameters::OctetKeyPair(OctetKeyPairParameters {
+                key_type: OctetKeyPairType::OctetKeyPair,
+                curve: EllipticCurve::Ed25519,
+                x: base64::encode_config(ed25519_public_key(public_key)?, base64::URL_SAFE_NO_PAD),
+            });
+
+            Ok(JwtKey {
+                algorithm: settings.algorithm,
+                encoding,
+                decoding: DecodingKey::from_ed_pem(public_key.as_bytes())?,
+                jwk: Some(public_jwk(settings, parameters)),
+            })
+        },
+        algorithm => anyhow::bail!("Unsupported algorithm {:?}", algorithm),
+    }
+}
+
+fn public_jwk(settings: &JwtKeySettings, parameters: AlgorithmParameters) -> Jwk {
+    Jwk {
+        common: CommonParameters {
+            public_key_use: Some(PublicKeyUse::Signature),
+            algorithm: Some(settings.algorithm),
+            key_id: Some(settings.kid.clone()),
+            ..Default::default()
+        },
+        algorithm: parameters,
+    }
+}
+
+/// Obtiene los 32 bytes de la llave publica de un PEM Ed25519
+fn ed25519_public_key(pem: &str) -> Result<Vec<u8>, anyhow::Error> {
+    let body: String = pem.lines()
+        .filter(|line| !line.starts_with("-----"))
+        .collect();
+    let der = base64::decode(body.trim())
+        .context("Invalid Ed25519 public key")?;
+
+    match der.strip_prefix(&ED25519_SPKI_PREFIX[..]) {
+        Some(key) if key.len() == 32 => Ok(key.to_vec()),
+        _ => anyhow::bail!("Invalid Ed25519 public key"),
+    }
+}
@@ -0,0 +1,240 @@
+use std::time::Duration;
+
+use anyhow::Context;
+use ldap3::{Ldap, LdapConnAsync, LdapConnSettings, Scope, SearchEntry, ldap_escape};
+use secrecy::{ExposeSecret, Secret};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::configuration::{LdapSettings, PasswordHashingSettings};
+use crate::telemetry::spawn_blocking_with_tracing;
+
+use super::password::{compute_password_hash, AuthError, Credentials};
+
+
+// Codigo de resultado de LDAP para credenciales invalidas (RFC 4511)
+const LDAP_INVALID_CREDENTIALS: u32 = 49;
+
+
+/// Cuenta del directorio que inicio sesion
+#[derive(Debug)]
+pub struct UsuarioDirectorio {
+    pub dn: String,
+    pub email: String,
+    pub nombres: String,
+    pub apellidos: String,
+    // DN de los grupos a los que pertenece
+    pub grupos: Vec<String>,
+}
+
+impl UsuarioDirectorio {
+    fn pertenece_a(&self, grupo: &str) -> bool {
+        self.grupos.iter().any(|g| g.eq_ignore_ascii_case(grupo))
+    }
+}
+
+
+/// Valida las credenciales contra el directorio y crea o actualiza el
+/// usuario local, regresa su id
+#[tracing::instrument(name = "Validate ldap credentials", skip(credentials, pool, settings, hashing))]
+pub async fn validate_ldap_credentials(
+    credentials: Credentials,
+    pool: &PgPool,
+    settings: &LdapSettings,
+    hashing: &PasswordHashingSettings,
+) -> Result<Uuid, AuthError> {
+    let timeout = Duration::from_secs(settings.timeout_seconds);
+
+    let directorio = tokio::time::timeout(
+            timeout,
+            autenticar_en_directorio(settings, &credentials.email, &credentials.password),
+        )
+        .await
+        .context("The directory did not respond in time")
+        .map_err(AuthError::UnexpectedError)??;
+
+    sincronizar_usuario(pool, settings, hashing, &directorio)
+        .await
+        .map_err(AuthError::UnexpectedError)
+}
+
+
+async fn conectar(settings: &LdapSettings) -> Result<Ldap, anyhow::Error> {
+    let conn_settings = LdapConnSettings::new()
+        .set_conn_timeout(Duration::from_secs(settings.timeout_seconds))
+        .set_starttls(settings.starttls);
+
+    let (conn, ldap) = LdapConnAsync::with_settings(conn_settings, &settings.url)
+        .await
+        .context("Failed to connect to the directory")?;
+    ldap3::drive!(conn);
+
+    Ok(ldap)
+}
+
+async fn bind_servicio(ldap: &mut Ldap, settings: &LdapSettings) -> Result<(), anyhow::Error> {
+    ldap.simple_bind(&settings.bind_dn, settings.bind_password.expose_secret())
+        .await
+        .and_then(|result| result.success())
+        .context("Failed to bind with the service account")?;
+
+    Ok(())
+}
+
+fn atributo(entry: &SearchEntry, nombre: &str) -> Option<String> {
+    entry.attrs.get(nombre)
+        .and_then(|valores| valores.first())
+        .cloned()
+}
+
+#[tracing::instrument(name = "Authenticate in directory", skip(settings, password))]
+async fn autenticar_en_directorio(
+    settings: &LdapSettings,
+    email: &str,
+    password: &Secret<String>,
+) -> Result<UsuarioDirectorio, AuthError> {
+    // El servidor acepta un bind sin password como anonimo
+    if password.expose_secret().is_empty() {
+        return Err(AuthError::InvalidCredentials(anyhow::anyhow!("Empty password.")));
+    }
+
+    let mut ldap = conectar(settings).await?;
+
+    // Buscar el DN del usuario con la cuenta de servicio
+    bind_servicio(&mut ldap, settings).await?;
+
+    let filtro = settings.user_filter.replace("{email}", &ldap_escape(email));
+    let (entries, _) = ldap.search(&settings.base_dn, Scope::Subtree, &filtro, vec!["mail", "givenName", "sn", "cn"])
+        .await
+        .and_then(|result| result.success())
+        .context("Failed to search the user in the directory")?;
+
+    let entry = match entries.len() {
+        1 => SearchEntry::construct(entries.into_iter().next().unwrap()),
+        0 => return Err(AuthError::InvalidCredentials(anyhow::anyhow!("Unknown email."))),
+        _ => return Err(AuthError::UnexpectedError(anyhow::anyhow!("The email matches more than one entry."))),
+    };
+
+    // Verificar el password con un bind del usuario
+    let bind = ldap.simple_bind(&entry.dn, password.expose_secret())
+        .await
+        .context("Failed to bind as the user")?;
+
+    match bind.rc {
+        0 => {},
+        LDAP_INVALID_CREDENTIALS => {
+            return Err(AuthError::InvalidCredentials(anyhow::anyhow!("Invalid password.")));
+        },
+        _ => {
+            bind.success().context("Failed to bind as the user")?;
+        },
+    }
+
+    // Los grupos se consultan con la cuenta de servicio
+    bind_servicio(&mut ldap, settings).await?;
+
+    let filtro = settings.group_filter.replace("{dn}", &ldap_escape(&entry.dn));
+    let (grupos, _) = ldap.search(&settings.group_base_dn, Scope::Subtree, &filtro, vec!["cn"])
+        .await
+        .and_then(|result| result.success())
+        .context("Failed to search the user groups")?;
+
+    let _ = ldap.unbind().await;
+
+    let nombres = atributo(&entry, "givenName")
+        .or_else(|| atributo(&entry, "cn"))
+        .unwrap_or_default();
+
+    Ok(UsuarioDirectorio {
+        email: atributo(&entry, "mail").unwrap_or_else(|| email.to_string()),
+        nombres,
+        apellidos: atributo(&entry, "sn").unwrap_or_default(),
+        grupos: grupos.into_iter()
+            .map(|grupo| SearchEntry::construct(grupo).dn)
+            .collect(),
+        dn: entry.dn,
+    })
+}
+
+
+/// Crea el usuario la primera vez que inicia sesion, despues solo
+/// actualiza el rol y el departamento segun sus grupos.
+/// El directorio decide el rol, un departamento sin grupo se conserva.
+#[tracing::instrument(name = "Sync directory user", skip(pool, settings, hashing))]
+async fn sincronizar_usuario(
+    pool: &PgPool,
+    settings: &LdapSettings,
+    hashing: &PasswordHashingSettings,
+    directorio: &UsuarioDirectorio,
+) -> Result<Uuid, anyhow::Error> {
+    let es_admin = settings.admin_groups.iter()
+        .any(|grupo| directorio.pertenece_a(grupo));
+
+    let departamento = settings.group_departments.iter()
+        .find(|mapeo| directorio.pertenece_a(&mapeo.group))
+        .map(|mapeo| mapeo.departamento.clone());
+
+    // El usuario ya existe, se actualiza sin calcular un hash
+    let existente = sqlx::query!(
+        r#"
+        UPDATE usuarios
+        SET
+            verificado = true,
+            rol = CASE WHEN $2 THEN 'admin'::usuario_rol ELSE 'normal'::usuario_rol END,
+            departamento = COALESCE((SELECT id FROM departamentos WHERE nombre = $3), departamento),
+            modificado_en = now()
+        WHERE email = $1
+        RETURNING usuario_id
+        "#,
+        directorio.email,
+        es_admin,
+        departamento,
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to update the directory user")?;
+
+    if let Some(row) = existente {
+        return Ok(row.usuario_id);
+    }
+
+    // El password local nunca se usa, se guarda uno aleatorio.
+    // Si otro inicio de sesion lo inserto mientras tanto se actualiza
+    let hashing = hashing.clone();
+    let password_hash = spawn_blocking_with_tracing(
+            move || compute_password_hash(Secret::new(Uuid::new_v4().to_string()), &hashing)
+        )
+        .await?
+        .context("Failed to hash password")?;
+
+    let row = sqlx::query!(
+        r#"
+        INSERT INTO usuarios
+        (usuario_id, nombres, apellidos, email, password_hash, verificado, rol, departamento)
+        VALUES (
+            $1, $2, $3, $4, $5, true,
+            CASE WHEN $6 THEN 'admin'::usuario_rol ELSE 'normal'::usuario_rol END,
+            (SELECT id FROM departamentos WHERE nombre = $7)
+        )
+        ON CONFLICT (email) DO UPDATE
+        SET
+            verificado = true,
+            rol = EXCLUDED.rol,
+            departamento = COALESCE(EXCLUDED.departamento, usuarios.departamento),
+            modificado_en = now()
+        RETURNING usuario_id
+        "#,
+        Uuid::new_v4(),
+        directorio.nombres,
+        directorio.apellidos,
+        directorio.email,
+        password_hash.expose_secret(),
+        es_admin,
+        departamento,
+    )
+    .fetch_one(pool)
+    .await
+    .context("Failed to store the directory user")?;
+
+    Ok(row.usuario_id)
+}
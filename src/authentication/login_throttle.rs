@@ -0,0 +1,192 @@
+use chrono::Utc;
+use redis::AsyncCommands;
+use uuid::Uuid;
+
+use crate::configuration::LoginThrottleSettings;
+use crate::error::error_chain_fmt;
+use crate::redis_pool::{RedisConnection, RedisPool};
+
+
+#[derive(thiserror::Error)]
+pub enum ThrottleError {
+    #[error("Account is locked for {0} seconds")]
+    Locked(u64),
+    #[error("Too many login attempts")]
+    TooManyAttempts,
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for ThrottleError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+
+fn normalizar_email(email: &str) -> String {
+    email.trim().to_lowercase()
+}
+
+fn get_email_attempts_key(email: &str) -> String {
+    format!("login.email:{}:attempts", normalizar_email(email))
+}
+
+fn get_ip_attempts_key(ip: &str) -> String {
+    format!("login.ip:{}:attempts", ip)
+}
+
+fn get_failures_key(email: &str) -> String {
+    format!("login.email:{}:failures", normalizar_email(email))
+}
+
+fn get_lockouts_key(email: &str) -> String {
+    format!("login.email:{}:lockouts", normalizar_email(email))
+}
+
+fn get_locked_key(email: &str) -> String {
+    format!("login.email:{}:locked", normalizar_email(email))
+}
+
+
+/// Registra el intento en una ventana deslizante y regresa cuantos
+/// intentos hay dentro de la ventana, incluyendo el actual
+async fn registrar_en_ventana(
+    redis: &RedisPool,
+    redis_con: &mut RedisConnection,
+    key: &str,
+    window_seconds: u64,
+) -> Result<u64, anyhow::Error> {
+    let now = Utc::now().timestamp_millis();
+    let window_start = now - (window_seconds as i64) * 1000;
+    // El miembro debe ser unico para no colapsar intentos en el mismo milisegundo
+    let member = format!("{}-{}", now, Uuid::new_v4());
+
+    let (count,): (u64,) = redis.timeout(
+        redis::pipe()
+            .atomic()
+            .zrembyscore(key, 0, window_start).ignore()
+            .zadd(key, member, now).ignore()
+            .zcard(key)
+            .expire(key, window_seconds as usize).ignore()
+            .query_async(redis_con)
+    ).await?;
+
+    Ok(count)
+}
+
+/// Verifica que el email no este bloqueado y que ni el email ni la ip
+/// hayan superado el limite de intentos de la ventana
+#[tracing::instrument(
+    name = "Verificar limites de inicio de sesion",
+    skip(redis, redis_con, settings)
+)]
+pub async fn verificar_limites(
+    redis: &RedisPool,
+    redis_con: &mut RedisConnection,
+    settings: &LoginThrottleSettings,
+    email: &str,
+    ip: &str,
+) -> Result<(), ThrottleError> {
+    let locked_ttl: i64 = redis.timeout(redis_con.ttl(get_locked_key(email)))
+        .await
+        .map_err(|e| ThrottleError::UnexpectedError(e.into()))?;
+
+    // TTL regresa -2 si la llave no existe
+    if locked_ttl > 0 {
+        return Err(ThrottleError::Locked(locked_ttl as u64));
+    }
+
+    let email_attempts = registrar_en_ventana(redis, redis_con, &get_email_attempts_key(email), settings.window_seconds)
+        .await?;
+
+    let ip_attempts = registrar_en_ventana(redis, redis_con, &get_ip_attempts_key(ip), settings.window_seconds)
+        .await?;
+
+    if email_attempts > settings.max_attempts_per_email || ip_attempts > settings.max_attempts_per_ip {
+        return Err(ThrottleError::TooManyAttempts);
+    }
+
+    Ok(())
+}
+
+/// Registra un intento fallido, si se alcanza el limite de fallos bloquea
+/// el email y regresa la duracion del bloqueo en segundos.
+/// Cada bloqueo consecutivo duplica la duracion del anterior.
+#[tracing::instrument(
+    name = "Registrar intento de inicio de sesion fallido",
+    skip(redis, redis_con, settings)
+)]
+pub async fn registrar_fallo(
+    redis: &RedisPool,
+    redis_con: &mut RedisConnection,
+    settings: &LoginThrottleSettings,
+    email: &str,
+) -> Result<Option<u64>, anyhow::Error> {
+    let failures_key = get_failures_key(email);
+
+    let (failures,): (u64,) = redis.timeout(
+        redis::pipe()
+            .atomic()
+            .incr(&failures_key, 1)
+            .expire(&failures_key, settings.window_seconds as usize).ignore()
+            .query_async(redis_con)
+    ).await?;
+
+    if failures < settings.failures_before_lockout {
+        return Ok(None);
+    }
+
+    let lockouts_key = get_lockouts_key(email);
+    let (lockouts,): (u32,) = redis.timeout(
+        redis::pipe()
+            .atomic()
+            .incr(&lockouts_key, 1)
+            .expire(&lockouts_key, settings.lockout_memory_seconds as usize).ignore()
+            .del(&failures_key).ignore()
+            .query_async(redis_con)
+    ).await?;
+
+    let duration = settings.lockout_base_seconds
+        .saturating_mul(2u64.saturating_pow(lockouts.saturating_sub(1)))
+        .min(settings.lockout_max_seconds);
+
+    let _: () = redis.timeout(redis_con.set_ex(get_locked_key(email), lockouts, duration as usize)).await?;
+
+    Ok(Some(duration))
+}
+
+/// Limpia los fallos y el historial de bloqueos despues de un inicio de sesion exitoso
+#[tracing::instrument(
+    name = "Registrar inicio de sesion exitoso",
+    skip(redis, redis_con)
+)]
+pub async fn registrar_exito(
+    redis: &RedisPool,
+    redis_con: &mut RedisConnection,
+    email: &str,
+) -> Result<(), anyhow::Error> {
+    let _: () = redis.timeout(redis_con.del(&[get_failures_key(email), get_lockouts_key(email)])).await?;
+
+    Ok(())
+}
+
+/// Quita el bloqueo y reinicia todos los contadores del email
+#[tracing::instrument(
+    name = "Desbloquear inicio de sesion",
+    skip(redis, redis_con)
+)]
+pub async fn desbloquear(
+    redis: &RedisPool,
+    redis_con: &mut RedisConnection,
+    email: &str,
+) -> Result<(), anyhow::Error> {
+    let _: () = redis.timeout(redis_con.del(&[
+        get_locked_key(email),
+        get_failures_key(email),
+        get_lockouts_key(email),
+        get_email_attempts_key(email),
+    ])).await?;
+
+    Ok(())
+}
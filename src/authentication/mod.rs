@@ -1,6 +1,15 @@
+pub mod api_keys;
+pub mod current_user;
 pub mod jwt_session;
+pub mod key_ring;
+pub mod ldap;
+pub mod login_throttle;
 pub mod middleware;
+pub mod oidc;
 pub mod password;
+pub mod password_policy;
+pub mod sessions;
+pub mod totp;
 
 
 pub use password::*;
@@ -0,0 +1,438 @@
+use std::collections::HashMap;
+
+use anyhow::Context;
+use chrono::NaiveDateTime;
+use openidconnect::core::{CoreAuthenticationFlow, CoreClient, CoreProviderMetadata};
+use openidconnect::reqwest::async_http_client;
+use openidconnect::{
+    AuthorizationCode, ClientId, ClientSecret, CsrfToken, IssuerUrl, Nonce, PkceCodeChallenge,
+    PkceCodeVerifier, RedirectUrl, Scope, TokenResponse,
+};
+use secrecy::ExposeSecret;
+use sqlx::PgPool;
+use tokio::sync::OnceCell;
+use uuid::Uuid;
+
+use crate::configuration::{OidcProviderSettings, OidcSettings};
+use crate::error::error_chain_fmt;
+use crate::redis_pool::{RedisConnection, RedisPool};
+
+
+// Tiempo para completar el inicio de sesion en el proveedor
+const ESTADO_EXPIRACION_SEGUNDOS: u64 = 600;
+
+
+#[derive(thiserror::Error)]
+pub enum OidcError {
+    #[error("Unknown provider")]
+    UnknownProvider,
+    #[error("The provider is not available")]
+    ProviderUnavailable(#[source] anyhow::Error),
+    #[error("Invalid or expired state")]
+    InvalidState,
+    #[error("Invalid authorization code or id token")]
+    InvalidToken(#[source] anyhow::Error),
+    #[error("The identity is not linked to any user")]
+    NotLinked,
+    #[error("The identity is linked to another user")]
+    AlreadyLinked,
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for OidcError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+
+struct Proveedor {
+    settings: OidcProviderSettings,
+    client: OnceCell<CoreClient>,
+}
+
+/// Proveedores OpenID Connect configurados.
+///
+/// La configuracion de cada proveedor se descubre la primera vez que se
+/// usa, asi la aplicacion arranca aunque un proveedor no este disponible.
+pub struct ProveedoresOidc {
+    proveedores: HashMap<String, Proveedor>,
+}
+
+impl ProveedoresOidc {
+    pub fn from_settings(settings: &OidcSettings) -> Self {
+        let proveedores = settings.providers.iter()
+            .map(|proveedor| (proveedor.name.clone(), Proveedor {
+                settings: proveedor.clone(),
+                client: OnceCell::new(),
+            }))
+            .collect();
+
+        Self { proveedores }
+    }
+
+    pub fn existe(&self, proveedor: &str) -> bool {
+        self.proveedores.contains_key(proveedor)
+    }
+
+    #[tracing::instrument(
+        name = "Obtener cliente OIDC",
+        skip(self)
+    )]
+    async fn client(&self, proveedor: &str) -> Result<(&CoreClient, &OidcProviderSettings), OidcError> {
+        let proveedor = self.proveedores.get(proveedor)
+            .ok_or(OidcError::UnknownProvider)?;
+
+        let client = proveedor.client.get_or_try_init(|| descubrir_cliente(&proveedor.settings))
+            .await
+            .map_err(OidcError::ProviderUnavailable)?;
+
+        Ok((client, &proveedor.settings))
+    }
+}
+
+async fn descubrir_cliente(settings: &OidcProviderSettings) -> Result<CoreClient, anyhow::Error> {
+    let issuer_url = IssuerUrl::new(settings.issuer_url.clone())
+        .context("Invalid issuer url")?;
+
+    let metadata = CoreProviderMetadata::discover_async(issuer_url, async_http_client)
+        .await
+        .context("Failed to discover the provider metadata")?;
+
+    let client = CoreClient::from_provider_metadata(
+            metadata,
+            ClientId::new(settings.client_id.clone()),
+            settings.client_secret.as_ref()
+                .map(|secret| ClientSecret::new(secret.expose_secret().clone())),
+        )
+        .set_redirect_uri(RedirectUrl::new(settings.redirect_url.clone()).context("Invalid redirect url")?);
+
+    Ok(client)
+}
+
+
+// Lo que se necesita para completar la autorizacion, se guarda en Redis
+// con el parametro state como llave
+#[derive(serde::Serialize, serde::Deserialize)]
+struct EstadoOidc {
+    proveedor: String,
+    pkce_verifier: String,
+    nonce: String,
+    // Usuario que vincula la identidad, None para iniciar sesion
+    vincular: Option<Uuid>,
+    dispositivo: Option<String>,
+}
+
+fn get_estado_key(state: &str) -> String {
+    format!("oidc.state:{}", state)
+}
+
+
+/// Identidad verificada por el proveedor
+#[derive(Debug)]
+pub struct IdentidadOidc {
+    pub proveedor: String,
+    pub sujeto: String,
+    pub email: Option<String>,
+    pub email_verificado: bool,
+}
+
+#[derive(Debug)]
+pub struct AutorizacionOidc {
+    pub identidad: IdentidadOidc,
+    pub vincular: Option<Uuid>,
+    pub dispositivo: Option<String>,
+}
+
+
+/// Regresa la url del proveedor a la que se redirige al usuario
+#[tracing::instrument(
+    name = "Iniciar autorizacion OIDC",
+    skip(proveedores, redis, redis_con)
+)]
+pub async fn iniciar_autorizacion(
+    proveedores: &ProveedoresOidc,
+    redis: &RedisPool,
+    redis_con: &mut RedisConnection,
+    proveedor: &str,
+    vincular: Option<Uuid>,
+    dispositivo: Option<String>,
+) -> Result<String, OidcError> {
+    let (client, settings) = proveedores.client(proveedor).await?;
+
+    let (pkce_challenge, pkce_verifier) = PkceCodeChallenge::new_random_sha256();
+
+    let (url, csrf_token, nonce) = client
+        .authorize_url(
+            CoreAuthenticationFlow::AuthorizationCode,
+            CsrfToken::new_random,
+            Nonce::new_random,
+        )
+        .add_scope(Scope::new("email".to_string()))
+        .add_scopes(settings.scopes.iter().cloned().map(Scope::new))
+        .set_pkce_challenge(pkce_challenge)
+        .url();
+
+    let estado = serde_json::to_string(&EstadoOidc {
+        proveedor: proveedor.to_string(),
+        pkce_verifier: pkce_verifier.secret().clone(),
+        nonce: nonce.secret().clone(),
+        vincular,
+        dispositivo,
+    })
+    .context("Failed to serialize oidc state")?;
+
+    let _: () = redis.timeout(
+        redis::cmd("SET")
+            .arg(get_estado_key(csrf_token.secret()))
+            .arg(estado)
+            .arg("EX")
+            .arg(ESTADO_EXPIRACION_SEGUNDOS)
+            .query_async(redis_con)
+    )
+    .await
+    .context("Failed to store oidc state")?;
+
+    Ok(url.to_string())
+}
+
+/// Canjea el codigo de autorizacion y verifica el id token.
+/// Cada state se puede usar una sola vez.
+#[tracing::instrument(
+    name = "Completar autorizacion OIDC",
+    skip(proveedores, redis, redis_con, code, state)
+)]
+pub async fn completar_autorizacion(
+    proveedores: &ProveedoresOidc,
+    redis: &RedisPool,
+    redis_con: &mut RedisConnection,
+    proveedor: &str,
+    code: &str,
+    state: &str,
+) -> Result<AutorizacionOidc, OidcError> {
+    if !proveedores.existe(proveedor) {
+        return Err(OidcError::UnknownProvider);
+    }
+
+    let key = get_estado_key(state);
+    let (valor,): (Option<String>,) = redis.timeout(
+        redis::pipe()
+            .atomic()
+            .get(&key)
+            .del(&key).ignore()
+            .query_async(redis_con)
+    )
+    .await
+    .context("Failed to fetch oidc state")?;
+
+    let estado: EstadoOidc = match valor {
+        Some(valor) => serde_json::from_str(&valor).context("Failed to deserialize oidc state")?,
+        None => return Err(OidcError::InvalidState),
+    };
+
+    if estado.proveedor != proveedor {
+        return Err(OidcError::InvalidState);
+    }
+
+    let (client, _) = proveedores.client(proveedor).await?;
+
+    let token_response = client
+        .exchange_code(AuthorizationCode::new(code.to_string()))
+        .set_pkce_verifier(PkceCodeVerifier::new(estado.pkce_verifier))
+        .request_async(async_http_client)
+        .await
+        .context("Failed to exchange the authorization code")
+        .map_err(OidcError::InvalidToken)?;
+
+    let id_token = token_response.id_token()
+        .ok_or_else(|| OidcError::InvalidToken(anyhow::anyhow!("The provider did not return an id token")))?;
+
+    let claims = id_token.claims(&client.id_token_verifier(), &Nonce::new(estado.nonce))
+        .context("Failed to verify the id token")
+        .map_err(OidcError::InvalidToken)?;
+
+    Ok(AutorizacionOidc {
+        identidad: IdentidadOidc {
+            proveedor: proveedor.to_string(),
+            sujeto: claims.subject().as_str().to_string(),
+            email: claims.email().map(|email| email.as_str().to_string()),
+            email_verificado: claims.email_verified().unwrap_or(false),
+        },
+        vincular: estado.vincular,
+        dispositivo: estado.dispositivo,
+    })
+}
+
+
+#[derive(Debug, serde::Serialize)]
+pub struct IdentidadExterna {
+    pub identidad_id: Uuid,
+    pub proveedor: String,
+    pub sujeto: String,
+    pub email: Option<String>,
+    pub creado_en: NaiveDateTime,
+    pub ultimo_login: Option<NaiveDateTime>,
+}
+
+
+/// Busca el usuario de la identidad, primero por el sujeto guardado y
+/// despues por el email si el proveedor lo verifico, en cuyo caso la
+/// identidad queda vinculada
+#[tracing::instrument(
+    name = "Buscar usuario de identidad externa",
+    skip(pool)
+)]
+pub async fn usuario_de_identidad(
+    pool: &PgPool,
+    identidad: &IdentidadOidc,
+) -> Result<Uuid, OidcError> {
+    let row = sqlx::query!(
+        r#"
+        UPDATE identidades_externas
+        SET ultimo_login = now()
+        WHERE proveedor = $1 AND sujeto = $2
+        RETURNING usuario_id
+        "#,
+        identidad.proveedor,
+        identidad.sujeto,
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to fetch external identity")?;
+
+    if let Some(row) = row {
+        return Ok(row.usuario_id);
+    }
+
+    // Un email sin verificar permitiria tomar la cuenta de alguien mas
+    let email = match &identidad.email {
+        Some(email) if identidad.email_verificado => email,
+        _ => return Err(OidcError::NotLinked),
+    };
+
+    let row = sqlx::query!(
+        r#"
+        SELECT usuario_id
+        FROM usuarios
+        WHERE lower(email) = lower($1)
+        "#,
+        email,
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to fetch user by email")?;
+
+    let usuario_id = match row {
+        Some(row) => row.usuario_id,
+        None => return Err(OidcError::NotLinked),
+    };
+
+    vincular_identidad(pool, &usuario_id, identidad).await?;
+
+    Ok(usuario_id)
+}
+
+/// Vincula la identidad al usuario, reemplaza la identidad anterior del
+/// mismo proveedor
+#[tracing::instrument(
+    name = "Vincular identidad externa",
+    skip(pool)
+)]
+pub async fn vincular_identidad(
+    pool: &PgPool,
+    usuario_id: &Uuid,
+    identidad: &IdentidadOidc,
+) -> Result<IdentidadExterna, OidcError> {
+    let existente = sqlx::query!(
+        r#"
+        SELECT usuario_id
+        FROM identidades_externas
+        WHERE proveedor = $1 AND sujeto = $2
+        "#,
+        identidad.proveedor,
+        identidad.sujeto,
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to fetch external identity")?;
+
+    if matches!(existente, Some(row) if row.usuario_id != *usuario_id) {
+        return Err(OidcError::AlreadyLinked);
+    }
+
+    let row = sqlx::query_as!(
+        IdentidadExterna,
+        r#"
+        INSERT INTO identidades_externas
+        (identidad_id, usuario_id, proveedor, sujeto, email, ultimo_login)
+        VALUES ($1, $2, $3, $4, $5, now())
+        ON CONFLICT (usuario_id, proveedor) DO UPDATE
+        SET
+            sujeto = EXCLUDED.sujeto,
+            email = EXCLUDED.email,
+            ultimo_login = now()
+        RETURNING identidad_id, proveedor, sujeto, email, creado_en, ultimo_login
+        "#,
+        Uuid::new_v4(),
+        usuario_id,
+        identidad.proveedor,
+        identidad.sujeto,
+        identidad.email,
+    )
+    .fetch_one(pool)
+    .await
+    .context("Failed to store external identity")?;
+
+    Ok(row)
+}
+
+#[tracing::instrument(
+    name = "Listar identidades externas",
+    skip(pool)
+)]
+pub async fn listar_identidades(
+    pool: &PgPool,
+    usuario_id: &Uuid,
+) -> Result<Vec<IdentidadExterna>, anyhow::Error> {
+    let rows = sqlx::query_as!(
+        IdentidadExterna,
+        r#"
+        SELECT identidad_id, proveedor, sujeto, email, creado_en, ultimo_login
+        FROM identidades_externas
+        WHERE usuario_id = $1
+        ORDER BY creado_en
+        "#,
+        usuario_id,
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to fetch external identities")?;
+
+    Ok(rows)
+}
+
+/// Regresa false si el usuario no tenia una identidad del proveedor
+#[tracing::instrument(
+    name = "Desvincular identidad externa",
+    skip(pool)
+)]
+pub async fn desvincular_identidad(
+    pool: &PgPool,
+    usuario_id: &Uuid,
+    proveedor: &str,
+) -> Result<bool, anyhow::Error> {
+    let result = sqlx::query!(
+        r#"
+        DELETE FROM identidades_externas
+        WHERE usuario_id = $1 AND proveedor = $2
+        "#,
+        usuario_id,
+        proveedor,
+    )
+    .execute(pool)
+    .await
+    .context("Failed to delete external identity")?;
+
+    Ok(result.rows_affected() > 0)
+}
@@ -4,6 +4,8 @@ use secrecy::{Secret, ExposeSecret};
 use argon2::{Argon2, Algorithm, PasswordVerifier, PasswordHash, password_hash::SaltString, Params, PasswordHasher, Version};
 use sqlx::PgPool;
 
+use crate::configuration::PasswordHashingSettings;
+
 #[derive(thiserror::Error, Debug)]
 pub enum AuthError {
     #[error("Invalid credentials")]
@@ -17,20 +19,35 @@ pub enum AuthError {
 pub struct Credentials {
     pub email: String,
     pub password: Secret<String>,
+    // Nombre opcional del dispositivo para identificar la sesion
+    #[serde(default)]
+    pub dispositivo: Option<String>,
+}
+
+/// Hash que se verifica cuando el correo no existe. Se calcula al arrancar con
+/// los parametros configurados para que tarde lo mismo que uno real
+#[derive(Clone)]
+pub struct DummyPasswordHash(Secret<String>);
+
+impl DummyPasswordHash {
+    pub fn new(hashing: &PasswordHashingSettings) -> Result<Self, anyhow::Error> {
+        let password = Secret::new(uuid::Uuid::new_v4().to_string());
+        let password_hash = compute_password_hash(password, hashing)
+            .context("Failed to compute dummy password hash")?;
+
+        Ok(Self(password_hash))
+    }
 }
 
-#[tracing::instrument(name = "Validate credentials", skip(credentials,pool))]
+#[tracing::instrument(name = "Validate credentials", skip(credentials, pool, hashing, dummy_hash))]
 pub async fn validate_credentials(
     credentials: Credentials,
-    pool: &PgPool
+    pool: &PgPool,
+    hashing: &PasswordHashingSettings,
+    dummy_hash: &DummyPasswordHash,
 ) -> Result<uuid::Uuid, AuthError> {
     let mut user_id = None;
-    let mut expected_password_hash = Secret::new(
-        "$argon2id$v=19$m=15000,t=2,p=1$\
-        gZiV/M1gPc22ElAH/Jh1Hw$\
-        CWOrkoo7oJBQ/iyh7uJ0LO2aLEfrHwTWllSAxT0zRno"
-        .to_string(),
-        );
+    let mut expected_password_hash = dummy_hash.0.clone();
 
     if let Some((stored_user_id, stored_password_hash)) =
         get_stored_credentials(&credentials.email, &pool)
@@ -41,6 +58,10 @@ pub async fn validate_credentials(
         expected_password_hash = stored_password_hash;
     }
 
+    let hash_anterior = expected_password_hash.expose_secret().clone();
+    let requiere_rehash = user_id.is_some() && necesita_rehash(&hash_anterior, hashing);
+    let password = Secret::new(credentials.password.expose_secret().clone());
+
     spawn_blocking_with_tracing(move || {
         verify_password_hash(expected_password_hash, credentials.password)
     })
@@ -48,10 +69,75 @@ pub async fn validate_credentials(
     .context("Failed to spawn blocking task")
     .map_err(AuthError::UnexpectedError)??;
 
-
-    user_id.ok_or_else(||
+    let user_id = user_id.ok_or_else(||
         AuthError::InvalidCredentials(anyhow::anyhow!("Unknown email."))
+    )?;
+
+    // La contraseña es correcta, es el unico momento en que se puede
+    // calcular el hash con los parametros nuevos
+    if requiere_rehash {
+        if let Err(e) = rehash_password(user_id, password, &hash_anterior, pool, hashing).await {
+            tracing::warn!("Failed to upgrade password hash: {:?}", e);
+        }
+    }
+
+    Ok(user_id)
+}
+
+/// El hash fue calculado con otro algoritmo o parametros que los configurados
+fn necesita_rehash(password_hash: &str, hashing: &PasswordHashingSettings) -> bool {
+    let password_hash = match PasswordHash::new(password_hash) {
+        Ok(password_hash) => password_hash,
+        Err(_) => return false,
+    };
+
+    if password_hash.algorithm != Algorithm::Argon2id.ident()
+        || password_hash.version != Some(Version::V0x13.into())
+    {
+        return true;
+    }
+
+    match Params::try_from(&password_hash) {
+        Ok(params) => {
+            params.m_cost() != hashing.memory_kib
+                || params.t_cost() != hashing.iterations
+                || params.p_cost() != hashing.parallelism
+        },
+        Err(_) => true,
+    }
+}
+
+/// Guarda el hash nuevo solo si no cambio la contraseña mientras tanto
+#[tracing::instrument(name = "Rehash password", skip(password, hash_anterior, pool, hashing))]
+async fn rehash_password(
+    user_id: uuid::Uuid,
+    password: Secret<String>,
+    hash_anterior: &str,
+    pool: &PgPool,
+    hashing: &PasswordHashingSettings,
+) -> Result<(), anyhow::Error> {
+    let hashing = hashing.clone();
+    let password_hash = spawn_blocking_with_tracing(
+            move || compute_password_hash(password, &hashing)
+        )
+        .await?
+        .context("Failed to hash password")?;
+
+    sqlx::query!(
+        r#"
+        UPDATE usuarios
+        SET password_hash = $1
+        WHERE usuario_id = $2 AND password_hash = $3
+        "#,
+        password_hash.expose_secret(),
+        user_id,
+        hash_anterior,
     )
+    .execute(pool)
+    .await
+    .context("Failed to store upgraded password hash")?;
+
+    Ok(())
 }
 
 #[tracing::instrument(name = "Get stored credentials", skip(email, pool))]
@@ -63,7 +149,7 @@ async fn get_stored_credentials(
         r#"
         SELECT usuario_id, password_hash
         FROM usuarios
-        WHERE email = $1
+        WHERE email = $1 AND anonimizado_en IS NULL
         "#,
         email,
     )
@@ -102,14 +188,16 @@ fn verify_password_hash(
 
 
 
-#[tracing::instrument(name = "Change password", skip(password, pool))]
+#[tracing::instrument(name = "Change password", skip(password, pool, hashing))]
 pub async fn change_password(
     user_id: uuid::Uuid,
     password: Secret<String>,
     pool: &PgPool,
+    hashing: &PasswordHashingSettings,
 ) -> Result<(), anyhow::Error> {
+    let hashing = hashing.clone();
     let password_hash = spawn_blocking_with_tracing(
-            move || compute_password_hash(password)
+            move || compute_password_hash(password, &hashing)
         )
         .await?
         .context("Failed to hash password")?;
@@ -129,12 +217,17 @@ pub async fn change_password(
     Ok(())
 }
 
-pub fn compute_password_hash(password: Secret<String>) -> Result<Secret<String>, anyhow::Error> {
+pub fn compute_password_hash(
+    password: Secret<String>,
+    hashing: &PasswordHashingSettings,
+) -> Result<Secret<String>, anyhow::Error> {
     let salt = SaltString::generate(&mut rand::thread_rng());
+    let params = Params::new(hashing.memory_kib, hashing.iterations, hashing.parallelism, None)
+        .context("Invalid Argon2 parameters")?;
     let password_hash = Argon2::new(
         Algorithm::Argon2id,
         Version::V0x13,
-        Params::new(15000, 2, 1, None).unwrap(),
+        params,
     )
     .hash_password(password.expose_secret().as_bytes(), &salt)?
     .to_string();
@@ -0,0 +1,266 @@
+use std::collections::HashSet;
+
+use anyhow::Context;
+use argon2::{Argon2, PasswordHash, PasswordVerifier};
+use secrecy::{ExposeSecret, Secret};
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+use crate::api_response::ApiResponse;
+use crate::configuration::PasswordPolicySettings;
+use crate::telemetry::spawn_blocking_with_tracing;
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReglaPassword {
+    Longitud,
+    Minusculas,
+    Mayusculas,
+    Digitos,
+    Simbolos,
+    Entropia,
+    Comun,
+    DatosPersonales,
+    Reutilizada,
+}
+
+/// Regla que no cumple la contraseña, se regresa en `data` de la respuesta
+#[derive(Debug, serde::Serialize)]
+pub struct ViolacionPassword {
+    pub regla: ReglaPassword,
+    pub mensaje: String,
+}
+
+impl ViolacionPassword {
+    fn new<S: Into<String>>(regla: ReglaPassword, mensaje: S) -> Self {
+        Self { regla, mensaje: mensaje.into() }
+    }
+}
+
+/// Respuesta 400 con la lista de reglas que no se cumplen
+pub fn error_password(violaciones: Vec<ViolacionPassword>) -> ApiResponse<Vec<ViolacionPassword>> {
+    ApiResponse::<Vec<ViolacionPassword>>::new()
+        .with_status_code(400)
+        .with_status("fail")
+        .with_message("La contraseña no cumple la politica de seguridad")
+        .with_data(violaciones)
+}
+
+
+pub struct PasswordPolicy {
+    settings: PasswordPolicySettings,
+    // En minusculas
+    comunes: HashSet<String>,
+}
+
+impl PasswordPolicy {
+    pub fn from_settings(settings: &PasswordPolicySettings) -> Result<Self, anyhow::Error> {
+        let comunes = match &settings.common_passwords_file {
+            Some(ruta) => std::fs::read_to_string(ruta)
+                .with_context(|| format!("Failed to read common passwords file {}", ruta))?
+                .lines()
+                .map(|linea| linea.trim())
+                .filter(|linea| !linea.is_empty() && !linea.starts_with('#'))
+                .map(|linea| linea.to_lowercase())
+                .collect(),
+            None => HashSet::new(),
+        };
+
+        Ok(Self { settings: settings.clone(), comunes })
+    }
+
+    /// Cuantas contraseñas anteriores no se pueden reutilizar
+    pub fn historial(&self) -> i64 {
+        self.settings.history
+    }
+
+    /// Revisa las reglas que no dependen de la base de datos.
+    /// `datos_personales` son el email, nombres, etc. que no puede contener.
+    pub fn validar(&self, password: &str, datos_personales: &[&str]) -> Vec<ViolacionPassword> {
+        let settings = &self.settings;
+        let mut violaciones = Vec::new();
+
+        let longitud = password.chars().count();
+        if longitud < settings.min_length || longitud > settings.max_length {
+            violaciones.push(ViolacionPassword::new(
+                ReglaPassword::Longitud,
+                format!("Debe tener entre {} y {} caracteres", settings.min_length, settings.max_length),
+            ));
+        }
+
+        if settings.require_lowercase && !password.chars().any(|c| c.is_lowercase()) {
+            violaciones.push(ViolacionPassword::new(ReglaPassword::Minusculas, "Debe tener una letra minuscula"));
+        }
+        if settings.require_uppercase && !password.chars().any(|c| c.is_uppercase()) {
+            violaciones.push(ViolacionPassword::new(ReglaPassword::Mayusculas, "Debe tener una letra mayuscula"));
+        }
+        if settings.require_digit && !password.chars().any(|c| c.is_ascii_digit()) {
+            violaciones.push(ViolacionPassword::new(ReglaPassword::Digitos, "Debe tener un numero"));
+        }
+        if settings.require_symbol && !password.chars().any(|c| !c.is_alphanumeric()) {
+            violaciones.push(ViolacionPassword::new(ReglaPassword::Simbolos, "Debe tener un simbolo"));
+        }
+
+        if estimar_entropia(password) < settings.min_entropy_bits as f64 {
+            violaciones.push(ViolacionPassword::new(
+                ReglaPassword::Entropia,
+                "Es muy facil de adivinar, evita repeticiones y secuencias",
+            ));
+        }
+
+        let password = password.to_lowercase();
+        if self.comunes.contains(&password) {
+            violaciones.push(ViolacionPassword::new(
+                ReglaPassword::Comun,
+                "Es una contraseña comun o filtrada",
+            ));
+        }
+
+        let contiene_datos = datos_personales.iter()
+            .flat_map(|dato| dato.split(|c: char| c == '@' || c.is_whitespace()))
+            .map(|parte| parte.trim().to_lowercase())
+            .filter(|parte| parte.chars().count() >= 4)
+            .any(|parte| password.contains(&parte));
+        if contiene_datos {
+            violaciones.push(ViolacionPassword::new(
+                ReglaPassword::DatosPersonales,
+                "No debe contener tu nombre o correo",
+            ));
+        }
+
+        violaciones
+    }
+}
+
+/// Estimacion de bits por el tamaño del alfabeto usado. Los caracteres que
+/// repiten o continuan una secuencia con el anterior no suman.
+pub fn estimar_entropia(password: &str) -> f64 {
+    let mut alfabeto = 0u32;
+    if password.chars().any(|c| c.is_ascii_lowercase()) { alfabeto += 26; }
+    if password.chars().any(|c| c.is_ascii_uppercase()) { alfabeto += 26; }
+    if password.chars().any(|c| c.is_ascii_digit()) { alfabeto += 10; }
+    if password.chars().any(|c| c.is_ascii_punctuation() || c == ' ') { alfabeto += 33; }
+    if password.chars().any(|c| !c.is_ascii()) { alfabeto += 100; }
+
+    if alfabeto == 0 {
+        return 0.0;
+    }
+
+    let mut anterior: Option<char> = None;
+    let mut efectivos = 0u32;
+    for c in password.chars() {
+        let continua = anterior
+            .map(|a| a == c || (a as u32) + 1 == c as u32 || (c as u32) + 1 == a as u32)
+            .unwrap_or(false);
+        if !continua {
+            efectivos += 1;
+        }
+        anterior = Some(c);
+    }
+
+    efectivos as f64 * (alfabeto as f64).log2()
+}
+
+
+/// Revisa la contraseña contra la actual y las anteriores del historial,
+/// en total las ultimas `historial` contraseñas del usuario
+#[tracing::instrument(
+    name = "Revisar reutilizacion de contraseña",
+    skip(pool, password)
+)]
+pub async fn password_reutilizado(
+    pool: &PgPool,
+    usuario_id: &Uuid,
+    password: &Secret<String>,
+    historial: i64,
+) -> Result<bool, anyhow::Error> {
+    if historial <= 0 {
+        return Ok(false);
+    }
+
+    let hashes: Vec<String> = sqlx::query!(
+        r#"
+        SELECT password_hash as "password_hash!" FROM usuarios WHERE usuario_id = $1
+        UNION ALL
+        (
+            SELECT password_hash FROM historial_passwords
+            WHERE usuario_id = $1
+            ORDER BY creado_en DESC
+            LIMIT $2
+        )
+        "#,
+        usuario_id,
+        historial - 1,
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to fetch password history")?
+    .into_iter()
+    .map(|row| row.password_hash)
+    .collect();
+
+    let password = Secret::new(password.expose_secret().clone());
+    spawn_blocking_with_tracing(move || {
+        hashes.iter().any(|hash| {
+            PasswordHash::new(hash)
+                .map(|hash| {
+                    Argon2::default()
+                        .verify_password(password.expose_secret().as_bytes(), &hash)
+                        .is_ok()
+                })
+                .unwrap_or(false)
+        })
+    })
+    .await
+    .context("Failed to spawn blocking task")
+}
+
+/// Guarda el hash de la contraseña que se reemplaza y borra los que ya no se revisan
+#[tracing::instrument(
+    name = "Guardar contraseña en historial",
+    skip(transaction, password_hash)
+)]
+pub async fn guardar_historial_password(
+    transaction: &mut Transaction<'_, Postgres>,
+    usuario_id: &Uuid,
+    password_hash: &Secret<String>,
+    historial: i64,
+) -> Result<(), anyhow::Error> {
+    if historial <= 1 {
+        return Ok(());
+    }
+
+    sqlx::query!(
+        r#"
+        INSERT INTO historial_passwords (historial_id, usuario_id, password_hash)
+        VALUES ($1, $2, $3)
+        "#,
+        Uuid::new_v4(),
+        usuario_id,
+        password_hash.expose_secret(),
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("Failed to store password history")?;
+
+    sqlx::query!(
+        r#"
+        DELETE FROM historial_passwords
+        WHERE usuario_id = $1
+            AND historial_id NOT IN (
+                SELECT historial_id FROM historial_passwords
+                WHERE usuario_id = $1
+                ORDER BY creado_en DESC
+                LIMIT $2
+            )
+        "#,
+        usuario_id,
+        historial - 1,
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("Failed to prune password history")?;
+
+    Ok(())
+}
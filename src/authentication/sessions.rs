@@ -0,0 +1,290 @@
+use actix_web::HttpRequest;
+use anyhow::Context;
+use chrono::{NaiveDateTime, Utc};
+use redis::AsyncCommands;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::redis_pool::{RedisConnection, RedisPool};
+
+use super::jwt_session::{TokenClaims, encode_jwt, revoke_user_tokens};
+use super::key_ring::KeyRing;
+
+
+/// Datos del cliente que inicia la sesion
+#[derive(Debug, Default, Clone, serde::Serialize, serde::Deserialize)]
+pub struct InfoSesion {
+    pub dispositivo: Option<String>,
+    pub ip: Option<String>,
+    pub user_agent: Option<String>,
+}
+
+impl InfoSesion {
+    pub fn from_request(req: &HttpRequest, dispositivo: Option<String>) -> Self {
+        let ip = req.connection_info()
+            .realip_remote_addr()
+            .map(|ip| ip.to_string());
+
+        let user_agent = req.headers()
+            .get(actix_web::http::header::USER_AGENT)
+            .and_then(|h| h.to_str().ok())
+            .map(|h| h.to_string());
+
+        Self { dispositivo, ip, user_agent }
+    }
+}
+
+
+#[derive(Debug, serde::Serialize)]
+pub struct Sesion {
+    pub sesion_id: Uuid,
+    pub dispositivo: Option<String>,
+    pub ip: Option<String>,
+    pub user_agent: Option<String>,
+    pub creado_en: NaiveDateTime,
+    pub ultimo_uso: NaiveDateTime,
+    pub expira_en: NaiveDateTime,
+}
+
+
+fn get_revoked_key(sesion_id: &Uuid) -> String {
+    format!("session.id:{}:revoked", sesion_id)
+}
+
+fn get_last_seen_key(sesion_id: &Uuid) -> String {
+    format!("session.id:{}:last.seen", sesion_id)
+}
+
+fn segundos_restantes(expira_en: &NaiveDateTime) -> usize {
+    (*expira_en - Utc::now().naive_utc()).num_seconds().max(1) as usize
+}
+
+
+/// Registra una nueva sesion y genera su jwt
+#[tracing::instrument(
+    name = "Crear sesion",
+    skip(pool, key_ring)
+)]
+pub async fn crear_sesion(
+    pool: &PgPool,
+    usuario_id: &Uuid,
+    info: &InfoSesion,
+    key_ring: &KeyRing,
+) -> Result<String, anyhow::Error> {
+    let sesion_id = Uuid::new_v4();
+    let claims = TokenClaims::new(usuario_id, &sesion_id, key_ring);
+    let expira_en = NaiveDateTime::from_timestamp_opt(claims.exp as i64, 0)
+        .context("Invalid token expiration")?;
+
+    sqlx::query!(
+        r#"
+        INSERT INTO sesiones
+        (sesion_id, usuario_id, dispositivo, ip, user_agent, expira_en)
+        VALUES ($1, $2, $3, $4, $5, $6)
+        "#,
+        sesion_id,
+        usuario_id,
+        info.dispositivo,
+        info.ip,
+        info.user_agent,
+        expira_en,
+    )
+    .execute(pool)
+    .await
+    .context("Failed to store session")?;
+
+    let token = encode_jwt(&claims, key_ring)?;
+
+    Ok(token)
+}
+
+/// Lista las sesiones activas del usuario con su ultimo uso mas reciente
+#[tracing::instrument(
+    name = "Listar sesiones activas",
+    skip(pool, redis, redis_con)
+)]
+pub async fn listar_sesiones(
+    pool: &PgPool,
+    redis: &RedisPool,
+    redis_con: &mut RedisConnection,
+    usuario_id: &Uuid,
+) -> Result<Vec<Sesion>, anyhow::Error> {
+    let mut sesiones = sqlx::query_as!(
+        Sesion,
+        r#"
+        SELECT sesion_id, dispositivo, ip, user_agent, creado_en, ultimo_uso, expira_en
+        FROM sesiones
+        WHERE usuario_id = $1 AND revocado_en IS NULL AND expira_en > now()
+        ORDER BY creado_en DESC
+        "#,
+        usuario_id,
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to fetch sessions")?;
+
+    if sesiones.is_empty() {
+        return Ok(sesiones);
+    }
+
+    // El ultimo uso se actualiza en Redis en cada peticion
+    let keys: Vec<String> = sesiones.iter()
+        .map(|s| get_last_seen_key(&s.sesion_id))
+        .collect();
+    let ultimos_usos: Vec<Option<i64>> = redis.timeout(
+        redis::cmd("MGET")
+            .arg(keys)
+            .query_async(redis_con)
+    ).await?;
+
+    for (sesion, ultimo_uso) in sesiones.iter_mut().zip(ultimos_usos) {
+        if let Some(ultimo_uso) = ultimo_uso.and_then(|t| NaiveDateTime::from_timestamp_opt(t, 0)) {
+            sesion.ultimo_uso = sesion.ultimo_uso.max(ultimo_uso);
+        }
+    }
+
+    Ok(sesiones)
+}
+
+/// Revoca una sesion del usuario, regresa false si no existe o ya expiro.
+/// Repetirla vuelve a marcarla en Redis, por si la primera vez fallo despues de
+/// guardar la revocacion en la base de datos
+#[tracing::instrument(
+    name = "Revocar sesion",
+    skip(pool, redis, redis_con)
+)]
+pub async fn revocar_sesion(
+    pool: &PgPool,
+    redis: &RedisPool,
+    redis_con: &mut RedisConnection,
+    usuario_id: &Uuid,
+    sesion_id: &Uuid,
+) -> Result<bool, anyhow::Error> {
+    let row = sqlx::query!(
+        r#"
+        UPDATE sesiones
+        SET revocado_en = COALESCE(revocado_en, now())
+        WHERE sesion_id = $1 AND usuario_id = $2 AND expira_en > now()
+        RETURNING expira_en
+        "#,
+        sesion_id,
+        usuario_id,
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to revoke session")?;
+
+    match row {
+        Some(row) => {
+            let _: () = redis.timeout(
+                redis_con.set_ex(get_revoked_key(sesion_id), 1, segundos_restantes(&row.expira_en))
+            ).await?;
+            Ok(true)
+        },
+        None => Ok(false),
+    }
+}
+
+/// Revoca todas las sesiones activas del usuario, excepto la indicada, y regresa
+/// cuantas se revocaron. Las que ya estaban revocadas y no han expirado se vuelven
+/// a marcar en Redis
+#[tracing::instrument(
+    name = "Revocar sesiones del usuario",
+    skip(pool, redis, redis_con)
+)]
+pub async fn revocar_sesiones(
+    pool: &PgPool,
+    redis: &RedisPool,
+    redis_con: &mut RedisConnection,
+    usuario_id: &Uuid,
+    excepto: Option<&Uuid>,
+) -> Result<u64, anyhow::Error> {
+    let rows = sqlx::query!(
+        r#"
+        WITH vigentes AS (
+            SELECT sesion_id, revocado_en IS NULL as nueva
+            FROM sesiones
+            WHERE usuario_id = $1
+                AND expira_en > now()
+                AND ($2::uuid IS NULL OR sesion_id <> $2)
+            FOR UPDATE
+        )
+        UPDATE sesiones s
+        SET revocado_en = COALESCE(s.revocado_en, now())
+        FROM vigentes v
+        WHERE s.sesion_id = v.sesion_id
+        RETURNING s.sesion_id, s.expira_en, v.nueva as "nueva!"
+        "#,
+        usuario_id,
+        excepto,
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to revoke sessions")?;
+
+    // Sin excepciones basta con invalidar todo lo emitido hasta ahora
+    if excepto.is_none() {
+        redis.timeout(revoke_user_tokens(redis_con, usuario_id)).await?;
+    }
+
+    if rows.is_empty() {
+        return Ok(0);
+    }
+
+    let mut pipe = redis::pipe();
+    for row in rows.iter() {
+        pipe.set_ex(get_revoked_key(&row.sesion_id), 1, segundos_restantes(&row.expira_en)).ignore();
+    }
+    let _: () = redis.timeout(pipe.query_async(redis_con)).await?;
+
+    Ok(rows.iter().filter(|row| row.nueva).count() as u64)
+}
+
+
+/// Verifica si la sesion fue revocada y registra su ultimo uso.
+/// Redis guarda el estado hasta que expira la sesion; si no lo tiene (se
+/// reinicio o se vacio) se consulta `sesiones.revocado_en` y se vuelve a guardar
+pub async fn verificar_sesion(
+    pool: &PgPool,
+    redis: &RedisPool,
+    redis_con: &mut RedisConnection,
+    sesion_id: &Uuid,
+    exp: usize,
+) -> Result<bool, anyhow::Error> {
+    let now = Utc::now().timestamp();
+    let ttl = (exp as i64 - now).max(1) as usize;
+    let key = get_revoked_key(sesion_id);
+
+    let (revocada,): (Option<bool>,) = redis.timeout(
+        redis::pipe()
+            .get(&key)
+            .set_ex(get_last_seen_key(sesion_id), now, ttl).ignore()
+            .query_async(redis_con)
+    ).await?;
+    if let Some(revocada) = revocada {
+        return Ok(revocada);
+    }
+
+    // Los tokens de suplantacion no tienen fila, solo se revocan por jti
+    let revocada = sqlx::query!(
+        r#"SELECT revocado_en IS NOT NULL as "revocada!" FROM sesiones WHERE sesion_id = $1"#,
+        sesion_id,
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to fetch session")?
+    .map_or(false, |row| row.revocada);
+
+    // NX para no pisar una revocacion que se guardo mientras tanto
+    let _: () = redis.timeout(
+        redis::cmd("SET")
+            .arg(&key)
+            .arg(i32::from(revocada))
+            .arg("EX")
+            .arg(ttl)
+            .arg("NX")
+            .query_async(redis_con)
+    ).await?;
+
+    Ok(revocada)
+}
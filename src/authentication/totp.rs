@@ -0,0 +1,492 @@
+use anyhow::Context;
+use chrono::Utc;
+use rand::{thread_rng, Rng, distributions::Alphanumeric};
+use sha2::{Digest, Sha256};
+use sqlx::PgPool;
+use totp_rs::{Algorithm, Secret, TOTP};
+use uuid::Uuid;
+
+use crate::error::error_chain_fmt;
+use crate::redis_pool::{RedisConnection, RedisPool};
+
+
+const TOTP_DIGITOS: usize = 6;
+const TOTP_PASO_SEGUNDOS: u64 = 30;
+// Acepta el codigo anterior y el siguiente por diferencias de reloj
+const TOTP_TOLERANCIA_PASOS: u64 = 1;
+const CODIGOS_RECUPERACION: usize = 10;
+const DESAFIO_EXPIRACION_SEGUNDOS: usize = 300;
+
+
+#[derive(thiserror::Error)]
+pub enum TotpError {
+    #[error("Two factor authentication is already enabled")]
+    AlreadyEnabled,
+    #[error("Two factor authentication is not enrolled")]
+    NotEnrolled,
+    #[error("Invalid code")]
+    InvalidCode,
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for TotpError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+
+#[derive(Debug, serde::Serialize)]
+pub struct InscripcionTotp {
+    pub secreto: String,
+    pub otpauth_uri: String,
+    // Imagen PNG en base64
+    pub qr: String,
+}
+
+
+fn construir_totp(secreto: &str, issuer: &str, email: &str) -> Result<TOTP, anyhow::Error> {
+    let secreto = Secret::Encoded(secreto.to_string())
+        .to_bytes()
+        .map_err(|e| anyhow::anyhow!("Invalid TOTP secret: {:?}", e))?;
+
+    TOTP::new(
+        Algorithm::SHA1,
+        TOTP_DIGITOS,
+        TOTP_TOLERANCIA_PASOS as u8,
+        TOTP_PASO_SEGUNDOS,
+        secreto,
+        Some(issuer.replace(':', "")),
+        email.to_string(),
+    )
+    .map_err(|e| anyhow::anyhow!("Invalid TOTP parameters: {:?}", e))
+}
+
+fn generar_secreto() -> String {
+    let mut bytes = [0u8; 20];
+    thread_rng().fill(&mut bytes);
+
+    Secret::Raw(bytes.to_vec()).to_encoded().to_string()
+}
+
+/// Regresa el paso de tiempo que corresponde al codigo, si es valido
+fn obtener_paso_valido(totp: &TOTP, codigo: &str) -> Option<i64> {
+    let paso_actual = Utc::now().timestamp() as u64 / TOTP_PASO_SEGUNDOS;
+    let primer_paso = paso_actual.saturating_sub(TOTP_TOLERANCIA_PASOS);
+
+    (primer_paso..=paso_actual + TOTP_TOLERANCIA_PASOS)
+        .find(|paso| totp.generate(paso * TOTP_PASO_SEGUNDOS) == codigo)
+        .map(|paso| paso as i64)
+}
+
+fn hash_codigo_recuperacion(codigo: &str) -> String {
+    let codigo = codigo.trim().to_lowercase().replace('-', "");
+    format!("{:x}", Sha256::digest(codigo.as_bytes()))
+}
+
+fn generar_codigo_recuperacion() -> String {
+    let mut rng = thread_rng();
+    let codigo: String = std::iter::repeat_with(|| rng.sample(Alphanumeric))
+        .map(char::from)
+        .take(10)
+        .collect::<String>()
+        .to_lowercase();
+
+    format!("{}-{}", &codigo[..5], &codigo[5..])
+}
+
+
+#[tracing::instrument(
+    name = "Query usuario tiene totp activo",
+    skip(pool)
+)]
+pub async fn tiene_totp_activo(
+    pool: &PgPool,
+    usuario_id: &Uuid,
+) -> Result<bool, anyhow::Error> {
+    let row = sqlx::query!(
+        r#"
+        SELECT EXISTS(
+            SELECT usuario_id FROM usuarios_totp
+            WHERE usuario_id = $1 AND confirmado = true
+        )
+        "#,
+        usuario_id,
+    )
+    .fetch_one(pool)
+    .await
+    .context("Failed to execute query")?;
+
+    Ok(row.exists.unwrap_or(false))
+}
+
+/// Genera un nuevo secreto sin confirmar, reemplazando cualquier
+/// inscripcion pendiente anterior
+#[tracing::instrument(
+    name = "Iniciar inscripcion totp",
+    skip(pool, issuer, email)
+)]
+pub async fn iniciar_inscripcion(
+    pool: &PgPool,
+    usuario_id: &Uuid,
+    issuer: &str,
+    email: &str,
+) -> Result<InscripcionTotp, TotpError> {
+    if tiene_totp_activo(pool, usuario_id).await? {
+        return Err(TotpError::AlreadyEnabled);
+    }
+
+    let secreto = generar_secreto();
+    let totp = construir_totp(&secreto, issuer, email)?;
+    let qr = totp.get_qr_base64()
+        .map_err(|e| anyhow::anyhow!("Failed to generate QR code: {}", e))?;
+
+    sqlx::query!(
+        r#"
+        INSERT INTO usuarios_totp
+        (usuario_id, secreto)
+        VALUES ($1, $2)
+        ON CONFLICT (usuario_id) DO UPDATE
+        SET secreto = EXCLUDED.secreto,
+            confirmado = false,
+            ultimo_paso = NULL,
+            creado_en = now()
+        "#,
+        usuario_id,
+        secreto,
+    )
+    .execute(pool)
+    .await
+    .context("Failed to store totp secret")?;
+
+    Ok(InscripcionTotp {
+        otpauth_uri: totp.get_url(),
+        secreto,
+        qr,
+    })
+}
+
+/// Confirma la inscripcion con un codigo valido y regresa los codigos de
+/// recuperacion, que solo se muestran esta vez
+#[tracing::instrument(
+    name = "Confirmar inscripcion totp",
+    skip(pool, issuer, email, codigo)
+)]
+pub async fn confirmar_inscripcion(
+    pool: &PgPool,
+    usuario_id: &Uuid,
+    issuer: &str,
+    email: &str,
+    codigo: &str,
+) -> Result<Vec<String>, TotpError> {
+    let row = sqlx::query!(
+        r#"
+        SELECT secreto, confirmado
+        FROM usuarios_totp
+        WHERE usuario_id = $1
+        "#,
+        usuario_id,
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to execute query")?
+    .ok_or(TotpError::NotEnrolled)?;
+
+    if row.confirmado {
+        return Err(TotpError::AlreadyEnabled);
+    }
+
+    let totp = construir_totp(&row.secreto, issuer, email)?;
+    let paso = obtener_paso_valido(&totp, codigo).ok_or(TotpError::InvalidCode)?;
+
+    let mut transaction = pool.begin()
+        .await
+        .context("Failed to begin transaction")?;
+
+    sqlx::query!(
+        r#"
+        UPDATE usuarios_totp
+        SET confirmado = true,
+            confirmado_en = now(),
+            ultimo_paso = $2
+        WHERE usuario_id = $1
+        "#,
+        usuario_id,
+        paso,
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to confirm totp")?;
+
+    let codigos = reemplazar_codigos_recuperacion(&mut transaction, usuario_id).await?;
+
+    transaction.commit()
+        .await
+        .context("Failed to commit transaction")?;
+
+    Ok(codigos)
+}
+
+async fn reemplazar_codigos_recuperacion(
+    transaction: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+    usuario_id: &Uuid,
+) -> Result<Vec<String>, anyhow::Error> {
+    sqlx::query!(
+        r#"DELETE FROM totp_codigos_recuperacion WHERE usuario_id = $1"#,
+        usuario_id,
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("Failed to delete recovery codes")?;
+
+    let codigos: Vec<String> = (0..CODIGOS_RECUPERACION)
+        .map(|_| generar_codigo_recuperacion())
+        .collect();
+    let hashes: Vec<String> = codigos.iter()
+        .map(|c| hash_codigo_recuperacion(c))
+        .collect();
+
+    sqlx::query!(
+        r#"
+        INSERT INTO totp_codigos_recuperacion
+        (codigo_hash, usuario_id)
+        SELECT codigo_hash, $2 FROM UNNEST($1::TEXT[]) AS codigo_hash
+        "#,
+        &hashes,
+        usuario_id,
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("Failed to store recovery codes")?;
+
+    Ok(codigos)
+}
+
+/// Genera nuevos codigos de recuperacion, invalidando los anteriores
+#[tracing::instrument(
+    name = "Regenerar codigos de recuperacion",
+    skip(pool)
+)]
+pub async fn regenerar_codigos_recuperacion(
+    pool: &PgPool,
+    usuario_id: &Uuid,
+) -> Result<Vec<String>, anyhow::Error> {
+    let mut transaction = pool.begin()
+        .await
+        .context("Failed to begin transaction")?;
+
+    let codigos = reemplazar_codigos_recuperacion(&mut transaction, usuario_id).await?;
+
+    transaction.commit()
+        .await
+        .context("Failed to commit transaction")?;
+
+    Ok(codigos)
+}
+
+/// Verifica un codigo TOTP o un codigo de recuperacion.
+/// Un codigo TOTP no se puede reutilizar y un codigo de recuperacion
+/// queda invalidado despues de usarse.
+#[tracing::instrument(
+    name = "Verificar segundo factor",
+    skip(pool, issuer, email, codigo)
+)]
+pub async fn verificar_segundo_factor(
+    pool: &PgPool,
+    usuario_id: &Uuid,
+    issuer: &str,
+    email: &str,
+    codigo: &str,
+) -> Result<(), TotpError> {
+    let row = sqlx::query!(
+        r#"
+        SELECT secreto, ultimo_paso
+        FROM usuarios_totp
+        WHERE usuario_id = $1 AND confirmado = true
+        "#,
+        usuario_id,
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to execute query")?
+    .ok_or(TotpError::NotEnrolled)?;
+
+    let codigo = codigo.trim();
+    let totp = construir_totp(&row.secreto, issuer, email)?;
+
+    if let Some(paso) = obtener_paso_valido(&totp, codigo) {
+        // Solo se acepta si el paso es posterior al ultimo usado
+        let actualizado = sqlx::query!(
+            r#"
+            UPDATE usuarios_totp
+            SET ultimo_paso = $2
+            WHERE usuario_id = $1 AND (ultimo_paso IS NULL OR ultimo_paso < $2)
+            "#,
+            usuario_id,
+            paso,
+        )
+        .execute(pool)
+        .await
+        .context("Failed to update totp last step")?;
+
+        if actualizado.rows_affected() == 0 {
+            return Err(TotpError::InvalidCode);
+        }
+
+        return Ok(());
+    }
+
+    let usado = sqlx::query!(
+        r#"
+        UPDATE totp_codigos_recuperacion
+        SET usado_en = now()
+        WHERE usuario_id = $1 AND codigo_hash = $2 AND usado_en IS NULL
+        "#,
+        usuario_id,
+        hash_codigo_recuperacion(codigo),
+    )
+    .execute(pool)
+    .await
+    .context("Failed to use recovery code")?;
+
+    if usado.rows_affected() == 0 {
+        return Err(TotpError::InvalidCode);
+    }
+
+    tracing::info!("Se uso un codigo de recuperacion");
+    Ok(())
+}
+
+#[tracing::instrument(
+    name = "Desactivar totp",
+    skip(pool)
+)]
+pub async fn desactivar_totp(
+    pool: &PgPool,
+    usuario_id: &Uuid,
+) -> Result<(), anyhow::Error> {
+    let mut transaction = pool.begin()
+        .await
+        .context("Failed to begin transaction")?;
+
+    sqlx::query!(
+        r#"DELETE FROM totp_codigos_recuperacion WHERE usuario_id = $1"#,
+        usuario_id,
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to delete recovery codes")?;
+
+    sqlx::query!(
+        r#"DELETE FROM usuarios_totp WHERE usuario_id = $1"#,
+        usuario_id,
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to delete totp")?;
+
+    transaction.commit()
+        .await
+        .context("Failed to commit transaction")?;
+
+    Ok(())
+}
+
+
+/// Desafio de inicio de sesion pendiente del segundo factor
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+pub struct DesafioLogin {
+    pub usuario_id: Uuid,
+    pub email: String,
+    pub dispositivo: Option<String>,
+    // El usuario debe inscribirse antes de poder iniciar sesion
+    pub inscripcion: bool,
+}
+
+fn get_desafio_key(desafio: &str) -> String {
+    format!("login.challenge:{}", desafio)
+}
+
+#[tracing::instrument(
+    name = "Crear desafio de segundo factor",
+    skip(redis, redis_con, email, dispositivo)
+)]
+pub async fn crear_desafio(
+    redis: &RedisPool,
+    redis_con: &mut RedisConnection,
+    usuario_id: &Uuid,
+    email: &str,
+    dispositivo: Option<String>,
+    inscripcion: bool,
+) -> Result<String, anyhow::Error> {
+    let mut rng = thread_rng();
+    let desafio: String = std::iter::repeat_with(|| rng.sample(Alphanumeric))
+        .map(char::from)
+        .take(40)
+        .collect();
+
+    let valor = serde_json::to_string(&DesafioLogin {
+        usuario_id: *usuario_id,
+        email: email.to_string(),
+        dispositivo,
+        inscripcion,
+    })?;
+
+    let _: () = redis.timeout(
+        redis::cmd("SET")
+            .arg(get_desafio_key(&desafio))
+            .arg(valor)
+            .arg("EX")
+            .arg(DESAFIO_EXPIRACION_SEGUNDOS)
+            .query_async(redis_con)
+    ).await?;
+
+    Ok(desafio)
+}
+
+#[tracing::instrument(
+    name = "Obtener desafio de segundo factor",
+    skip_all
+)]
+pub async fn obtener_desafio(
+    redis: &RedisPool,
+    redis_con: &mut RedisConnection,
+    desafio: &str,
+) -> Result<Option<DesafioLogin>, anyhow::Error> {
+    let valor: Option<String> = redis.timeout(
+        redis::cmd("GET")
+            .arg(get_desafio_key(desafio))
+            .query_async(redis_con)
+    ).await?;
+
+    match valor {
+        Some(valor) => Ok(Some(serde_json::from_str(&valor)?)),
+        None => Ok(None),
+    }
+}
+
+/// Obtiene el desafio y lo borra, cada desafio solo admite un intento
+#[tracing::instrument(
+    name = "Consumir desafio de segundo factor",
+    skip_all
+)]
+pub async fn consumir_desafio(
+    redis: &RedisPool,
+    redis_con: &mut RedisConnection,
+    desafio: &str,
+) -> Result<Option<DesafioLogin>, anyhow::Error> {
+    let key = get_desafio_key(desafio);
+    let (valor,): (Option<String>,) = redis.timeout(
+        redis::pipe()
+            .atomic()
+            .get(&key)
+            .del(&key).ignore()
+            .query_async(redis_con)
+    ).await?;
+
+    match valor {
+        Some(valor) => Ok(Some(serde_json::from_str(&valor)?)),
+        None => Ok(None),
+    }
+}
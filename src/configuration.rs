@@ -12,6 +12,17 @@ pub struct Settings {
     pub email_client: EmailClientSettings,
     // shared cache redis
     pub redis_client: RedisClientSettings,
+    pub login_throttle: LoginThrottleSettings,
+    pub two_factor: TwoFactorSettings,
+    pub jwt: JwtSettings,
+    pub ldap: LdapSettings,
+    pub oidc: OidcSettings,
+    pub signup: SignupSettings,
+    pub password_policy: PasswordPolicySettings,
+    pub password_hashing: PasswordHashingSettings,
+    pub user_retention: UserRetentionSettings,
+    pub user_export: UserExportSettings,
+    pub licenses: LicenseSettings,
 }
 
 
@@ -24,6 +35,35 @@ pub struct ApplicationSettings {
     pub hmca_secret: HmacKey,
 }
 
+#[derive(serde::Deserialize, Clone)]
+pub struct JwtSettings {
+    pub issuer: String,
+    pub audience: String,
+    // Llave con la que se firman los tokens nuevos, sin valor se firma con la llave "legacy"
+    pub signing_kid: Option<String>,
+    // Todas las llaves no retiradas verifican tokens, una llave sin private_key
+    // o con verify_only solo verifica los tokens que firmo antes de rotarla
+    #[serde(default)]
+    pub keys: Vec<JwtKeySettings>,
+}
+
+#[derive(serde::Deserialize, Clone)]
+pub struct JwtKeySettings {
+    pub kid: String,
+    pub algorithm: jsonwebtoken::Algorithm,
+    // Llaves HS256
+    pub secret: Option<Secret<String>>,
+    // Llaves RS256 y EdDSA en formato PEM
+    pub private_key: Option<Secret<String>>,
+    pub public_key: Option<String>,
+    // La llave verifica pero ya no firma
+    #[serde(default)]
+    pub verify_only: bool,
+    // La llave ya no firma ni verifica, sus tokens se rechazan
+    #[serde(default)]
+    pub retired: bool,
+}
+
 #[derive(serde::Deserialize, Clone)]
 pub struct EmailClientSettings {
     pub smtp_host: String,
@@ -37,6 +77,182 @@ pub struct EmailClientSettings {
 #[derive(serde::Deserialize, Clone)]
 pub struct RedisClientSettings {
     pub uri: String,
+    // 0 desactiva el cache de usuarios
+    #[serde(default)]
+    pub user_cache_seconds: u64,
+    #[serde(default = "default_redis_connect_timeout")]
+    pub connect_timeout_milliseconds: u64,
+    #[serde(default = "default_redis_command_timeout")]
+    pub command_timeout_milliseconds: u64,
+    // Si Redis no responde, true omite la verificacion de tokens revocados
+    // y los limites de intentos de login, false rechaza las peticiones
+    #[serde(default)]
+    pub fail_open: bool,
+}
+
+fn default_redis_connect_timeout() -> u64 {
+    1000
+}
+
+fn default_redis_command_timeout() -> u64 {
+    500
+}
+
+#[derive(serde::Deserialize, Clone)]
+pub struct LoginThrottleSettings {
+    // Ventana deslizante para contar intentos y fallos
+    pub window_seconds: u64,
+    pub max_attempts_per_email: u64,
+    pub max_attempts_per_ip: u64,
+    pub failures_before_lockout: u64,
+    // El primer bloqueo dura lockout_base_seconds y cada bloqueo
+    // consecutivo dura el doble, hasta lockout_max_seconds
+    pub lockout_base_seconds: u64,
+    pub lockout_max_seconds: u64,
+    // Tiempo que se recuerdan los bloqueos para calcular el siguiente
+    pub lockout_memory_seconds: u64,
+}
+
+#[derive(serde::Deserialize, Clone)]
+pub struct SignupSettings {
+    // Dominios de correo que se pueden registrar, sin dominios se acepta cualquiera
+    #[serde(default)]
+    pub allowed_domains: Vec<String>,
+    // Las cuentas nuevas esperan a que un administrador las apruebe
+    pub require_approval: bool,
+}
+
+impl SignupSettings {
+    pub fn allows(&self, email: &str) -> bool {
+        if self.allowed_domains.is_empty() {
+            return true;
+        }
+
+        match email.rsplit_once('@') {
+            Some((_, dominio)) => self.allowed_domains.iter().any(|d| d.eq_ignore_ascii_case(dominio)),
+            None => false,
+        }
+    }
+}
+
+#[derive(serde::Deserialize, Clone)]
+pub struct PasswordPolicySettings {
+    pub min_length: usize,
+    pub max_length: usize,
+    pub require_lowercase: bool,
+    pub require_uppercase: bool,
+    pub require_digit: bool,
+    pub require_symbol: bool,
+    // Estimacion minima, las repeticiones y secuencias no suman
+    pub min_entropy_bits: u32,
+    // Cuantas contraseñas anteriores no se pueden reutilizar, 0 lo desactiva
+    pub history: i64,
+    // Lista de contraseñas comunes o filtradas, una por linea
+    pub common_passwords_file: Option<String>,
+}
+
+/// Parametros de Argon2id para los hashes nuevos, los hashes guardados
+/// con otros parametros se actualizan en el siguiente inicio de sesion
+#[derive(serde::Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct PasswordHashingSettings {
+    pub memory_kib: u32,
+    pub iterations: u32,
+    pub parallelism: u32,
+}
+
+#[derive(serde::Deserialize, Clone)]
+pub struct UserRetentionSettings {
+    // Dias para restaurar un usuario desactivado antes de anonimizarlo
+    pub anonymize_after_days: i32,
+    // Cada cuanto busca el worker usuarios por anonimizar
+    pub worker_interval_seconds: u64,
+}
+
+#[derive(serde::Deserialize, Clone)]
+pub struct UserExportSettings {
+    // Horas que el enlace de descarga es valido
+    pub link_expiration_hours: i32,
+    // Cada cuanto busca el worker exportaciones pendientes
+    pub worker_interval_seconds: u64,
+}
+
+#[derive(serde::Deserialize, Clone)]
+pub struct LicenseSettings {
+    // Dias antes del vencimiento para avisar al conductor
+    pub reminder_days_before: i32,
+    // Cada cuanto busca el worker licencias por vencer
+    pub worker_interval_seconds: u64,
+}
+
+#[derive(serde::Deserialize, Clone)]
+pub struct TwoFactorSettings {
+    // Los administradores sin TOTP deben inscribirse al iniciar sesion
+    pub require_for_admins: bool,
+    // Nombre que muestra la aplicacion autenticadora
+    pub issuer: String,
+}
+
+#[derive(serde::Deserialize, Clone)]
+pub struct LdapSettings {
+    // Los correos de estos dominios inician sesion con el directorio,
+    // sin dominios el directorio no se usa
+    #[serde(default)]
+    pub domains: Vec<String>,
+    pub url: String,
+    #[serde(default)]
+    pub starttls: bool,
+    pub timeout_seconds: u64,
+    // Cuenta de servicio para buscar usuarios y grupos
+    pub bind_dn: String,
+    pub bind_password: Secret<String>,
+    pub base_dn: String,
+    // {email} se reemplaza por el correo del usuario
+    pub user_filter: String,
+    pub group_base_dn: String,
+    // {dn} se reemplaza por el DN del usuario
+    pub group_filter: String,
+    // DN de los grupos cuyos miembros son administradores
+    #[serde(default)]
+    pub admin_groups: Vec<String>,
+    #[serde(default)]
+    pub group_departments: Vec<LdapGroupDepartment>,
+}
+
+#[derive(serde::Deserialize, Clone)]
+pub struct LdapGroupDepartment {
+    pub group: String,
+    // Nombre del departamento
+    pub departamento: String,
+}
+
+impl LdapSettings {
+    pub fn applies_to(&self, email: &str) -> bool {
+        match email.rsplit_once('@') {
+            Some((_, dominio)) => self.domains.iter().any(|d| d.eq_ignore_ascii_case(dominio)),
+            None => false,
+        }
+    }
+}
+
+#[derive(serde::Deserialize, Clone)]
+pub struct OidcSettings {
+    #[serde(default)]
+    pub providers: Vec<OidcProviderSettings>,
+}
+
+#[derive(serde::Deserialize, Clone)]
+pub struct OidcProviderSettings {
+    // Nombre del proveedor en las rutas, /api/auth/oidc/{name}
+    pub name: String,
+    pub issuer_url: String,
+    pub client_id: String,
+    // Los clientes publicos solo usan PKCE
+    pub client_secret: Option<Secret<String>>,
+    // Ruta del frontend que recibe el code y el state
+    pub redirect_url: String,
+    // Ademas de openid y email
+    #[serde(default)]
+    pub scopes: Vec<String>,
 }
 
 /*
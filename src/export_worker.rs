@@ -0,0 +1,427 @@
+use std::collections::HashSet;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use anyhow::Context;
+use rand::{thread_rng, Rng, distributions::Alphanumeric};
+use sha2::{Digest, Sha256};
+use sqlx::PgPool;
+use uuid::Uuid;
+use zip::write::FileOptions;
+use zip::{CompressionMethod, ZipWriter};
+
+use crate::configuration::{Settings, UserExportSettings};
+use crate::email_client::EmailClient;
+use crate::routes::licenses::sqlx::obtener_licencia_sqlx;
+use crate::startup::get_connection_pool;
+use crate::upload::image::get_uploads_path;
+
+
+const IMAGEN_DEFAULT: &str = "default-user.jpeg";
+
+
+pub enum ExecutionOutcome {
+    TaskCompleted,
+    EmptyQueue,
+}
+
+
+pub async fn run_worker_until_stopped(configuration: Settings) -> Result<(), anyhow::Error> {
+    let connection_pool = get_connection_pool(&configuration.database);
+    let email_client = EmailClient::new(
+        configuration.email_client.smtp_host,
+        configuration.email_client.smtp_name,
+        configuration.email_client.smtp_username,
+        configuration.email_client.smtp_password,
+        configuration.email_client.smtp_port,
+    )?;
+    worker_loop(
+        connection_pool,
+        email_client,
+        configuration.application.base_url,
+        configuration.user_export,
+    ).await
+}
+
+async fn worker_loop(
+    pool: PgPool,
+    email_client: EmailClient,
+    base_url: String,
+    settings: UserExportSettings,
+) -> Result<(), anyhow::Error> {
+    loop {
+        if let Err(e) = expirar_exportaciones(&pool).await {
+            tracing::error!(
+                error.cause_chain = ?e,
+                error.message = %e,
+                "Failed to expire user exports"
+            );
+        }
+
+        match try_execute_task(&pool, &email_client, &base_url, &settings).await {
+            Ok(ExecutionOutcome::EmptyQueue) => {
+                tokio::time::sleep(Duration::from_secs(settings.worker_interval_seconds)).await;
+            }
+            Ok(ExecutionOutcome::TaskCompleted) => {}
+            Err(_) => {
+                tokio::time::sleep(Duration::from_secs(1)).await;
+            }
+        }
+    }
+}
+
+
+pub fn hash_token(token: &str) -> String {
+    format!("{:x}", Sha256::digest(token.as_bytes()))
+}
+
+fn generar_token() -> String {
+    let mut rng = thread_rng();
+    std::iter::repeat_with(|| rng.sample(Alphanumeric))
+        .map(char::from)
+        .take(40)
+        .collect()
+}
+
+/// Carpeta donde se guardan los ZIP generados
+pub fn get_exports_path() -> Result<PathBuf, std::io::Error> {
+    Ok(get_uploads_path()?.join("exports"))
+}
+
+
+/// Genera el ZIP de la exportacion pendiente mas antigua y avisa al usuario
+#[tracing::instrument(
+    skip_all,
+    fields(exportacion_id=tracing::field::Empty, user_id=tracing::field::Empty),
+    err
+)]
+pub async fn try_execute_task(
+    pool: &PgPool,
+    email_client: &EmailClient,
+    base_url: &str,
+    settings: &UserExportSettings,
+) -> Result<ExecutionOutcome, anyhow::Error> {
+    let mut transaction = pool.begin()
+        .await
+        .context("Failed to begin transaction")?;
+
+    // SKIP LOCKED permite varios workers sin procesar dos veces la misma
+    let tarea = sqlx::query!(
+        r#"
+        SELECT e.exportacion_id, e.usuario_id, u.email
+        FROM exportaciones e
+        JOIN usuarios u ON u.usuario_id = e.usuario_id
+        WHERE e.estado = 'pendiente'
+        ORDER BY e.creado_en
+        FOR UPDATE OF e SKIP LOCKED
+        LIMIT 1
+        "#,
+    )
+    .fetch_optional(&mut transaction)
+    .await
+    .context("Failed to fetch pending export")?;
+
+    let tarea = match tarea {
+        Some(tarea) => tarea,
+        None => return Ok(ExecutionOutcome::EmptyQueue),
+    };
+
+    let span = tracing::Span::current();
+    span.record("exportacion_id", &tracing::field::display(&tarea.exportacion_id));
+    span.record("user_id", &tracing::field::display(&tarea.usuario_id));
+
+    let archivo = match generar_zip(pool, &tarea.exportacion_id, &tarea.usuario_id).await {
+        Ok(archivo) => archivo,
+        Err(e) => {
+            tracing::error!(
+                error.cause_chain = ?e,
+                error.message = %e,
+                "Failed to build user export"
+            );
+            sqlx::query!(
+                r#"
+                UPDATE exportaciones
+                SET estado = 'fallida', completado_en = now()
+                WHERE exportacion_id = $1
+                "#,
+                tarea.exportacion_id,
+            )
+            .execute(&mut transaction)
+            .await
+            .context("Failed to mark export as failed")?;
+            transaction.commit()
+                .await
+                .context("Failed to commit transaction")?;
+            return Ok(ExecutionOutcome::TaskCompleted);
+        }
+    };
+
+    let token = generar_token();
+    sqlx::query!(
+        r#"
+        UPDATE exportaciones
+        SET estado = 'lista',
+            archivo = $2,
+            token_hash = $3,
+            completado_en = now(),
+            expira_en = now() + make_interval(hours => $4)
+        WHERE exportacion_id = $1
+        "#,
+        tarea.exportacion_id,
+        archivo,
+        hash_token(&token),
+        settings.link_expiration_hours,
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to mark export as ready")?;
+
+    transaction.commit()
+        .await
+        .context("Failed to commit transaction")?;
+
+    let download_link = format!(
+        "{}/api/auth/export/download?token={}",
+        base_url,
+        token,
+    );
+
+    if let Err(e) = email_client.send_email(
+            &tarea.email,
+            "Tus datos estan listos",
+            &format!("La exportacion de tus datos de Control Parque Vehicular esta lista.<br />\
+                     Haz click <a href=\"{}\">aqui</a> para descargarla, el enlace expira en {} horas.",
+                     download_link, settings.link_expiration_hours),
+            &format!("La exportacion de tus datos de Control Parque Vehicular esta lista.\n\
+                     Visita {} para descargarla, el enlace expira en {} horas.",
+                     download_link, settings.link_expiration_hours),
+        ).await
+    {
+        tracing::error!("No se pudo enviar el enlace de la exportacion: {:?}", e);
+    }
+
+    Ok(ExecutionOutcome::TaskCompleted)
+}
+
+
+/// Borra los ZIP cuyo enlace ya expiro
+#[tracing::instrument(skip(pool))]
+pub async fn expirar_exportaciones(pool: &PgPool) -> Result<u64, anyhow::Error> {
+    let expiradas = sqlx::query!(
+        r#"
+        UPDATE exportaciones e
+        SET estado = 'expirada', archivo = NULL, token_hash = NULL
+        FROM exportaciones anterior
+        WHERE anterior.exportacion_id = e.exportacion_id
+            AND e.estado = 'lista'
+            AND e.expira_en < now()
+        RETURNING anterior.archivo
+        "#,
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to expire exports")?;
+
+    let exports_path = get_exports_path()
+        .context("Failed to get exports path")?;
+    for archivo in expiradas.iter().filter_map(|row| row.archivo.as_ref()) {
+        if let Err(e) = std::fs::remove_file(exports_path.join(archivo)) {
+            tracing::warn!("No se pudo borrar la exportacion {}: {:?}", archivo, e);
+        }
+    }
+
+    Ok(expiradas.len() as u64)
+}
+
+
+#[derive(serde::Serialize)]
+struct Perfil {
+    usuario_id: Uuid,
+    nombres: String,
+    apellidos: String,
+    email: String,
+    numero_empleado: Option<i16>,
+    departamento: Option<String>,
+    rol: String,
+    imagen: String,
+    activo: bool,
+    verificado: bool,
+    creado_en: chrono::NaiveDateTime,
+    modificado_en: chrono::NaiveDateTime,
+}
+
+#[derive(serde::Serialize)]
+struct PeticionExportada {
+    peticion_id: Uuid,
+    vehiculo_id: Uuid,
+    estado: String,
+    inicio: chrono::NaiveDateTime,
+    finalizo: chrono::NaiveDateTime,
+    actividad_descripcion: String,
+    actividad_comentario: String,
+    kilometraje_inicial: i32,
+    kilometraje_final: i32,
+    usuario_licencia_imagen: String,
+    creado_en: chrono::NaiveDateTime,
+    modificado_en: chrono::NaiveDateTime,
+}
+
+#[derive(serde::Serialize)]
+struct InicioSesion {
+    dispositivo: Option<String>,
+    ip: Option<String>,
+    user_agent: Option<String>,
+    creado_en: chrono::NaiveDateTime,
+    ultimo_uso: chrono::NaiveDateTime,
+    expira_en: chrono::NaiveDateTime,
+    revocado_en: Option<chrono::NaiveDateTime>,
+}
+
+/// Junta los datos del usuario en uploads/exports, regresa el nombre del ZIP
+#[tracing::instrument(skip(pool))]
+async fn generar_zip(
+    pool: &PgPool,
+    exportacion_id: &Uuid,
+    usuario_id: &Uuid,
+) -> Result<String, anyhow::Error> {
+    let perfil = sqlx::query_as!(
+        Perfil,
+        r#"
+        SELECT
+            u.usuario_id, u.nombres, u.apellidos, u.email, u.numero_empleado,
+            d.nombre as "departamento?",
+            u.rol::text as "rol!",
+            u.imagen, u.activo, u.verificado, u.creado_en, u.modificado_en
+        FROM usuarios u
+        LEFT JOIN departamentos d ON d.id = u.departamento
+        WHERE u.usuario_id = $1
+        "#,
+        usuario_id,
+    )
+    .fetch_one(pool)
+    .await
+    .context("Failed to fetch user profile")?;
+
+    let peticiones = sqlx::query_as!(
+        PeticionExportada,
+        r#"
+        SELECT
+            peticion_id, vehiculo_id,
+            estado::text as "estado!",
+            inicio, finalizo,
+            actividad_descripcion, actividad_comentario,
+            kilometraje_inicial, kilometraje_final,
+            usuario_licencia_imagen,
+            creado_en, modificado_en
+        FROM peticiones
+        WHERE usuario_id = $1
+        ORDER BY inicio
+        "#,
+        usuario_id,
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to fetch user requests")?;
+
+    let sesiones = sqlx::query_as!(
+        InicioSesion,
+        r#"
+        SELECT dispositivo, ip, user_agent, creado_en, ultimo_uso, expira_en, revocado_en
+        FROM sesiones
+        WHERE usuario_id = $1
+        ORDER BY creado_en
+        "#,
+        usuario_id,
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to fetch login history")?;
+
+    let licencia = obtener_licencia_sqlx(pool, usuario_id).await?;
+
+    let uploads_path = get_uploads_path()
+        .context("Failed to get uploads path")?;
+
+    // (ruta dentro del ZIP, archivo en uploads)
+    let mut imagenes = vec![];
+    if perfil.imagen != IMAGEN_DEFAULT {
+        if let Some(nombre) = nombre_seguro(&perfil.imagen) {
+            imagenes.push((format!("imagenes/perfil/{}", nombre), uploads_path.join("users").join(nombre)));
+        }
+    }
+    let imagenes_licencia = licencia.iter()
+        .flat_map(|licencia| [licencia.imagen_frente.as_deref(), licencia.imagen_reverso.as_deref()])
+        .flatten()
+        .chain(peticiones.iter().map(|peticion| peticion.usuario_licencia_imagen.as_str()));
+    // Varias peticiones pueden compartir la misma imagen de licencia,
+    // el ZIP no admite entradas con el mismo nombre
+    let mut vistas = HashSet::new();
+    for imagen in imagenes_licencia {
+        if let Some(nombre) = nombre_seguro(imagen).filter(|nombre| vistas.insert(*nombre)) {
+            imagenes.push((format!("imagenes/licencias/{}", nombre), uploads_path.join("licenses").join(nombre)));
+        }
+    }
+
+    let json = vec![
+        ("perfil.json", serde_json::to_vec_pretty(&perfil)?),
+        ("peticiones.json", serde_json::to_vec_pretty(&peticiones)?),
+        ("inicios_sesion.json", serde_json::to_vec_pretty(&sesiones)?),
+        ("licencia.json", serde_json::to_vec_pretty(&licencia)?),
+    ];
+
+    let exports_path = get_exports_path()
+        .context("Failed to get exports path")?;
+    let archivo = format!("{}.zip", exportacion_id);
+    let zip_path = exports_path.join(&archivo);
+
+    // La compresion y lectura de imagenes es bloqueante
+    tokio::task::spawn_blocking(move || escribir_zip(&exports_path, &zip_path, json, imagenes))
+        .await
+        .context("Failed to join export task")??;
+
+    Ok(archivo)
+}
+
+fn escribir_zip(
+    exports_path: &Path,
+    zip_path: &Path,
+    json: Vec<(&str, Vec<u8>)>,
+    imagenes: Vec<(String, PathBuf)>,
+) -> Result<(), anyhow::Error> {
+    std::fs::create_dir_all(exports_path)
+        .context("Failed to create exports dir")?;
+    let file = std::fs::File::create(zip_path)
+        .context("Failed to create zip file")?;
+
+    let mut zip = ZipWriter::new(file);
+    let comprimido = FileOptions::default().compression_method(CompressionMethod::Deflated);
+    // Las imagenes ya estan comprimidas
+    let sin_comprimir = FileOptions::default().compression_method(CompressionMethod::Stored);
+
+    for (nombre, contenido) in json {
+        zip.start_file(nombre, comprimido)?;
+        zip.write_all(&contenido)?;
+    }
+
+    for (nombre, path) in imagenes {
+        match std::fs::read(&path) {
+            Ok(contenido) => {
+                zip.start_file(nombre, sin_comprimir)?;
+                zip.write_all(&contenido)?;
+            }
+            Err(e) => tracing::warn!("No se pudo leer la imagen {}: {:?}", path.display(), e),
+        }
+    }
+
+    zip.finish()?;
+    Ok(())
+}
+
+/// Solo el nombre del archivo, evita salir de la carpeta de uploads
+pub(crate) fn nombre_seguro(nombre: &str) -> Option<&str> {
+    Path::new(nombre)
+        .file_name()
+        .and_then(|nombre| nombre.to_str())
+        .filter(|nombre| !nombre.is_empty())
+}
@@ -1,10 +1,15 @@
+pub mod anonymization_worker;
 pub mod authentication;
 pub mod api_response;
+pub mod audit;
 pub mod configuration;
 pub mod email_client;
 pub mod error;
+pub mod export_worker;
+pub mod license_reminder_worker;
 pub mod upload;
 pub mod models;
+pub mod redis_pool;
 pub mod startup;
 pub mod telemetry;
 pub mod routes;
@@ -0,0 +1,101 @@
+use std::time::Duration;
+
+use anyhow::Context;
+use sqlx::PgPool;
+
+use crate::configuration::{LicenseSettings, Settings};
+use crate::email_client::EmailClient;
+use crate::startup::get_connection_pool;
+
+
+pub async fn run_worker_until_stopped(configuration: Settings) -> Result<(), anyhow::Error> {
+    let connection_pool = get_connection_pool(&configuration.database);
+    let email_client = EmailClient::new(
+        configuration.email_client.smtp_host,
+        configuration.email_client.smtp_name,
+        configuration.email_client.smtp_username,
+        configuration.email_client.smtp_password,
+        configuration.email_client.smtp_port,
+    )?;
+    worker_loop(connection_pool, email_client, configuration.licenses).await
+}
+
+async fn worker_loop(
+    pool: PgPool,
+    email_client: EmailClient,
+    settings: LicenseSettings,
+) -> Result<(), anyhow::Error> {
+    loop {
+        match enviar_recordatorios(&pool, &email_client, settings.reminder_days_before).await {
+            Ok(0) => {},
+            Ok(enviados) => tracing::info!("Se enviaron {} recordatorios de licencia", enviados),
+            Err(e) => tracing::error!(
+                error.cause_chain = ?e,
+                error.message = %e,
+                "Failed to send license reminders"
+            ),
+        }
+        tokio::time::sleep(Duration::from_secs(settings.worker_interval_seconds)).await;
+    }
+}
+
+
+/// Avisa una vez a cada conductor cuya licencia verificada vence en
+/// `dias` o menos, regresa cuantos avisos se enviaron
+#[tracing::instrument(skip(pool, email_client))]
+pub async fn enviar_recordatorios(
+    pool: &PgPool,
+    email_client: &EmailClient,
+    dias: i32,
+) -> Result<u64, anyhow::Error> {
+    // Se marcan antes de enviar para no avisar dos veces con varios workers
+    let licencias = sqlx::query!(
+        r#"
+        UPDATE licencias l
+        SET recordatorio_enviado_en = now()
+        FROM usuarios u
+        WHERE u.usuario_id = l.usuario_id
+            AND l.verificada_en IS NOT NULL
+            AND l.recordatorio_enviado_en IS NULL
+            AND l.expira_en <= CURRENT_DATE + $1
+            AND u.activo
+            AND u.anonimizado_en IS NULL
+        RETURNING l.licencia_id, l.expira_en, u.email
+        "#,
+        dias,
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to fetch expiring licenses")?;
+
+    let mut enviados = 0;
+    for licencia in licencias {
+        let result = email_client.send_email(
+                &licencia.email,
+                "Tu licencia de conducir esta por vencer",
+                &format!("Tu licencia de conducir vence el {}.<br />\
+                         Registra la licencia renovada en Control Parque Vehicular para seguir solicitando vehiculos.",
+                         licencia.expira_en),
+                &format!("Tu licencia de conducir vence el {}.\n\
+                         Registra la licencia renovada en Control Parque Vehicular para seguir solicitando vehiculos.",
+                         licencia.expira_en),
+            ).await;
+
+        match result {
+            Ok(_) => enviados += 1,
+            Err(e) => {
+                tracing::error!("No se pudo enviar el recordatorio de licencia: {:?}", e);
+                // Se reintenta en la siguiente vuelta del worker
+                sqlx::query!(
+                    "UPDATE licencias SET recordatorio_enviado_en = NULL WHERE licencia_id = $1",
+                    licencia.licencia_id,
+                )
+                .execute(pool)
+                .await
+                .context("Failed to reset license reminder")?;
+            }
+        }
+    }
+
+    Ok(enviados)
+}
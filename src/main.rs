@@ -1,6 +1,9 @@
+use control_parque_vehicular::{anonymization_worker, export_worker, license_reminder_worker};
 use control_parque_vehicular::configuration::get_configuration;
 use control_parque_vehicular::startup::Application;
 use control_parque_vehicular::telemetry::{init_subscriber, get_subscriber};
+use std::fmt::{Debug, Display};
+use tokio::task::JoinError;
 
 
 
@@ -12,12 +15,51 @@ async fn main() -> anyhow::Result<()> {
     //env_logger::init();
     let subscriber = get_subscriber("control-parque-vehicular".into(), "info".into(), std::io::stdout);
     init_subscriber(subscriber);
-    
+
     // Get Environmental variables
     let configuration = get_configuration().expect("Failed to read configuration.");
-    let application = Application::build(configuration).await?;
+    let application = Application::build(configuration.clone()).await?;
+    let application_task = tokio::spawn(application.run_until_stopped());
+    // Anonimiza los usuarios desactivados al terminar el plazo para restaurarlos
+    let anonymization_task = tokio::spawn(anonymization_worker::run_worker_until_stopped(configuration.clone()));
+    // Genera los ZIP de las exportaciones de datos solicitadas
+    let export_task = tokio::spawn(export_worker::run_worker_until_stopped(configuration.clone()));
+    // Avisa a los conductores antes de que venza su licencia
+    let license_reminder_task = tokio::spawn(license_reminder_worker::run_worker_until_stopped(configuration));
 
-    application.run_until_stopped().await?;
+    tokio::select! {
+        o = application_task => report_exit("API", o),
+        o = anonymization_task => report_exit("Anonymization worker", o),
+        o = export_task => report_exit("Export worker", o),
+        o = license_reminder_task => report_exit("License reminder worker", o),
+    };
 
     Ok(())
 }
+
+fn report_exit(
+    task_name: &str,
+    outcome: Result<Result<(), impl Debug + Display>, JoinError>,
+) {
+    match outcome {
+        Ok(Ok(())) => {
+            tracing::info!("{} has exited", task_name)
+        }
+        Ok(Err(e)) => {
+            tracing::error!(
+                error.cause_chain = ?e,
+                error.message = %e,
+                "{} failed",
+                task_name
+            )
+        }
+        Err(e) => {
+            tracing::error!(
+                error.cause_chain = ?e,
+                error.message = %e,
+                "{}' task failed to complete",
+                task_name
+            )
+        }
+    }
+}
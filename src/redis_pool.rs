@@ -0,0 +1,111 @@
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Duration;
+
+use redis::RedisResult;
+use redis::aio::ConnectionManager;
+use tokio::sync::OnceCell;
+
+use crate::configuration::RedisClientSettings;
+use crate::error::error_chain_fmt;
+
+
+/// Conexion multiplexada compartida, se puede clonar libremente
+pub type RedisConnection = ConnectionManager;
+
+
+#[derive(thiserror::Error)]
+pub enum RedisPoolError {
+    #[error("Redis did not respond in time")]
+    Timeout,
+    #[error(transparent)]
+    Redis(#[from] redis::RedisError),
+}
+
+impl std::fmt::Debug for RedisPoolError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+
+/// Acceso compartido a Redis para toda la aplicacion.
+///
+/// La conexion se establece la primera vez que se usa, asi la aplicacion
+/// puede arrancar aunque Redis no este disponible, y despues se reconecta
+/// sola. Todas las copias comparten la misma conexion.
+#[derive(Clone)]
+pub struct RedisPool {
+    client: redis::Client,
+    manager: Arc<OnceCell<ConnectionManager>>,
+    connect_timeout: Duration,
+    command_timeout: Duration,
+    fail_open: bool,
+}
+
+impl std::fmt::Debug for RedisPool {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RedisPool")
+            .field("client", &self.client)
+            .field("fail_open", &self.fail_open)
+            .finish()
+    }
+}
+
+impl RedisPool {
+    pub fn new(settings: &RedisClientSettings) -> RedisResult<Self> {
+        Ok(Self {
+            client: redis::Client::open(settings.uri.clone())?,
+            manager: Arc::new(OnceCell::new()),
+            connect_timeout: Duration::from_millis(settings.connect_timeout_milliseconds),
+            command_timeout: Duration::from_millis(settings.command_timeout_milliseconds),
+            fail_open: settings.fail_open,
+        })
+    }
+
+    /// Si es verdadero las verificaciones de revocacion y los limites de
+    /// intentos de login se omiten cuando Redis no esta disponible, en lugar
+    /// de rechazar la peticion
+    pub fn fail_open(&self) -> bool {
+        self.fail_open
+    }
+
+    #[tracing::instrument(
+        name = "Obtener conexion a Redis",
+        skip(self)
+    )]
+    pub async fn get(&self) -> Result<RedisConnection, RedisPoolError> {
+        let manager = tokio::time::timeout(
+                self.connect_timeout,
+                self.manager.get_or_try_init(|| ConnectionManager::new(self.client.clone())),
+            )
+            .await
+            .map_err(|_| RedisPoolError::Timeout)??;
+
+        Ok(manager.clone())
+    }
+
+    /// Conexion para funciones que se pueden omitir si Redis no esta disponible:
+    /// None con fail open, error con fail closed
+    pub async fn get_segun_politica(&self) -> Result<Option<RedisConnection>, RedisPoolError> {
+        match self.get().await {
+            Ok(con) => Ok(Some(con)),
+            Err(e) if self.fail_open => {
+                tracing::warn!("Redis no esta disponible, se omite: {:?}", e);
+                Ok(None)
+            },
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Limita el tiempo de espera de un comando
+    pub async fn timeout<T, F>(&self, command: F) -> Result<T, RedisPoolError>
+    where
+        F: Future<Output = RedisResult<T>>,
+    {
+        tokio::time::timeout(self.command_timeout, command)
+            .await
+            .map_err(|_| RedisPoolError::Timeout)?
+            .map_err(Into::into)
+    }
+}
@@ -0,0 +1,37 @@
+use actix_web::{HttpResponse, web};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::api_response::{e404, e500, ApiResponse, e403};
+use crate::authentication::api_keys::revocar_api_key;
+use crate::authentication::current_user::CurrentUser;
+
+
+#[tracing::instrument(
+    name = "Revocar API key",
+    skip(usuario, pool)
+)]
+pub async fn delete_api_key(
+    usuario: CurrentUser,
+    pool: web::Data<PgPool>,
+    uuid: web::Path<Uuid>,
+) -> Result<HttpResponse, actix_web::Error> {
+
+    if !usuario.es_admin() {
+        return Err(e403().with_message("No tienes los permisos requeridos"))?;
+    }
+
+    let revocada = revocar_api_key(&pool, &uuid).await
+        .map_err(|_| e500())?;
+
+    if !revocada {
+        return Err(e404().with_message("No se encontro la API key"))?;
+    }
+
+    // Respuesta exitosa
+    let api_response = ApiResponse::<()>::new()
+        .with_message("API key revocada")
+        .to_resp();
+
+    Ok(api_response)
+}
@@ -0,0 +1,33 @@
+use actix_web::{HttpResponse, web};
+use sqlx::PgPool;
+
+use crate::api_response::{e500, ApiResponse, e403};
+use crate::authentication::api_keys::{listar_api_keys, ApiKey};
+use crate::authentication::current_user::CurrentUser;
+
+
+#[tracing::instrument(
+    name = "Obtener API keys",
+    skip_all
+)]
+pub async fn api_keys_get(
+    usuario: CurrentUser,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+
+    if !usuario.es_admin() {
+        return Err(e403().with_message("No tienes los permisos requeridos"))?;
+    }
+
+    // Query DB
+    let api_keys = listar_api_keys(&pool).await
+        .map_err(|_| e500())?;
+
+    // Respuesta exitosa
+    let api_response = ApiResponse::<Vec<ApiKey>>::new()
+        .with_message("Lista de API keys")
+        .with_data(api_keys)
+        .to_resp();
+
+    Ok(api_response)
+}
@@ -0,0 +1,59 @@
+use actix_web::{HttpResponse, web};
+use chrono::Utc;
+use sqlx::PgPool;
+
+use crate::api_response::{e400, e500, ApiResponse, e403};
+use crate::authentication::api_keys::{crear_api_key, ApiKey, NuevaApiKey};
+use crate::authentication::current_user::CurrentUser;
+
+
+#[derive(Debug, serde::Serialize)]
+pub struct ApiKeyCreada {
+    #[serde(flatten)]
+    pub api_key: ApiKey,
+    // Valor completo, no se vuelve a mostrar
+    pub key: String,
+}
+
+
+#[tracing::instrument(
+    name = "Crear API key",
+    skip(usuario, pool)
+)]
+pub async fn api_key_post(
+    usuario: CurrentUser,
+    pool: web::Data<PgPool>,
+    body: web::Json<NuevaApiKey>,
+) -> Result<HttpResponse, actix_web::Error> {
+
+    if !usuario.es_admin() {
+        return Err(e403().with_message("No tienes los permisos requeridos"))?;
+    }
+
+    let nueva = body.into_inner();
+
+    if nueva.nombre.trim().is_empty() {
+        return Err(e400().with_message("El nombre de la API key es obligatorio"))?;
+    }
+    if nueva.scopes.is_empty() {
+        return Err(e400().with_message("La API key necesita al menos un permiso"))?;
+    }
+    if let Some(scope) = nueva.scope_invalido() {
+        return Err(e400().with_message(format!("Permiso invalido {}", scope)))?;
+    }
+    if matches!(nueva.expira_en, Some(expira_en) if expira_en <= Utc::now().naive_utc()) {
+        return Err(e400().with_message("La fecha de expiracion ya paso"))?;
+    }
+
+    // Query insertar DB
+    let (api_key, key) = crear_api_key(&pool, &usuario.usuario_id, &nueva).await
+        .map_err(|_| e500())?;
+
+    // Respuesta exitosa
+    let api_response = ApiResponse::<ApiKeyCreada>::new()
+        .with_message("Nueva API key, guardala porque no se vuelve a mostrar")
+        .with_data(ApiKeyCreada { api_key, key })
+        .to_resp();
+
+    Ok(api_response)
+}
@@ -0,0 +1,34 @@
+use actix_web::{HttpResponse, web};
+use sqlx::PgPool;
+
+use crate::api_response::{e500, ApiResponse, e403};
+use crate::audit::{listar_auditoria, FiltroAuditoria, RegistroAuditoria};
+use crate::authentication::current_user::CurrentUser;
+
+
+#[tracing::instrument(
+    name = "Obtener auditoria",
+    skip(usuario, pool)
+)]
+pub async fn audit_get(
+    usuario: CurrentUser,
+    pool: web::Data<PgPool>,
+    filtro: web::Query<FiltroAuditoria>,
+) -> Result<HttpResponse, actix_web::Error> {
+
+    if !usuario.es_admin() {
+        return Err(e403().with_message("No tienes los permisos requeridos"))?;
+    }
+
+    // Query DB
+    let registros = listar_auditoria(&pool, &filtro).await
+        .map_err(|_| e500())?;
+
+    // Respuesta exitosa
+    let api_response = ApiResponse::<Vec<RegistroAuditoria>>::new()
+        .with_message("Registros de auditoria")
+        .with_data(registros)
+        .to_resp();
+
+    Ok(api_response)
+}
@@ -0,0 +1,136 @@
+use actix_web::{HttpResponse, web};
+use anyhow::Context;
+use secrecy::{ExposeSecret, Secret};
+use sqlx::PgPool;
+use validator::Validate;
+
+use crate::api_response::{e400, e404, e409, e500, ApiResponse};
+use crate::authentication::password::compute_password_hash;
+use crate::configuration::PasswordHashingSettings;
+use crate::authentication::password_policy::{error_password, PasswordPolicy};
+use crate::routes::invitations::sqlx::{
+    aceptar_invitacion_sqlx, existe_usuario_o_invitacion_sqlx, insertar_usuario_invitado_sqlx,
+    obtener_invitacion_por_token_sqlx, Invitacion,
+};
+use crate::telemetry::spawn_blocking_with_tracing;
+
+
+#[derive(serde::Deserialize)]
+pub struct Parameters {
+    token: String,
+}
+
+#[derive(serde::Deserialize, Validate)]
+pub struct AceptarInvitacion {
+    pub token: String,
+    #[validate(length(min = 1))]
+    pub nombres: String,
+    #[validate(length(min = 1))]
+    pub apellidos: String,
+    #[validate(length(min = 8))]
+    pub password: String,
+}
+
+
+/// Datos de la invitacion para mostrarlos antes de crear la contraseña
+#[tracing::instrument(
+    name = "Obtener invitacion",
+    skip_all
+)]
+pub async fn get_invitation(
+    parameters: web::Query<Parameters>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+
+    let invitacion = obtener_invitacion_por_token_sqlx(&pool, &parameters.token).await
+        .map_err(|_| e500())?
+        .ok_or(e404().with_message("Invitacion invalida o expirada"))?;
+
+    // Respuesta exitosa
+    let api_response = ApiResponse::<Invitacion>::new()
+        .with_message("Invitacion pendiente")
+        .with_data(invitacion)
+        .to_resp();
+
+    Ok(api_response)
+}
+
+
+#[tracing::instrument(
+    name = "Aceptar invitacion",
+    skip_all,
+    fields(user_id=tracing::field::Empty)
+)]
+pub async fn accept_invitation(
+    pool: web::Data<PgPool>,
+    body: web::Json<AceptarInvitacion>,
+    password_policy: web::Data<PasswordPolicy>,
+    hashing: web::Data<PasswordHashingSettings>,
+) -> Result<HttpResponse, actix_web::Error> {
+
+    let aceptar = body.into_inner();
+    if let Err(val_errors) = aceptar.validate() {
+        return Err(e400().with_message(format!("{:?}", val_errors)))?;
+    }
+
+    let invitacion = obtener_invitacion_por_token_sqlx(&pool, &aceptar.token).await
+        .map_err(|_| e500())?
+        .ok_or(e404().with_message("Invitacion invalida o expirada"))?;
+
+    let violaciones = password_policy.validar(
+        &aceptar.password,
+        &[&invitacion.email, &aceptar.nombres, &aceptar.apellidos],
+    );
+    if !violaciones.is_empty() {
+        return Err(error_password(violaciones))?;
+    }
+
+    let password = Secret::new(aceptar.password);
+    let hashing = hashing.get_ref().clone();
+    let password_hash = spawn_blocking_with_tracing(move || compute_password_hash(password, &hashing))
+        .await
+        .context("Failed to spawn blocking task")
+        .and_then(|hash| hash)
+        .map_err(|_| e500())?;
+
+    let mut transaction = pool.begin()
+        .await
+        .map_err(|_| e500())?;
+
+    // El token deja de ser valido en cuanto se acepta
+    let (invitacion_id, email) = aceptar_invitacion_sqlx(&mut transaction, &aceptar.token).await
+        .map_err(|_| e500())?
+        .ok_or(e404().with_message("Invitacion invalida o expirada"))?;
+
+    // La cuenta se pudo crear por otro medio despues de la invitacion
+    let (existe_usuario, _) = existe_usuario_o_invitacion_sqlx(&pool, &email).await
+        .map_err(|_| e500())?;
+    if existe_usuario {
+        return Err(e409().with_message("Ya existe usuario con ese correo electronico"))?;
+    }
+
+    let usuario_id = insertar_usuario_invitado_sqlx(
+            &mut transaction,
+            &invitacion_id,
+            aceptar.nombres.trim(),
+            aceptar.apellidos.trim(),
+            password_hash.expose_secret(),
+        )
+        .await
+        .map_err(|_| e500())?;
+
+    transaction.commit()
+        .await
+        .map_err(|_| e500())?;
+
+    tracing::Span::current()
+        .record("user_id", &tracing::field::display(&usuario_id));
+
+    // Respuesta exitosa
+    let api_response = ApiResponse::<()>::new()
+        .with_status_code(201)
+        .with_message("Usuario creado, ya puedes iniciar sesion")
+        .to_resp();
+
+    Ok(api_response)
+}
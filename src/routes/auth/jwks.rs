@@ -0,0 +1,18 @@
+use actix_web::{HttpResponse, web};
+
+use crate::authentication::key_ring::KeyRing;
+
+
+/// Publica las llaves asimetricas en formato JWKS (RFC 7517),
+/// las llaves HMAC nunca se exponen
+#[tracing::instrument(
+    name = "Get JWKS",
+    skip_all,
+)]
+pub async fn get_jwks(
+    key_ring: web::Data<KeyRing>,
+) -> Result<HttpResponse, actix_web::Error> {
+    Ok(HttpResponse::Ok()
+        .insert_header(("Cache-Control", "public, max-age=300"))
+        .json(key_ring.jwks()))
+}
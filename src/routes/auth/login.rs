@@ -1,9 +1,19 @@
-use actix_web::{HttpResponse, web};
+use actix_web::{HttpResponse, HttpRequest, web};
+use anyhow::Context;
 use sqlx::PgPool;
+use uuid::Uuid;
 
-use crate::api_response::{ApiResponse, e500, e401};
-use crate::authentication::jwt_session::{create_jwt, HmacKey};
-use crate::authentication::{Credentials, validate_credentials, AuthError};
+use crate::api_response::{ApiResponse, e500, e401, e403, e429, e503};
+use crate::authentication::key_ring::KeyRing;
+use crate::authentication::sessions::{crear_sesion, InfoSesion};
+use crate::authentication::{Credentials, DummyPasswordHash, validate_credentials, AuthError};
+use crate::authentication::ldap::validate_ldap_credentials;
+use crate::authentication::login_throttle::{verificar_limites, registrar_fallo, registrar_exito, ThrottleError};
+use crate::authentication::totp::{tiene_totp_activo, crear_desafio};
+use crate::configuration::{LdapSettings, LoginThrottleSettings, PasswordHashingSettings, TwoFactorSettings};
+use crate::routes::users::sqlx::obtener_usuario_por_id_sqlx;
+use crate::email_client::EmailClient;
+use crate::redis_pool::{RedisConnection, RedisPool};
 
 
 #[tracing::instrument(
@@ -14,44 +24,284 @@ use crate::authentication::{Credentials, validate_credentials, AuthError};
 pub async fn login_user(
     pool: web::Data<PgPool>,
     body: web::Json<Credentials>,
-    key:  web::Data<HmacKey>,
+    key_ring: web::Data<KeyRing>,
+    redis_pool: web::Data<RedisPool>,
+    throttle_settings: web::Data<LoginThrottleSettings>,
+    email_client: web::Data<EmailClient>,
+    two_factor: web::Data<TwoFactorSettings>,
+    ldap: web::Data<LdapSettings>,
+    hashing: web::Data<PasswordHashingSettings>,
+    dummy_hash: web::Data<DummyPasswordHash>,
+    req: HttpRequest,
 ) -> Result<HttpResponse, actix_web::Error> {
 
     // Convertir json a credentials
     let credentials = body.0;
+    let email = credentials.email.clone();
+    let dispositivo = credentials.dispositivo.clone();
 
     // Log email
     tracing::Span::current()
         .record("email", &tracing::field::display(&credentials.email));
 
-    // Validar credenciales 
-    match validate_credentials(credentials, &pool).await {
+    // Verificar limites de intentos por email e ip
+    let ip = req.connection_info()
+        .realip_remote_addr()
+        .unwrap_or("desconocida")
+        .to_string();
+
+    // Sin Redis y con fail open se omiten los limites de intentos. Un comando que
+    // no responde a tiempo sigue la misma politica que una conexion fallida
+    let mut redis_con = redis_pool.get_segun_politica().await
+        .map_err(|_| e503())?;
+
+    if let Some(con) = redis_con.as_mut() {
+        match verificar_limites(&redis_pool, con, &throttle_settings, &email, &ip).await {
+            Ok(()) => {},
+            Err(ThrottleError::Locked(segundos)) => {
+                return Err(e429()
+                    .with_message(format!("Cuenta bloqueada temporalmente, intenta de nuevo en {} segundos", segundos)))?;
+            },
+            Err(ThrottleError::TooManyAttempts) => {
+                return Err(e429().with_message("Demasiados intentos de inicio de sesion, intenta mas tarde"))?;
+            },
+            Err(ThrottleError::UnexpectedError(e)) if redis_pool.fail_open() => {
+                tracing::warn!("No se pudieron verificar los limites de intentos: {:?}", e);
+            },
+            Err(ThrottleError::UnexpectedError(_)) => return Err(e503())?,
+        }
+    }
+
+    // Validar credenciales, los dominios del directorio se validan con LDAP
+    let resultado = if ldap.applies_to(&email) {
+        validate_ldap_credentials(credentials, &pool, &ldap, &hashing).await
+    } else {
+        validate_credentials(credentials, &pool, &hashing, &dummy_hash).await
+    };
+
+    match resultado {
         Ok(user_id) => {
             tracing::Span::current()
                 .record("usuario_id", &tracing::field::display(&user_id));
-            // Generar jwt
-            let token: String = match create_jwt(&user_id, &key) {
-                Ok(token) => token,
-                Err(e) => {
-                   tracing::error!("No se pudo crear el JWT {}", e);
-                   return Err(e500())?;
-                }
-            };
 
-            // Respuesta exitosa
-            let api_response = ApiResponse::<String>::new()
-               .with_message("Token creaado")
-               .with_data(token)
-               .to_resp();
+            // Las credenciales son validas, pero la cuenta puede no estar aprobada
+            verificar_aprobacion(&pool, &user_id).await?;
+
+            let desafio = desafio_segundo_factor(
+                &pool,
+                &redis_pool,
+                redis_con.as_mut(),
+                &two_factor,
+                &user_id,
+                &email,
+                dispositivo.clone(),
+            ).await?;
+            if let Some(api_response) = desafio {
+                return Ok(api_response);
+            }
 
-            Ok(api_response)
+            if let Some(con) = redis_con.as_mut() {
+                if let Err(e) = registrar_exito(&redis_pool, con, &email).await {
+                    if !redis_pool.fail_open() {
+                        return Err(e503())?;
+                    }
+                    tracing::warn!("No se pudieron reiniciar los intentos fallidos: {:?}", e);
+                }
+            }
+
+            respuesta_token(&pool, &user_id, &InfoSesion::from_request(&req, dispositivo), &key_ring).await
         },
         Err(e) => {
             let api_response =  match e {
-                AuthError::InvalidCredentials(_) => e401().with_message("credenciales invalidas"),
+                AuthError::InvalidCredentials(_) => {
+                    let bloqueo = match redis_con.as_mut() {
+                        Some(con) => match registrar_fallo(&redis_pool, con, &throttle_settings, &email).await {
+                            Ok(bloqueo) => bloqueo,
+                            Err(e) if redis_pool.fail_open() => {
+                                tracing::warn!("No se pudo registrar el intento fallido: {:?}", e);
+                                None
+                            },
+                            Err(_) => return Err(e503())?,
+                        },
+                        None => None,
+                    };
+
+                    if let Some(segundos) = bloqueo {
+                        tracing::warn!("Se bloqueo el inicio de sesion por {} segundos", segundos);
+                        // El bloqueo ya esta activo aunque el correo no se pueda enviar
+                        if let Err(e) = notificar_bloqueo(&pool, &email_client, &email, segundos).await {
+                            tracing::error!("No se pudo notificar el bloqueo: {:?}", e);
+                        }
+                    }
+
+                    e401().with_message("credenciales invalidas")
+                },
                 AuthError::UnexpectedError(_) => e500(),
             };
             Err(api_response)?
         }
     }
 }
+
+
+/// Respuesta de un inicio de sesion que espera el segundo factor
+#[derive(Debug, serde::Serialize)]
+pub struct RespuestaDesafio {
+    pub desafio: String,
+    pub requiere_inscripcion: bool,
+}
+
+/// Con segundo factor activo, o requerido para administradores, el token se
+/// entrega hasta verificar el codigo. Regresa la respuesta con el desafio.
+pub async fn desafio_segundo_factor(
+    pool: &PgPool,
+    redis: &RedisPool,
+    redis_con: Option<&mut RedisConnection>,
+    two_factor: &TwoFactorSettings,
+    user_id: &Uuid,
+    email: &str,
+    dispositivo: Option<String>,
+) -> Result<Option<HttpResponse>, actix_web::Error> {
+    let tiene_totp = tiene_totp_activo(pool, user_id).await
+        .map_err(|_| e500())?;
+
+    let requiere_inscripcion = if tiene_totp {
+        false
+    } else if two_factor.require_for_admins {
+        let usuario = obtener_usuario_por_id_sqlx(pool, user_id).await
+            .map_err(|_| e500())?
+            .ok_or(e500())?;
+        if !usuario.es_admin() {
+            return Ok(None);
+        }
+        true
+    } else {
+        return Ok(None);
+    };
+
+    // El segundo factor nunca se omite, sin Redis no hay donde guardar el desafio
+    let redis_con = redis_con.ok_or(e503())?;
+    let desafio = crear_desafio(redis, redis_con, user_id, email, dispositivo, requiere_inscripcion).await
+        .map_err(|_| e503())?;
+
+    let api_response = if requiere_inscripcion {
+        ApiResponse::<RespuestaDesafio>::new()
+            .with_status_code(403)
+            .with_status("fail")
+            .with_message("Los administradores deben activar el segundo factor")
+            .with_data(RespuestaDesafio { desafio, requiere_inscripcion })
+            .to_resp()
+    } else {
+        ApiResponse::<RespuestaDesafio>::new()
+            .with_status_code(202)
+            .with_message("Se requiere el codigo del segundo factor")
+            .with_data(RespuestaDesafio { desafio, requiere_inscripcion })
+            .to_resp()
+    };
+
+    Ok(Some(api_response))
+}
+
+/// Solo las cuentas aprobadas por un administrador y no desactivadas pueden iniciar sesion
+#[tracing::instrument(
+    name = "Verificar aprobacion de cuenta",
+    skip(pool)
+)]
+pub async fn verificar_aprobacion(
+    pool: &PgPool,
+    user_id: &Uuid,
+) -> Result<(), actix_web::Error> {
+    let row = sqlx::query!(
+        r#"
+        SELECT aprobacion::text as "aprobacion!", desactivado_en IS NOT NULL as "desactivado!"
+        FROM usuarios
+        WHERE usuario_id = $1
+        "#,
+        user_id,
+    )
+    .fetch_one(pool)
+    .await
+    .map_err(|_| e500())?;
+
+    if row.desactivado {
+        return Err(e403().with_message("Tu cuenta esta desactivada"))?;
+    }
+
+    match row.aprobacion.as_str() {
+        "aprobado" => Ok(()),
+        "pendiente" => Err(e403().with_message("Tu cuenta esta pendiente de aprobacion"))?,
+        _ => Err(e403().with_message("Tu cuenta fue rechazada"))?,
+    }
+}
+
+
+/// Genera el jwt del usuario y la respuesta de inicio de sesion exitoso
+pub async fn respuesta_token(
+    pool: &PgPool,
+    user_id: &Uuid,
+    info: &InfoSesion,
+    key_ring: &KeyRing,
+) -> Result<HttpResponse, actix_web::Error> {
+    // Registrar sesion y generar jwt
+    let token: String = match crear_sesion(pool, user_id, info, key_ring).await {
+        Ok(token) => token,
+        Err(e) => {
+           tracing::error!("No se pudo crear el JWT {}", e);
+           return Err(e500())?;
+        }
+    };
+
+    // Respuesta exitosa
+    let api_response = ApiResponse::<String>::new()
+       .with_message("Token creaado")
+       .with_data(token)
+       .to_resp();
+
+    Ok(api_response)
+}
+
+
+#[tracing::instrument(
+    name = "Notificar bloqueo de cuenta",
+    skip(pool, email_client, email)
+)]
+async fn notificar_bloqueo(
+    pool: &PgPool,
+    email_client: &EmailClient,
+    email: &str,
+    segundos: u64,
+) -> Result<(), anyhow::Error> {
+    // Solo se notifica a cuentas existentes
+    let row = sqlx::query!(
+        r#"
+        SELECT EXISTS(
+            SELECT usuario_id FROM usuarios
+            WHERE email = $1
+        )
+        "#,
+        email
+    )
+    .fetch_one(pool)
+    .await
+    .context("Failed to performed a query to check if user exists")?;
+
+    if !row.exists.unwrap_or(false) {
+        return Ok(());
+    }
+
+    let minutos = (segundos + 59) / 60;
+
+    email_client.send_email(
+        email,
+        "Cuenta bloqueada temporalmente",
+        &format!("Detectamos varios intentos fallidos de inicio de sesion en tu cuenta.<br />\
+                 Por seguridad se bloqueo el inicio de sesion durante {} minutos.<br />\
+                 Si no fuiste tu, contacta al administrador.",
+                 minutos),
+        &format!("Detectamos varios intentos fallidos de inicio de sesion en tu cuenta.\n\
+                 Por seguridad se bloqueo el inicio de sesion durante {} minutos.\n\
+                 Si no fuiste tu, contacta al administrador.",
+                 minutos),
+    )
+    .await
+}
@@ -1,6 +1,8 @@
-use actix_web::HttpResponse;
+use actix_web::{HttpResponse, web};
+use sqlx::PgPool;
 
 use crate::authentication::jwt_session::JwtSession;
+use crate::authentication::sessions::revocar_sesion;
 use crate::api_response::{ApiResponse, e500};
 
 
@@ -10,8 +12,14 @@ use crate::api_response::{ApiResponse, e500};
 )]
 pub async fn logout_user(
     session: JwtSession,
+    pool: web::Data<PgPool>,
 ) -> Result<HttpResponse, actix_web::Error> {
-    match session.blacklist_session() {
+    let mut redis_con = session.redis.get().await
+        .map_err(|_| e500())?;
+    revocar_sesion(&pool, &session.redis, &mut redis_con, &session.user_id, &session.session_id).await
+        .map_err(|_| e500())?;
+
+    match session.blacklist_session().await {
         Ok(_) => {
             Ok(ApiResponse::<()>::new().with_message("You have logout").to_resp())
         },
@@ -2,3 +2,7 @@ pub mod login;
 pub mod logout;
 pub mod register;
 pub mod signup_confirm;
+pub mod two_factor;
+pub mod jwks;
+pub mod oidc;
+pub mod invitation;
@@ -0,0 +1,139 @@
+use actix_web::{HttpResponse, HttpRequest, web};
+use sqlx::PgPool;
+
+use crate::api_response::{ApiResponse, e401, e404, e409, e500, e503};
+use crate::authentication::key_ring::KeyRing;
+use crate::authentication::oidc::{
+    completar_autorizacion, iniciar_autorizacion, usuario_de_identidad, OidcError, ProveedoresOidc,
+};
+use crate::authentication::sessions::InfoSesion;
+use crate::configuration::TwoFactorSettings;
+use crate::redis_pool::RedisPool;
+use crate::routes::users::sqlx::obtener_usuario_por_id_sqlx;
+
+use super::login::{desafio_segundo_factor, respuesta_token, verificar_aprobacion};
+
+
+#[derive(Debug, serde::Deserialize)]
+pub struct QueryAutorizacion {
+    // Nombre opcional del dispositivo para identificar la sesion
+    pub dispositivo: Option<String>,
+}
+
+#[derive(Debug, serde::Deserialize)]
+pub struct CodigoAutorizacion {
+    pub code: String,
+    pub state: String,
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct UrlAutorizacion {
+    pub url: String,
+}
+
+
+pub fn error_oidc(e: OidcError) -> actix_web::Error {
+    let api_response = match e {
+        OidcError::UnknownProvider => e404().with_message("No existe el proveedor"),
+        OidcError::ProviderUnavailable(_) => e503(),
+        OidcError::InvalidState => e401().with_message("Estado invalido o expirado"),
+        OidcError::InvalidToken(_) => e401().with_message("No se pudo verificar la identidad"),
+        OidcError::NotLinked => e401().with_message("No hay una cuenta vinculada a esta identidad"),
+        OidcError::AlreadyLinked => e409().with_message("La identidad ya esta vinculada a otra cuenta"),
+        OidcError::UnexpectedError(_) => e500(),
+    };
+    api_response.into()
+}
+
+
+#[tracing::instrument(
+    name = "Iniciar login OIDC",
+    skip(proveedores, redis_pool)
+)]
+pub async fn oidc_authorize(
+    proveedores: web::Data<ProveedoresOidc>,
+    redis_pool: web::Data<RedisPool>,
+    proveedor: web::Path<String>,
+    query: web::Query<QueryAutorizacion>,
+) -> Result<HttpResponse, actix_web::Error> {
+
+    let mut redis_con = redis_pool.get().await
+        .map_err(|_| e503())?;
+
+    let url = iniciar_autorizacion(&proveedores, &redis_pool, &mut redis_con, &proveedor, None, query.into_inner().dispositivo).await
+        .map_err(|e| {
+            tracing::error!("No se pudo iniciar la autorizacion {:?}", e);
+            error_oidc(e)
+        })?;
+
+    // Respuesta exitosa
+    let api_response = ApiResponse::<UrlAutorizacion>::new()
+        .with_message("Redirige al usuario al proveedor")
+        .with_data(UrlAutorizacion { url })
+        .to_resp();
+
+    Ok(api_response)
+}
+
+
+/// Completa el login con el proveedor y entrega el mismo jwt que
+/// `login_user`, con el mismo desafio si el usuario requiere segundo factor
+#[tracing::instrument(
+    name = "Completar login OIDC",
+    skip(pool, proveedores, redis_pool, key_ring, two_factor, body, req),
+    fields(user_id=tracing::field::Empty)
+)]
+pub async fn oidc_callback(
+    pool: web::Data<PgPool>,
+    proveedores: web::Data<ProveedoresOidc>,
+    redis_pool: web::Data<RedisPool>,
+    key_ring: web::Data<KeyRing>,
+    two_factor: web::Data<TwoFactorSettings>,
+    proveedor: web::Path<String>,
+    body: web::Json<CodigoAutorizacion>,
+    req: HttpRequest,
+) -> Result<HttpResponse, actix_web::Error> {
+
+    // El state se guarda en Redis, ni con fail open se acepta un callback sin validarlo
+    let mut redis_con = redis_pool.get_segun_politica().await
+        .map_err(|_| e503())?
+        .ok_or(e503().with_message("No se puede validar el inicio de sesion con el proveedor"))?;
+
+    let autorizacion = completar_autorizacion(&proveedores, &redis_pool, &mut redis_con, &proveedor, &body.code, &body.state).await
+        .map_err(|e| {
+            tracing::warn!("No se pudo completar la autorizacion {:?}", e);
+            error_oidc(e)
+        })?;
+
+    // Un state para vincular no sirve para iniciar sesion
+    if autorizacion.vincular.is_some() {
+        return Err(error_oidc(OidcError::InvalidState));
+    }
+
+    let usuario_id = usuario_de_identidad(&pool, &autorizacion.identidad).await
+        .map_err(error_oidc)?;
+
+    tracing::Span::current()
+        .record("user_id", &tracing::field::display(&usuario_id));
+
+    verificar_aprobacion(&pool, &usuario_id).await?;
+
+    let usuario = obtener_usuario_por_id_sqlx(&pool, &usuario_id).await
+        .map_err(|_| e500())?
+        .ok_or(e500())?;
+    let desafio = desafio_segundo_factor(
+        &pool,
+        &redis_pool,
+        Some(&mut redis_con),
+        &two_factor,
+        &usuario_id,
+        &usuario.email,
+        autorizacion.dispositivo.clone(),
+    ).await?;
+    if let Some(api_response) = desafio {
+        return Ok(api_response);
+    }
+
+    let info = InfoSesion::from_request(&req, autorizacion.dispositivo);
+    respuesta_token(&pool, &usuario_id, &info, &key_ring).await
+}
@@ -1,15 +1,17 @@
-use crate::api_response::{e401, e500, ApiResponse, e409};
+use crate::api_response::{e401, e500, ApiResponse, e403, e409};
 use crate::email_client::EmailClient;
 use crate::authentication::password::compute_password_hash;
+use crate::authentication::password_policy::{error_password, PasswordPolicy};
 use crate::telemetry::spawn_blocking_with_tracing;
 use crate::startup::ApplicationBaseUrl;
+use crate::configuration::{PasswordHashingSettings, SignupSettings};
 
 use common::models::user::SignupUsuario;
 use actix_web::{HttpResponse, web};
 use anyhow::Context;
 use rand::{thread_rng, Rng, distributions::Alphanumeric};
 use sqlx::{PgPool, Transaction, Postgres};
-use secrecy::ExposeSecret;
+use secrecy::{ExposeSecret, Secret};
 use uuid::Uuid;
 use validator::Validate;
 
@@ -24,6 +26,9 @@ pub async fn signup_user(
     body: web::Json<SignupUsuario>,
     email_client: web::Data<EmailClient>,
     base_url: web::Data<ApplicationBaseUrl>,
+    signup_settings: web::Data<SignupSettings>,
+    password_policy: web::Data<PasswordPolicy>,
+    hashing: web::Data<PasswordHashingSettings>,
 ) -> Result<HttpResponse, actix_web::Error> {
 
     // Validar signup body
@@ -31,6 +36,18 @@ pub async fn signup_user(
     // Extraer los Error irregresarlos en un mensaje del api
     signup_usuario.validate().map_err(|_| e401().with_message("Invalid body"))?;
 
+    if !signup_settings.allows(&signup_usuario.email) {
+        return Err(e403().with_message("No se permite registrar cuentas con ese dominio de correo"))?;
+    }
+
+    let violaciones = password_policy.validar(
+        signup_usuario.password.expose_secret(),
+        &[&signup_usuario.email, &signup_usuario.nombres, &signup_usuario.apellidos],
+    );
+    if !violaciones.is_empty() {
+        return Err(error_password(violaciones))?;
+    }
+
 
     let mut transaction = pool.begin()
         .await
@@ -48,7 +65,7 @@ pub async fn signup_user(
     dbg!("intentando insertar usuario", &signup_usuario);
     // insert new user in database
     let usuario_email = signup_usuario.email.clone();
-    let usuario_id = insertar_usuario_sqlx(&mut transaction, signup_usuario)
+    let usuario_id = insertar_usuario_sqlx(&mut transaction, signup_usuario, signup_settings.require_approval, &hashing)
         .await
         .map_err(|_| e500())?;
 
@@ -71,11 +88,17 @@ pub async fn signup_user(
         .await
         .map_err(|_| e500())?;
 
+    let mensaje = if signup_settings.require_approval {
+        "Usuario creado, un administrador debe aprobar la cuenta"
+    } else {
+        "Usuario creado"
+    };
+
     // Maybe change Created to Accepted due to email not being sent
     Ok(
         ApiResponse::<()>::new()
             .with_status_code(201)
-            .with_message("Usuario creado")
+            .with_message(mensaje)
             .to_resp()
      )
 }
@@ -106,15 +129,19 @@ async fn existe_usuario_con_email(
 
 #[tracing::instrument(
     name = "insert new user",
-    skip(usuario, transaction)
+    skip(usuario, transaction, hashing)
 )]
 async fn insertar_usuario_sqlx(
     transaction: &mut Transaction<'_, Postgres>,
     usuario: SignupUsuario,
+    pendiente_aprobacion: bool,
+    hashing: &PasswordHashingSettings,
 ) -> Result<uuid::Uuid, anyhow::Error> {
 
+    let hashing = hashing.clone();
+    let password: Secret<String> = usuario.password.into();
     let password_hash = spawn_blocking_with_tracing(
-            move || compute_password_hash(usuario.password.into())
+            move || compute_password_hash(password, &hashing)
         )
         .await?
         .context("Failed to hash password")?;
@@ -123,8 +150,9 @@ async fn insertar_usuario_sqlx(
     let row = sqlx::query!(
         r#"
         INSERT INTO usuarios
-        (usuario_id, nombres, apellidos, email, password_hash)
-        VALUES ($1, $2, $3, $4, $5)
+        (usuario_id, nombres, apellidos, email, password_hash, aprobacion)
+        VALUES ($1, $2, $3, $4, $5,
+            CASE WHEN $6 THEN 'pendiente'::usuario_aprobacion ELSE 'aprobado'::usuario_aprobacion END)
         RETURNING usuario_id
         "#,
         uuid,
@@ -132,6 +160,7 @@ async fn insertar_usuario_sqlx(
         usuario.apellidos,
         usuario.email,
         password_hash.expose_secret(),
+        pendiente_aprobacion,
     )
     .fetch_one(transaction)
     .await
@@ -0,0 +1,209 @@
+use actix_web::{HttpResponse, HttpRequest, web};
+use sqlx::PgPool;
+
+use crate::api_response::{ApiResponse, e400, e401, e409, e429, e500, e503};
+use crate::authentication::key_ring::KeyRing;
+use crate::authentication::sessions::{crear_sesion, InfoSesion};
+use crate::authentication::login_throttle::{verificar_limites, registrar_fallo, registrar_exito, ThrottleError};
+use crate::authentication::totp::{
+    confirmar_inscripcion, consumir_desafio, iniciar_inscripcion, obtener_desafio,
+    verificar_segundo_factor, InscripcionTotp, TotpError,
+};
+use crate::configuration::{LoginThrottleSettings, TwoFactorSettings};
+use crate::redis_pool::RedisPool;
+
+use super::login::respuesta_token;
+
+
+#[derive(Debug, serde::Deserialize)]
+pub struct Desafio {
+    pub desafio: String,
+}
+
+#[derive(Debug, serde::Deserialize)]
+pub struct CodigoDesafio {
+    pub desafio: String,
+    pub codigo: String,
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct TokenConCodigos {
+    pub token: String,
+    pub codigos_recuperacion: Vec<String>,
+}
+
+
+fn ip_de_peticion(req: &HttpRequest) -> String {
+    req.connection_info()
+        .realip_remote_addr()
+        .unwrap_or("desconocida")
+        .to_string()
+}
+
+fn error_de_limites(e: ThrottleError) -> actix_web::Error {
+    let api_response = match e {
+        ThrottleError::Locked(segundos) => e429()
+            .with_message(format!("Cuenta bloqueada temporalmente, intenta de nuevo en {} segundos", segundos)),
+        ThrottleError::TooManyAttempts => e429()
+            .with_message("Demasiados intentos de inicio de sesion, intenta mas tarde"),
+        // Igual que cuando no hay conexion a Redis
+        ThrottleError::UnexpectedError(_) => e503(),
+    };
+    api_response.into()
+}
+
+
+#[tracing::instrument(
+    name = "Login segundo factor",
+    skip_all,
+    fields(user_id=tracing::field::Empty)
+)]
+pub async fn login_second_factor(
+    pool: web::Data<PgPool>,
+    body: web::Json<CodigoDesafio>,
+    key_ring: web::Data<KeyRing>,
+    redis_pool: web::Data<RedisPool>,
+    throttle_settings: web::Data<LoginThrottleSettings>,
+    two_factor: web::Data<TwoFactorSettings>,
+    req: HttpRequest,
+) -> Result<HttpResponse, actix_web::Error> {
+
+    let mut redis_con = redis_pool.get().await
+        .map_err(|_| e503())?;
+
+    // Cada desafio admite un solo intento
+    let desafio = consumir_desafio(&redis_pool, &mut redis_con, &body.desafio).await
+        .map_err(|_| e503())?
+        .ok_or(e401().with_message("Desafio invalido o expirado"))?;
+
+    if desafio.inscripcion {
+        return Err(e400().with_message("Debes activar el segundo factor antes de iniciar sesion"))?;
+    }
+
+    tracing::Span::current()
+        .record("user_id", &tracing::field::display(&desafio.usuario_id));
+
+    verificar_limites(&redis_pool, &mut redis_con, &throttle_settings, &desafio.email, &ip_de_peticion(&req)).await
+        .map_err(error_de_limites)?;
+
+    match verificar_segundo_factor(&pool, &desafio.usuario_id, &two_factor.issuer, &desafio.email, &body.codigo).await {
+        Ok(()) => {
+            registrar_exito(&redis_pool, &mut redis_con, &desafio.email).await
+                .map_err(|_| e503())?;
+
+            let info = InfoSesion::from_request(&req, desafio.dispositivo);
+            respuesta_token(&pool, &desafio.usuario_id, &info, &key_ring).await
+        },
+        Err(TotpError::InvalidCode) => {
+            let bloqueo = registrar_fallo(&redis_pool, &mut redis_con, &throttle_settings, &desafio.email).await
+                .map_err(|_| e503())?;
+
+            if let Some(segundos) = bloqueo {
+                tracing::warn!("Se bloqueo el inicio de sesion por {} segundos", segundos);
+            }
+
+            Err(e401().with_message("Codigo invalido"))?
+        },
+        Err(TotpError::NotEnrolled) => Err(e401().with_message("Desafio invalido o expirado"))?,
+        Err(_) => Err(e500())?,
+    }
+}
+
+
+#[tracing::instrument(
+    name = "Iniciar inscripcion de segundo factor con desafio",
+    skip_all
+)]
+pub async fn setup_from_challenge(
+    pool: web::Data<PgPool>,
+    body: web::Json<Desafio>,
+    redis_pool: web::Data<RedisPool>,
+    two_factor: web::Data<TwoFactorSettings>,
+) -> Result<HttpResponse, actix_web::Error> {
+
+    let mut redis_con = redis_pool.get().await
+        .map_err(|_| e503())?;
+
+    let desafio = obtener_desafio(&redis_pool, &mut redis_con, &body.desafio).await
+        .map_err(|_| e503())?
+        .filter(|desafio| desafio.inscripcion)
+        .ok_or(e401().with_message("Desafio invalido o expirado"))?;
+
+    let inscripcion = iniciar_inscripcion(&pool, &desafio.usuario_id, &two_factor.issuer, &desafio.email).await
+        .map_err(|e| match e {
+            TotpError::AlreadyEnabled => e409().with_message("El segundo factor ya esta activo"),
+            _ => e500(),
+        })?;
+
+    // Respuesta exitosa
+    let api_response = ApiResponse::<InscripcionTotp>::new()
+        .with_message("Escanea el codigo QR y confirma con un codigo")
+        .with_data(inscripcion)
+        .to_resp();
+
+    Ok(api_response)
+}
+
+
+#[tracing::instrument(
+    name = "Confirmar inscripcion de segundo factor con desafio",
+    skip_all,
+    fields(user_id=tracing::field::Empty)
+)]
+pub async fn confirm_setup_from_challenge(
+    pool: web::Data<PgPool>,
+    body: web::Json<CodigoDesafio>,
+    key_ring: web::Data<KeyRing>,
+    redis_pool: web::Data<RedisPool>,
+    throttle_settings: web::Data<LoginThrottleSettings>,
+    two_factor: web::Data<TwoFactorSettings>,
+    req: HttpRequest,
+) -> Result<HttpResponse, actix_web::Error> {
+
+    let mut redis_con = redis_pool.get().await
+        .map_err(|_| e503())?;
+
+    let desafio = obtener_desafio(&redis_pool, &mut redis_con, &body.desafio).await
+        .map_err(|_| e503())?
+        .filter(|desafio| desafio.inscripcion)
+        .ok_or(e401().with_message("Desafio invalido o expirado"))?;
+
+    tracing::Span::current()
+        .record("user_id", &tracing::field::display(&desafio.usuario_id));
+
+    verificar_limites(&redis_pool, &mut redis_con, &throttle_settings, &desafio.email, &ip_de_peticion(&req)).await
+        .map_err(error_de_limites)?;
+
+    let codigos_recuperacion = match confirmar_inscripcion(&pool, &desafio.usuario_id, &two_factor.issuer, &desafio.email, &body.codigo).await {
+        Ok(codigos) => codigos,
+        Err(TotpError::InvalidCode) => {
+            registrar_fallo(&redis_pool, &mut redis_con, &throttle_settings, &desafio.email).await
+                .map_err(|_| e503())?;
+            return Err(e401().with_message("Codigo invalido"))?;
+        },
+        Err(TotpError::NotEnrolled) => return Err(e400().with_message("No hay una inscripcion pendiente"))?,
+        Err(TotpError::AlreadyEnabled) => return Err(e409().with_message("El segundo factor ya esta activo"))?,
+        Err(TotpError::UnexpectedError(_)) => return Err(e500())?,
+    };
+
+    // La inscripcion completa el inicio de sesion
+    consumir_desafio(&redis_pool, &mut redis_con, &body.desafio).await
+        .map_err(|_| e503())?;
+    registrar_exito(&redis_pool, &mut redis_con, &desafio.email).await
+        .map_err(|_| e503())?;
+
+    let info = InfoSesion::from_request(&req, desafio.dispositivo);
+    let token = crear_sesion(&pool, &desafio.usuario_id, &info, &key_ring).await
+        .map_err(|e| {
+            tracing::error!("No se pudo crear el JWT {}", e);
+            e500()
+        })?;
+
+    // Respuesta exitosa
+    let api_response = ApiResponse::<TokenConCodigos>::new()
+        .with_message("Segundo factor activado, guarda tus codigos de recuperacion")
+        .with_data(TokenConCodigos { token, codigos_recuperacion })
+        .to_resp();
+
+    Ok(api_response)
+}
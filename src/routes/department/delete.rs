@@ -1,73 +1,148 @@
 use actix_web::{HttpResponse, web};
 use sqlx::PgPool;
-use anyhow::Context;
+use uuid::Uuid;
 
-use crate::authentication::jwt_session::JwtSession;
-use crate::api_response::{e500, ApiResponse, e403, e404};
+use crate::authentication::current_user::{CurrentUser, invalidar_cache_usuario};
+use crate::api_response::{e500, ApiResponse, e400, e403, e404, e409};
+use super::sqlx::{
+    borrar_departamento_sqlx, listar_ids_administradores_sqlx, quitar_administrador_sqlx, quitar_jefe_sqlx,
+    DestinoBorrado, Reasignacion, ResultadoBorrado,
+};
 
 //use super::get::department_get_with_id;
-use crate::routes::users::sqlx::obtener_usuario_por_id_sqlx;
 
 
 #[tracing::instrument(
-    name = "Query borrar departamento",
-    skip_all
+    name = "Borrar departamento por id",
+    skip(usuario, pool)
 )]
-async fn borrar_departamento_por_nombre_sqlx(
+pub async fn delete_department(
+    usuario: CurrentUser,
+    pool: web::Data<PgPool>,
+    id: web::Path<i32>,
+    query: web::Query<DestinoBorrado>,
+) -> Result<HttpResponse, actix_web::Error> {
+
+    if !usuario.es_admin() {
+        return Err(e403().with_message("No tienes los permisos requeridos"))?;
+    }
+
+    let id = id.into_inner();
+    let reasignacion = reasignar_y_borrar(&usuario, &pool, id, query.destino, false).await?;
+
+    // Respuesta exitosa
+    let api_response = ApiResponse::<Reasignacion>::new()
+        .with_message("Departament eliminado")
+        .with_data(reasignacion)
+        .to_resp();
+
+    Ok(api_response)
+}
+
+
+/// Borra el departamento y traduce el resultado a errores de la API
+pub async fn reasignar_y_borrar(
+    usuario: &CurrentUser,
     pool: &PgPool,
     id: i32,
-) -> Result<bool, anyhow::Error> {
+    destino: Option<i32>,
+    mover_jefes: bool,
+) -> Result<Reasignacion, actix_web::Error> {
 
-    let query = sqlx::query!(
-        r#"
-        DELETE FROM departamentos
-        WHERE id = $1
-        "#,
-        id)
-        .execute(pool)
-        .await
-        .context("Failed to execute query")?;
+    if destino == Some(id) {
+        return Err(e400().with_message("El destino debe ser otro departamento"))?;
+    }
 
+    // Antes de borrar, el borrado quita a los administradores del departamento
+    let administradores = listar_ids_administradores_sqlx(pool).await
+        .map_err(|_| e500())?;
+    let resultado = borrar_departamento_sqlx(pool, id, destino, mover_jefes).await
+        .map_err(|_| e500())?;
 
-    Ok(query.rows_affected() != 0)
+    let reasignacion = match resultado {
+        ResultadoBorrado::Borrado(reasignacion) => reasignacion,
+        ResultadoBorrado::NoEncontrado => {
+            return Err(e404().with_message("No se encontro el departamento"))?;
+        },
+        ResultadoBorrado::DestinoNoEncontrado => {
+            return Err(e404().with_message("No se encontro el departamento destino"))?;
+        },
+        ResultadoBorrado::EnUso { miembros, vehiculos, flujos } => {
+            return Err(e409().with_message(format!(
+                "El departamento tiene {} miembros, {} vehiculos y {} flujos de aprobacion, indica un destino para moverlos",
+                miembros,
+                vehiculos,
+                flujos
+            )))?;
+        },
+    };
+
+    // El cache guarda el nombre del departamento y el alcance de los administradores
+    for usuario_id in reasignacion.usuarios.iter().chain(administradores.iter()) {
+        invalidar_cache_usuario(&usuario.redis, usuario_id).await;
+    }
+
+    Ok(reasignacion)
 }
 
 
 #[tracing::instrument(
-    name = "Borrar departamento por id",
-    skip_all
+    name = "Quitar jefe de departamento",
+    skip(usuario, pool)
 )]
-pub async fn delete_department(
-    session: JwtSession,
+pub async fn delete_department_head(
+    usuario: CurrentUser,
     pool: web::Data<PgPool>,
-    id: web::Path<i32>,
+    path: web::Path<(i32, Uuid)>,
 ) -> Result<HttpResponse, actix_web::Error> {
 
-    // Usuario es admin ?
-    let usuario = obtener_usuario_por_id_sqlx(&pool, &session.user_id).await
-        .map_err(|_| e500())?
-        .ok_or(e500())?;
-    
     if !usuario.es_admin() {
         return Err(e403().with_message("No tienes los permisos requeridos"))?;
     }
-    
-    
-    // Query Borrar DB
-    match borrar_departamento_por_nombre_sqlx(&pool, id.into_inner()).await {
-        Ok(deleted) => {
-            if !deleted {
-               return Err(e404().with_message("No se encontro el departamento"))?;
-            }
-        },
-        Err(_) => { 
-            return Err(e500())?;
-        },
+
+    let (id, usuario_id) = path.into_inner();
+    let quitado = quitar_jefe_sqlx(&pool, id, &usuario_id).await
+        .map_err(|_| e500())?;
+    if !quitado {
+        return Err(e404().with_message("El usuario no es jefe del departamento"))?;
     }
 
     // Respuesta exitosa
     let api_response = ApiResponse::<()>::new()
-        .with_message("Departament eliminado")
+        .with_message("Jefe del departamento eliminado")
+        .to_resp();
+
+    Ok(api_response)
+}
+
+
+#[tracing::instrument(
+    name = "Quitar administrador de departamento",
+    skip(usuario, pool)
+)]
+pub async fn delete_department_admin(
+    usuario: CurrentUser,
+    pool: web::Data<PgPool>,
+    path: web::Path<(i32, Uuid)>,
+) -> Result<HttpResponse, actix_web::Error> {
+
+    if !usuario.es_admin() {
+        return Err(e403().with_message("No tienes los permisos requeridos"))?;
+    }
+    usuario.rechazar_api_key()?;
+
+    let (id, usuario_id) = path.into_inner();
+    let quitado = quitar_administrador_sqlx(&pool, id, &usuario_id).await
+        .map_err(|_| e500())?;
+    if !quitado {
+        return Err(e404().with_message("El usuario no es administrador del departamento"))?;
+    }
+
+    invalidar_cache_usuario(&usuario.redis, &usuario_id).await;
+
+    // Respuesta exitosa
+    let api_response = ApiResponse::<()>::new()
+        .with_message("Administrador del departamento eliminado")
         .to_resp();
 
     Ok(api_response)
@@ -2,33 +2,18 @@ use actix_web::{HttpResponse, web};
 use sqlx::PgPool;
 use anyhow::Context;
 
-use crate::authentication::jwt_session::JwtSession;
+use crate::authentication::current_user::CurrentUser;
 //use crate::models::department::Department;
 use common::models::department::Departamento;
-use crate::api_response::{e500, ApiResponse, e404};
+use crate::api_response::{e500, ApiResponse, e403, e404};
 
-use crate::routes::users::sqlx::obtener_usuario_por_id_sqlx;
+use super::sqlx::{
+    construir_arbol, es_jefe_departamento_sqlx, listar_administradores_sqlx, listar_departamentos_sqlx,
+    listar_jefes_sqlx, listar_miembros_sqlx, obtener_departamento_detalle_sqlx, DepartamentoDetalle,
+    FiltroMiembros, JefeDepartamento, MiembroDepartamento, NodoDepartamento,
+};
 
 
-#[tracing::instrument(
-    name = "Query all departments",
-    skip_all
-)]
-async fn obtener_departamentos_sqlx(
-    pool: &PgPool
-) -> Result<Vec<Departamento>, anyhow::Error> {
-    let departamentos: Vec<Departamento> = sqlx::query_as!(
-        Departamento,
-        r#"
-        SELECT *
-        FROM departamentos
-        "#)
-        .fetch_all(pool)
-        .await
-        .context("Failed to execute query")?;
-
-    Ok(departamentos)
-}
 
 #[tracing::instrument(
     name = "Query department with id",
@@ -42,7 +27,7 @@ pub async fn obtener_departamento_por_id_sqlx(
     let departamento: Option<Departamento> = sqlx::query_as!(
         Departamento,
         r#"
-        SELECT *
+        SELECT id, nombre
         FROM departamentos
         WHERE id = $1
         "#,
@@ -59,21 +44,16 @@ pub async fn obtener_departamento_por_id_sqlx(
     skip_all
 )]
 pub async fn departments_get(
-    session: JwtSession,
+    _usuario: CurrentUser,
     pool: web::Data<PgPool>,
 ) -> Result<HttpResponse, actix_web::Error> {
 
-    // Session actual tiene un usuario valido ?
-    let _usuario = obtener_usuario_por_id_sqlx(&pool, &session.user_id).await
-        .map_err(|_| e500())?
-        .ok_or(e500())?;
-
     // Query departamentos DB
-    let departamentos = obtener_departamentos_sqlx(&pool).await
+    let departamentos = listar_departamentos_sqlx(&pool).await
         .map_err(|_| e500())?;
 
     // Respuesta exitosa
-    let api_response = ApiResponse::<Vec<Departamento>>::new()
+    let api_response = ApiResponse::<Vec<DepartamentoDetalle>>::new()
         .with_message("Lista de departamentos")
         .with_data(departamentos)
         .to_resp();
@@ -87,27 +67,142 @@ pub async fn departments_get(
     skip_all
 )]
 pub async fn department_get(
-    session: JwtSession,
+    _usuario: CurrentUser,
     pool: web::Data<PgPool>,
     id: web::Path<i32>,
 ) -> Result<HttpResponse, actix_web::Error> {
 
-    // Session actual tiene un usuario valido ?
-    let _usuario = obtener_usuario_por_id_sqlx(&pool, &session.user_id).await
-        .map_err(|_| e500())?
-        .ok_or(e500())?;
-
     // Query departamento DB
-    let departamento = obtener_departamento_por_id_sqlx(&pool, id.into_inner()).await
+    let departamento = obtener_departamento_detalle_sqlx(&pool, id.into_inner()).await
         .map_err(|_| e500())?
         .ok_or(e404().with_message("Department not found"))?;
 
 
     // Respuesta exitosa
-    let api_response = ApiResponse::<Departamento>::new()
+    let api_response = ApiResponse::<DepartamentoDetalle>::new()
         .with_message("Departamento")
         .with_data(departamento)
         .to_resp();
 
     Ok(api_response)
 }
+
+
+#[tracing::instrument(
+    name = "Obtener arbol de departamentos",
+    skip_all
+)]
+pub async fn departments_tree_get(
+    _usuario: CurrentUser,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+
+    let departamentos = listar_departamentos_sqlx(&pool).await
+        .map_err(|_| e500())?;
+
+    // Respuesta exitosa
+    let api_response = ApiResponse::<Vec<NodoDepartamento>>::new()
+        .with_message("Arbol de departamentos")
+        .with_data(construir_arbol(departamentos))
+        .to_resp();
+
+    Ok(api_response)
+}
+
+
+#[tracing::instrument(
+    name = "Obtener miembros de departamento",
+    skip(usuario, pool)
+)]
+pub async fn department_members_get(
+    usuario: CurrentUser,
+    pool: web::Data<PgPool>,
+    id: web::Path<i32>,
+    filtro: web::Query<FiltroMiembros>,
+) -> Result<HttpResponse, actix_web::Error> {
+
+    let id = id.into_inner();
+    obtener_departamento_por_id_sqlx(&pool, id).await
+        .map_err(|_| e500())?
+        .ok_or(e404().with_message("No se encontro el departamento"))?;
+
+    // Los jefes y administradores ven a los miembros de su departamento y de los hijos
+    if !usuario.administra_departamento(Some(id)) {
+        let es_jefe = es_jefe_departamento_sqlx(&pool, &usuario.usuario_id, id).await
+            .map_err(|_| e500())?;
+        if !es_jefe {
+            return Err(e403().with_message("No tienes los permisos requeridos"))?;
+        }
+    }
+
+    let miembros = listar_miembros_sqlx(&pool, id, filtro.subdepartamentos.unwrap_or(false)).await
+        .map_err(|_| e500())?;
+
+    // Respuesta exitosa
+    let api_response = ApiResponse::<Vec<MiembroDepartamento>>::new()
+        .with_message("Miembros del departamento")
+        .with_data(miembros)
+        .to_resp();
+
+    Ok(api_response)
+}
+
+
+#[tracing::instrument(
+    name = "Obtener jefes de departamento",
+    skip_all
+)]
+pub async fn department_heads_get(
+    _usuario: CurrentUser,
+    pool: web::Data<PgPool>,
+    id: web::Path<i32>,
+) -> Result<HttpResponse, actix_web::Error> {
+
+    let id = id.into_inner();
+    obtener_departamento_por_id_sqlx(&pool, id).await
+        .map_err(|_| e500())?
+        .ok_or(e404().with_message("No se encontro el departamento"))?;
+
+    let jefes = listar_jefes_sqlx(&pool, id).await
+        .map_err(|_| e500())?;
+
+    // Respuesta exitosa
+    let api_response = ApiResponse::<Vec<JefeDepartamento>>::new()
+        .with_message("Jefes del departamento")
+        .with_data(jefes)
+        .to_resp();
+
+    Ok(api_response)
+}
+
+
+#[tracing::instrument(
+    name = "Obtener administradores de departamento",
+    skip(usuario, pool)
+)]
+pub async fn department_admins_get(
+    usuario: CurrentUser,
+    pool: web::Data<PgPool>,
+    id: web::Path<i32>,
+) -> Result<HttpResponse, actix_web::Error> {
+
+    let id = id.into_inner();
+    if !usuario.administra_departamento(Some(id)) {
+        return Err(e403().with_message("No tienes los permisos requeridos"))?;
+    }
+
+    obtener_departamento_por_id_sqlx(&pool, id).await
+        .map_err(|_| e500())?
+        .ok_or(e404().with_message("No se encontro el departamento"))?;
+
+    let administradores = listar_administradores_sqlx(&pool, id).await
+        .map_err(|_| e500())?;
+
+    // Respuesta exitosa
+    let api_response = ApiResponse::<Vec<JefeDepartamento>>::new()
+        .with_message("Administradores del departamento")
+        .with_data(administradores)
+        .to_resp();
+
+    Ok(api_response)
+}
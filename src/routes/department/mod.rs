@@ -2,3 +2,5 @@ pub mod get;
 pub mod post;
 pub mod patch;
 pub mod delete;
+pub mod sqlx;
+pub mod quotas;
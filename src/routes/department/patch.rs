@@ -4,10 +4,13 @@ use anyhow::Context;
 
 use common::models::department::{Departamento, ActualizaDepartamento};
 
-use crate::authentication::jwt_session::JwtSession;
-use crate::api_response::{e500, ApiResponse, e403, e404};
-use crate::routes::users::sqlx::obtener_usuario_por_id_sqlx;
+use crate::authentication::current_user::{CurrentUser, invalidar_cache_usuario};
+use crate::api_response::{e500, ApiResponse, e400, e403, e404, e409};
 use super::get::obtener_departamento_por_id_sqlx;
+use super::sqlx::{
+    actualizar_jerarquia_sqlx, es_jerarquia_invalida, listar_ids_administradores_sqlx, ActualizaJerarquia,
+    DepartamentoDetalle, ResultadoJerarquia,
+};
 
 
 #[tracing::instrument(
@@ -24,7 +27,7 @@ async fn actualizar_departamento_sqlx(
         UPDATE departamentos
         SET nombre = $2
         WHERE id = $1
-        RETURNING *
+        RETURNING id, nombre
         "#,
         departamento.id,
         departamento.nombre)
@@ -42,17 +45,12 @@ async fn actualizar_departamento_sqlx(
     skip_all
 )]
 pub async fn patch_department(
-    session: JwtSession,
+    usuario: CurrentUser,
     pool: web::Data<PgPool>,
     id: web::Path<i32>,
     body: web::Json<ActualizaDepartamento>,
 ) -> Result<HttpResponse, actix_web::Error> {
 
-    // Usuario es admin ?
-    let usuario = obtener_usuario_por_id_sqlx(&pool, &session.user_id).await
-        .map_err(|_| e500())?
-        .ok_or(e500())?;
-    
     if !usuario.es_admin() {
         return Err(e403().with_message("No tienes los permisos requeridos"))?;
     }
@@ -82,3 +80,51 @@ pub async fn patch_department(
 
     Ok(api_response)
 }
+
+
+#[tracing::instrument(
+    name = "Patch department hierarchy",
+    skip(usuario, pool)
+)]
+pub async fn patch_department_hierarchy(
+    usuario: CurrentUser,
+    pool: web::Data<PgPool>,
+    id: web::Path<i32>,
+    body: web::Json<ActualizaJerarquia>,
+) -> Result<HttpResponse, actix_web::Error> {
+
+    if !usuario.es_admin() {
+        return Err(e403().with_message("No tienes los permisos requeridos"))?;
+    }
+
+    let resultado = actualizar_jerarquia_sqlx(&pool, id.into_inner(), &body).await
+        .map_err(|e| match es_jerarquia_invalida(&e) {
+            Some(motivo) => e400().with_message(motivo),
+            None => e500(),
+        })?;
+
+    let departamento = match resultado {
+        ResultadoJerarquia::Actualizado(departamento) => departamento,
+        ResultadoJerarquia::NoEncontrado => {
+            return Err(e404().with_message("No se encontro el departamento"))?;
+        },
+        ResultadoJerarquia::Ciclo => {
+            return Err(e409().with_message("El departamento no puede depender de si mismo ni de sus hijos"))?;
+        },
+    };
+
+    // El alcance en cache de los administradores incluye los subdepartamentos
+    let administradores = listar_ids_administradores_sqlx(&pool).await
+        .map_err(|_| e500())?;
+    for usuario_id in administradores.iter() {
+        invalidar_cache_usuario(&usuario.redis, usuario_id).await;
+    }
+
+    // Respuesta exitosa
+    let api_response = ApiResponse::<DepartamentoDetalle>::new()
+        .with_message("Jerarquia del departamento actualizada")
+        .with_data(departamento)
+        .to_resp();
+
+    Ok(api_response)
+}
@@ -3,11 +3,15 @@ use sqlx::PgPool;
 use anyhow::Context;
 
 use common::models::department::{Departamento, NuevoDepartamento};
+use uuid::Uuid;
 
-use crate::authentication::jwt_session::JwtSession;
-use crate::api_response::{e500, ApiResponse, e403};
-
+use crate::authentication::current_user::{CurrentUser, invalidar_cache_usuario};
+use crate::api_response::{e500, ApiResponse, e400, e403, e404, e409};
 use crate::routes::users::sqlx::obtener_usuario_por_id_sqlx;
+use super::get::obtener_departamento_por_id_sqlx;
+use super::delete::reasignar_y_borrar;
+use super::sqlx::{agregar_administrador_sqlx, agregar_jefe_sqlx, FusionDepartamentos, Reasignacion};
+
 
 
 #[tracing::instrument(
@@ -24,7 +28,7 @@ async fn insertar_departmamento_con_nombre_sqlx(
         INSERT INTO departamentos
         (nombre)
         VALUES ($1)
-        RETURNING *
+        RETURNING id, nombre
         "#,
         nombre)
         .fetch_one(pool)
@@ -37,19 +41,14 @@ async fn insertar_departmamento_con_nombre_sqlx(
 
 #[tracing::instrument(
     name = "Crear departamento",
-    skip(session, pool)
+    skip(usuario, pool)
 )]
 pub async fn department_post(
-    session: JwtSession,
+    usuario: CurrentUser,
     pool: web::Data<PgPool>,
     body: web::Json<NuevoDepartamento>,
 ) -> Result<HttpResponse, actix_web::Error> {
 
-    // Usuario es admin ?
-    let usuario = obtener_usuario_por_id_sqlx(&pool, &session.user_id).await
-        .map_err(|_| e500())?
-        .ok_or(e500())?;
-
     if !usuario.es_admin() {
         return Err(e403().with_message("No tienes los permisos requeridos"))?;
     }
@@ -67,3 +66,121 @@ pub async fn department_post(
 
     Ok(api_response)
 }
+
+
+#[tracing::instrument(
+    name = "Agregar jefe de departamento",
+    skip(usuario, pool)
+)]
+pub async fn department_head_post(
+    usuario: CurrentUser,
+    pool: web::Data<PgPool>,
+    path: web::Path<(i32, Uuid)>,
+) -> Result<HttpResponse, actix_web::Error> {
+
+    if !usuario.es_admin() {
+        return Err(e403().with_message("No tienes los permisos requeridos"))?;
+    }
+
+    let (id, usuario_id) = path.into_inner();
+    obtener_departamento_por_id_sqlx(&pool, id).await
+        .map_err(|_| e500())?
+        .ok_or(e404().with_message("No se encontro el departamento"))?;
+
+    let jefe = obtener_usuario_por_id_sqlx(&pool, &usuario_id).await
+        .map_err(|_| e500())?
+        .ok_or(e404().with_message("No se encontro el usuario"))?;
+    if !jefe.activo {
+        return Err(e400().with_message("El usuario esta desactivado"))?;
+    }
+
+    let agregado = agregar_jefe_sqlx(&pool, id, &usuario_id).await
+        .map_err(|_| e500())?;
+    if !agregado {
+        return Err(e409().with_message("El usuario ya es jefe del departamento"))?;
+    }
+
+    // Respuesta exitosa
+    let api_response = ApiResponse::<()>::new()
+        .with_message(format!("{} es jefe del departamento", jefe.email))
+        .to_resp();
+
+    Ok(api_response)
+}
+
+
+#[tracing::instrument(
+    name = "Fusionar departamentos",
+    skip(usuario, pool)
+)]
+pub async fn merge_departments(
+    usuario: CurrentUser,
+    pool: web::Data<PgPool>,
+    id: web::Path<i32>,
+    body: web::Json<FusionDepartamentos>,
+) -> Result<HttpResponse, actix_web::Error> {
+
+    if !usuario.es_admin() {
+        return Err(e403().with_message("No tienes los permisos requeridos"))?;
+    }
+
+    // Igual que borrar con destino, pero los jefes y administradores pasan al destino
+    let reasignacion = reasignar_y_borrar(&usuario, &pool, id.into_inner(), Some(body.destino), true).await?;
+
+    // Respuesta exitosa
+    let api_response = ApiResponse::<Reasignacion>::new()
+        .with_message("Departamentos fusionados")
+        .with_data(reasignacion)
+        .to_resp();
+
+    Ok(api_response)
+}
+
+
+/// El administrador de departamento solo administra los usuarios, vehiculos
+/// y peticiones de ese departamento y sus subdepartamentos
+#[tracing::instrument(
+    name = "Agregar administrador de departamento",
+    skip(usuario, pool)
+)]
+pub async fn department_admin_post(
+    usuario: CurrentUser,
+    pool: web::Data<PgPool>,
+    path: web::Path<(i32, Uuid)>,
+) -> Result<HttpResponse, actix_web::Error> {
+
+    if !usuario.es_admin() {
+        return Err(e403().with_message("No tienes los permisos requeridos"))?;
+    }
+    usuario.rechazar_api_key()?;
+
+    let (id, usuario_id) = path.into_inner();
+    obtener_departamento_por_id_sqlx(&pool, id).await
+        .map_err(|_| e500())?
+        .ok_or(e404().with_message("No se encontro el departamento"))?;
+
+    let administrador = obtener_usuario_por_id_sqlx(&pool, &usuario_id).await
+        .map_err(|_| e500())?
+        .ok_or(e404().with_message("No se encontro el usuario"))?;
+    if !administrador.activo {
+        return Err(e400().with_message("El usuario esta desactivado"))?;
+    }
+    if administrador.es_admin() {
+        return Err(e400().with_message("El usuario ya administra todos los departamentos"))?;
+    }
+
+    let agregado = agregar_administrador_sqlx(&pool, id, &usuario_id, &usuario.usuario_id).await
+        .map_err(|_| e500())?;
+    if !agregado {
+        return Err(e409().with_message("El usuario ya es administrador del departamento"))?;
+    }
+
+    invalidar_cache_usuario(&usuario.redis, &usuario_id).await;
+
+    // Respuesta exitosa
+    let api_response = ApiResponse::<()>::new()
+        .with_message(format!("{} es administrador del departamento", administrador.email))
+        .to_resp();
+
+    Ok(api_response)
+}
@@ -0,0 +1,492 @@
+use actix_web::{HttpResponse, web};
+use anyhow::Context;
+use chrono::{Datelike, NaiveDate, NaiveDateTime};
+use sqlx::{PgExecutor, PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+use crate::api_response::{ApiResponse, e400, e403, e404, e500};
+use crate::authentication::current_user::CurrentUser;
+
+use super::get::obtener_departamento_por_id_sqlx;
+use super::sqlx::{es_jefe_departamento_sqlx, listar_departamentos_sqlx};
+
+
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize, serde::Deserialize, sqlx::Type)]
+#[sqlx(type_name = "modo_cuota", rename_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum ModoCuota {
+    // La peticion se crea con una advertencia
+    Advertir,
+    Bloquear,
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct Cuota {
+    pub departamento_id: i32,
+    pub km_max: Option<i32>,
+    pub horas_max: Option<i32>,
+    pub vehiculos_simultaneos_max: Option<i32>,
+    pub presupuesto_combustible: Option<i32>,
+    pub modo: ModoCuota,
+    pub modificado_en: NaiveDateTime,
+}
+
+#[derive(Debug, serde::Deserialize)]
+pub struct ActualizaCuota {
+    pub km_max: Option<i32>,
+    pub horas_max: Option<i32>,
+    pub vehiculos_simultaneos_max: Option<i32>,
+    pub presupuesto_combustible: Option<i32>,
+    pub modo: Option<ModoCuota>,
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct Consumo {
+    pub km: i64,
+    pub horas: f64,
+    pub combustible: i64,
+    // Vehiculos distintos reservados al mismo tiempo
+    pub vehiculos_simultaneos: i64,
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct ConsumoDepartamento {
+    pub departamento_id: i32,
+    pub periodo_inicio: NaiveDate,
+    pub periodo_fin: NaiveDate,
+    pub consumo: Consumo,
+    pub cuota: Option<Cuota>,
+}
+
+/// Lo que pide la peticion nueva
+#[derive(Debug)]
+pub struct PeticionACuota {
+    pub vehiculo_id: Uuid,
+    pub inicio: NaiveDateTime,
+    pub finalizo: NaiveDateTime,
+    pub distancia_estimada_km: Option<i32>,
+    pub costo_combustible_estimado: Option<i32>,
+}
+
+/// Mes calendario de la fecha, el fin no se incluye
+pub fn periodo_de(fecha: NaiveDate) -> (NaiveDate, NaiveDate) {
+    let inicio = fecha.with_day(1).unwrap_or(fecha);
+    let fin = if inicio.month() == 12 {
+        NaiveDate::from_ymd_opt(inicio.year() + 1, 1, 1)
+    } else {
+        NaiveDate::from_ymd_opt(inicio.year(), inicio.month() + 1, 1)
+    }
+    .unwrap_or(inicio);
+
+    (inicio, fin)
+}
+
+/// Limites que rebasaria el consumo del periodo, ya con la peticion nueva
+pub fn limites_excedidos(cuota: &Cuota, consumo: &Consumo) -> Vec<String> {
+    let mut excedidos = vec![];
+    if let Some(km_max) = cuota.km_max.filter(|max| consumo.km > i64::from(*max)) {
+        excedidos.push(format!("{} de {} km del mes", consumo.km, km_max));
+    }
+    if let Some(horas_max) = cuota.horas_max.filter(|max| consumo.horas > f64::from(*max)) {
+        excedidos.push(format!("{:.1} de {} horas del mes", consumo.horas, horas_max));
+    }
+    if let Some(max) = cuota.vehiculos_simultaneos_max.filter(|max| consumo.vehiculos_simultaneos > i64::from(*max)) {
+        excedidos.push(format!("{} de {} vehiculos al mismo tiempo", consumo.vehiculos_simultaneos, max));
+    }
+    if let Some(presupuesto) = cuota.presupuesto_combustible.filter(|max| consumo.combustible > i64::from(*max)) {
+        excedidos.push(format!("{} de {} de presupuesto de combustible", consumo.combustible, presupuesto));
+    }
+    excedidos
+}
+
+
+#[tracing::instrument(
+    name = "Query cuota de departamento",
+    skip(pool)
+)]
+async fn obtener_cuota_sqlx(
+    pool: &PgPool,
+    departamento_id: i32,
+) -> Result<Option<Cuota>, anyhow::Error> {
+    let cuota = sqlx::query_as!(
+        Cuota,
+        r#"
+        SELECT
+            departamento_id, km_max, horas_max, vehiculos_simultaneos_max,
+            presupuesto_combustible, modo as "modo!: ModoCuota", modificado_en
+        FROM cuotas_departamento
+        WHERE departamento_id = $1
+        "#,
+        departamento_id,
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to execute query")?;
+
+    Ok(cuota)
+}
+
+#[tracing::instrument(
+    name = "Query guardar cuota de departamento",
+    skip(pool)
+)]
+async fn guardar_cuota_sqlx(
+    pool: &PgPool,
+    departamento_id: i32,
+    cuota: &ActualizaCuota,
+    admin_id: &Uuid,
+) -> Result<Cuota, anyhow::Error> {
+    let cuota = sqlx::query_as!(
+        Cuota,
+        r#"
+        INSERT INTO cuotas_departamento
+        (departamento_id, km_max, horas_max, vehiculos_simultaneos_max,
+         presupuesto_combustible, modo, modificado_por)
+        VALUES ($1, $2, $3, $4, $5, $6, $7)
+        ON CONFLICT (departamento_id) DO UPDATE
+        SET km_max = EXCLUDED.km_max,
+            horas_max = EXCLUDED.horas_max,
+            vehiculos_simultaneos_max = EXCLUDED.vehiculos_simultaneos_max,
+            presupuesto_combustible = EXCLUDED.presupuesto_combustible,
+            modo = EXCLUDED.modo,
+            modificado_por = EXCLUDED.modificado_por,
+            modificado_en = now()
+        RETURNING
+            departamento_id, km_max, horas_max, vehiculos_simultaneos_max,
+            presupuesto_combustible, modo as "modo!: ModoCuota", modificado_en
+        "#,
+        departamento_id,
+        cuota.km_max,
+        cuota.horas_max,
+        cuota.vehiculos_simultaneos_max,
+        cuota.presupuesto_combustible,
+        cuota.modo.unwrap_or(ModoCuota::Advertir) as ModoCuota,
+        admin_id,
+    )
+    .fetch_one(pool)
+    .await
+    .context("Failed to execute query")?;
+
+    Ok(cuota)
+}
+
+#[tracing::instrument(
+    name = "Query borrar cuota de departamento",
+    skip(pool)
+)]
+async fn borrar_cuota_sqlx(
+    pool: &PgPool,
+    departamento_id: i32,
+) -> Result<bool, anyhow::Error> {
+    let result = sqlx::query!(
+        "DELETE FROM cuotas_departamento WHERE departamento_id = $1",
+        departamento_id,
+    )
+    .execute(pool)
+    .await
+    .context("Failed to execute query")?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+/// Consumo de las peticiones no rechazadas que inician en el periodo, los
+/// vehiculos simultaneos se cuentan entre desde y hasta
+#[tracing::instrument(
+    name = "Query consumo de departamento",
+    skip(executor)
+)]
+async fn obtener_consumo_sqlx(
+    executor: impl PgExecutor<'_>,
+    departamento_id: i32,
+    periodo: (NaiveDate, NaiveDate),
+    desde: NaiveDateTime,
+    hasta: NaiveDateTime,
+) -> Result<Consumo, anyhow::Error> {
+    let consumo = sqlx::query_as!(
+        Consumo,
+        r#"
+        SELECT
+            COALESCE(SUM(distancia_estimada_km), 0)::BIGINT as "km!",
+            COALESCE(SUM(EXTRACT(EPOCH FROM finalizo - inicio)) / 3600, 0)::FLOAT8 as "horas!",
+            COALESCE(SUM(costo_combustible_estimado), 0)::BIGINT as "combustible!",
+            (
+                SELECT COUNT(DISTINCT vehiculo_id)
+                FROM peticiones
+                WHERE departamento_id = $1
+                    AND estado IN ('pendiente', 'aceptada')
+                    AND inicio < $5 AND finalizo > $4
+            ) as "vehiculos_simultaneos!"
+        FROM peticiones
+        WHERE departamento_id = $1
+            AND estado IS DISTINCT FROM 'rechazada'
+            AND inicio >= $2::date AND inicio < $3::date
+        "#,
+        departamento_id,
+        periodo.0,
+        periodo.1,
+        desde,
+        hasta,
+    )
+    .fetch_one(executor)
+    .await
+    .context("Failed to execute query")?;
+
+    Ok(consumo)
+}
+
+/// Cuota del departamento y los limites que rebasaria la peticion, bloquea la
+/// cuota hasta terminar la transaccion para que dos peticiones no la rebasen juntas
+#[tracing::instrument(
+    name = "Query revisar cuota para peticion",
+    skip(transaction)
+)]
+pub async fn revisar_cuota_sqlx(
+    transaction: &mut Transaction<'_, Postgres>,
+    departamento_id: i32,
+    peticion: &PeticionACuota,
+) -> Result<Option<(ModoCuota, Vec<String>)>, anyhow::Error> {
+    let cuota = sqlx::query_as!(
+        Cuota,
+        r#"
+        SELECT
+            departamento_id, km_max, horas_max, vehiculos_simultaneos_max,
+            presupuesto_combustible, modo as "modo!: ModoCuota", modificado_en
+        FROM cuotas_departamento
+        WHERE departamento_id = $1
+        FOR UPDATE
+        "#,
+        departamento_id,
+    )
+    .fetch_optional(&mut *transaction)
+    .await
+    .context("Failed to lock quota")?;
+
+    let cuota = match cuota {
+        Some(cuota) => cuota,
+        None => return Ok(None),
+    };
+
+    let periodo = periodo_de(peticion.inicio.date());
+    let mut consumo = obtener_consumo_sqlx(&mut *transaction, departamento_id, periodo, peticion.inicio, peticion.finalizo).await?;
+    consumo.km += i64::from(peticion.distancia_estimada_km.unwrap_or(0));
+    consumo.horas += (peticion.finalizo - peticion.inicio).num_minutes() as f64 / 60.0;
+    consumo.combustible += i64::from(peticion.costo_combustible_estimado.unwrap_or(0));
+    // Un vehiculo que el departamento ya tiene reservado en ese horario no suma
+    let ya_reservado = sqlx::query!(
+        r#"
+        SELECT EXISTS (
+            SELECT 1 FROM peticiones
+            WHERE departamento_id = $1
+                AND vehiculo_id = $2
+                AND estado IN ('pendiente', 'aceptada')
+                AND inicio < $4 AND finalizo > $3
+        ) as "reservado!"
+        "#,
+        departamento_id,
+        peticion.vehiculo_id,
+        peticion.inicio,
+        peticion.finalizo,
+    )
+    .fetch_one(&mut *transaction)
+    .await
+    .context("Failed to check vehicle reservation")?
+    .reservado;
+    if !ya_reservado {
+        consumo.vehiculos_simultaneos += 1;
+    }
+
+    Ok(Some((cuota.modo, limites_excedidos(&cuota, &consumo))))
+}
+
+async fn consumo_del_mes(
+    pool: &PgPool,
+    departamento_id: i32,
+) -> Result<ConsumoDepartamento, anyhow::Error> {
+    let ahora = chrono::Utc::now().naive_utc();
+    let periodo = periodo_de(ahora.date());
+
+    Ok(ConsumoDepartamento {
+        departamento_id,
+        periodo_inicio: periodo.0,
+        periodo_fin: periodo.1,
+        consumo: obtener_consumo_sqlx(pool, departamento_id, periodo, ahora, ahora).await?,
+        cuota: obtener_cuota_sqlx(pool, departamento_id).await?,
+    })
+}
+
+/// Administradores y jefes del departamento o de sus padres
+async fn rechazar_sin_acceso(
+    usuario: &CurrentUser,
+    pool: &PgPool,
+    departamento_id: i32,
+) -> Result<(), actix_web::Error> {
+    obtener_departamento_por_id_sqlx(pool, departamento_id).await
+        .map_err(|_| e500())?
+        .ok_or(e404().with_message("No se encontro el departamento"))?;
+
+    if usuario.administra_departamento(Some(departamento_id)) {
+        return Ok(());
+    }
+    let es_jefe = es_jefe_departamento_sqlx(pool, &usuario.usuario_id, departamento_id).await
+        .map_err(|_| e500())?;
+    if !es_jefe {
+        return Err(e403().with_message("No tienes los permisos requeridos"))?;
+    }
+
+    Ok(())
+}
+
+
+#[tracing::instrument(
+    name = "Obtener cuota de departamento",
+    skip(usuario, pool)
+)]
+pub async fn department_quota_get(
+    usuario: CurrentUser,
+    pool: web::Data<PgPool>,
+    id: web::Path<i32>,
+) -> Result<HttpResponse, actix_web::Error> {
+
+    let id = id.into_inner();
+    rechazar_sin_acceso(&usuario, &pool, id).await?;
+
+    let cuota = obtener_cuota_sqlx(&pool, id).await
+        .map_err(|_| e500())?
+        .ok_or(e404().with_message("El departamento no tiene cuota"))?;
+
+    // Respuesta exitosa
+    let api_response = ApiResponse::<Cuota>::new()
+        .with_message("Cuota del departamento")
+        .with_data(cuota)
+        .to_resp();
+
+    Ok(api_response)
+}
+
+
+#[tracing::instrument(
+    name = "Actualizar cuota de departamento",
+    skip(usuario, pool)
+)]
+pub async fn patch_department_quota(
+    usuario: CurrentUser,
+    pool: web::Data<PgPool>,
+    id: web::Path<i32>,
+    body: web::Json<ActualizaCuota>,
+) -> Result<HttpResponse, actix_web::Error> {
+
+    if !usuario.es_admin() {
+        return Err(e403().with_message("No tienes los permisos requeridos"))?;
+    }
+
+    let cuota = body.into_inner();
+    let negativo = [cuota.km_max, cuota.horas_max, cuota.vehiculos_simultaneos_max, cuota.presupuesto_combustible]
+        .iter()
+        .any(|limite| limite.map(|limite| limite < 0).unwrap_or(false));
+    if negativo {
+        return Err(e400().with_message("Los limites no pueden ser negativos"))?;
+    }
+
+    let id = id.into_inner();
+    obtener_departamento_por_id_sqlx(&pool, id).await
+        .map_err(|_| e500())?
+        .ok_or(e404().with_message("No se encontro el departamento"))?;
+
+    let cuota = guardar_cuota_sqlx(&pool, id, &cuota, &usuario.usuario_id).await
+        .map_err(|_| e500())?;
+
+    // Respuesta exitosa
+    let api_response = ApiResponse::<Cuota>::new()
+        .with_message("Cuota del departamento actualizada")
+        .with_data(cuota)
+        .to_resp();
+
+    Ok(api_response)
+}
+
+
+#[tracing::instrument(
+    name = "Borrar cuota de departamento",
+    skip(usuario, pool)
+)]
+pub async fn delete_department_quota(
+    usuario: CurrentUser,
+    pool: web::Data<PgPool>,
+    id: web::Path<i32>,
+) -> Result<HttpResponse, actix_web::Error> {
+
+    if !usuario.es_admin() {
+        return Err(e403().with_message("No tienes los permisos requeridos"))?;
+    }
+
+    let borrada = borrar_cuota_sqlx(&pool, id.into_inner()).await
+        .map_err(|_| e500())?;
+    if !borrada {
+        return Err(e404().with_message("El departamento no tiene cuota"))?;
+    }
+
+    // Respuesta exitosa
+    let api_response = ApiResponse::<()>::new()
+        .with_message("Cuota del departamento eliminada")
+        .to_resp();
+
+    Ok(api_response)
+}
+
+
+#[tracing::instrument(
+    name = "Obtener consumo de departamento",
+    skip(usuario, pool)
+)]
+pub async fn department_usage_get(
+    usuario: CurrentUser,
+    pool: web::Data<PgPool>,
+    id: web::Path<i32>,
+) -> Result<HttpResponse, actix_web::Error> {
+
+    let id = id.into_inner();
+    rechazar_sin_acceso(&usuario, &pool, id).await?;
+
+    let consumo = consumo_del_mes(&pool, id).await
+        .map_err(|_| e500())?;
+
+    // Respuesta exitosa
+    let api_response = ApiResponse::<ConsumoDepartamento>::new()
+        .with_message("Consumo del departamento en el mes")
+        .with_data(consumo)
+        .to_resp();
+
+    Ok(api_response)
+}
+
+
+#[tracing::instrument(
+    name = "Obtener consumo de todos los departamentos",
+    skip_all
+)]
+pub async fn departments_usage_get(
+    usuario: CurrentUser,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+
+    if !usuario.es_admin() {
+        return Err(e403().with_message("No tienes los permisos requeridos"))?;
+    }
+
+    let departamentos = listar_departamentos_sqlx(&pool).await
+        .map_err(|_| e500())?;
+
+    let mut consumos = Vec::with_capacity(departamentos.len());
+    for departamento in departamentos {
+        consumos.push(consumo_del_mes(&pool, departamento.id).await
+            .map_err(|_| e500())?);
+    }
+
+    // Respuesta exitosa
+    let api_response = ApiResponse::<Vec<ConsumoDepartamento>>::new()
+        .with_message("Consumo de los departamentos en el mes")
+        .with_data(consumos)
+        .to_resp();
+
+    Ok(api_response)
+}
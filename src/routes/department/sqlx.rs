@@ -0,0 +1,813 @@
+use std::collections::HashMap;
+
+use anyhow::Context;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+
+/// Departamento con su lugar en la jerarquia y sus jefes
+#[derive(Debug, serde::Serialize)]
+pub struct DepartamentoDetalle {
+    pub id: i32,
+    pub nombre: String,
+    pub padre_id: Option<i32>,
+    pub centro_costos: Option<String>,
+    pub jefes: Vec<Uuid>,
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct NodoDepartamento {
+    #[serde(flatten)]
+    pub departamento: DepartamentoDetalle,
+    pub hijos: Vec<NodoDepartamento>,
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct MiembroDepartamento {
+    pub usuario_id: Uuid,
+    pub nombres: String,
+    pub apellidos: String,
+    pub email: String,
+    pub departamento_id: i32,
+    pub es_jefe: bool,
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct JefeDepartamento {
+    pub usuario_id: Uuid,
+    pub nombres: String,
+    pub apellidos: String,
+    pub email: String,
+    pub departamento_id: i32,
+}
+
+#[derive(Debug, serde::Deserialize)]
+pub struct ActualizaJerarquia {
+    // None deja al departamento en la raiz
+    pub padre_id: Option<i32>,
+    pub centro_costos: Option<String>,
+}
+
+#[derive(Debug, serde::Deserialize)]
+pub struct FiltroMiembros {
+    // Incluir a los miembros de los departamentos hijos
+    pub subdepartamentos: Option<bool>,
+}
+
+#[derive(Debug, serde::Deserialize)]
+pub struct DestinoBorrado {
+    // Departamento que recibe a los miembros y vehiculos
+    pub destino: Option<i32>,
+}
+
+#[derive(Debug, serde::Deserialize)]
+pub struct FusionDepartamentos {
+    pub destino: i32,
+}
+
+/// Lo que se movio al departamento destino
+#[derive(Debug, serde::Serialize)]
+pub struct Reasignacion {
+    pub destino: Option<i32>,
+    pub miembros: usize,
+    pub vehiculos: u64,
+    pub subdepartamentos: u64,
+    pub flujos: u64,
+    // Para invalidar su cache
+    #[serde(skip)]
+    pub usuarios: Vec<Uuid>,
+}
+
+#[derive(Debug)]
+pub enum ResultadoBorrado {
+    Borrado(Reasignacion),
+    NoEncontrado,
+    DestinoNoEncontrado,
+    // Tiene miembros, vehiculos o flujos de aprobacion y no se indico destino
+    EnUso { miembros: i64, vehiculos: i64, flujos: i64 },
+}
+
+#[derive(Debug)]
+pub enum ResultadoJerarquia {
+    Actualizado(DepartamentoDetalle),
+    NoEncontrado,
+    // El padre es el mismo departamento o uno de sus descendientes
+    Ciclo,
+}
+
+
+/// Acomoda los departamentos en arboles a partir de su padre
+pub fn construir_arbol(departamentos: Vec<DepartamentoDetalle>) -> Vec<NodoDepartamento> {
+    let ids: Vec<i32> = departamentos.iter().map(|d| d.id).collect();
+    let mut por_padre: HashMap<Option<i32>, Vec<DepartamentoDetalle>> = HashMap::new();
+    for departamento in departamentos {
+        // Un padre que no esta en la lista deja al departamento en la raiz
+        let padre = departamento.padre_id.filter(|padre| ids.contains(padre));
+        por_padre.entry(padre).or_default().push(departamento);
+    }
+
+    fn hijos_de(
+        padre: Option<i32>,
+        por_padre: &mut HashMap<Option<i32>, Vec<DepartamentoDetalle>>,
+    ) -> Vec<NodoDepartamento> {
+        por_padre.remove(&padre)
+            .unwrap_or_default()
+            .into_iter()
+            .map(|departamento| {
+                let hijos = hijos_de(Some(departamento.id), por_padre);
+                NodoDepartamento { departamento, hijos }
+            })
+            .collect()
+    }
+
+    hijos_de(None, &mut por_padre)
+}
+
+
+#[tracing::instrument(
+    name = "Query listar departamentos con jerarquia",
+    skip_all
+)]
+pub async fn listar_departamentos_sqlx(
+    pool: &PgPool,
+) -> Result<Vec<DepartamentoDetalle>, anyhow::Error> {
+    let departamentos = sqlx::query_as!(
+        DepartamentoDetalle,
+        r#"
+        SELECT
+            d.id, d.nombre, d.padre_id, d.centro_costos,
+            COALESCE(
+                array_agg(j.usuario_id ORDER BY j.creado_en) FILTER (WHERE j.usuario_id IS NOT NULL),
+                '{}'
+            ) as "jefes!"
+        FROM departamentos d
+        LEFT JOIN departamentos_jefes j ON j.departamento_id = d.id
+        GROUP BY d.id
+        ORDER BY d.nombre
+        "#)
+        .fetch_all(pool)
+        .await
+        .context("Failed to execute query")?;
+
+    Ok(departamentos)
+}
+
+#[tracing::instrument(
+    name = "Query departamento con jerarquia",
+    skip(pool)
+)]
+pub async fn obtener_departamento_detalle_sqlx(
+    pool: &PgPool,
+    id: i32,
+) -> Result<Option<DepartamentoDetalle>, anyhow::Error> {
+    let departamento = sqlx::query_as!(
+        DepartamentoDetalle,
+        r#"
+        SELECT
+            d.id, d.nombre, d.padre_id, d.centro_costos,
+            COALESCE(
+                array_agg(j.usuario_id ORDER BY j.creado_en) FILTER (WHERE j.usuario_id IS NOT NULL),
+                '{}'
+            ) as "jefes!"
+        FROM departamentos d
+        LEFT JOIN departamentos_jefes j ON j.departamento_id = d.id
+        WHERE d.id = $1
+        GROUP BY d.id
+        "#,
+        id)
+        .fetch_optional(pool)
+        .await
+        .context("Failed to execute query")?;
+
+    Ok(departamento)
+}
+
+/// Cambia el padre y el centro de costos sin permitir ciclos
+#[tracing::instrument(
+    name = "Query actualizar jerarquia de departamento",
+    skip(pool)
+)]
+pub async fn actualizar_jerarquia_sqlx(
+    pool: &PgPool,
+    id: i32,
+    jerarquia: &ActualizaJerarquia,
+) -> Result<ResultadoJerarquia, anyhow::Error> {
+    let mut transaction = pool.begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+
+    // Dos cambios simultaneos podrian formar un ciclo entre ellos
+    sqlx::query!("LOCK TABLE departamentos IN SHARE ROW EXCLUSIVE MODE")
+        .execute(&mut transaction)
+        .await
+        .context("Failed to lock departments")?;
+
+    if let Some(padre_id) = jerarquia.padre_id {
+        let ciclo = sqlx::query!(
+            r#"
+            WITH RECURSIVE ancestros AS (
+                SELECT id, padre_id FROM departamentos WHERE id = $2
+                UNION
+                SELECT d.id, d.padre_id
+                FROM departamentos d JOIN ancestros a ON d.id = a.padre_id
+            )
+            SELECT EXISTS (SELECT 1 FROM ancestros WHERE id = $1) as "ciclo!"
+            "#,
+            id,
+            padre_id,
+        )
+        .fetch_one(&mut transaction)
+        .await
+        .context("Failed to check department cycle")?
+        .ciclo;
+
+        if ciclo {
+            return Ok(ResultadoJerarquia::Ciclo);
+        }
+    }
+
+    let centro_costos = jerarquia.centro_costos
+        .as_deref()
+        .map(str::trim)
+        .filter(|centro| !centro.is_empty());
+    let actualizado = sqlx::query!(
+        r#"
+        UPDATE departamentos
+        SET padre_id = $2, centro_costos = $3
+        WHERE id = $1
+        "#,
+        id,
+        jerarquia.padre_id,
+        centro_costos,
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to update department hierarchy")?
+    .rows_affected() > 0;
+
+    transaction.commit()
+        .await
+        .context("Failed to commit SQL transaction")?;
+
+    if !actualizado {
+        return Ok(ResultadoJerarquia::NoEncontrado);
+    }
+
+    Ok(obtener_departamento_detalle_sqlx(pool, id).await?
+        .map(ResultadoJerarquia::Actualizado)
+        .unwrap_or(ResultadoJerarquia::NoEncontrado))
+}
+
+#[tracing::instrument(
+    name = "Query miembros de departamento",
+    skip(pool)
+)]
+pub async fn listar_miembros_sqlx(
+    pool: &PgPool,
+    id: i32,
+    subdepartamentos: bool,
+) -> Result<Vec<MiembroDepartamento>, anyhow::Error> {
+    let miembros = sqlx::query_as!(
+        MiembroDepartamento,
+        r#"
+        WITH RECURSIVE arbol AS (
+            SELECT id FROM departamentos WHERE id = $1
+            UNION
+            SELECT d.id
+            FROM departamentos d JOIN arbol a ON d.padre_id = a.id
+            WHERE $2
+        )
+        SELECT
+            u.usuario_id, u.nombres, u.apellidos, u.email,
+            u.departamento as "departamento_id!",
+            EXISTS (
+                SELECT 1 FROM departamentos_jefes j
+                WHERE j.departamento_id = u.departamento AND j.usuario_id = u.usuario_id
+            ) as "es_jefe!"
+        FROM usuarios u JOIN arbol a ON u.departamento = a.id
+        WHERE u.activo
+        ORDER BY u.apellidos, u.nombres
+        "#,
+        id,
+        subdepartamentos,
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to execute query")?;
+
+    Ok(miembros)
+}
+
+/// Jefes asignados directamente al departamento
+#[tracing::instrument(
+    name = "Query jefes de departamento",
+    skip(pool)
+)]
+pub async fn listar_jefes_sqlx(
+    pool: &PgPool,
+    id: i32,
+) -> Result<Vec<JefeDepartamento>, anyhow::Error> {
+    let jefes = sqlx::query_as!(
+        JefeDepartamento,
+        r#"
+        SELECT u.usuario_id, u.nombres, u.apellidos, u.email, j.departamento_id
+        FROM departamentos_jefes j JOIN usuarios u ON u.usuario_id = j.usuario_id
+        WHERE j.departamento_id = $1 AND u.activo
+        ORDER BY j.creado_en
+        "#,
+        id,
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to execute query")?;
+
+    Ok(jefes)
+}
+
+/// Jefes que aprueban y reciben los reportes de un miembro del departamento, los del
+/// departamento mas cercano hacia arriba que tenga jefes activos sin contar al miembro
+#[tracing::instrument(
+    name = "Query jefes para miembro del departamento",
+    skip(pool)
+)]
+pub async fn obtener_jefes_para_miembro_sqlx(
+    pool: &PgPool,
+    id: i32,
+    usuario_id: &Uuid,
+) -> Result<Vec<JefeDepartamento>, anyhow::Error> {
+    let jefes = sqlx::query_as!(
+        JefeDepartamento,
+        r#"
+        WITH RECURSIVE ancestros AS (
+            SELECT d.id, d.padre_id, 0 as nivel
+            FROM departamentos d
+            WHERE d.id = $2
+            UNION ALL
+            SELECT d.id, d.padre_id, a.nivel + 1
+            FROM departamentos d JOIN ancestros a ON d.id = a.padre_id
+            WHERE a.nivel < 32
+        ), jefes AS (
+            SELECT u.usuario_id, u.nombres, u.apellidos, u.email, a.id as departamento_id, a.nivel
+            FROM ancestros a
+            JOIN departamentos_jefes j ON j.departamento_id = a.id
+            JOIN usuarios u ON u.usuario_id = j.usuario_id
+            WHERE u.activo AND u.usuario_id <> $1
+        )
+        SELECT
+            usuario_id as "usuario_id!",
+            nombres as "nombres!",
+            apellidos as "apellidos!",
+            email as "email!",
+            departamento_id as "departamento_id!"
+        FROM jefes
+        WHERE nivel = (SELECT MIN(nivel) FROM jefes)
+        ORDER BY apellidos, nombres
+        "#,
+        usuario_id,
+        id,
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to execute query")?;
+
+    Ok(jefes)
+}
+
+/// Los jefes de un departamento tambien lo son de sus descendientes
+#[tracing::instrument(
+    name = "Query es jefe de departamento",
+    skip(pool)
+)]
+pub async fn es_jefe_departamento_sqlx(
+    pool: &PgPool,
+    usuario_id: &Uuid,
+    id: i32,
+) -> Result<bool, anyhow::Error> {
+    let row = sqlx::query!(
+        r#"
+        WITH RECURSIVE ancestros AS (
+            SELECT id, padre_id FROM departamentos WHERE id = $2
+            UNION
+            SELECT d.id, d.padre_id
+            FROM departamentos d JOIN ancestros a ON d.id = a.padre_id
+        )
+        SELECT EXISTS (
+            SELECT 1 FROM departamentos_jefes j JOIN ancestros a ON j.departamento_id = a.id
+            WHERE j.usuario_id = $1
+        ) as "es_jefe!"
+        "#,
+        usuario_id,
+        id,
+    )
+    .fetch_one(pool)
+    .await
+    .context("Failed to execute query")?;
+
+    Ok(row.es_jefe)
+}
+
+/// Regresa false si ya era jefe
+#[tracing::instrument(
+    name = "Query agregar jefe de departamento",
+    skip(pool)
+)]
+pub async fn agregar_jefe_sqlx(
+    pool: &PgPool,
+    id: i32,
+    usuario_id: &Uuid,
+) -> Result<bool, anyhow::Error> {
+    let result = sqlx::query!(
+        r#"
+        INSERT INTO departamentos_jefes (departamento_id, usuario_id)
+        VALUES ($1, $2)
+        ON CONFLICT DO NOTHING
+        "#,
+        id,
+        usuario_id,
+    )
+    .execute(pool)
+    .await
+    .context("Failed to execute query")?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+/// Regresa false si no era jefe
+#[tracing::instrument(
+    name = "Query quitar jefe de departamento",
+    skip(pool)
+)]
+pub async fn quitar_jefe_sqlx(
+    pool: &PgPool,
+    id: i32,
+    usuario_id: &Uuid,
+) -> Result<bool, anyhow::Error> {
+    let result = sqlx::query!(
+        r#"
+        DELETE FROM departamentos_jefes
+        WHERE departamento_id = $1 AND usuario_id = $2
+        "#,
+        id,
+        usuario_id,
+    )
+    .execute(pool)
+    .await
+    .context("Failed to execute query")?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+/// Administradores asignados directamente al departamento
+#[tracing::instrument(
+    name = "Query administradores de departamento",
+    skip(pool)
+)]
+pub async fn listar_administradores_sqlx(
+    pool: &PgPool,
+    id: i32,
+) -> Result<Vec<JefeDepartamento>, anyhow::Error> {
+    let administradores = sqlx::query_as!(
+        JefeDepartamento,
+        r#"
+        SELECT u.usuario_id, u.nombres, u.apellidos, u.email, a.departamento_id
+        FROM administradores_departamento a JOIN usuarios u ON u.usuario_id = a.usuario_id
+        WHERE a.departamento_id = $1 AND u.activo
+        ORDER BY a.creado_en
+        "#,
+        id,
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to execute query")?;
+
+    Ok(administradores)
+}
+
+/// Usuarios que administran al menos un departamento, su alcance incluye los
+/// subdepartamentos y cambia con la jerarquia
+#[tracing::instrument(
+    name = "Query usuarios administradores de departamento",
+    skip(pool)
+)]
+pub async fn listar_ids_administradores_sqlx(
+    pool: &PgPool,
+) -> Result<Vec<Uuid>, anyhow::Error> {
+    let rows = sqlx::query!("SELECT DISTINCT usuario_id FROM administradores_departamento")
+        .fetch_all(pool)
+        .await
+        .context("Failed to execute query")?;
+
+    Ok(rows.into_iter().map(|row| row.usuario_id).collect())
+}
+
+/// Departamentos asignados al usuario como administrador y todos sus descendientes
+#[tracing::instrument(
+    name = "Query departamentos administrados",
+    skip(pool)
+)]
+pub async fn obtener_departamentos_administrados_sqlx(
+    pool: &PgPool,
+    usuario_id: &Uuid,
+) -> Result<Vec<i32>, anyhow::Error> {
+    let rows = sqlx::query!(
+        r#"
+        WITH RECURSIVE administrados AS (
+            SELECT departamento_id as id
+            FROM administradores_departamento
+            WHERE usuario_id = $1
+            UNION
+            SELECT d.id
+            FROM departamentos d JOIN administrados a ON d.padre_id = a.id
+        )
+        SELECT id as "id!" FROM administrados ORDER BY id
+        "#,
+        usuario_id,
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to execute query")?;
+
+    Ok(rows.into_iter().map(|row| row.id).collect())
+}
+
+/// Regresa false si ya era administrador
+#[tracing::instrument(
+    name = "Query agregar administrador de departamento",
+    skip(pool)
+)]
+pub async fn agregar_administrador_sqlx(
+    pool: &PgPool,
+    id: i32,
+    usuario_id: &Uuid,
+    creado_por: &Uuid,
+) -> Result<bool, anyhow::Error> {
+    let result = sqlx::query!(
+        r#"
+        INSERT INTO administradores_departamento (departamento_id, usuario_id, creado_por)
+        VALUES ($1, $2, $3)
+        ON CONFLICT DO NOTHING
+        "#,
+        id,
+        usuario_id,
+        creado_por,
+    )
+    .execute(pool)
+    .await
+    .context("Failed to execute query")?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+/// Regresa false si no era administrador
+#[tracing::instrument(
+    name = "Query quitar administrador de departamento",
+    skip(pool)
+)]
+pub async fn quitar_administrador_sqlx(
+    pool: &PgPool,
+    id: i32,
+    usuario_id: &Uuid,
+) -> Result<bool, anyhow::Error> {
+    let result = sqlx::query!(
+        r#"
+        DELETE FROM administradores_departamento
+        WHERE departamento_id = $1 AND usuario_id = $2
+        "#,
+        id,
+        usuario_id,
+    )
+    .execute(pool)
+    .await
+    .context("Failed to execute query")?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+/// Borra el departamento moviendo todo al destino en una sola transaccion,
+/// sin destino solo se borra si no tiene miembros, vehiculos ni flujos de aprobacion
+#[tracing::instrument(
+    name = "Query borrar departamento",
+    skip(pool)
+)]
+pub async fn borrar_departamento_sqlx(
+    pool: &PgPool,
+    id: i32,
+    destino: Option<i32>,
+    mover_jefes: bool,
+) -> Result<ResultadoBorrado, anyhow::Error> {
+    let mut transaction = pool.begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+
+    // Mismo candado que los cambios de jerarquia
+    sqlx::query!("LOCK TABLE departamentos IN SHARE ROW EXCLUSIVE MODE")
+        .execute(&mut transaction)
+        .await
+        .context("Failed to lock departments")?;
+
+    let padre_id = match sqlx::query!("SELECT padre_id FROM departamentos WHERE id = $1", id)
+        .fetch_optional(&mut transaction)
+        .await
+        .context("Failed to fetch department")?
+    {
+        Some(row) => row.padre_id,
+        None => return Ok(ResultadoBorrado::NoEncontrado),
+    };
+
+    let destino = match destino {
+        Some(destino) => destino,
+        None => {
+            let uso = sqlx::query!(
+                r#"
+                SELECT
+                    (SELECT COUNT(*) FROM usuarios WHERE departamento = $1) as "miembros!",
+                    (SELECT COUNT(*) FROM vehiculos WHERE departamento = $1) as "vehiculos!",
+                    (SELECT COUNT(*) FROM flujos_aprobacion WHERE departamento_id = $1) as "flujos!"
+                "#,
+                id,
+            )
+            .fetch_one(&mut transaction)
+            .await
+            .context("Failed to count department usage")?;
+
+            if uso.miembros > 0 || uso.vehiculos > 0 || uso.flujos > 0 {
+                return Ok(ResultadoBorrado::EnUso {
+                    miembros: uso.miembros,
+                    vehiculos: uso.vehiculos,
+                    flujos: uso.flujos,
+                });
+            }
+
+            sqlx::query!("DELETE FROM departamentos WHERE id = $1", id)
+                .execute(&mut transaction)
+                .await
+                .context("Failed to delete department")?;
+            transaction.commit()
+                .await
+                .context("Failed to commit SQL transaction")?;
+
+            return Ok(ResultadoBorrado::Borrado(Reasignacion {
+                destino: None,
+                miembros: 0,
+                vehiculos: 0,
+                subdepartamentos: 0,
+                flujos: 0,
+                usuarios: vec![],
+            }));
+        },
+    };
+
+    // Si el destino esta debajo del departamento sube a su lugar, asi sus
+    // ancestros no terminan colgando de el
+    let posicion_destino = sqlx::query!(
+        r#"
+        WITH RECURSIVE ancestros AS (
+            SELECT id, padre_id FROM departamentos WHERE id = $2
+            UNION
+            SELECT d.id, d.padre_id
+            FROM departamentos d JOIN ancestros a ON d.id = a.padre_id
+        )
+        SELECT
+            EXISTS (SELECT 1 FROM ancestros WHERE id = $2) as "existe!",
+            EXISTS (SELECT 1 FROM ancestros WHERE id = $1) as "descendiente!"
+        "#,
+        id,
+        destino,
+    )
+    .fetch_one(&mut transaction)
+    .await
+    .context("Failed to check target department")?;
+
+    if !posicion_destino.existe {
+        return Ok(ResultadoBorrado::DestinoNoEncontrado);
+    }
+    if posicion_destino.descendiente {
+        sqlx::query!("UPDATE departamentos SET padre_id = $2 WHERE id = $1", destino, padre_id)
+            .execute(&mut transaction)
+            .await
+            .context("Failed to move target department")?;
+    }
+
+    let usuarios: Vec<Uuid> = sqlx::query!(
+        r#"
+        UPDATE usuarios
+        SET departamento = $2, modificado_en = now()
+        WHERE departamento = $1
+        RETURNING usuario_id
+        "#,
+        id,
+        destino,
+    )
+    .fetch_all(&mut transaction)
+    .await
+    .context("Failed to move members")?
+    .into_iter()
+    .map(|row| row.usuario_id)
+    .collect();
+
+    let vehiculos = sqlx::query!(
+        r#"
+        UPDATE vehiculos
+        SET departamento = $2, modificado_en = now()
+        WHERE departamento = $1
+        "#,
+        id,
+        destino,
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to move vehicles")?
+    .rows_affected();
+
+    sqlx::query!("UPDATE invitaciones SET departamento = $2 WHERE departamento = $1", id, destino)
+        .execute(&mut transaction)
+        .await
+        .context("Failed to move invitations")?;
+
+    // Las peticiones en curso conservan su flujo, ahora del departamento destino
+    let flujos = sqlx::query!(
+        "UPDATE flujos_aprobacion SET departamento_id = $2 WHERE departamento_id = $1",
+        id,
+        destino,
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to move workflows")?
+    .rows_affected();
+
+    // El consumo del mes se sigue contando en el departamento que absorbe al borrado
+    sqlx::query!("UPDATE peticiones SET departamento_id = $2 WHERE departamento_id = $1", id, destino)
+        .execute(&mut transaction)
+        .await
+        .context("Failed to move requests")?;
+
+    let subdepartamentos = sqlx::query!(
+        "UPDATE departamentos SET padre_id = $2 WHERE padre_id = $1 AND id <> $2",
+        id,
+        destino,
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to move child departments")?
+    .rows_affected();
+
+    if mover_jefes {
+        sqlx::query!(
+            r#"
+            INSERT INTO departamentos_jefes (departamento_id, usuario_id, creado_en)
+            SELECT $2, usuario_id, creado_en
+            FROM departamentos_jefes
+            WHERE departamento_id = $1
+            ON CONFLICT DO NOTHING
+            "#,
+            id,
+            destino,
+        )
+        .execute(&mut transaction)
+        .await
+        .context("Failed to move department heads")?;
+
+        sqlx::query!(
+            r#"
+            INSERT INTO administradores_departamento (departamento_id, usuario_id, creado_por, creado_en)
+            SELECT $2, usuario_id, creado_por, creado_en
+            FROM administradores_departamento
+            WHERE departamento_id = $1
+            ON CONFLICT DO NOTHING
+            "#,
+            id,
+            destino,
+        )
+        .execute(&mut transaction)
+        .await
+        .context("Failed to move department administrators")?;
+    }
+
+    sqlx::query!("DELETE FROM departamentos WHERE id = $1", id)
+        .execute(&mut transaction)
+        .await
+        .context("Failed to delete department")?;
+
+    transaction.commit()
+        .await
+        .context("Failed to commit SQL transaction")?;
+
+    Ok(ResultadoBorrado::Borrado(Reasignacion {
+        destino: Some(destino),
+        miembros: usuarios.len(),
+        vehiculos,
+        subdepartamentos,
+        flujos,
+        usuarios,
+    }))
+}
+
+/// El padre no existe o el centro de costos ya lo usa otro departamento
+pub fn es_jerarquia_invalida(e: &anyhow::Error) -> Option<&'static str> {
+    let e = e.downcast_ref::<sqlx::Error>()?.as_database_error()?;
+    match (e.code().as_deref(), e.constraint()) {
+        (Some("23503"), Some("departamentos_padre_id_fkey")) => Some("No se encontro el departamento padre"),
+        (Some("23505"), Some("departamentos_centro_costos_key")) => Some("El centro de costos ya esta asignado"),
+        _ => None,
+    }
+}
@@ -0,0 +1,34 @@
+use actix_web::{HttpResponse, web};
+use sqlx::PgPool;
+
+use crate::api_response::{e500, ApiResponse, e403};
+use crate::authentication::current_user::CurrentUser;
+
+use super::sqlx::{obtener_invitaciones_pendientes_sqlx, Invitacion};
+
+
+#[tracing::instrument(
+    name = "Obtener invitaciones pendientes",
+    skip_all
+)]
+pub async fn get_invitations(
+    usuario: CurrentUser,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+
+    if !usuario.es_admin() {
+        return Err(e403().with_message("No tienes los permisos requeridos"))?;
+    }
+
+    // Query DB
+    let invitaciones = obtener_invitaciones_pendientes_sqlx(&pool).await
+        .map_err(|_| e500())?;
+
+    // Respuesta exitosa
+    let api_response = ApiResponse::<Vec<Invitacion>>::new()
+        .with_message("Lista de invitaciones pendientes")
+        .with_data(invitaciones)
+        .to_resp();
+
+    Ok(api_response)
+}
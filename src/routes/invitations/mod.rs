@@ -0,0 +1,5 @@
+pub mod get;
+pub mod post;
+pub mod delete;
+
+pub mod sqlx;
@@ -0,0 +1,131 @@
+use actix_web::{HttpResponse, web};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::api_response::{e400, e404, e409, e500, ApiResponse, e403};
+use crate::authentication::current_user::CurrentUser;
+use crate::email_client::EmailClient;
+use crate::startup::ApplicationBaseUrl;
+
+use super::sqlx::{
+    existe_usuario_o_invitacion_sqlx, generar_token_invitacion, insertar_invitacion_sqlx,
+    renovar_invitacion_sqlx, Invitacion, NuevaInvitacion, INVITACION_EXPIRACION_DIAS,
+};
+
+
+#[tracing::instrument(
+    name = "Crear invitacion",
+    skip(usuario, pool, email_client, base_url)
+)]
+pub async fn post_invitation(
+    usuario: CurrentUser,
+    pool: web::Data<PgPool>,
+    email_client: web::Data<EmailClient>,
+    base_url: web::Data<ApplicationBaseUrl>,
+    body: web::Json<NuevaInvitacion>,
+) -> Result<HttpResponse, actix_web::Error> {
+
+    if !usuario.es_admin() {
+        return Err(e403().with_message("No tienes los permisos requeridos"))?;
+    }
+
+    let nueva = body.into_inner();
+
+    if !validator::validate_email(nueva.email.trim()) {
+        return Err(e400().with_message("Correo electronico invalido"))?;
+    }
+
+    let (existe_usuario, existe_invitacion) = existe_usuario_o_invitacion_sqlx(&pool, nueva.email.trim()).await
+        .map_err(|_| e500())?;
+
+    if existe_usuario {
+        return Err(e409().with_message("Ya existe usuario con ese correo electronico"))?;
+    }
+    if existe_invitacion {
+        return Err(e409().with_message("Ya existe una invitacion pendiente para ese correo electronico"))?;
+    }
+
+    // Query insertar invitacion DB
+    let token = generar_token_invitacion();
+    let invitacion = insertar_invitacion_sqlx(&pool, nueva, &usuario.usuario_id, &token).await
+        .map_err(|_| e500())?
+        .ok_or(e400().with_message("No existe el departamento"))?;
+
+    enviar_invitacion(&email_client, &base_url.0, &invitacion.email, &token).await
+        .map_err(|_| e500())?;
+
+    // Respuesta exitosa
+    let api_response = ApiResponse::<Invitacion>::new()
+        .with_status_code(201)
+        .with_message("Invitacion enviada")
+        .with_data(invitacion)
+        .to_resp();
+
+    Ok(api_response)
+}
+
+
+#[tracing::instrument(
+    name = "Reenviar invitacion",
+    skip(usuario, pool, email_client, base_url)
+)]
+pub async fn resend_invitation(
+    usuario: CurrentUser,
+    pool: web::Data<PgPool>,
+    email_client: web::Data<EmailClient>,
+    base_url: web::Data<ApplicationBaseUrl>,
+    uuid: web::Path<Uuid>,
+) -> Result<HttpResponse, actix_web::Error> {
+
+    if !usuario.es_admin() {
+        return Err(e403().with_message("No tienes los permisos requeridos"))?;
+    }
+
+    // El enlace anterior deja de funcionar
+    let token = generar_token_invitacion();
+    let email = renovar_invitacion_sqlx(&pool, &uuid, &token).await
+        .map_err(|_| e500())?
+        .ok_or(e404().with_message("No se encontro la invitacion pendiente"))?;
+
+    enviar_invitacion(&email_client, &base_url.0, &email, &token).await
+        .map_err(|_| e500())?;
+
+    // Respuesta exitosa
+    let api_response = ApiResponse::<()>::new()
+        .with_message("Invitacion reenviada")
+        .to_resp();
+
+    Ok(api_response)
+}
+
+
+#[tracing::instrument(
+    name = "Send invitation email",
+    skip(email_client, email, token)
+)]
+async fn enviar_invitacion(
+    email_client: &EmailClient,
+    base_url: &str,
+    email: &str,
+    token: &str,
+) -> Result<(), anyhow::Error> {
+    let invitation_link = format!(
+        "{}/api/auth/invitations?token={}",
+        base_url,
+        token,
+        );
+
+    email_client.send_email(
+        email,
+        "Invitacion a Control Parque Vehicular",
+        &format!("Te invitaron a Control Parque Vehicular!<br />\
+                 Haz click <a href=\"{}\">aqui</a> para crear tu contraseña.<br />\
+                 El enlace es valido durante {} dias.",
+                 invitation_link, INVITACION_EXPIRACION_DIAS),
+        &format!("Te invitaron a Control Parque Vehicular!\n\
+                 Visita {} para crear tu contraseña.\n\
+                 El enlace es valido durante {} dias.",
+                 invitation_link, INVITACION_EXPIRACION_DIAS),
+    )
+    .await
+}
@@ -0,0 +1,344 @@
+use anyhow::Context;
+use chrono::NaiveDateTime;
+use common::models::user::UsuarioRol;
+use rand::{thread_rng, Rng, distributions::Alphanumeric};
+use sha2::{Digest, Sha256};
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+
+/// Dias que el enlace de la invitacion es valido
+pub const INVITACION_EXPIRACION_DIAS: i64 = 7;
+
+
+#[derive(Debug, serde::Serialize)]
+pub struct Invitacion {
+    pub invitacion_id: Uuid,
+    pub email: String,
+    pub departamento: Option<String>,
+    pub rol: UsuarioRol,
+    pub numero_empleado: Option<i16>,
+    pub invitado_por: Option<Uuid>,
+    pub creado_en: NaiveDateTime,
+    pub expira_en: NaiveDateTime,
+}
+
+#[derive(Debug, serde::Deserialize)]
+pub struct NuevaInvitacion {
+    pub email: String,
+    // Nombre del departamento
+    pub departamento: Option<String>,
+    pub rol: Option<UsuarioRol>,
+    pub numero_empleado: Option<i16>,
+}
+
+
+pub fn generar_token_invitacion() -> String {
+    let mut rng = thread_rng();
+    std::iter::repeat_with(|| rng.sample(Alphanumeric))
+        .map(char::from)
+        .take(40)
+        .collect()
+}
+
+pub fn hash_token_invitacion(token: &str) -> String {
+    format!("{:x}", Sha256::digest(token.as_bytes()))
+}
+
+fn expiracion() -> NaiveDateTime {
+    chrono::Utc::now().naive_utc() + chrono::Duration::days(INVITACION_EXPIRACION_DIAS)
+}
+
+
+#[tracing::instrument(
+    name = "Query existe usuario o invitacion pendiente",
+    skip(pool)
+)]
+pub async fn existe_usuario_o_invitacion_sqlx(
+    pool: &PgPool,
+    email: &str,
+) -> Result<(bool, bool), anyhow::Error> {
+    let row = sqlx::query!(
+        r#"
+        SELECT
+            EXISTS(SELECT 1 FROM usuarios WHERE lower(email) = lower($1)) as "usuario!",
+            EXISTS(
+                SELECT 1 FROM invitaciones
+                WHERE lower(email) = lower($1)
+                    AND aceptado_en IS NULL
+                    AND revocado_en IS NULL
+            ) as "invitacion!"
+        "#,
+        email,
+    )
+    .fetch_one(pool)
+    .await
+    .context("Failed to execute query")?;
+
+    Ok((row.usuario, row.invitacion))
+}
+
+/// Regresa None si el departamento no existe
+#[tracing::instrument(
+    name = "Query insertar invitacion",
+    skip(pool, token)
+)]
+pub async fn insertar_invitacion_sqlx(
+    pool: &PgPool,
+    nueva: NuevaInvitacion,
+    invitado_por: &Uuid,
+    token: &str,
+) -> Result<Option<Invitacion>, anyhow::Error> {
+    let departamento_id = match &nueva.departamento {
+        Some(nombre) => {
+            let row = sqlx::query!("SELECT id FROM departamentos WHERE nombre = $1", nombre)
+                .fetch_optional(pool)
+                .await
+                .context("Failed to execute query")?;
+
+            match row {
+                Some(row) => Some(row.id),
+                None => return Ok(None),
+            }
+        },
+        None => None,
+    };
+
+    let invitacion = sqlx::query_as!(
+        Invitacion,
+        r#"
+        WITH nueva AS (
+            INSERT INTO invitaciones
+            (invitacion_id, email, departamento, rol, numero_empleado, token_hash, invitado_por, expira_en)
+            VALUES ($1, $2, $3, COALESCE($4, 'normal'::usuario_rol), $5, $6, $7, $8)
+            RETURNING *
+        )
+        SELECT
+            nueva.invitacion_id,
+            nueva.email,
+            d.nombre as "departamento?",
+            nueva.rol as "rol!: UsuarioRol",
+            nueva.numero_empleado,
+            nueva.invitado_por,
+            nueva.creado_en,
+            nueva.expira_en
+        FROM nueva LEFT JOIN departamentos d
+        ON nueva.departamento = d.id
+        "#,
+        Uuid::new_v4(),
+        nueva.email.trim(),
+        departamento_id,
+        nueva.rol as Option<UsuarioRol>,
+        nueva.numero_empleado,
+        hash_token_invitacion(token),
+        invitado_por,
+        expiracion(),
+    )
+    .fetch_one(pool)
+    .await
+    .context("Failed to execute query")?;
+
+    Ok(Some(invitacion))
+}
+
+#[tracing::instrument(
+    name = "Query invitaciones pendientes",
+    skip(pool)
+)]
+pub async fn obtener_invitaciones_pendientes_sqlx(
+    pool: &PgPool,
+) -> Result<Vec<Invitacion>, anyhow::Error> {
+    let invitaciones = sqlx::query_as!(
+        Invitacion,
+        r#"
+        SELECT
+            i.invitacion_id,
+            i.email,
+            d.nombre as "departamento?",
+            i.rol as "rol!: UsuarioRol",
+            i.numero_empleado,
+            i.invitado_por,
+            i.creado_en,
+            i.expira_en
+        FROM invitaciones i LEFT JOIN departamentos d
+        ON i.departamento = d.id
+        WHERE i.aceptado_en IS NULL AND i.revocado_en IS NULL
+        ORDER BY i.creado_en DESC
+        "#,
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to execute query")?;
+
+    Ok(invitaciones)
+}
+
+/// Cambia el token de una invitacion pendiente y reinicia su expiracion,
+/// el enlace anterior deja de funcionar. Regresa el email de la invitacion.
+#[tracing::instrument(
+    name = "Query renovar token de invitacion",
+    skip(pool, token)
+)]
+pub async fn renovar_invitacion_sqlx(
+    pool: &PgPool,
+    invitacion_id: &Uuid,
+    token: &str,
+) -> Result<Option<String>, anyhow::Error> {
+    let row = sqlx::query!(
+        r#"
+        UPDATE invitaciones
+        SET token_hash = $2, expira_en = $3
+        WHERE invitacion_id = $1
+            AND aceptado_en IS NULL
+            AND revocado_en IS NULL
+        RETURNING email
+        "#,
+        invitacion_id,
+        hash_token_invitacion(token),
+        expiracion(),
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to execute query")?;
+
+    Ok(row.map(|row| row.email))
+}
+
+#[tracing::instrument(
+    name = "Query revocar invitacion",
+    skip(pool)
+)]
+pub async fn revocar_invitacion_sqlx(
+    pool: &PgPool,
+    invitacion_id: &Uuid,
+) -> Result<bool, anyhow::Error> {
+    let result = sqlx::query!(
+        r#"
+        UPDATE invitaciones
+        SET revocado_en = now()
+        WHERE invitacion_id = $1
+            AND aceptado_en IS NULL
+            AND revocado_en IS NULL
+        "#,
+        invitacion_id,
+    )
+    .execute(pool)
+    .await
+    .context("Failed to execute query")?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+
+/// Invitacion vigente con el token
+#[tracing::instrument(
+    name = "Query invitacion por token",
+    skip_all
+)]
+pub async fn obtener_invitacion_por_token_sqlx(
+    pool: &PgPool,
+    token: &str,
+) -> Result<Option<Invitacion>, anyhow::Error> {
+    let invitacion = sqlx::query_as!(
+        Invitacion,
+        r#"
+        SELECT
+            i.invitacion_id,
+            i.email,
+            d.nombre as "departamento?",
+            i.rol as "rol!: UsuarioRol",
+            i.numero_empleado,
+            i.invitado_por,
+            i.creado_en,
+            i.expira_en
+        FROM invitaciones i LEFT JOIN departamentos d
+        ON i.departamento = d.id
+        WHERE i.token_hash = $1
+            AND i.aceptado_en IS NULL
+            AND i.revocado_en IS NULL
+            AND i.expira_en > now()
+        "#,
+        hash_token_invitacion(token),
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to execute query")?;
+
+    Ok(invitacion)
+}
+
+/// Marca la invitacion como aceptada, el token deja de ser valido.
+/// Regresa None si el token no es de una invitacion vigente.
+#[tracing::instrument(
+    name = "Query aceptar invitacion",
+    skip_all
+)]
+pub async fn aceptar_invitacion_sqlx(
+    transaction: &mut Transaction<'_, Postgres>,
+    token: &str,
+) -> Result<Option<(Uuid, String)>, anyhow::Error> {
+    let row = sqlx::query!(
+        r#"
+        UPDATE invitaciones
+        SET aceptado_en = now()
+        WHERE token_hash = $1
+            AND aceptado_en IS NULL
+            AND revocado_en IS NULL
+            AND expira_en > now()
+        RETURNING invitacion_id, email
+        "#,
+        hash_token_invitacion(token),
+    )
+    .fetch_optional(transaction)
+    .await
+    .context("Failed to execute query")?;
+
+    Ok(row.map(|row| (row.invitacion_id, row.email)))
+}
+
+/// Crea la cuenta verificada con los datos de la invitacion
+#[tracing::instrument(
+    name = "Query insertar usuario invitado",
+    skip(transaction, password_hash)
+)]
+pub async fn insertar_usuario_invitado_sqlx(
+    transaction: &mut Transaction<'_, Postgres>,
+    invitacion_id: &Uuid,
+    nombres: &str,
+    apellidos: &str,
+    password_hash: &str,
+) -> Result<Uuid, anyhow::Error> {
+    let row = sqlx::query!(
+        r#"
+        INSERT INTO usuarios
+        (usuario_id, nombres, apellidos, email, password_hash, verificado, departamento, rol, numero_empleado)
+        SELECT $2, $3, $4, i.email, $5, true, i.departamento, i.rol, i.numero_empleado
+        FROM invitaciones i
+        WHERE i.invitacion_id = $1
+        RETURNING usuario_id
+        "#,
+        invitacion_id,
+        Uuid::new_v4(),
+        nombres,
+        apellidos,
+        password_hash,
+    )
+    .fetch_one(&mut *transaction)
+    .await
+    .context("Failed to insert invited user")?;
+
+    sqlx::query!(
+        r#"
+        UPDATE invitaciones
+        SET usuario_id = $2
+        WHERE invitacion_id = $1
+        "#,
+        invitacion_id,
+        row.usuario_id,
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("Failed to execute query")?;
+
+    Ok(row.usuario_id)
+}
@@ -0,0 +1,86 @@
+use actix_web::{HttpResponse, web};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::api_response::{ApiResponse, e403, e404, e500};
+use crate::authentication::current_user::CurrentUser;
+
+use super::sqlx::{listar_licencias_sqlx, obtener_licencia_sqlx, FiltroLicencias, Licencia};
+
+
+#[tracing::instrument(
+    name = "Obtener mi licencia",
+    skip_all
+)]
+pub async fn get_my_license(
+    usuario: CurrentUser,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+
+    let licencia = obtener_licencia_sqlx(&pool, &usuario.usuario_id).await
+        .map_err(|_| e500())?
+        .ok_or(e404().with_message("No has registrado tu licencia"))?;
+
+    // Respuesta exitosa
+    let api_response = ApiResponse::<Licencia>::new()
+        .with_message("Tu licencia")
+        .with_data(licencia)
+        .to_resp();
+
+    Ok(api_response)
+}
+
+
+#[tracing::instrument(
+    name = "Obtener licencias",
+    skip(usuario, pool)
+)]
+pub async fn get_licenses(
+    usuario: CurrentUser,
+    pool: web::Data<PgPool>,
+    filtro: web::Query<FiltroLicencias>,
+) -> Result<HttpResponse, actix_web::Error> {
+
+    if !usuario.es_admin() {
+        return Err(e403().with_message("No tienes los permisos requeridos"))?;
+    }
+
+    let licencias = listar_licencias_sqlx(&pool, filtro.pendientes.unwrap_or(false)).await
+        .map_err(|_| e500())?;
+
+    // Respuesta exitosa
+    let api_response = ApiResponse::<Vec<Licencia>>::new()
+        .with_message("Lista de licencias")
+        .with_data(licencias)
+        .to_resp();
+
+    Ok(api_response)
+}
+
+
+#[tracing::instrument(
+    name = "Obtener licencia de usuario",
+    skip(usuario, pool)
+)]
+pub async fn get_license_by_user(
+    usuario: CurrentUser,
+    pool: web::Data<PgPool>,
+    uuid: web::Path<Uuid>,
+) -> Result<HttpResponse, actix_web::Error> {
+
+    if !usuario.es_admin() {
+        return Err(e403().with_message("No tienes los permisos requeridos"))?;
+    }
+
+    let licencia = obtener_licencia_sqlx(&pool, &uuid).await
+        .map_err(|_| e500())?
+        .ok_or(e404().with_message("El usuario no ha registrado su licencia"))?;
+
+    // Respuesta exitosa
+    let api_response = ApiResponse::<Licencia>::new()
+        .with_message("Licencia")
+        .with_data(licencia)
+        .to_resp();
+
+    Ok(api_response)
+}
@@ -0,0 +1,79 @@
+use actix_web::{HttpRequest, HttpResponse, web};
+use actix_files::NamedFile;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::api_response::{e403, e404, e500};
+use crate::authentication::current_user::CurrentUser;
+use crate::upload::image::get_uploads_path;
+
+use super::sqlx::{obtener_licencia_sqlx, LadoLicencia};
+
+
+/// Las imagenes de licencias solo las ven su dueño y los administradores
+async fn servir_imagen_licencia(
+    pool: &PgPool,
+    usuario_id: &Uuid,
+    lado: LadoLicencia,
+    req: &HttpRequest,
+) -> Result<HttpResponse, actix_web::Error> {
+    let licencia = obtener_licencia_sqlx(pool, usuario_id).await
+        .map_err(|_| e500())?
+        .ok_or(e404().with_message("No se encontro la licencia"))?;
+
+    let imagen = match lado {
+        LadoLicencia::Frente => licencia.imagen_frente,
+        LadoLicencia::Reverso => licencia.imagen_reverso,
+    }
+    .ok_or(e404().with_message("No se encontro el archivo"))?;
+
+    let file_path = get_uploads_path()
+        .map_err(|_| e500())?
+        .join("licenses")
+        .join(imagen);
+
+    // Obtener el archivo y enviar respuesta
+    match NamedFile::open_async(file_path).await {
+        Ok(f) => Ok(f.into_response(req)),
+        Err(e) => {
+            match e.kind() {
+                std::io::ErrorKind::NotFound => { Err(e404().with_message("No se encontro el archivo"))? },
+                _ => { Err(e500())? },
+            }
+        }
+    }
+}
+
+
+#[tracing::instrument(
+    name = "Serve imagen de mi licencia",
+    skip(usuario, pool, req)
+)]
+pub async fn get_my_license_picture(
+    usuario: CurrentUser,
+    pool: web::Data<PgPool>,
+    lado: web::Path<LadoLicencia>,
+    req: HttpRequest,
+) -> Result<HttpResponse, actix_web::Error> {
+    servir_imagen_licencia(&pool, &usuario.usuario_id, *lado, &req).await
+}
+
+
+#[tracing::instrument(
+    name = "Serve imagen de licencia de usuario",
+    skip(usuario, pool, req)
+)]
+pub async fn get_license_picture(
+    usuario: CurrentUser,
+    pool: web::Data<PgPool>,
+    path: web::Path<(Uuid, LadoLicencia)>,
+    req: HttpRequest,
+) -> Result<HttpResponse, actix_web::Error> {
+
+    if !usuario.es_admin() {
+        return Err(e403().with_message("No tienes los permisos requeridos"))?;
+    }
+
+    let (usuario_id, lado) = path.into_inner();
+    servir_imagen_licencia(&pool, &usuario_id, lado, &req).await
+}
@@ -0,0 +1,6 @@
+pub mod get;
+pub mod post;
+pub mod patch;
+pub mod image;
+
+pub mod sqlx;
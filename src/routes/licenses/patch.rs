@@ -0,0 +1,64 @@
+use actix_web::{HttpRequest, HttpResponse, web};
+use actix_multipart::Multipart;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::api_response::{ApiResponse, e404, e500};
+use crate::authentication::current_user::CurrentUser;
+use crate::upload::image::{get_uploads_path, handle_picture_multipart};
+
+use super::sqlx::{actualizar_imagen_licencia_sqlx, obtener_licencia_sqlx, LadoLicencia, Licencia};
+
+
+#[tracing::instrument(
+    name = "Actualizar imagen de mi licencia",
+    skip(usuario, pool, payload, req)
+)]
+pub async fn patch_my_license_picture(
+    usuario: CurrentUser,
+    pool: web::Data<PgPool>,
+    lado: web::Path<LadoLicencia>,
+    payload: Multipart,
+    req: HttpRequest,
+) -> Result<HttpResponse, actix_web::Error> {
+
+    // La licencia se registra antes de subir sus imagenes
+    obtener_licencia_sqlx(&pool, &usuario.usuario_id).await
+        .map_err(|_| e500())?
+        .ok_or(e404().with_message("No has registrado tu licencia"))?;
+
+    // Guardar Imagen
+    let base_path = get_uploads_path()
+        .map_err(|_| e500())?
+        .join("licenses");
+    std::fs::create_dir_all(&base_path)
+        .map_err(|_| e500())?;
+
+    let picture_filename = format!("{}-{}.jpeg", usuario.usuario_id, Uuid::new_v4());
+    let save_path = base_path.join(&picture_filename);
+
+    handle_picture_multipart(payload, req, &save_path.to_string_lossy(), None).await
+        .map_err(|_| e500())?;
+
+    let imagen_anterior = actualizar_imagen_licencia_sqlx(&pool, &usuario.usuario_id, *lado, &picture_filename).await
+        .map_err(|_| e500())?
+        .ok_or(e404().with_message("No has registrado tu licencia"))?;
+
+    if let Some(imagen_anterior) = imagen_anterior {
+        if let Err(e) = std::fs::remove_file(base_path.join(imagen_anterior)) {
+            tracing::warn!("No se pudo borrar la imagen anterior de la licencia: {:?}", e);
+        }
+    }
+
+    let licencia = obtener_licencia_sqlx(&pool, &usuario.usuario_id).await
+        .map_err(|_| e500())?
+        .ok_or(e500())?;
+
+    // Respuesta exitosa
+    let api_response = ApiResponse::<Licencia>::new()
+        .with_message("Imagen de la licencia actualizada, un administrador la verificara")
+        .with_data(licencia)
+        .to_resp();
+
+    Ok(api_response)
+}
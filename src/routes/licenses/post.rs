@@ -0,0 +1,155 @@
+use actix_web::{HttpResponse, web};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::api_response::{ApiResponse, e400, e403, e404, e409, e500};
+use crate::authentication::current_user::CurrentUser;
+use crate::email_client::EmailClient;
+use crate::routes::users::sqlx::obtener_usuario_por_id_sqlx;
+
+use super::sqlx::{
+    es_clase_invalida, guardar_licencia_sqlx, obtener_licencia_sqlx, rechazar_licencia_sqlx,
+    verificar_licencia_sqlx, Licencia, NuevaLicencia,
+};
+
+
+#[derive(Debug, serde::Deserialize)]
+pub struct RechazoLicencia {
+    pub motivo: String,
+}
+
+
+#[tracing::instrument(
+    name = "Registrar mi licencia",
+    skip(usuario, pool)
+)]
+pub async fn post_my_license(
+    usuario: CurrentUser,
+    pool: web::Data<PgPool>,
+    body: web::Json<NuevaLicencia>,
+) -> Result<HttpResponse, actix_web::Error> {
+
+    let nueva_licencia = body.into_inner();
+
+    if nueva_licencia.numero.trim().is_empty() || nueva_licencia.estado_emisor.trim().is_empty() {
+        return Err(e400().with_message("El numero y el estado emisor son requeridos"))?;
+    }
+    if nueva_licencia.expira_en < chrono::Utc::now().date_naive() {
+        return Err(e400().with_message("La licencia ya esta vencida"))?;
+    }
+
+    let licencia = guardar_licencia_sqlx(&pool, &usuario.usuario_id, &nueva_licencia).await
+        .map_err(|e| {
+            if es_clase_invalida(&e) {
+                e400().with_message("Clase de licencia invalida")
+            } else {
+                e500()
+            }
+        })?;
+
+    // Respuesta exitosa
+    let api_response = ApiResponse::<Licencia>::new()
+        .with_message("Licencia registrada, un administrador la verificara")
+        .with_data(licencia)
+        .to_resp();
+
+    Ok(api_response)
+}
+
+
+#[tracing::instrument(
+    name = "Verificar licencia",
+    skip(usuario, pool)
+)]
+pub async fn verify_license(
+    usuario: CurrentUser,
+    pool: web::Data<PgPool>,
+    uuid: web::Path<Uuid>,
+) -> Result<HttpResponse, actix_web::Error> {
+
+    if !usuario.es_admin() {
+        return Err(e403().with_message("No tienes los permisos requeridos"))?;
+    }
+    // Un administrador no verifica su propia licencia
+    if *uuid == usuario.usuario_id {
+        return Err(e403().with_message("No puedes verificar tu propia licencia"))?;
+    }
+
+    let licencia = obtener_licencia_sqlx(&pool, &uuid).await
+        .map_err(|_| e500())?
+        .ok_or(e404().with_message("El usuario no ha registrado su licencia"))?;
+
+    if licencia.expira_en < chrono::Utc::now().date_naive() {
+        return Err(e409().with_message("La licencia esta vencida"))?;
+    }
+    if licencia.imagen_frente.is_none() || licencia.imagen_reverso.is_none() {
+        return Err(e409().with_message("Faltan las imagenes de la licencia"))?;
+    }
+
+    let licencia = verificar_licencia_sqlx(&pool, &uuid, &usuario.usuario_id).await
+        .map_err(|_| e500())?
+        .ok_or(e404().with_message("El usuario no ha registrado su licencia"))?;
+
+    // Respuesta exitosa
+    let api_response = ApiResponse::<Licencia>::new()
+        .with_message("Licencia verificada")
+        .with_data(licencia)
+        .to_resp();
+
+    Ok(api_response)
+}
+
+
+#[tracing::instrument(
+    name = "Rechazar licencia",
+    skip(usuario, pool, email_client)
+)]
+pub async fn reject_license(
+    usuario: CurrentUser,
+    pool: web::Data<PgPool>,
+    email_client: web::Data<EmailClient>,
+    uuid: web::Path<Uuid>,
+    body: web::Json<RechazoLicencia>,
+) -> Result<HttpResponse, actix_web::Error> {
+
+    if !usuario.es_admin() {
+        return Err(e403().with_message("No tienes los permisos requeridos"))?;
+    }
+    if *uuid == usuario.usuario_id {
+        return Err(e403().with_message("No puedes rechazar tu propia licencia"))?;
+    }
+
+    let motivo = body.into_inner().motivo.trim().to_string();
+    if motivo.is_empty() {
+        return Err(e400().with_message("Indica el motivo del rechazo"))?;
+    }
+
+    let licencia = rechazar_licencia_sqlx(&pool, &uuid, &usuario.usuario_id, &motivo).await
+        .map_err(|_| e500())?
+        .ok_or(e404().with_message("El usuario no ha registrado su licencia"))?;
+
+    // Avisar al usuario para que corrija su licencia
+    let conductor = obtener_usuario_por_id_sqlx(&pool, &uuid).await
+        .map_err(|_| e500())?;
+    if let Some(conductor) = conductor {
+        if let Err(e) = email_client.send_email(
+                &conductor.email,
+                "Licencia rechazada",
+                &format!("Tu licencia de conducir registrada en Control Parque Vehicular fue rechazada.<br />\
+                         Motivo: {}", motivo),
+                &format!("Tu licencia de conducir registrada en Control Parque Vehicular fue rechazada.\n\
+                         Motivo: {}", motivo),
+            ).await
+        {
+            tracing::error!("No se pudo notificar el rechazo de la licencia: {:?}", e);
+        }
+    }
+
+    // Respuesta exitosa
+    let api_response = ApiResponse::<Licencia>::new()
+        .with_message("Licencia rechazada")
+        .with_data(licencia)
+        .to_resp();
+
+    Ok(api_response)
+}
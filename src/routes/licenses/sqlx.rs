@@ -0,0 +1,322 @@
+use anyhow::Context;
+use chrono::{NaiveDate, NaiveDateTime};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+
+#[derive(Debug, serde::Serialize)]
+pub struct Licencia {
+    pub licencia_id: Uuid,
+    pub usuario_id: Uuid,
+    pub numero: String,
+    pub clase: String,
+    pub estado_emisor: String,
+    pub expira_en: NaiveDate,
+    pub imagen_frente: Option<String>,
+    pub imagen_reverso: Option<String>,
+    pub verificada_en: Option<NaiveDateTime>,
+    pub verificada_por: Option<Uuid>,
+    pub rechazo_motivo: Option<String>,
+    pub creado_en: NaiveDateTime,
+    pub modificado_en: NaiveDateTime,
+}
+
+#[derive(Debug, serde::Deserialize)]
+pub struct NuevaLicencia {
+    pub numero: String,
+    pub clase: String,
+    pub estado_emisor: String,
+    pub expira_en: NaiveDate,
+}
+
+#[derive(Debug, Clone, Copy, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LadoLicencia {
+    Frente,
+    Reverso,
+}
+
+#[derive(Debug, serde::Deserialize)]
+pub struct FiltroLicencias {
+    // Solo las que esperan verificacion
+    pub pendientes: Option<bool>,
+}
+
+/// Datos para decidir si el usuario puede manejar el vehiculo
+#[derive(Debug)]
+pub struct Elegibilidad {
+    pub clase_requerida: String,
+    pub tiene_licencia: bool,
+    pub verificada: bool,
+    pub vigente: bool,
+    pub cubre_clase: bool,
+}
+
+impl Elegibilidad {
+    /// Motivo por el que no puede usar el vehiculo, None si puede
+    pub fn motivo_rechazo(&self) -> Option<String> {
+        if !self.tiene_licencia {
+            Some("Registra tu licencia de conducir antes de solicitar un vehiculo".to_string())
+        } else if !self.verificada {
+            Some("Tu licencia de conducir no ha sido verificada".to_string())
+        } else if !self.vigente {
+            Some("Tu licencia de conducir vence antes de terminar el viaje".to_string())
+        } else if !self.cubre_clase {
+            Some(format!("Tu licencia no cubre la clase {} que requiere el vehiculo", self.clase_requerida))
+        } else {
+            None
+        }
+    }
+}
+
+
+#[tracing::instrument(
+    name = "Query obtener licencia",
+    skip(pool)
+)]
+pub async fn obtener_licencia_sqlx(
+    pool: &PgPool,
+    usuario_id: &Uuid,
+) -> Result<Option<Licencia>, anyhow::Error> {
+    let licencia = sqlx::query_as!(
+        Licencia,
+        r#"
+        SELECT
+            licencia_id, usuario_id, numero, clase, estado_emisor, expira_en,
+            imagen_frente, imagen_reverso, verificada_en, verificada_por, rechazo_motivo,
+            creado_en, modificado_en
+        FROM licencias
+        WHERE usuario_id = $1
+        "#,
+        usuario_id,
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to execute query")?;
+
+    Ok(licencia)
+}
+
+#[tracing::instrument(
+    name = "Query listar licencias",
+    skip(pool)
+)]
+pub async fn listar_licencias_sqlx(
+    pool: &PgPool,
+    solo_pendientes: bool,
+) -> Result<Vec<Licencia>, anyhow::Error> {
+    let licencias = sqlx::query_as!(
+        Licencia,
+        r#"
+        SELECT
+            licencia_id, usuario_id, numero, clase, estado_emisor, expira_en,
+            imagen_frente, imagen_reverso, verificada_en, verificada_por, rechazo_motivo,
+            creado_en, modificado_en
+        FROM licencias
+        WHERE NOT $1 OR (verificada_en IS NULL AND rechazo_motivo IS NULL)
+        ORDER BY modificado_en
+        "#,
+        solo_pendientes,
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to execute query")?;
+
+    Ok(licencias)
+}
+
+/// Registra o reemplaza la licencia del usuario, queda pendiente de verificar
+#[tracing::instrument(
+    name = "Query guardar licencia",
+    skip(pool)
+)]
+pub async fn guardar_licencia_sqlx(
+    pool: &PgPool,
+    usuario_id: &Uuid,
+    licencia: &NuevaLicencia,
+) -> Result<Licencia, anyhow::Error> {
+    let licencia = sqlx::query_as!(
+        Licencia,
+        r#"
+        INSERT INTO licencias
+        (licencia_id, usuario_id, numero, clase, estado_emisor, expira_en)
+        VALUES ($1, $2, $3, $4, $5, $6)
+        ON CONFLICT (usuario_id) DO UPDATE
+        SET numero = EXCLUDED.numero,
+            clase = EXCLUDED.clase,
+            estado_emisor = EXCLUDED.estado_emisor,
+            expira_en = EXCLUDED.expira_en,
+            verificada_en = NULL,
+            verificada_por = NULL,
+            rechazo_motivo = NULL,
+            recordatorio_enviado_en = NULL,
+            modificado_en = now()
+        RETURNING
+            licencia_id, usuario_id, numero, clase, estado_emisor, expira_en,
+            imagen_frente, imagen_reverso, verificada_en, verificada_por, rechazo_motivo,
+            creado_en, modificado_en
+        "#,
+        Uuid::new_v4(),
+        usuario_id,
+        licencia.numero.trim(),
+        licencia.clase.trim().to_uppercase(),
+        licencia.estado_emisor.trim(),
+        licencia.expira_en,
+    )
+    .fetch_one(pool)
+    .await
+    .context("Failed to execute query")?;
+
+    Ok(licencia)
+}
+
+/// Regresa la imagen anterior, o None si el usuario no tiene licencia
+#[tracing::instrument(
+    name = "Query actualizar imagen de licencia",
+    skip(pool)
+)]
+pub async fn actualizar_imagen_licencia_sqlx(
+    pool: &PgPool,
+    usuario_id: &Uuid,
+    lado: LadoLicencia,
+    imagen: &str,
+) -> Result<Option<Option<String>>, anyhow::Error> {
+    // Una imagen nueva requiere verificar la licencia otra vez
+    let row = sqlx::query!(
+        r#"
+        UPDATE licencias l
+        SET imagen_frente = CASE WHEN $2 THEN $3 ELSE l.imagen_frente END,
+            imagen_reverso = CASE WHEN $2 THEN l.imagen_reverso ELSE $3 END,
+            verificada_en = NULL,
+            verificada_por = NULL,
+            rechazo_motivo = NULL,
+            modificado_en = now()
+        FROM licencias anterior
+        WHERE l.usuario_id = $1 AND anterior.licencia_id = l.licencia_id
+        RETURNING CASE WHEN $2 THEN anterior.imagen_frente ELSE anterior.imagen_reverso END as imagen_anterior
+        "#,
+        usuario_id,
+        matches!(lado, LadoLicencia::Frente),
+        imagen,
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to execute query")?;
+
+    Ok(row.map(|row| row.imagen_anterior))
+}
+
+/// Regresa None si el usuario no tiene licencia
+#[tracing::instrument(
+    name = "Query verificar licencia",
+    skip(pool)
+)]
+pub async fn verificar_licencia_sqlx(
+    pool: &PgPool,
+    usuario_id: &Uuid,
+    admin_id: &Uuid,
+) -> Result<Option<Licencia>, anyhow::Error> {
+    let licencia = sqlx::query_as!(
+        Licencia,
+        r#"
+        UPDATE licencias
+        SET verificada_en = now(),
+            verificada_por = $2,
+            rechazo_motivo = NULL,
+            modificado_en = now()
+        WHERE usuario_id = $1
+        RETURNING
+            licencia_id, usuario_id, numero, clase, estado_emisor, expira_en,
+            imagen_frente, imagen_reverso, verificada_en, verificada_por, rechazo_motivo,
+            creado_en, modificado_en
+        "#,
+        usuario_id,
+        admin_id,
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to execute query")?;
+
+    Ok(licencia)
+}
+
+/// Regresa None si el usuario no tiene licencia
+#[tracing::instrument(
+    name = "Query rechazar licencia",
+    skip(pool)
+)]
+pub async fn rechazar_licencia_sqlx(
+    pool: &PgPool,
+    usuario_id: &Uuid,
+    admin_id: &Uuid,
+    motivo: &str,
+) -> Result<Option<Licencia>, anyhow::Error> {
+    let licencia = sqlx::query_as!(
+        Licencia,
+        r#"
+        UPDATE licencias
+        SET verificada_en = NULL,
+            verificada_por = $2,
+            rechazo_motivo = $3,
+            modificado_en = now()
+        WHERE usuario_id = $1
+        RETURNING
+            licencia_id, usuario_id, numero, clase, estado_emisor, expira_en,
+            imagen_frente, imagen_reverso, verificada_en, verificada_por, rechazo_motivo,
+            creado_en, modificado_en
+        "#,
+        usuario_id,
+        admin_id,
+        motivo,
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to execute query")?;
+
+    Ok(licencia)
+}
+
+/// Regresa None si el vehiculo no existe
+#[tracing::instrument(
+    name = "Query elegibilidad para manejar vehiculo",
+    skip(pool)
+)]
+pub async fn obtener_elegibilidad_sqlx(
+    pool: &PgPool,
+    usuario_id: &Uuid,
+    vehiculo_id: &Uuid,
+    hasta: &NaiveDateTime,
+) -> Result<Option<Elegibilidad>, anyhow::Error> {
+    let elegibilidad = sqlx::query_as!(
+        Elegibilidad,
+        r#"
+        SELECT
+            v.clase_licencia as clase_requerida,
+            l.licencia_id IS NOT NULL as "tiene_licencia!",
+            COALESCE(l.verificada_en IS NOT NULL, false) as "verificada!",
+            COALESCE(l.expira_en >= $3::timestamp::date, false) as "vigente!",
+            COALESCE(v.clase_licencia = ANY(c.cubre), false) as "cubre_clase!"
+        FROM vehiculos v
+        LEFT JOIN licencias l ON l.usuario_id = $1
+        LEFT JOIN clases_licencia c ON c.clase = l.clase
+        WHERE v.vehiculo_id = $2
+        "#,
+        usuario_id,
+        vehiculo_id,
+        hasta,
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to execute query")?;
+
+    Ok(elegibilidad)
+}
+
+/// La clase no existe en clases_licencia
+pub fn es_clase_invalida(e: &anyhow::Error) -> bool {
+    e.downcast_ref::<sqlx::Error>()
+        .and_then(|e| e.as_database_error())
+        .map(|e| e.code().as_deref() == Some("23503")
+            && matches!(e.constraint(), Some("licencias_clase_fkey") | Some("vehiculos_clase_licencia_fkey")))
+        .unwrap_or(false)
+}
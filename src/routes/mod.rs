@@ -4,9 +4,14 @@ mod images;
 
 pub mod department;
 pub mod auth;
+pub mod api_keys;
+pub mod audit;
+pub mod invitations;
+pub mod licenses;
 pub mod users;
 pub mod vehicules;
 pub mod requests;
+pub mod workflows;
 
 pub mod struct_check;
 
@@ -0,0 +1,556 @@
+use actix_web::{HttpResponse, web};
+use anyhow::Context;
+use chrono::NaiveDateTime;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::api_response::{ApiResponse, e400, e403, e404, e409, e500};
+use crate::authentication::current_user::CurrentUser;
+use crate::email_client::EmailClient;
+use crate::routes::users::sqlx::obtener_usuario_por_id_sqlx;
+use crate::routes::workflows::sqlx::{
+    aprobadores_del_paso_sqlx, delegante_vigente_sqlx, obtener_pasos_sqlx, paso_pendiente,
+    PasoFlujo,
+};
+
+
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize, sqlx::Type)]
+#[sqlx(type_name = "decision_aprobacion", rename_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum DecisionAprobacion {
+    Aprobada,
+    Rechazada,
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct Aprobacion {
+    pub aprobacion_id: Uuid,
+    pub orden: i16,
+    pub aprobador_id: Option<Uuid>,
+    // Aprobador ausente que delego la decision
+    pub en_nombre_de: Option<Uuid>,
+    pub decision: DecisionAprobacion,
+    pub comentario: Option<String>,
+    pub decidido_en: NaiveDateTime,
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct PeticionPorAprobar {
+    pub peticion_id: Uuid,
+    pub usuario_id: Uuid,
+    pub vehiculo_id: Uuid,
+    pub inicio: NaiveDateTime,
+    pub finalizo: NaiveDateTime,
+    pub distancia_estimada_km: Option<i32>,
+    pub flujo_id: Option<Uuid>,
+    #[serde(skip)]
+    pub departamento_id: Option<i32>,
+    #[serde(skip)]
+    pub pendiente: bool,
+    #[serde(skip)]
+    pub aprobados: i64,
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct SeguimientoAprobacion {
+    pub flujo_id: Option<Uuid>,
+    pub paso_pendiente: Option<PasoFlujo>,
+    pub aprobaciones: Vec<Aprobacion>,
+}
+
+#[derive(Debug, serde::Deserialize)]
+pub struct SolicitudDecision {
+    pub comentario: Option<String>,
+}
+
+
+#[tracing::instrument(
+    name = "Query peticion para aprobar",
+    skip(pool)
+)]
+async fn obtener_peticion_sqlx(
+    pool: &PgPool,
+    peticion_id: &Uuid,
+) -> Result<Option<PeticionPorAprobar>, anyhow::Error> {
+    let peticion = sqlx::query_as!(
+        PeticionPorAprobar,
+        r#"
+        SELECT
+            p.peticion_id, p.usuario_id, p.vehiculo_id, p.inicio, p.finalizo,
+            p.distancia_estimada_km, p.flujo_id, p.departamento_id,
+            COALESCE(p.estado = 'pendiente', false) as "pendiente!",
+            (
+                SELECT COUNT(*) FROM peticiones_aprobaciones a
+                WHERE a.peticion_id = p.peticion_id AND a.decision = 'aprobada'
+            ) as "aprobados!"
+        FROM peticiones p
+        WHERE p.peticion_id = $1
+        "#,
+        peticion_id,
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to execute query")?;
+
+    Ok(peticion)
+}
+
+/// Peticiones pendientes cuyo paso actual puede decidir el usuario, por si mismo o a
+/// nombre de un aprobador ausente que delego en el. Mismas reglas que `facultad_para_decidir`
+#[tracing::instrument(
+    name = "Query peticiones por aprobar",
+    skip(pool)
+)]
+async fn listar_peticiones_por_aprobar_sqlx(
+    pool: &PgPool,
+    usuario_id: &Uuid,
+) -> Result<Vec<PeticionPorAprobar>, anyhow::Error> {
+    let peticiones = sqlx::query_as!(
+        PeticionPorAprobar,
+        r#"
+        WITH RECURSIVE facultades AS (
+            SELECT $1::uuid as usuario_id
+            UNION
+            SELECT delegante_id FROM delegaciones
+            WHERE delegado_id = $1 AND now() BETWEEN inicio AND fin
+        ), pendientes AS (
+            SELECT
+                p.peticion_id, p.usuario_id, p.vehiculo_id, p.inicio, p.finalizo,
+                p.distancia_estimada_km, p.flujo_id, p.departamento_id,
+                (
+                    SELECT COUNT(*) FROM peticiones_aprobaciones a
+                    WHERE a.peticion_id = p.peticion_id AND a.decision = 'aprobada'
+                ) as aprobados
+            FROM peticiones p
+            WHERE p.estado = 'pendiente' AND p.usuario_id <> $1
+        ), pasos AS (
+            -- Sin flujo solo hay un paso de administrador
+            SELECT pe.peticion_id, COALESCE(fp.tipo, 'admin') as tipo, fp.usuario_id
+            FROM pendientes pe
+            LEFT JOIN flujos_aprobacion_pasos fp
+                ON fp.flujo_id = pe.flujo_id AND fp.orden = pe.aprobados + 1
+            WHERE fp.flujo_id IS NOT NULL OR (pe.aprobados = 0 AND NOT EXISTS (
+                SELECT 1 FROM flujos_aprobacion_pasos x WHERE x.flujo_id = pe.flujo_id
+            ))
+        ), ancestros AS (
+            SELECT pe.peticion_id, d.id, d.padre_id, 0 as nivel
+            FROM pendientes pe JOIN departamentos d ON d.id = pe.departamento_id
+            UNION ALL
+            SELECT a.peticion_id, d.id, d.padre_id, a.nivel + 1
+            FROM departamentos d JOIN ancestros a ON d.id = a.padre_id
+            WHERE a.nivel < 32
+        ), jefes AS (
+            SELECT a.peticion_id, j.usuario_id, a.nivel
+            FROM ancestros a
+            JOIN pendientes pe ON pe.peticion_id = a.peticion_id
+            JOIN departamentos_jefes j ON j.departamento_id = a.id
+            JOIN usuarios u ON u.usuario_id = j.usuario_id
+            WHERE u.activo AND u.usuario_id <> pe.usuario_id
+        ), directos AS (
+            -- Aprobadores activos del paso, sin contar al solicitante
+            SELECT pa.peticion_id, pa.usuario_id
+            FROM pasos pa
+            JOIN pendientes pe ON pe.peticion_id = pa.peticion_id
+            JOIN usuarios u ON u.usuario_id = pa.usuario_id
+            WHERE pa.tipo = 'usuario' AND u.activo AND u.usuario_id <> pe.usuario_id
+            UNION
+            SELECT pa.peticion_id, j.usuario_id
+            FROM pasos pa
+            JOIN jefes j ON j.peticion_id = pa.peticion_id
+            WHERE pa.tipo = 'jefe_departamento'
+                AND j.nivel = (SELECT MIN(k.nivel) FROM jefes k WHERE k.peticion_id = j.peticion_id)
+        ), pasos_admin AS (
+            -- Un paso sin aprobadores activos lo deciden los administradores
+            SELECT pa.peticion_id
+            FROM pasos pa
+            WHERE pa.tipo = 'admin'
+                OR NOT EXISTS (SELECT 1 FROM directos d WHERE d.peticion_id = pa.peticion_id)
+        ), aprobadores AS (
+            SELECT d.peticion_id, f.usuario_id
+            FROM directos d JOIN facultades f ON f.usuario_id = d.usuario_id
+            UNION
+            SELECT pa.peticion_id, f.usuario_id
+            FROM pasos_admin pa
+            JOIN ancestros a ON a.peticion_id = pa.peticion_id
+            JOIN administradores_departamento ad ON ad.departamento_id = a.id
+            JOIN facultades f ON f.usuario_id = ad.usuario_id
+            JOIN usuarios u ON u.usuario_id = f.usuario_id
+            WHERE u.activo
+            UNION
+            SELECT pa.peticion_id, f.usuario_id
+            FROM pasos_admin pa
+            CROSS JOIN facultades f
+            JOIN usuarios u ON u.usuario_id = f.usuario_id
+            WHERE u.rol = 'admin' AND u.activo
+        )
+        SELECT
+            pe.peticion_id as "peticion_id!",
+            pe.usuario_id as "usuario_id!",
+            pe.vehiculo_id as "vehiculo_id!",
+            pe.inicio as "inicio!",
+            pe.finalizo as "finalizo!",
+            pe.distancia_estimada_km,
+            pe.flujo_id,
+            pe.departamento_id,
+            true as "pendiente!",
+            pe.aprobados as "aprobados!"
+        FROM pendientes pe
+        WHERE EXISTS (
+            -- Nadie aprueba su propia peticion, ni por delegacion
+            SELECT 1 FROM aprobadores ap
+            WHERE ap.peticion_id = pe.peticion_id AND ap.usuario_id <> pe.usuario_id
+        )
+        ORDER BY pe.inicio
+        "#,
+        usuario_id,
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to execute query")?;
+
+    Ok(peticiones)
+}
+
+#[tracing::instrument(
+    name = "Query aprobaciones de peticion",
+    skip(pool)
+)]
+async fn listar_aprobaciones_sqlx(
+    pool: &PgPool,
+    peticion_id: &Uuid,
+) -> Result<Vec<Aprobacion>, anyhow::Error> {
+    let aprobaciones = sqlx::query_as!(
+        Aprobacion,
+        r#"
+        SELECT
+            aprobacion_id, orden, aprobador_id, en_nombre_de,
+            decision as "decision!: DecisionAprobacion",
+            comentario, decidido_en
+        FROM peticiones_aprobaciones
+        WHERE peticion_id = $1
+        ORDER BY orden
+        "#,
+        peticion_id,
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to execute query")?;
+
+    Ok(aprobaciones)
+}
+
+/// Guarda la decision del paso y actualiza el estado de la peticion.
+/// Regresa false si otro aprobador decidio primero
+#[tracing::instrument(
+    name = "Query registrar decision de aprobacion",
+    skip(pool)
+)]
+#[allow(clippy::too_many_arguments)]
+async fn registrar_decision_sqlx(
+    pool: &PgPool,
+    peticion_id: &Uuid,
+    orden: i16,
+    aprobador_id: &Uuid,
+    en_nombre_de: Option<Uuid>,
+    decision: DecisionAprobacion,
+    comentario: Option<&str>,
+    ultimo_paso: bool,
+) -> Result<bool, anyhow::Error> {
+    let mut transaction = pool.begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+
+    let pendiente = sqlx::query!(
+        "SELECT peticion_id FROM peticiones WHERE peticion_id = $1 AND estado = 'pendiente' FOR UPDATE",
+        peticion_id,
+    )
+    .fetch_optional(&mut transaction)
+    .await
+    .context("Failed to lock request")?
+    .is_some();
+
+    let decisiones = sqlx::query!(
+        r#"SELECT COUNT(*) as "decisiones!" FROM peticiones_aprobaciones WHERE peticion_id = $1"#,
+        peticion_id,
+    )
+    .fetch_one(&mut transaction)
+    .await
+    .context("Failed to count approvals")?
+    .decisiones;
+
+    if !pendiente || decisiones != i64::from(orden) - 1 {
+        return Ok(false);
+    }
+
+    sqlx::query!(
+        r#"
+        INSERT INTO peticiones_aprobaciones
+        (aprobacion_id, peticion_id, orden, aprobador_id, en_nombre_de, decision, comentario)
+        VALUES ($1, $2, $3, $4, $5, $6, $7)
+        "#,
+        Uuid::new_v4(),
+        peticion_id,
+        orden,
+        aprobador_id,
+        en_nombre_de,
+        decision as DecisionAprobacion,
+        comentario,
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to insert approval")?;
+
+    let rechazada = decision == DecisionAprobacion::Rechazada;
+    if rechazada || ultimo_paso {
+        sqlx::query!(
+            r#"
+            UPDATE peticiones
+            SET
+                estado = CASE WHEN $2 THEN 'rechazada'::estado_peticion ELSE 'aceptada'::estado_peticion END,
+                modificado_en = now()
+            WHERE peticion_id = $1
+            "#,
+            peticion_id,
+            rechazada,
+        )
+        .execute(&mut transaction)
+        .await
+        .context("Failed to update request state")?;
+    }
+
+    transaction.commit()
+        .await
+        .context("Failed to commit SQL transaction")?;
+
+    Ok(true)
+}
+
+
+/// Pasos del flujo de la peticion, vacio si la aprueba un administrador
+async fn pasos_de(pool: &PgPool, peticion: &PeticionPorAprobar) -> Result<Vec<PasoFlujo>, anyhow::Error> {
+    match peticion.flujo_id {
+        Some(flujo_id) => obtener_pasos_sqlx(pool, &flujo_id).await,
+        None => Ok(vec![]),
+    }
+}
+
+/// Puede decidir el paso: Some(None) por si mismo, Some(Some(id)) a nombre de quien delego
+async fn facultad_para_decidir(
+    pool: &PgPool,
+    usuario_id: &Uuid,
+    peticion: &PeticionPorAprobar,
+    paso: &PasoFlujo,
+) -> Result<Option<Option<Uuid>>, anyhow::Error> {
+    if *usuario_id == peticion.usuario_id {
+        return Ok(None);
+    }
+
+    let aprobadores = aprobadores_del_paso_sqlx(pool, paso, &peticion.usuario_id, peticion.departamento_id).await?;
+    if aprobadores.contains(usuario_id) {
+        return Ok(Some(None));
+    }
+
+    Ok(delegante_vigente_sqlx(pool, usuario_id, &aprobadores).await?
+        .map(Some))
+}
+
+async fn decidir_peticion(
+    usuario: &CurrentUser,
+    pool: &PgPool,
+    email_client: &EmailClient,
+    peticion_id: &Uuid,
+    decision: DecisionAprobacion,
+    comentario: Option<&str>,
+) -> Result<(), actix_web::Error> {
+
+    usuario.rechazar_suplantacion()?;
+
+    let peticion = obtener_peticion_sqlx(pool, peticion_id).await
+        .map_err(|_| e500())?
+        .ok_or(e404().with_message("No se encontro la peticion"))?;
+    if !peticion.pendiente {
+        return Err(e409().with_message("La peticion ya no esta pendiente"))?;
+    }
+    if peticion.usuario_id == usuario.usuario_id {
+        return Err(e403().with_message("No puedes decidir tu propia peticion"))?;
+    }
+
+    let pasos = pasos_de(pool, &peticion).await
+        .map_err(|_| e500())?;
+    let paso = paso_pendiente(&pasos, peticion.aprobados)
+        .ok_or(e409().with_message("La peticion no tiene pasos pendientes"))?;
+    let ultimo_paso = usize::try_from(paso.orden).unwrap_or(0) >= pasos.len().max(1);
+
+    let en_nombre_de = facultad_para_decidir(pool, &usuario.usuario_id, &peticion, &paso).await
+        .map_err(|_| e500())?
+        .ok_or(e403().with_message("No te corresponde decidir este paso"))?;
+
+    let registrada = registrar_decision_sqlx(
+        pool,
+        peticion_id,
+        paso.orden,
+        &usuario.usuario_id,
+        en_nombre_de,
+        decision,
+        comentario,
+        ultimo_paso,
+    ).await
+        .map_err(|_| e500())?;
+    if !registrada {
+        return Err(e409().with_message("La peticion cambio mientras decidias, vuelve a consultarla"))?;
+    }
+
+    // Solo se avisa al solicitante cuando hay una respuesta final
+    let resultado = match decision {
+        DecisionAprobacion::Rechazada => Some("rechazada"),
+        DecisionAprobacion::Aprobada if ultimo_paso => Some("aceptada"),
+        DecisionAprobacion::Aprobada => None,
+    };
+    if let Some(resultado) = resultado {
+        let solicitante = obtener_usuario_por_id_sqlx(pool, &peticion.usuario_id).await
+            .map_err(|_| e500())?;
+        if let Some(solicitante) = solicitante {
+            let comentario = comentario.unwrap_or("");
+            if let Err(e) = email_client.send_email(
+                    &solicitante.email,
+                    &format!("Peticion {}", resultado),
+                    &format!("Tu peticion de vehiculo para el {} fue {}.<br />{}", peticion.inicio, resultado, comentario),
+                    &format!("Tu peticion de vehiculo para el {} fue {}.\n{}", peticion.inicio, resultado, comentario),
+                ).await
+            {
+                tracing::error!("No se pudo notificar la decision de la peticion: {:?}", e);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+
+#[tracing::instrument(
+    name = "Obtener peticiones por aprobar",
+    skip_all
+)]
+pub async fn get_pending_request_approvals(
+    usuario: CurrentUser,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+
+    // Las que el usuario puede decidir ahora, por si mismo o por delegacion
+    let por_aprobar = listar_peticiones_por_aprobar_sqlx(&pool, &usuario.usuario_id).await
+        .map_err(|_| e500())?;
+
+    // Respuesta exitosa
+    let api_response = ApiResponse::<Vec<PeticionPorAprobar>>::new()
+        .with_message("Peticiones por aprobar")
+        .with_data(por_aprobar)
+        .to_resp();
+
+    Ok(api_response)
+}
+
+
+#[tracing::instrument(
+    name = "Obtener aprobaciones de peticion",
+    skip(usuario, pool)
+)]
+pub async fn get_request_approvals(
+    usuario: CurrentUser,
+    pool: web::Data<PgPool>,
+    uuid: web::Path<Uuid>,
+) -> Result<HttpResponse, actix_web::Error> {
+
+    let peticion = obtener_peticion_sqlx(&pool, &uuid).await
+        .map_err(|_| e500())?
+        .ok_or(e404().with_message("No se encontro la peticion"))?;
+    let aprobaciones = listar_aprobaciones_sqlx(&pool, &uuid).await
+        .map_err(|_| e500())?;
+    let pasos = pasos_de(&pool, &peticion).await
+        .map_err(|_| e500())?;
+    let paso = paso_pendiente(&pasos, peticion.aprobados)
+        .filter(|_| peticion.pendiente);
+
+    // El solicitante, los administradores del departamento y quienes participan en la aprobacion
+    let participa = aprobaciones.iter()
+        .any(|a| a.aprobador_id == Some(usuario.usuario_id) || a.en_nombre_de == Some(usuario.usuario_id));
+    let puede_ver = usuario.administra_departamento(peticion.departamento_id)
+        || peticion.usuario_id == usuario.usuario_id
+        || participa
+        || match &paso {
+            Some(paso) => facultad_para_decidir(&pool, &usuario.usuario_id, &peticion, paso).await
+                .map_err(|_| e500())?
+                .is_some(),
+            None => false,
+        };
+    if !puede_ver {
+        return Err(e403().with_message("No tienes los permisos requeridos"))?;
+    }
+
+    // Respuesta exitosa
+    let api_response = ApiResponse::<SeguimientoAprobacion>::new()
+        .with_message("Aprobaciones de la peticion")
+        .with_data(SeguimientoAprobacion {
+            flujo_id: peticion.flujo_id,
+            paso_pendiente: paso,
+            aprobaciones,
+        })
+        .to_resp();
+
+    Ok(api_response)
+}
+
+
+#[tracing::instrument(
+    name = "Aprobar peticion",
+    skip(usuario, pool, email_client)
+)]
+pub async fn approve_request(
+    usuario: CurrentUser,
+    pool: web::Data<PgPool>,
+    email_client: web::Data<EmailClient>,
+    uuid: web::Path<Uuid>,
+    body: Option<web::Json<SolicitudDecision>>,
+) -> Result<HttpResponse, actix_web::Error> {
+
+    let comentario = body
+        .and_then(|body| body.into_inner().comentario)
+        .map(|comentario| comentario.trim().to_string())
+        .filter(|comentario| !comentario.is_empty());
+
+    decidir_peticion(&usuario, &pool, &email_client, &uuid, DecisionAprobacion::Aprobada, comentario.as_deref()).await?;
+
+    // Respuesta exitosa
+    let api_response = ApiResponse::<()>::new()
+        .with_message("Peticion aprobada")
+        .to_resp();
+
+    Ok(api_response)
+}
+
+
+#[tracing::instrument(
+    name = "Rechazar peticion",
+    skip(usuario, pool, email_client)
+)]
+pub async fn reject_request(
+    usuario: CurrentUser,
+    pool: web::Data<PgPool>,
+    email_client: web::Data<EmailClient>,
+    uuid: web::Path<Uuid>,
+    body: web::Json<SolicitudDecision>,
+) -> Result<HttpResponse, actix_web::Error> {
+
+    let comentario = body.into_inner().comentario
+        .map(|comentario| comentario.trim().to_string())
+        .filter(|comentario| !comentario.is_empty())
+        .ok_or(e400().with_message("Indica el motivo del rechazo"))?;
+
+    decidir_peticion(&usuario, &pool, &email_client, &uuid, DecisionAprobacion::Rechazada, Some(&comentario)).await?;
+
+    // Respuesta exitosa
+    let api_response = ApiResponse::<()>::new()
+        .with_message("Peticion rechazada")
+        .to_resp();
+
+    Ok(api_response)
+}
@@ -1,32 +1,71 @@
 use actix_web::{HttpResponse, web};
 use anyhow::Context;
-use sqlx::PgPool;
+use sqlx::{PgPool, Postgres, Transaction};
 use uuid::Uuid;
 
-use crate::authentication::jwt_session::JwtSession;
-use crate::api_response::{ApiResponse, e500, e403};
+use crate::authentication::current_user::CurrentUser;
+use crate::api_response::{ApiResponse, e500, e400, e403, e404, e409};
+use crate::routes::licenses::sqlx::obtener_elegibilidad_sqlx;
+use crate::routes::department::quotas::{revisar_cuota_sqlx, ModoCuota, PeticionACuota};
+use crate::routes::workflows::sqlx::asignar_flujo_sqlx;
 
-use crate::routes::users::sqlx::obtener_usuario_por_id_sqlx;
 
 use common::models::request::{NuevaPeticion, Peticion, EstadoPeticion};
 
 
+#[derive(Debug, serde::Deserialize)]
+pub struct SolicitudPeticion {
+    #[serde(flatten)]
+    pub peticion: NuevaPeticion,
+    #[serde(flatten)]
+    pub estimados: EstimadosPeticion,
+}
+
+// Para las reglas de los flujos de aprobacion y las cuotas
+#[derive(Debug, serde::Deserialize)]
+pub struct EstimadosPeticion {
+    pub distancia_estimada_km: Option<i32>,
+    pub costo_combustible_estimado: Option<i32>,
+}
+
+
+#[tracing::instrument(
+    name = "Query departamento del usuario",
+    skip(transaction)
+)]
+async fn obtener_departamento_usuario_sqlx(
+    transaction: &mut Transaction<'_, Postgres>,
+    usuario_id: &Uuid,
+) -> Result<Option<i32>, anyhow::Error> {
+    let row = sqlx::query!(
+        "SELECT departamento FROM usuarios WHERE usuario_id = $1",
+        usuario_id,
+    )
+    .fetch_one(transaction)
+    .await
+    .context("Fallo la ejecucion del query")?;
+
+    Ok(row.departamento)
+}
+
 #[tracing::instrument(
     name = "Query insertar nueva peticion",
-    skip(pool)
+    skip(transaction)
 )]
 async fn insertar_nueva_peticion_sqlx(
-    pool: &PgPool,
+    transaction: &mut Transaction<'_, Postgres>,
     peticion: NuevaPeticion,
     usuario_id: &Uuid,
     vehiculo_id: &Uuid,
+    departamento_id: Option<i32>,
+    estimados: &EstimadosPeticion,
 ) -> Result<Peticion, anyhow::Error> {
     let peticion: Peticion = sqlx::query_as!(
         Peticion,
         r#"
         INSERT INTO peticiones
-        (peticion_id, usuario_id, vehiculo_id, inicio, finalizo, kilometraje_inicial, kilometraje_final, usuario_licencia_imagen)
-        VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+        (peticion_id, usuario_id, vehiculo_id, inicio, finalizo, kilometraje_inicial, kilometraje_final, usuario_licencia_imagen, distancia_estimada_km, departamento_id, costo_combustible_estimado)
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11)
         RETURNING 
             peticion_id, usuario_id, vehiculo_id,
             inicio, finalizo,
@@ -48,8 +87,11 @@ async fn insertar_nueva_peticion_sqlx(
         200000,
         //peticion.usuario_licencia_imagen,
         String::from("Image name"),
+        estimados.distancia_estimada_km,
+        departamento_id,
+        estimados.costo_combustible_estimado,
     )
-    .fetch_one(pool)
+    .fetch_one(transaction)
     .await
     .context("Fallo la ejecucion del query")?;
 
@@ -61,21 +103,16 @@ async fn insertar_nueva_peticion_sqlx(
 
 #[tracing::instrument(
     name = "Post nueva peticion",
-    skip(pool, session)
+    skip(pool, usuario)
 )]
 pub async fn post_new_request(
-    session: JwtSession,
+    usuario: CurrentUser,
     pool: web::Data<PgPool>,
     //vehiculo_id: web::Path<VehiculoId>,
     vehiculo_id: web::Path<Uuid>,
-    peticion: web::Json<NuevaPeticion>
+    peticion: web::Json<SolicitudPeticion>
 ) -> Result<HttpResponse, actix_web::Error> {
 
-    // Usuario es admin ?
-    let usuario = obtener_usuario_por_id_sqlx(&pool, &session.user_id).await
-        .map_err(|_| e500())?
-        .ok_or(e500())?;
-
     /*
     if !usuario.es_admin() {
         return Err(e403().with_message("No tienes los permisos requeridos"))?;
@@ -83,15 +120,76 @@ pub async fn post_new_request(
     */
 
     let vehiculo_id = vehiculo_id.into_inner();
+    let SolicitudPeticion { peticion, estimados } = peticion.into_inner();
+    let negativo = [estimados.distancia_estimada_km, estimados.costo_combustible_estimado]
+        .iter()
+        .any(|valor| valor.map(|valor| valor < 0).unwrap_or(false));
+    if negativo {
+        return Err(e400().with_message("La distancia y el costo estimados no pueden ser negativos"))?;
+    }
+    // Un intervalo invertido restaria horas al uso del departamento
+    if peticion.inicio > peticion.finalizo {
+        return Err(e400().with_message("El inicio debe ser anterior al final de la peticion"))?;
+    }
+
+    // Se requiere licencia verificada, vigente hasta el final del viaje y de la clase del vehiculo
+    let elegibilidad = obtener_elegibilidad_sqlx(&pool, &usuario.usuario_id, &vehiculo_id, &peticion.finalizo).await
+        .map_err(|_| e500())?
+        .ok_or(e404().with_message("No se encontro el Vehiculo"))?;
+    if let Some(motivo) = elegibilidad.motivo_rechazo() {
+        return Err(e403().with_message(motivo))?;
+    }
 
-    // Query insertar nuevo vehiculo DB
-    let peticion = peticion.into_inner();
-    let nueva_peticion = insertar_nueva_peticion_sqlx(&pool, peticion, &session.user_id, &vehiculo_id).await
+    // La peticion se guarda con su flujo de aprobacion o no se guarda
+    let mut transaction = pool.begin().await
+        .map_err(|_| e500())?;
+
+    // Se carga al departamento del solicitante y se revisa contra su cuota del mes
+    let departamento_id = obtener_departamento_usuario_sqlx(&mut transaction, &usuario.usuario_id).await
+        .map_err(|_| e500())?;
+    let mut advertencias = vec![];
+    if let Some(departamento_id) = departamento_id {
+        let revision = revisar_cuota_sqlx(&mut transaction, departamento_id, &PeticionACuota {
+            vehiculo_id,
+            inicio: peticion.inicio,
+            finalizo: peticion.finalizo,
+            distancia_estimada_km: estimados.distancia_estimada_km,
+            costo_combustible_estimado: estimados.costo_combustible_estimado,
+        }).await
+            .map_err(|_| e500())?;
+        if let Some((modo, excedidos)) = revision.filter(|(_, excedidos)| !excedidos.is_empty()) {
+            if modo == ModoCuota::Bloquear {
+                return Err(e409().with_message(format!(
+                    "La peticion rebasa la cuota del departamento: {}",
+                    excedidos.join(", ")
+                )))?;
+            }
+            advertencias = excedidos;
+        }
+    }
+
+    let nueva_peticion = insertar_nueva_peticion_sqlx(
+        &mut transaction,
+        peticion,
+        &usuario.usuario_id,
+        &vehiculo_id,
+        departamento_id,
+        &estimados,
+    ).await
+        .map_err(|_| e500())?;
+    asignar_flujo_sqlx(&mut transaction, &nueva_peticion.peticion_id).await
+        .map_err(|_| e500())?;
+    transaction.commit().await
         .map_err(|_| e500())?;
 
     // Respuesta exitosa
+    let mensaje = if advertencias.is_empty() {
+        String::from("Nuevo peticion")
+    } else {
+        format!("Nuevo peticion, rebasa la cuota del departamento: {}", advertencias.join(", "))
+    };
     let api_response = ApiResponse::<Peticion>::new()
-        .with_message("Nuevo peticion")
+        .with_message(mensaje)
         .with_data(nueva_peticion)
         .to_resp();
 
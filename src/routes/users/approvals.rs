@@ -0,0 +1,211 @@
+use actix_web::{HttpResponse, web};
+use anyhow::Context;
+use chrono::NaiveDateTime;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::api_response::{ApiResponse, e404, e403, e500};
+use crate::authentication::current_user::CurrentUser;
+use crate::email_client::EmailClient;
+
+
+#[derive(Debug, serde::Serialize)]
+pub struct UsuarioPendiente {
+    pub usuario_id: Uuid,
+    pub nombres: String,
+    pub apellidos: String,
+    pub email: String,
+    pub verificado: bool,
+    pub creado_en: NaiveDateTime,
+}
+
+#[derive(Debug, serde::Deserialize)]
+pub struct Rechazo {
+    pub motivo: Option<String>,
+}
+
+
+#[tracing::instrument(
+    name = "Query usuarios pendientes de aprobacion",
+    skip(pool)
+)]
+async fn obtener_usuarios_pendientes_sqlx(
+    pool: &PgPool,
+    departamentos: Option<&[i32]>,
+) -> Result<Vec<UsuarioPendiente>, anyhow::Error> {
+    let usuarios = sqlx::query_as!(
+        UsuarioPendiente,
+        r#"
+        SELECT usuario_id, nombres, apellidos, email, verificado, creado_en
+        FROM usuarios
+        WHERE aprobacion = 'pendiente'
+            AND ($1::int[] IS NULL OR departamento = ANY($1))
+        ORDER BY creado_en
+        "#,
+        departamentos,
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to execute query")?;
+
+    Ok(usuarios)
+}
+
+/// Regresa el email del usuario, o None si no estaba pendiente
+#[tracing::instrument(
+    name = "Query decidir aprobacion de usuario",
+    skip(pool)
+)]
+async fn decidir_aprobacion_sqlx(
+    pool: &PgPool,
+    usuario_id: &Uuid,
+    admin_id: &Uuid,
+    departamentos: Option<&[i32]>,
+    aprobado: bool,
+    motivo: Option<&str>,
+) -> Result<Option<String>, anyhow::Error> {
+    let row = sqlx::query!(
+        r#"
+        UPDATE usuarios
+        SET
+            aprobacion = CASE WHEN $3 THEN 'aprobado'::usuario_aprobacion ELSE 'rechazado'::usuario_aprobacion END,
+            aprobacion_motivo = $4,
+            aprobado_por = $2,
+            aprobado_en = now(),
+            modificado_en = now()
+        WHERE usuario_id = $1 AND aprobacion = 'pendiente'
+            AND ($5::int[] IS NULL OR departamento = ANY($5))
+        RETURNING email
+        "#,
+        usuario_id,
+        admin_id,
+        aprobado,
+        motivo,
+        departamentos,
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to execute query")?;
+
+    Ok(row.map(|row| row.email))
+}
+
+
+#[tracing::instrument(
+    name = "Obtener usuarios pendientes de aprobacion",
+    skip_all
+)]
+pub async fn get_pending_approvals(
+    usuario: CurrentUser,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+
+    if !usuario.administra() {
+        return Err(e403().with_message("No tienes los permisos requeridos"))?;
+    }
+
+    let usuarios = obtener_usuarios_pendientes_sqlx(&pool, usuario.departamentos_administrados()).await
+        .map_err(|_| e500())?;
+
+    // Respuesta exitosa
+    let api_response = ApiResponse::<Vec<UsuarioPendiente>>::new()
+        .with_message("Usuarios pendientes de aprobacion")
+        .with_data(usuarios)
+        .to_resp();
+
+    Ok(api_response)
+}
+
+
+#[tracing::instrument(
+    name = "Aprobar usuario",
+    skip(usuario, pool, email_client)
+)]
+pub async fn approve_user(
+    usuario: CurrentUser,
+    pool: web::Data<PgPool>,
+    email_client: web::Data<EmailClient>,
+    uuid: web::Path<Uuid>,
+) -> Result<HttpResponse, actix_web::Error> {
+
+    if !usuario.administra() {
+        return Err(e403().with_message("No tienes los permisos requeridos"))?;
+    }
+
+    let email = decidir_aprobacion_sqlx(&pool, &uuid, &usuario.usuario_id, usuario.departamentos_administrados(), true, None).await
+        .map_err(|_| e500())?
+        .ok_or(e404().with_message("No se encontro el usuario pendiente de aprobacion"))?;
+
+    // La decision ya quedo guardada aunque el correo no se pueda enviar
+    if let Err(e) = email_client.send_email(
+            &email,
+            "Cuenta aprobada",
+            "Tu cuenta de Control Parque Vehicular fue aprobada, ya puedes iniciar sesion.",
+            "Tu cuenta de Control Parque Vehicular fue aprobada, ya puedes iniciar sesion.",
+        ).await
+    {
+        tracing::error!("No se pudo notificar la aprobacion: {:?}", e);
+    }
+
+    // Respuesta exitosa
+    let api_response = ApiResponse::<()>::new()
+        .with_message("Usuario aprobado")
+        .to_resp();
+
+    Ok(api_response)
+}
+
+
+#[tracing::instrument(
+    name = "Rechazar usuario",
+    skip(usuario, pool, email_client)
+)]
+pub async fn reject_user(
+    usuario: CurrentUser,
+    pool: web::Data<PgPool>,
+    email_client: web::Data<EmailClient>,
+    uuid: web::Path<Uuid>,
+    body: web::Json<Rechazo>,
+) -> Result<HttpResponse, actix_web::Error> {
+
+    if !usuario.administra() {
+        return Err(e403().with_message("No tienes los permisos requeridos"))?;
+    }
+
+    let motivo = body.into_inner().motivo
+        .map(|motivo| motivo.trim().to_string())
+        .filter(|motivo| !motivo.is_empty());
+
+    let email = decidir_aprobacion_sqlx(
+        &pool,
+        &uuid,
+        &usuario.usuario_id,
+        usuario.departamentos_administrados(),
+        false,
+        motivo.as_deref(),
+    ).await
+        .map_err(|_| e500())?
+        .ok_or(e404().with_message("No se encontro el usuario pendiente de aprobacion"))?;
+
+    let detalle = motivo
+        .map(|motivo| format!(" Motivo: {}", motivo))
+        .unwrap_or_default();
+
+    // La decision ya quedo guardada aunque el correo no se pueda enviar
+    if let Err(e) = email_client.send_email(
+            &email,
+            "Cuenta rechazada",
+            &format!("Tu solicitud de cuenta en Control Parque Vehicular fue rechazada.{}", detalle),
+            &format!("Tu solicitud de cuenta en Control Parque Vehicular fue rechazada.{}", detalle),
+        ).await
+    {
+        tracing::error!("No se pudo notificar el rechazo: {:?}", e);
+    }
+
+    // Respuesta exitosa
+    let api_response = ApiResponse::<()>::new()
+        .with_message("Usuario rechazado")
+        .to_resp();
+
+    Ok(api_response)
+}
@@ -2,35 +2,33 @@ use actix_web::{HttpResponse, web};
 use sqlx::PgPool;
 use uuid::Uuid;
 
-use crate::authentication::jwt_session::JwtSession;
-use crate::api_response::{ApiResponse, e500, e403, e404};
+use crate::authentication::current_user::{CurrentUser, invalidar_cache_usuario};
+use crate::authentication::sessions::revocar_sesiones;
+use crate::api_response::{ApiResponse, e500, e403, e404, e409};
+use crate::configuration::UserRetentionSettings;
 
-use super::sqlx::{obtener_usuario_por_id_sqlx, borrar_usuario_por_id_sqlx};
+use super::sqlx::{obtener_usuario_en_alcance_sqlx, desactivar_usuario_sqlx};
 
 
+/// El usuario no se borra para conservar su historial de peticiones,
+/// se desactiva y se anonimiza al terminar el plazo para restaurarlo
 #[tracing::instrument(
     name = "Borrar usuario",
-    skip(session, pool)
+    skip(usuario, pool, retention)
 )]
 pub async fn users_delete_user_by_id(
-    session: JwtSession,
+    usuario: CurrentUser,
     pool: web::Data<PgPool>,
+    retention: web::Data<UserRetentionSettings>,
     uuid: web::Path<Uuid>,
 ) -> Result<HttpResponse, actix_web::Error> {
 
-    // Usuario es admin ?
-    let usuario = obtener_usuario_por_id_sqlx(&pool, &session.user_id).await
-        // Map SQLX error a 500
-        .map_err(|_| e500())?
-        // Si query regresa Some map a OK o error
-        .ok_or(e500())?;
-
-    if !usuario.es_admin() {
+    if !usuario.administra() {
         return Err(e403().with_message("No tienes los permisos requeridos"))?;
     }
 
     // Otro Usuario valido ?
-    let otro_usuario = obtener_usuario_por_id_sqlx(&pool, &uuid).await
+    let otro_usuario = obtener_usuario_en_alcance_sqlx(&pool, &uuid, usuario.departamentos_administrados()).await
         .map_err(|_| e500())?
         .ok_or(e404().with_message("No se encontro el usuario"))?;
 
@@ -38,13 +36,64 @@ pub async fn users_delete_user_by_id(
        return Err(e403().with_message("No puedes eliminar otro administrador!"))?; 
     }
 
-    // Query borrar DB
-    borrar_usuario_por_id_sqlx(&pool, &uuid).await
+    // Query desactivar DB
+    let desactivado = desactivar_usuario_sqlx(&pool, &uuid, &usuario.usuario_id).await
+        .map_err(|_| e500())?;
+    if !desactivado {
+        return Err(e409().with_message("El usuario ya esta desactivado"))?;
+    }
+
+    let mut redis_con = usuario.redis.get().await
+        .map_err(|_| e500())?;
+    revocar_sesiones(&pool, &usuario.redis, &mut redis_con, &uuid, None).await
+        .map_err(|_| e500())?;
+
+    invalidar_cache_usuario(&usuario.redis, &uuid).await;
+
+    // Respuesta exitosa
+    let api_response = ApiResponse::<()>::new()
+        .with_message(format!(
+            "Usuario desactivado, se puede restaurar durante {} dias antes de anonimizarlo",
+            retention.anonymize_after_days,
+        ))
+        .to_resp();
+
+    Ok(api_response)
+}
+
+
+#[tracing::instrument(
+    name = "Cerrar sesiones de usuario",
+    skip(usuario, pool)
+)]
+pub async fn users_delete_sessions_by_id(
+    usuario: CurrentUser,
+    pool: web::Data<PgPool>,
+    uuid: web::Path<Uuid>,
+) -> Result<HttpResponse, actix_web::Error> {
+
+    if !usuario.administra() {
+        return Err(e403().with_message("No tienes los permisos requeridos"))?;
+    }
+
+    // Otro Usuario valido ?
+    let otro_usuario = obtener_usuario_en_alcance_sqlx(&pool, &uuid, usuario.departamentos_administrados()).await
+        .map_err(|_| e500())?
+        .ok_or(e404().with_message("No se encontro el usuario"))?;
+
+    if otro_usuario.es_admin() && usuario.usuario_id != otro_usuario.usuario_id {
+        return Err(e403().with_message("No puedes cerrar las sesiones de otro administrador!"))?;
+    }
+
+    let mut redis_con = usuario.redis.get().await
+        .map_err(|_| e500())?;
+
+    let sesiones = revocar_sesiones(&pool, &usuario.redis, &mut redis_con, &otro_usuario.usuario_id, None).await
         .map_err(|_| e500())?;
 
     // Respuesta exitosa
     let api_response = ApiResponse::<()>::new()
-        .with_message("Usuario eliminado")
+        .with_message(format!("Se cerraron {} sesiones del usuario", sesiones))
         .to_resp();
 
     Ok(api_response)
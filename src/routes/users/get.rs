@@ -1,42 +1,52 @@
 use actix_web::{HttpResponse, web};
+use actix_web::http::header::{HeaderName, HeaderValue};
 use sqlx::PgPool;
 use uuid::Uuid;
 
 use common::models::user::Usuario;
 
-use crate::authentication::jwt_session::JwtSession;
-use crate::api_response::{ApiResponse, e500, e403, e404};
+use crate::authentication::current_user::CurrentUser;
+use crate::api_response::{ApiResponse, e500, e400, e403, e404};
 
-use super::sqlx::{obtener_usuarios_sqlx, obtener_usuario_por_id_sqlx};
+use super::sqlx::{buscar_usuarios_sqlx, obtener_usuario_en_alcance_sqlx, FiltroUsuarios, PAGINA_MAX};
+
+
+/// Encabezado con el total de usuarios que cumplen el filtro
+pub const TOTAL_COUNT_HEADER: &str = "x-total-count";
 
 
 #[tracing::instrument(
     name = "Obtener todos los usuarios",
-    skip_all
+    skip(usuario, pool)
 )]
 pub async fn users_get_all(
-    session: JwtSession,
+    usuario: CurrentUser,
     pool: web::Data<PgPool>,
+    filtro: web::Query<FiltroUsuarios>,
 ) -> Result<HttpResponse, actix_web::Error> {
 
-    // Usuario es admin ?
-    let usuario = obtener_usuario_por_id_sqlx(&pool, &session.user_id).await
-        .map_err(|_| e500())?
-        .ok_or(e500())?;
-
-    if !usuario.es_admin() {
+    if !usuario.administra() {
         return Err(e403().with_message("No tienes los permisos requeridos"))?;
     }
 
-    // Obtener Usuario de DB
-    let usuarios = obtener_usuarios_sqlx(&pool).await
+    let pagina_invalida = filtro.pagina.map(|pagina| !(1..=PAGINA_MAX).contains(&pagina)).unwrap_or(false);
+    if pagina_invalida {
+        return Err(e400().with_message(format!("La pagina debe estar entre 1 y {}", PAGINA_MAX)))?;
+    }
+
+    // Obtener pagina de usuarios de DB
+    let (usuarios, total) = buscar_usuarios_sqlx(&pool, filtro.into_inner(), usuario.departamentos_administrados()).await
         .map_err(|_| e500())?;
 
-    // Respuesta exitosa
-    let api_response = ApiResponse::<Vec<Usuario>>::new()
+    // Respuesta exitosa, data sigue siendo la lista para no romper clientes
+    let mut api_response = ApiResponse::<Vec<Usuario>>::new()
         .with_message("Lista de Usuarios")
         .with_data(usuarios)
         .to_resp();
+    api_response.headers_mut().insert(
+        HeaderName::from_static(TOTAL_COUNT_HEADER),
+        HeaderValue::from(total),
+    );
 
     Ok(api_response)
 }
@@ -44,25 +54,21 @@ pub async fn users_get_all(
 
 #[tracing::instrument(
     name = "Obtener usuario",
-    skip(session, pool)
+    skip(usuario, pool)
 )]
 pub async fn users_get_user_by_id(
-    session: JwtSession,
+    usuario: CurrentUser,
     pool: web::Data<PgPool>,
     uuid: web::Path<Uuid>,
 ) -> Result<HttpResponse, actix_web::Error> {
     
-    // Usuario es admin ?
-    let usuario = obtener_usuario_por_id_sqlx(&pool, &session.user_id).await
-        .map_err(|_| e500())?
-        .ok_or(e500())?;
 
-    if !usuario.es_admin() {
+    if !usuario.administra() {
         return Err(e403().with_message("No tienes los permisos requeridos"))?;
     }
 
     // Otro Usuario valido?
-    let otro_usuario = obtener_usuario_por_id_sqlx(&pool, &uuid).await
+    let otro_usuario = obtener_usuario_en_alcance_sqlx(&pool, &uuid, usuario.departamentos_administrados()).await
         .map_err(|_| e500())?;
     let otro_usuario = otro_usuario.ok_or(e404().with_message("No se encontro el Usuario"))?;
 
@@ -1,45 +1,75 @@
 use actix_web::{web, HttpResponse};
 use actix_web::HttpRequest;
 use actix_files::NamedFile;
+use anyhow::Context;
 
 use sqlx::PgPool;
 
-use crate::authentication::jwt_session::JwtSession;
+use crate::authentication::current_user::CurrentUser;
 use crate::api_response::{e500, e404, e403};
 
 use crate::upload::image::get_uploads_path;
-use crate::routes::users::sqlx::obtener_usuario_por_id_sqlx;
 
 #[derive(serde::Serialize, serde::Deserialize, Debug)]
 pub struct File {
     pub filename: String
 }
 
+#[tracing::instrument(
+    name = "Query imagen de usuario en alcance",
+    skip(pool)
+)]
+async fn imagen_en_alcance_sqlx(
+    pool: &PgPool,
+    imagen: &str,
+    departamentos: &[i32],
+) -> Result<bool, anyhow::Error> {
+    let row = sqlx::query!(
+        r#"
+        SELECT EXISTS(
+            SELECT usuario_id FROM usuarios
+            WHERE imagen = $1 AND departamento = ANY($2)
+        ) as "existe!"
+        "#,
+        imagen,
+        departamentos,
+    )
+    .fetch_one(pool)
+    .await
+    .context("Failed to execute query")?;
+
+    Ok(row.existe)
+}
+
 #[tracing::instrument(
     name = "Serve imagen estatica del usuario",
-    skip(session, pool, req)
+    skip(usuario, pool, req)
 )]
 pub async fn get_imagen_usuario(
-    session: JwtSession,
+    usuario: CurrentUser,
     pool: web::Data<PgPool>,
     req: HttpRequest,
     file: web::Path<String>,
 ) -> Result<HttpResponse, actix_web::Error> {
-    // Usuario es admin ?
-    let usuario = obtener_usuario_por_id_sqlx(&pool, &session.user_id).await
-        .map_err(|_| e500())?
-        .ok_or(e500())?;
 
-    if !usuario.es_admin() {
+    if !usuario.administra() {
         return Err(e403().with_message("No tienes los permisos requeridos"))?;
     }
 
+    let file = file.into_inner();
+    if let Some(departamentos) = usuario.departamentos_administrados() {
+        let en_alcance = imagen_en_alcance_sqlx(&pool, &file, departamentos).await
+            .map_err(|_| e500())?;
+        if !en_alcance {
+            return Err(e404().with_message("No se encontro el archivo"))?;
+        }
+    }
+
     // Obtener path
     let base_path = get_uploads_path()
         .map_err(|_| e500())?
         .join("users");
 
-    let file = file.into_inner();
     let file_path = base_path.join(&file);
     //dbg!(&file_path);
     
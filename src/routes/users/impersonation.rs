@@ -0,0 +1,104 @@
+use actix_web::{HttpRequest, HttpResponse, web};
+use chrono::NaiveDateTime;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::api_response::{ApiResponse, e400, e403, e404, e500};
+use crate::audit::{AccionAuditoria, registrar_auditoria};
+use crate::authentication::current_user::CurrentUser;
+use crate::authentication::jwt_session::{TokenClaims, encode_jwt};
+use crate::authentication::key_ring::KeyRing;
+
+use super::sqlx::obtener_usuario_por_id_sqlx;
+
+
+#[derive(Debug, serde::Deserialize)]
+pub struct SolicitudSuplantacion {
+    // Motivo del soporte, queda en la auditoria
+    pub motivo: String,
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct Suplantacion {
+    pub token: String,
+    pub expira_en: NaiveDateTime,
+}
+
+
+#[tracing::instrument(
+    name = "Suplantar usuario",
+    skip(usuario, pool, key_ring, body, req)
+)]
+pub async fn impersonate_user(
+    usuario: CurrentUser,
+    pool: web::Data<PgPool>,
+    key_ring: web::Data<KeyRing>,
+    uuid: web::Path<Uuid>,
+    body: web::Json<SolicitudSuplantacion>,
+    req: HttpRequest,
+) -> Result<HttpResponse, actix_web::Error> {
+
+    if !usuario.es_admin() {
+        return Err(e403().with_message("No tienes los permisos requeridos"))?;
+    }
+
+    // Solo con una sesion propia, no con API keys ni otra suplantacion
+    if usuario.session.is_none() {
+        return Err(e403().with_message("Esta ruta requiere iniciar sesion"))?;
+    }
+    usuario.rechazar_suplantacion()?;
+
+    let motivo = body.into_inner().motivo.trim().to_string();
+    if motivo.is_empty() {
+        return Err(e400().with_message("Indica el motivo de la suplantacion"))?;
+    }
+
+    if *uuid == usuario.usuario_id {
+        return Err(e400().with_message("No puedes suplantarte a ti mismo"))?;
+    }
+
+    let otro_usuario = obtener_usuario_por_id_sqlx(&pool, &uuid).await
+        .map_err(|_| e500())?
+        .ok_or(e404().with_message("No se encontro el usuario"))?;
+
+    if otro_usuario.es_admin() {
+        return Err(e403().with_message("No puedes suplantar a otro administrador"))?;
+    }
+    if !otro_usuario.activo {
+        return Err(e400().with_message("El usuario esta desactivado"))?;
+    }
+
+    let claims = TokenClaims::impersonation(&otro_usuario.usuario_id, &usuario.usuario_id, &key_ring);
+    let expira_en = NaiveDateTime::from_timestamp_opt(claims.exp as i64, 0)
+        .ok_or(e500())?;
+    let token = encode_jwt(&claims, &key_ring)
+        .map_err(|_| e500())?;
+
+    // Sin registro en la auditoria no se entrega el token
+    let ip = req.connection_info()
+        .realip_remote_addr()
+        .map(|ip| ip.to_string());
+    registrar_auditoria(
+        &pool,
+        Some(&usuario.usuario_id),
+        Some(&otro_usuario.usuario_id),
+        AccionAuditoria::SuplantacionIniciada,
+        &motivo,
+        ip.as_deref(),
+    ).await
+        .map_err(|_| e500())?;
+
+    tracing::info!(
+        "El administrador {} suplanta al usuario {}",
+        usuario.usuario_id,
+        otro_usuario.usuario_id
+    );
+
+    // Respuesta exitosa
+    let api_response = ApiResponse::<Suplantacion>::new()
+        .with_message(format!("Token para actuar como {}", otro_usuario.email))
+        .with_data(Suplantacion { token, expira_en })
+        .to_resp();
+
+    Ok(api_response)
+}
@@ -0,0 +1,200 @@
+use actix_web::{HttpResponse, web};
+use anyhow::Context;
+use chrono::NaiveDateTime;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::api_response::{ApiResponse, e400, e404, e500};
+use crate::authentication::current_user::CurrentUser;
+use crate::routes::users::sqlx::obtener_usuario_por_id_sqlx;
+
+
+#[derive(Debug, serde::Serialize)]
+pub struct Delegacion {
+    pub delegacion_id: Uuid,
+    pub delegante_id: Uuid,
+    pub delegado_id: Uuid,
+    pub inicio: NaiveDateTime,
+    pub fin: NaiveDateTime,
+    pub motivo: Option<String>,
+    pub creado_en: NaiveDateTime,
+}
+
+#[derive(Debug, serde::Deserialize)]
+pub struct NuevaDelegacion {
+    pub delegado_id: Uuid,
+    pub inicio: NaiveDateTime,
+    pub fin: NaiveDateTime,
+    pub motivo: Option<String>,
+}
+
+
+/// Delegaciones que el usuario dio o recibio y no han terminado
+#[tracing::instrument(
+    name = "Query mis delegaciones",
+    skip(pool)
+)]
+async fn obtener_delegaciones_sqlx(
+    pool: &PgPool,
+    usuario_id: &Uuid,
+) -> Result<Vec<Delegacion>, anyhow::Error> {
+    let delegaciones = sqlx::query_as!(
+        Delegacion,
+        r#"
+        SELECT delegacion_id, delegante_id, delegado_id, inicio, fin, motivo, creado_en
+        FROM delegaciones
+        WHERE (delegante_id = $1 OR delegado_id = $1) AND fin > now()
+        ORDER BY inicio
+        "#,
+        usuario_id,
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to execute query")?;
+
+    Ok(delegaciones)
+}
+
+#[tracing::instrument(
+    name = "Query insertar delegacion",
+    skip(pool)
+)]
+async fn insertar_delegacion_sqlx(
+    pool: &PgPool,
+    delegante_id: &Uuid,
+    delegacion: &NuevaDelegacion,
+) -> Result<Delegacion, anyhow::Error> {
+    let delegacion = sqlx::query_as!(
+        Delegacion,
+        r#"
+        INSERT INTO delegaciones (delegacion_id, delegante_id, delegado_id, inicio, fin, motivo)
+        VALUES ($1, $2, $3, $4, $5, $6)
+        RETURNING delegacion_id, delegante_id, delegado_id, inicio, fin, motivo, creado_en
+        "#,
+        Uuid::new_v4(),
+        delegante_id,
+        delegacion.delegado_id,
+        delegacion.inicio,
+        delegacion.fin,
+        delegacion.motivo.as_deref().map(str::trim).filter(|motivo| !motivo.is_empty()),
+    )
+    .fetch_one(pool)
+    .await
+    .context("Failed to execute query")?;
+
+    Ok(delegacion)
+}
+
+/// Regresa false si la delegacion no es del usuario
+#[tracing::instrument(
+    name = "Query borrar delegacion",
+    skip(pool)
+)]
+async fn borrar_delegacion_sqlx(
+    pool: &PgPool,
+    delegante_id: &Uuid,
+    delegacion_id: &Uuid,
+) -> Result<bool, anyhow::Error> {
+    let result = sqlx::query!(
+        "DELETE FROM delegaciones WHERE delegacion_id = $1 AND delegante_id = $2",
+        delegacion_id,
+        delegante_id,
+    )
+    .execute(pool)
+    .await
+    .context("Failed to execute query")?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+
+#[tracing::instrument(
+    name = "Obtener mis delegaciones",
+    skip_all
+)]
+pub async fn get_my_delegations(
+    usuario: CurrentUser,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+
+    let delegaciones = obtener_delegaciones_sqlx(&pool, &usuario.usuario_id).await
+        .map_err(|_| e500())?;
+
+    // Respuesta exitosa
+    let api_response = ApiResponse::<Vec<Delegacion>>::new()
+        .with_message("Mis delegaciones")
+        .with_data(delegaciones)
+        .to_resp();
+
+    Ok(api_response)
+}
+
+
+#[tracing::instrument(
+    name = "Delegar mis aprobaciones",
+    skip(usuario, pool)
+)]
+pub async fn post_my_delegation(
+    usuario: CurrentUser,
+    pool: web::Data<PgPool>,
+    body: web::Json<NuevaDelegacion>,
+) -> Result<HttpResponse, actix_web::Error> {
+
+    usuario.rechazar_suplantacion()?;
+
+    let delegacion = body.into_inner();
+    if delegacion.delegado_id == usuario.usuario_id {
+        return Err(e400().with_message("No puedes delegar en ti mismo"))?;
+    }
+    if delegacion.fin <= delegacion.inicio {
+        return Err(e400().with_message("El fin debe ser despues del inicio"))?;
+    }
+    if delegacion.fin <= chrono::Utc::now().naive_utc() {
+        return Err(e400().with_message("La delegacion ya termino"))?;
+    }
+
+    let delegado = obtener_usuario_por_id_sqlx(&pool, &delegacion.delegado_id).await
+        .map_err(|_| e500())?
+        .ok_or(e404().with_message("No se encontro el usuario"))?;
+    if !delegado.activo {
+        return Err(e400().with_message("El usuario esta desactivado"))?;
+    }
+
+    let delegacion = insertar_delegacion_sqlx(&pool, &usuario.usuario_id, &delegacion).await
+        .map_err(|_| e500())?;
+
+    // Respuesta exitosa
+    let api_response = ApiResponse::<Delegacion>::new()
+        .with_message(format!("{} aprobara por ti durante tu ausencia", delegado.email))
+        .with_data(delegacion)
+        .to_resp();
+
+    Ok(api_response)
+}
+
+
+#[tracing::instrument(
+    name = "Borrar mi delegacion",
+    skip(usuario, pool)
+)]
+pub async fn delete_my_delegation(
+    usuario: CurrentUser,
+    pool: web::Data<PgPool>,
+    uuid: web::Path<Uuid>,
+) -> Result<HttpResponse, actix_web::Error> {
+
+    usuario.rechazar_suplantacion()?;
+
+    let borrada = borrar_delegacion_sqlx(&pool, &usuario.usuario_id, &uuid).await
+        .map_err(|_| e500())?;
+    if !borrada {
+        return Err(e404().with_message("No se encontro la delegacion"))?;
+    }
+
+    // Respuesta exitosa
+    let api_response = ApiResponse::<()>::new()
+        .with_message("Delegacion eliminada")
+        .to_resp();
+
+    Ok(api_response)
+}
@@ -0,0 +1,353 @@
+use actix_web::{HttpResponse, web};
+use anyhow::Context;
+use rand::{thread_rng, Rng, distributions::Alphanumeric};
+use secrecy::Secret;
+use sha2::{Digest, Sha256};
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+use crate::api_response::{ApiResponse, e400, e404, e409, e500};
+use crate::authentication::{validate_credentials, AuthError, Credentials, DummyPasswordHash};
+use crate::authentication::current_user::{CurrentUser, invalidar_cache_usuario};
+use crate::configuration::{LdapSettings, PasswordHashingSettings};
+use crate::email_client::EmailClient;
+use crate::redis_pool::RedisPool;
+use crate::routes::users::sqlx::{email_en_uso_sqlx, es_email_duplicado};
+use crate::startup::ApplicationBaseUrl;
+
+
+/// Horas que el enlace de confirmacion es valido
+const CAMBIO_EMAIL_EXPIRACION_HORAS: i64 = 24;
+
+
+#[derive(serde::Deserialize)]
+pub struct CambiarMiEmail {
+    pub email: String,
+    // Se pide la contraseña para que una sesion robada no pueda tomar la cuenta
+    pub password: Secret<String>,
+}
+
+#[derive(serde::Deserialize)]
+pub struct Parameters {
+    token: String,
+}
+
+
+fn hash_token(token: &str) -> String {
+    format!("{:x}", Sha256::digest(token.as_bytes()))
+}
+
+fn generar_token() -> String {
+    let mut rng = thread_rng();
+    std::iter::repeat_with(|| rng.sample(Alphanumeric))
+        .map(char::from)
+        .take(40)
+        .collect()
+}
+
+
+#[tracing::instrument(
+    name = "Query guardar cambio de email",
+    skip(transaction, token)
+)]
+async fn guardar_cambio_email_sqlx(
+    transaction: &mut Transaction<'_, Postgres>,
+    usuario_id: &Uuid,
+    email_anterior: &str,
+    email_nuevo: &str,
+    token: &str,
+) -> Result<(), anyhow::Error> {
+    // Una solicitud nueva reemplaza a la pendiente
+    sqlx::query!(
+        r#"
+        DELETE FROM cambios_email
+        WHERE usuario_id = $1 AND confirmado_en IS NULL
+        "#,
+        usuario_id,
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("Failed to execute query")?;
+
+    sqlx::query!(
+        r#"
+        INSERT INTO cambios_email
+        (cambio_id, usuario_id, email_anterior, email_nuevo, token_hash, expira_en)
+        VALUES ($1, $2, $3, $4, $5, $6)
+        "#,
+        Uuid::new_v4(),
+        usuario_id,
+        email_anterior,
+        email_nuevo,
+        hash_token(token),
+        chrono::Utc::now().naive_utc() + chrono::Duration::hours(CAMBIO_EMAIL_EXPIRACION_HORAS),
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("Failed to execute query")?;
+
+    Ok(())
+}
+
+#[tracing::instrument(
+    name = "Query cancelar cambio de email",
+    skip(pool)
+)]
+async fn cancelar_cambio_email_sqlx(
+    pool: &PgPool,
+    usuario_id: &Uuid,
+) -> Result<bool, anyhow::Error> {
+    let result = sqlx::query!(
+        r#"
+        DELETE FROM cambios_email
+        WHERE usuario_id = $1 AND confirmado_en IS NULL
+        "#,
+        usuario_id,
+    )
+    .execute(pool)
+    .await
+    .context("Failed to execute query")?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+struct CambioEmail {
+    usuario_id: Uuid,
+    email_anterior: String,
+    email_nuevo: String,
+}
+
+/// Aplica el cambio del token, regresa None si el token no es valido
+#[tracing::instrument(
+    name = "Query confirmar cambio de email",
+    skip_all
+)]
+async fn confirmar_cambio_email_sqlx(
+    pool: &PgPool,
+    token: &str,
+) -> Result<Option<CambioEmail>, anyhow::Error> {
+    let mut transaction = pool.begin()
+        .await
+        .context("Failed to begin transaction")?;
+
+    let cambio = sqlx::query_as!(
+        CambioEmail,
+        r#"
+        UPDATE cambios_email
+        SET confirmado_en = now()
+        WHERE token_hash = $1
+            AND confirmado_en IS NULL
+            AND expira_en > now()
+        RETURNING usuario_id, email_anterior, email_nuevo
+        "#,
+        hash_token(token),
+    )
+    .fetch_optional(&mut transaction)
+    .await
+    .context("Failed to execute query")?;
+
+    let cambio = match cambio {
+        Some(cambio) => cambio,
+        None => return Ok(None),
+    };
+
+    sqlx::query!(
+        r#"
+        UPDATE usuarios
+        SET email = $2, modificado_en = now()
+        WHERE usuario_id = $1
+        "#,
+        cambio.usuario_id,
+        cambio.email_nuevo,
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to update user email")?;
+
+    transaction.commit()
+        .await
+        .context("Failed to commit transaction")?;
+
+    Ok(Some(cambio))
+}
+
+
+#[tracing::instrument(
+    name = "Solicitar cambio de mi email",
+    skip_all,
+    fields(user_id=tracing::field::Empty)
+)]
+pub async fn request_email_change_me(
+    usuario: CurrentUser,
+    pool: web::Data<PgPool>,
+    email_client: web::Data<EmailClient>,
+    base_url: web::Data<ApplicationBaseUrl>,
+    ldap: web::Data<LdapSettings>,
+    hashing: web::Data<PasswordHashingSettings>,
+    dummy_hash: web::Data<DummyPasswordHash>,
+    body: web::Json<CambiarMiEmail>,
+) -> Result<HttpResponse, actix_web::Error> {
+
+    usuario.rechazar_suplantacion()?;
+
+    tracing::Span::current()
+        .record("user_id", &tracing::field::display(&usuario.usuario_id));
+
+    // El correo de las cuentas del directorio se cambia en el directorio
+    if ldap.applies_to(&usuario.email) {
+        return Err(e400().with_message("El correo de esta cuenta se administra en el directorio"))?;
+    }
+
+    let CambiarMiEmail { email, password } = body.into_inner();
+    let email = email.trim().to_string();
+
+    if !validator::validate_email(&email) {
+        return Err(e400().with_message("Correo electronico invalido"))?;
+    }
+    if email.eq_ignore_ascii_case(&usuario.email) {
+        return Err(e400().with_message("El correo nuevo es igual al actual"))?;
+    }
+
+    let credentials = Credentials { email: usuario.email.clone(), password, dispositivo: None };
+    validate_credentials(credentials, &pool, &hashing, &dummy_hash).await
+        .map_err(|e| match e {
+            AuthError::InvalidCredentials(_) => e400().with_message("Contraseña actual no concuerda"),
+            AuthError::UnexpectedError(_) => e500(),
+        })?;
+
+    let email_en_uso = email_en_uso_sqlx(&pool, &email, &usuario.usuario_id).await
+        .map_err(|_| e500())?;
+    if email_en_uso {
+        return Err(e409().with_message("Ya existe usuario con ese correo electronico"))?;
+    }
+
+    // Guardar solicitud, el correo actual sigue activo hasta confirmar
+    let token = generar_token();
+    let mut transaction = pool.begin()
+        .await
+        .map_err(|_| e500())?;
+    guardar_cambio_email_sqlx(&mut transaction, &usuario.usuario_id, &usuario.email, &email, &token).await
+        .map_err(|_| e500())?;
+    transaction.commit()
+        .await
+        .map_err(|_| e500())?;
+
+    let confirmation_link = format!(
+        "{}/api/auth/email-change/confirm?token={}",
+        base_url.0,
+        token,
+    );
+
+    email_client.send_email(
+            &email,
+            "Confirma tu nuevo correo",
+            &format!("Solicitaste usar este correo en Control Parque Vehicular.<br />\
+                     Haz click <a href=\"{}\">aqui</a> para confirmar el cambio.",
+                     confirmation_link),
+            &format!("Solicitaste usar este correo en Control Parque Vehicular.\n\
+                     Visita {} para confirmar el cambio.",
+                     confirmation_link),
+        )
+        .await
+        .map_err(|_| e500())?;
+
+    // Avisar al correo actual, la solicitud ya quedo guardada
+    if let Err(e) = email_client.send_email(
+            &usuario.email,
+            "Solicitud de cambio de correo",
+            &format!("Se solicito cambiar el correo de tu cuenta a {}.<br />\
+                     Si no fuiste tu, cambia tu contraseña y contacta al administrador.",
+                     email),
+            &format!("Se solicito cambiar el correo de tu cuenta a {}.\n\
+                     Si no fuiste tu, cambia tu contraseña y contacta al administrador.",
+                     email),
+        ).await
+    {
+        tracing::error!("No se pudo notificar al correo actual: {:?}", e);
+    }
+
+    // Respuesta exitosa
+    let api_response = ApiResponse::<()>::new()
+        .with_status_code(202)
+        .with_message("Te enviamos un enlace al nuevo correo para confirmar el cambio")
+        .to_resp();
+
+    Ok(api_response)
+}
+
+
+#[tracing::instrument(
+    name = "Cancelar cambio de mi email",
+    skip_all
+)]
+pub async fn cancel_email_change_me(
+    usuario: CurrentUser,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+
+    usuario.rechazar_suplantacion()?;
+
+    let cancelado = cancelar_cambio_email_sqlx(&pool, &usuario.usuario_id).await
+        .map_err(|_| e500())?;
+
+    if !cancelado {
+        return Err(e404().with_message("No tienes un cambio de correo pendiente"))?;
+    }
+
+    // Respuesta exitosa
+    let api_response = ApiResponse::<()>::new()
+        .with_message("Cambio de correo cancelado")
+        .to_resp();
+
+    Ok(api_response)
+}
+
+
+#[tracing::instrument(
+    name = "Confirmar cambio de email",
+    skip_all,
+    fields(user_id=tracing::field::Empty)
+)]
+pub async fn confirm_email_change(
+    parameters: web::Query<Parameters>,
+    pool: web::Data<PgPool>,
+    redis_pool: web::Data<RedisPool>,
+    email_client: web::Data<EmailClient>,
+) -> Result<HttpResponse, actix_web::Error> {
+
+    let cambio = confirmar_cambio_email_sqlx(&pool, &parameters.token).await
+        .map_err(|e| {
+            if es_email_duplicado(&e) {
+                e409().with_message("Ya existe usuario con ese correo electronico")
+            } else {
+                e500()
+            }
+        })?
+        .ok_or(e400().with_message("Token invalido o expirado"))?;
+
+    tracing::Span::current()
+        .record("user_id", &tracing::field::display(&cambio.usuario_id));
+
+    invalidar_cache_usuario(&redis_pool, &cambio.usuario_id).await;
+
+    if let Err(e) = email_client.send_email(
+            &cambio.email_anterior,
+            "Tu correo cambio",
+            &format!("El correo de tu cuenta de Control Parque Vehicular ahora es {}.<br />\
+                     Si no fuiste tu, contacta al administrador.",
+                     cambio.email_nuevo),
+            &format!("El correo de tu cuenta de Control Parque Vehicular ahora es {}.\n\
+                     Si no fuiste tu, contacta al administrador.",
+                     cambio.email_nuevo),
+        ).await
+    {
+        tracing::error!("No se pudo notificar al correo anterior: {:?}", e);
+    }
+
+    // Respuesta exitosa
+    let api_response = ApiResponse::<()>::new()
+        .with_message("Correo actualizado")
+        .to_resp();
+
+    Ok(api_response)
+}
@@ -0,0 +1,206 @@
+use actix_web::{HttpRequest, HttpResponse, web};
+use actix_web::http::header::{ContentDisposition, DispositionParam, DispositionType};
+use actix_files::NamedFile;
+use anyhow::Context;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::api_response::{ApiResponse, e400, e404, e409, e500};
+use crate::authentication::current_user::CurrentUser;
+use crate::export_worker::{get_exports_path, hash_token};
+
+
+#[derive(Debug, serde::Serialize, sqlx::Type)]
+#[sqlx(type_name = "estado_exportacion", rename_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum EstadoExportacion {
+    Pendiente,
+    Lista,
+    Fallida,
+    Expirada,
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct Exportacion {
+    pub exportacion_id: Uuid,
+    pub estado: EstadoExportacion,
+    pub creado_en: chrono::NaiveDateTime,
+    pub completado_en: Option<chrono::NaiveDateTime>,
+    pub expira_en: Option<chrono::NaiveDateTime>,
+}
+
+#[derive(serde::Deserialize)]
+pub struct Parameters {
+    token: String,
+}
+
+
+/// Regresa false si el usuario ya tiene una exportacion en proceso
+#[tracing::instrument(
+    name = "Query solicitar exportacion",
+    skip(pool)
+)]
+async fn solicitar_exportacion_sqlx(
+    pool: &PgPool,
+    usuario_id: &Uuid,
+) -> Result<bool, anyhow::Error> {
+    let result = sqlx::query!(
+        r#"
+        INSERT INTO exportaciones (exportacion_id, usuario_id)
+        VALUES ($1, $2)
+        ON CONFLICT (usuario_id) WHERE estado = 'pendiente' DO NOTHING
+        "#,
+        Uuid::new_v4(),
+        usuario_id,
+    )
+    .execute(pool)
+    .await
+    .context("Failed to execute query")?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+#[tracing::instrument(
+    name = "Query obtener ultima exportacion",
+    skip(pool)
+)]
+async fn obtener_ultima_exportacion_sqlx(
+    pool: &PgPool,
+    usuario_id: &Uuid,
+) -> Result<Option<Exportacion>, anyhow::Error> {
+    let exportacion = sqlx::query_as!(
+        Exportacion,
+        r#"
+        SELECT
+            exportacion_id,
+            estado as "estado!: EstadoExportacion",
+            creado_en, completado_en, expira_en
+        FROM exportaciones
+        WHERE usuario_id = $1
+        ORDER BY creado_en DESC
+        LIMIT 1
+        "#,
+        usuario_id,
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to execute query")?;
+
+    Ok(exportacion)
+}
+
+/// Archivo de la exportacion del token, None si no existe o ya expiro
+#[tracing::instrument(
+    name = "Query obtener archivo de exportacion",
+    skip_all
+)]
+async fn obtener_archivo_exportacion_sqlx(
+    pool: &PgPool,
+    token: &str,
+) -> Result<Option<String>, anyhow::Error> {
+    let row = sqlx::query!(
+        r#"
+        SELECT archivo as "archivo!"
+        FROM exportaciones
+        WHERE token_hash = $1
+            AND estado = 'lista'
+            AND archivo IS NOT NULL
+            AND expira_en > now()
+        "#,
+        hash_token(token),
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to execute query")?;
+
+    Ok(row.map(|row| row.archivo))
+}
+
+
+#[tracing::instrument(
+    name = "Solicitar exportacion de mis datos",
+    skip_all,
+    fields(user_id=tracing::field::Empty)
+)]
+pub async fn request_export_me(
+    usuario: CurrentUser,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+
+    usuario.rechazar_suplantacion()?;
+
+    tracing::Span::current()
+        .record("user_id", &tracing::field::display(&usuario.usuario_id));
+
+    // El ZIP se genera en el worker de exportaciones
+    let solicitada = solicitar_exportacion_sqlx(&pool, &usuario.usuario_id).await
+        .map_err(|_| e500())?;
+
+    if !solicitada {
+        return Err(e409().with_message("Ya tienes una exportacion en proceso"))?;
+    }
+
+    // Respuesta exitosa
+    let api_response = ApiResponse::<()>::new()
+        .with_status_code(202)
+        .with_message("Te enviaremos un correo cuando tus datos esten listos para descargar")
+        .to_resp();
+
+    Ok(api_response)
+}
+
+
+#[tracing::instrument(
+    name = "Obtener estado de mi exportacion",
+    skip_all
+)]
+pub async fn get_export_me(
+    usuario: CurrentUser,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+
+    let exportacion = obtener_ultima_exportacion_sqlx(&pool, &usuario.usuario_id).await
+        .map_err(|_| e500())?
+        .ok_or(e404().with_message("No has solicitado una exportacion"))?;
+
+    // Respuesta exitosa
+    let api_response = ApiResponse::<Exportacion>::new()
+        .with_message("Tu exportacion")
+        .with_data(exportacion)
+        .to_resp();
+
+    Ok(api_response)
+}
+
+
+#[tracing::instrument(
+    name = "Descargar exportacion",
+    skip_all
+)]
+pub async fn download_export(
+    parameters: web::Query<Parameters>,
+    pool: web::Data<PgPool>,
+    req: HttpRequest,
+) -> Result<HttpResponse, actix_web::Error> {
+
+    // El enlace llega por correo, el token es la autorizacion
+    let archivo = obtener_archivo_exportacion_sqlx(&pool, &parameters.token).await
+        .map_err(|_| e500())?
+        .ok_or(e400().with_message("Enlace invalido o expirado"))?;
+
+    let file_path = get_exports_path()
+        .map_err(|_| e500())?
+        .join(archivo);
+
+    let file = NamedFile::open_async(file_path).await
+        .map_err(|e| match e.kind() {
+            std::io::ErrorKind::NotFound => e404().with_message("No se encontro el archivo"),
+            _ => e500(),
+        })?
+        .set_content_disposition(ContentDisposition {
+            disposition: DispositionType::Attachment,
+            parameters: vec![DispositionParam::Filename(String::from("mis-datos.zip"))],
+        });
+
+    Ok(file.into_response(&req))
+}
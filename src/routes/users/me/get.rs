@@ -1,11 +1,9 @@
-use actix_web::{HttpResponse, web};
-use sqlx::PgPool;
+use actix_web::HttpResponse;
 
 use common::models::user::Usuario;
 
-use crate::authentication::jwt_session::JwtSession;
-use crate::api_response::{ApiResponse, e500};
-use crate::routes::users::sqlx::obtener_usuario_por_id_sqlx;
+use crate::authentication::current_user::CurrentUser;
+use crate::api_response::ApiResponse;
 
 
 #[tracing::instrument(
@@ -13,21 +11,13 @@ use crate::routes::users::sqlx::obtener_usuario_por_id_sqlx;
     skip_all,
 )]
 pub async fn user_get_me(
-    session: JwtSession,
-    pool: web::Data<PgPool>,
+    usuario: CurrentUser,
 ) -> Result<HttpResponse, actix_web::Error> {
 
-    // Sesion actual tiene un usuario valido ?
-    let usuario = obtener_usuario_por_id_sqlx(&pool, &session.user_id).await
-        .map_err(|_| e500())?
-        .ok_or(e500())?;
-
-    // Talvez deberia utilizar otro query con referencias joined
-
     // Respuesta exitosa 
     let api_response = ApiResponse::<Usuario>::new()
         .with_message("Tu informacion de usuario")
-        .with_data(usuario)
+        .with_data(usuario.into_inner())
         .to_resp();
     Ok(api_response)
 }
@@ -0,0 +1,132 @@
+use actix_web::{HttpResponse, web};
+use sqlx::PgPool;
+
+use crate::api_response::{ApiResponse, e404, e500, e503};
+use crate::authentication::current_user::CurrentUser;
+use crate::authentication::oidc::{
+    completar_autorizacion, desvincular_identidad, iniciar_autorizacion, listar_identidades,
+    vincular_identidad, IdentidadExterna, OidcError, ProveedoresOidc,
+};
+use crate::routes::auth::oidc::{error_oidc, CodigoAutorizacion, UrlAutorizacion};
+
+
+#[tracing::instrument(
+    name = "Obtener mis identidades externas",
+    skip_all,
+)]
+pub async fn get_my_identities(
+    usuario: CurrentUser,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+
+    let identidades = listar_identidades(&pool, &usuario.usuario_id).await
+        .map_err(|_| e500())?;
+
+    // Respuesta exitosa
+    let api_response = ApiResponse::<Vec<IdentidadExterna>>::new()
+        .with_message("Tus identidades externas")
+        .with_data(identidades)
+        .to_resp();
+
+    Ok(api_response)
+}
+
+
+#[tracing::instrument(
+    name = "Iniciar vinculacion de identidad externa",
+    skip(usuario, proveedores)
+)]
+pub async fn link_identity(
+    usuario: CurrentUser,
+    proveedores: web::Data<ProveedoresOidc>,
+    proveedor: web::Path<String>,
+) -> Result<HttpResponse, actix_web::Error> {
+
+    usuario.rechazar_suplantacion()?;
+
+    let mut redis_con = usuario.redis.get().await
+        .map_err(|_| e503())?;
+
+    let url = iniciar_autorizacion(&proveedores, &usuario.redis, &mut redis_con, &proveedor, Some(usuario.usuario_id), None).await
+        .map_err(|e| {
+            tracing::error!("No se pudo iniciar la autorizacion {:?}", e);
+            error_oidc(e)
+        })?;
+
+    // Respuesta exitosa
+    let api_response = ApiResponse::<UrlAutorizacion>::new()
+        .with_message("Redirige al usuario al proveedor")
+        .with_data(UrlAutorizacion { url })
+        .to_resp();
+
+    Ok(api_response)
+}
+
+
+#[tracing::instrument(
+    name = "Completar vinculacion de identidad externa",
+    skip(usuario, pool, proveedores, body)
+)]
+pub async fn link_identity_callback(
+    usuario: CurrentUser,
+    pool: web::Data<PgPool>,
+    proveedores: web::Data<ProveedoresOidc>,
+    proveedor: web::Path<String>,
+    body: web::Json<CodigoAutorizacion>,
+) -> Result<HttpResponse, actix_web::Error> {
+
+    usuario.rechazar_suplantacion()?;
+
+    let mut redis_con = usuario.redis.get().await
+        .map_err(|_| e503())?;
+
+    let autorizacion = completar_autorizacion(&proveedores, &usuario.redis, &mut redis_con, &proveedor, &body.code, &body.state).await
+        .map_err(|e| {
+            tracing::warn!("No se pudo completar la autorizacion {:?}", e);
+            error_oidc(e)
+        })?;
+
+    // El state debe ser del mismo usuario que inicio la vinculacion
+    if autorizacion.vincular != Some(usuario.usuario_id) {
+        return Err(error_oidc(OidcError::InvalidState));
+    }
+
+    let identidad = vincular_identidad(&pool, &usuario.usuario_id, &autorizacion.identidad).await
+        .map_err(error_oidc)?;
+
+    // Respuesta exitosa
+    let api_response = ApiResponse::<IdentidadExterna>::new()
+        .with_message("Identidad vinculada")
+        .with_data(identidad)
+        .to_resp();
+
+    Ok(api_response)
+}
+
+
+#[tracing::instrument(
+    name = "Desvincular identidad externa",
+    skip(usuario, pool)
+)]
+pub async fn unlink_identity(
+    usuario: CurrentUser,
+    pool: web::Data<PgPool>,
+    proveedor: web::Path<String>,
+) -> Result<HttpResponse, actix_web::Error> {
+
+    usuario.rechazar_suplantacion()?;
+
+    let desvinculada = desvincular_identidad(&pool, &usuario.usuario_id, &proveedor).await
+        .map_err(|_| e500())?;
+
+    if !desvinculada {
+        return Err(e404().with_message("No tienes una identidad de ese proveedor"))?;
+    }
+
+    // Respuesta exitosa
+    let api_response = ApiResponse::<()>::new()
+        .with_message("Identidad desvinculada")
+        .to_resp();
+
+    Ok(api_response)
+}
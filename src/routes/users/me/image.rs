@@ -1,29 +1,21 @@
-use actix_web::{web, HttpResponse};
+use actix_web::HttpResponse;
 use actix_web::HttpRequest;
 use actix_files::NamedFile;
 
-use sqlx::PgPool;
-
-use crate::authentication::jwt_session::JwtSession;
+use crate::authentication::current_user::CurrentUser;
 use crate::api_response::{e500, e404, e403};
 
 use crate::upload::image::get_uploads_path;
-use crate::routes::users::sqlx::obtener_usuario_por_id_sqlx;
 
 
 #[tracing::instrument(
     name = "Serve imagen estatica de mi perfil usuario",
-    skip(session, pool, req)
+    skip(usuario, req)
 )]
 pub async fn get_imagen_usuario(
-    session: JwtSession,
-    pool: web::Data<PgPool>,
+    usuario: CurrentUser,
     req: HttpRequest,
 ) -> Result<HttpResponse, actix_web::Error> {
-    // Usuario es valido?
-    let usuario = obtener_usuario_por_id_sqlx(&pool, &session.user_id).await
-        .map_err(|_| e500())?
-        .ok_or(e500())?;
 
     // Obtener path
     let base_path = get_uploads_path()
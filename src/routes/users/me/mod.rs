@@ -2,4 +2,10 @@ pub mod get;
 pub mod patch;
 
 pub mod password;
+pub mod email;
 pub mod image;
+pub mod two_factor;
+pub mod sessions;
+pub mod identities;
+pub mod export;
+pub mod delegations;
@@ -1,6 +1,12 @@
 use crate::api_response::{e400, e500, ApiResponse};
 use crate::authentication::password::compute_password_hash;
-use crate::authentication::jwt_session::JwtSession;
+use crate::authentication::password_policy::{
+    error_password, guardar_historial_password, password_reutilizado, PasswordPolicy,
+    ReglaPassword, ViolacionPassword,
+};
+use crate::authentication::current_user::CurrentUser;
+use crate::authentication::sessions::revocar_sesiones;
+use crate::configuration::{LdapSettings, PasswordHashingSettings};
 use crate::telemetry::spawn_blocking_with_tracing;
 use crate::routes::users::sqlx::obtener_usuario_por_id_sqlx;
 
@@ -10,7 +16,7 @@ use actix_web::{HttpResponse, web};
 use anyhow::Context;
 use argon2::{Argon2, PasswordHash, PasswordVerifier};
 use secrecy::{Secret, ExposeSecret};
-use sqlx::PgPool;
+use sqlx::{PgPool, Postgres, Transaction};
 use uuid::Uuid;
 use validator::Validate;
 
@@ -22,16 +28,20 @@ use validator::Validate;
     fields(user_id=tracing::field::Empty)
 )]
 pub async fn change_user_password(
-    session: JwtSession,
+    usuario: CurrentUser,
     pool: web::Data<PgPool>,
     body: web::Json<CambiarMiPassword>,
+    ldap: web::Data<LdapSettings>,
+    password_policy: web::Data<PasswordPolicy>,
+    hashing: web::Data<PasswordHashingSettings>,
 ) -> Result<HttpResponse, actix_web::Error> {
 
-    // Session actual tiene un usuario valido ?
-    let usuario = obtener_usuario_por_id_sqlx(&pool, &session.user_id).await
-        .map_err(|_| e500())?
-        .ok_or(e500())?;
+    usuario.rechazar_suplantacion()?;
 
+    // La contraseña de las cuentas del directorio se cambia en el directorio
+    if ldap.applies_to(&usuario.email) {
+        return Err(e400().with_message("La contraseña de esta cuenta se administra en el directorio"))?;
+    }
 
     // Destructed body and validate
     let password_form = body.0;
@@ -43,16 +53,40 @@ pub async fn change_user_password(
     // Verificar password actual con password en DB
     let password_actual = password_form.password_actual.clone();
 
-    let parsed_hash = PasswordHash::new(&usuario.password_hash)
+    // El password_hash no se guarda en el cache, se obtiene de la DB
+    let password_hash = obtener_usuario_por_id_sqlx(&pool, &usuario.usuario_id).await
+        .map_err(|_| e500())?
+        .ok_or(e500())?
+        .password_hash;
+
+    let parsed_hash = PasswordHash::new(&password_hash)
         .map_err(|_| e500())?;
 
     Argon2::default().verify_password(&password_actual.as_bytes(), &parsed_hash)
         .map_err(|_| e400().with_message("Contraseña actual no concuerda"))?;
 
+    // Politica de contraseñas
+    let mut violaciones = password_policy.validar(
+        &password_form.password_nuevo,
+        &[&usuario.email, &usuario.nombres, &usuario.apellidos],
+    );
+    let nuevo_password = Secret::new(password_form.password_nuevo.clone());
+    let reutilizado = password_reutilizado(&pool, &usuario.usuario_id, &nuevo_password, password_policy.historial()).await
+        .map_err(|_| e500())?;
+    if reutilizado {
+        violaciones.push(ViolacionPassword {
+            regla: ReglaPassword::Reutilizada,
+            mensaje: format!("No puede ser ninguna de tus ultimas {} contraseñas", password_policy.historial()),
+        });
+    }
+    if !violaciones.is_empty() {
+        return Err(error_password(violaciones))?;
+    }
+
     // Calcular nuevo password hash con la nueva contraseña
-    let nuevo_password = Secret::new(password_form.password_actual.clone());
+    let hashing = hashing.get_ref().clone();
     let nuevo_password_hash = spawn_blocking_with_tracing(
-            move || compute_password_hash(nuevo_password)
+            move || compute_password_hash(nuevo_password, &hashing)
         )
         .await
         .map_err(|_| e500())?
@@ -60,11 +94,23 @@ pub async fn change_user_password(
 
 
     // Query insertar nueva contraseña
-    insertar_usuario_password_hash(&pool, nuevo_password_hash, &usuario.usuario_id).await
+    let mut transaction = pool.begin()
+        .await
+        .map_err(|_| e500())?;
+    // La contraseña anterior pasa al historial
+    guardar_historial_password(&mut transaction, &usuario.usuario_id, &Secret::new(password_hash), password_policy.historial()).await
+        .map_err(|_| e500())?;
+    insertar_usuario_password_hash(&mut transaction, &nuevo_password_hash, &usuario.usuario_id).await
+        .map_err(|_| e500())?;
+    transaction.commit()
+        .await
         .map_err(|_| e500())?;
 
-
-    //TODO add current token to blacklist to enforce relogin
+    // Cerrar las demas sesiones del usuario
+    let mut redis_con = usuario.redis.get().await
+        .map_err(|_| e500())?;
+    revocar_sesiones(&pool, &usuario.redis, &mut redis_con, &usuario.usuario_id, usuario.session_id().as_ref()).await
+        .map_err(|_| e500())?;
 
     // Respuesta exitosa
     let api_response = ApiResponse::<()>::new()
@@ -78,11 +124,11 @@ pub async fn change_user_password(
 
 #[tracing::instrument(
     name = "Insertar nuevo password_hash del usuario",
-    skip(pool, password_hash)
+    skip(transaction, password_hash)
 )]
 async fn insertar_usuario_password_hash(
-    pool: &PgPool,
-    password_hash: Secret<String>,
+    transaction: &mut Transaction<'_, Postgres>,
+    password_hash: &Secret<String>,
     usuario_id: &Uuid,
 ) -> Result<(), anyhow::Error> {
 
@@ -97,7 +143,7 @@ async fn insertar_usuario_password_hash(
         usuario_id,
         password_hash.expose_secret(),
     )
-    .execute(pool)
+    .execute(transaction)
     .await
     .context("Failed to update stored new user password_hash")?;
     
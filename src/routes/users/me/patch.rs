@@ -4,11 +4,11 @@ use sqlx::PgPool;
 
 use common::models::user::{Usuario, ActualizaMiUsuario};
 
-use crate::authentication::jwt_session::JwtSession;
+use crate::authentication::current_user::{CurrentUser, invalidar_cache_usuario};
 use crate::api_response::{ApiResponse, e500};
 use crate::upload::image::get_uploads_path;
 
-use crate::routes::users::sqlx::{obtener_usuario_por_id_sqlx, actualizar_usuario_sqlx, actualizar_imagen_usuario_sqlx};
+use crate::routes::users::sqlx::{actualizar_usuario_sqlx, actualizar_imagen_usuario_sqlx};
 
 
 #[tracing::instrument(
@@ -16,15 +16,11 @@ use crate::routes::users::sqlx::{obtener_usuario_por_id_sqlx, actualizar_usuario
     skip_all
 )]
 pub async fn user_patch_me(
-    session: JwtSession,
+    usuario: CurrentUser,
     pool: web::Data<PgPool>,
     body: web::Json<ActualizaMiUsuario>,
 ) -> Result<HttpResponse, actix_web::Error> {
-
-    // Sesion actual tiene un usuario valido ?
-    let mut usuario = obtener_usuario_por_id_sqlx(&pool, &session.user_id).await
-        .map_err(|_| e500())?
-        .ok_or(e500())?;
+    let CurrentUser { usuario: mut usuario, redis, .. } = usuario;
     
     // Validar actualizacion
     let update_body = body.into_inner();
@@ -37,6 +33,8 @@ pub async fn user_patch_me(
     let usuario_actualizado = actualizar_usuario_sqlx(&pool, usuario).await
         .map_err(|_| e500())?;
 
+    invalidar_cache_usuario(&redis, &usuario_actualizado.usuario_id).await;
+
 
     // Respuesta exitosa
     let api_response = ApiResponse::<Usuario>::new()
@@ -55,16 +53,12 @@ use crate::upload::image::handle_picture_multipart;
     skip_all
 )]
 pub async fn user_picture_patch_me(
-    session: JwtSession,
+    usuario: CurrentUser,
     pool: web::Data<PgPool>,
     payload: Multipart,
     req: HttpRequest, 
 ) -> Result<HttpResponse, actix_web::Error> {
-
-    // Sesion actual tiene un usuario valido ?
-    let mut usuario = obtener_usuario_por_id_sqlx(&pool, &session.user_id).await
-        .map_err(|_| e500())?
-        .ok_or(e500())?;
+    let CurrentUser { usuario: mut usuario, redis, .. } = usuario;
 
     // Guardar Imagen
     let base_path = get_uploads_path()
@@ -86,6 +80,8 @@ pub async fn user_picture_patch_me(
     let usuario_actualizado = actualizar_imagen_usuario_sqlx(&pool, usuario).await
         .map_err(|_| e500())?;
 
+    invalidar_cache_usuario(&redis, &usuario_actualizado.usuario_id).await;
+
     // Respueta exitosa
     let api_response = ApiResponse::<Usuario>::new()
         .with_message("Usuario actualizado")
@@ -0,0 +1,105 @@
+use actix_web::{HttpResponse, web};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::api_response::{ApiResponse, e404, e500};
+use crate::authentication::current_user::CurrentUser;
+use crate::authentication::sessions::{listar_sesiones, revocar_sesion, revocar_sesiones, Sesion};
+
+
+#[derive(Debug, serde::Serialize)]
+pub struct MiSesion {
+    #[serde(flatten)]
+    pub sesion: Sesion,
+    // La sesion con la que se hizo la peticion
+    pub actual: bool,
+}
+
+
+#[tracing::instrument(
+    name = "Obtener mis sesiones",
+    skip_all,
+)]
+pub async fn get_my_sessions(
+    usuario: CurrentUser,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+
+    let mut redis_con = usuario.redis.get().await
+        .map_err(|_| e500())?;
+
+    let sesiones: Vec<MiSesion> = listar_sesiones(&pool, &usuario.redis, &mut redis_con, &usuario.usuario_id).await
+        .map_err(|_| e500())?
+        .into_iter()
+        .map(|sesion| MiSesion {
+            actual: Some(sesion.sesion_id) == usuario.session_id(),
+            sesion,
+        })
+        .collect();
+
+    // Respuesta exitosa
+    let api_response = ApiResponse::<Vec<MiSesion>>::new()
+        .with_message("Tus sesiones activas")
+        .with_data(sesiones)
+        .to_resp();
+
+    Ok(api_response)
+}
+
+
+#[tracing::instrument(
+    name = "Revocar una de mis sesiones",
+    skip(usuario, pool)
+)]
+pub async fn delete_my_session(
+    usuario: CurrentUser,
+    pool: web::Data<PgPool>,
+    uuid: web::Path<Uuid>,
+) -> Result<HttpResponse, actix_web::Error> {
+
+    usuario.rechazar_suplantacion()?;
+
+    let mut redis_con = usuario.redis.get().await
+        .map_err(|_| e500())?;
+
+    let revocada = revocar_sesion(&pool, &usuario.redis, &mut redis_con, &usuario.usuario_id, &uuid).await
+        .map_err(|_| e500())?;
+
+    if !revocada {
+        return Err(e404().with_message("No se encontro la sesion"))?;
+    }
+
+    // Respuesta exitosa
+    let api_response = ApiResponse::<()>::new()
+        .with_message("Sesion cerrada")
+        .to_resp();
+
+    Ok(api_response)
+}
+
+
+#[tracing::instrument(
+    name = "Revocar todas mis sesiones",
+    skip_all,
+)]
+pub async fn delete_my_sessions(
+    usuario: CurrentUser,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+
+    usuario.rechazar_suplantacion()?;
+
+    let mut redis_con = usuario.redis.get().await
+        .map_err(|_| e500())?;
+
+    // Incluye la sesion actual
+    revocar_sesiones(&pool, &usuario.redis, &mut redis_con, &usuario.usuario_id, None).await
+        .map_err(|_| e500())?;
+
+    // Respuesta exitosa
+    let api_response = ApiResponse::<()>::new()
+        .with_message("Se cerraron todas tus sesiones")
+        .to_resp();
+
+    Ok(api_response)
+}
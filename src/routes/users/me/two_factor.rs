@@ -0,0 +1,143 @@
+use actix_web::{HttpResponse, web};
+use sqlx::PgPool;
+
+use crate::api_response::{ApiResponse, e400, e403, e404, e409, e500};
+use crate::authentication::current_user::CurrentUser;
+use crate::authentication::totp::{
+    confirmar_inscripcion, desactivar_totp, iniciar_inscripcion, regenerar_codigos_recuperacion,
+    verificar_segundo_factor, InscripcionTotp, TotpError,
+};
+use crate::configuration::TwoFactorSettings;
+
+
+#[derive(Debug, serde::Deserialize)]
+pub struct CodigoTotp {
+    pub codigo: String,
+}
+
+
+fn error_totp(e: TotpError) -> actix_web::Error {
+    let api_response = match e {
+        TotpError::AlreadyEnabled => e409().with_message("El segundo factor ya esta activo"),
+        TotpError::NotEnrolled => e404().with_message("El segundo factor no esta activo"),
+        TotpError::InvalidCode => e400().with_message("Codigo invalido"),
+        TotpError::UnexpectedError(_) => e500(),
+    };
+    api_response.into()
+}
+
+
+#[tracing::instrument(
+    name = "Iniciar mi inscripcion de segundo factor",
+    skip_all,
+)]
+pub async fn enroll_me(
+    usuario: CurrentUser,
+    pool: web::Data<PgPool>,
+    two_factor: web::Data<TwoFactorSettings>,
+) -> Result<HttpResponse, actix_web::Error> {
+
+    usuario.rechazar_suplantacion()?;
+
+    let inscripcion = iniciar_inscripcion(&pool, &usuario.usuario_id, &two_factor.issuer, &usuario.email).await
+        .map_err(error_totp)?;
+
+    // Respuesta exitosa
+    let api_response = ApiResponse::<InscripcionTotp>::new()
+        .with_message("Escanea el codigo QR y confirma con un codigo")
+        .with_data(inscripcion)
+        .to_resp();
+
+    Ok(api_response)
+}
+
+
+#[tracing::instrument(
+    name = "Confirmar mi inscripcion de segundo factor",
+    skip_all,
+)]
+pub async fn confirm_enroll_me(
+    usuario: CurrentUser,
+    pool: web::Data<PgPool>,
+    two_factor: web::Data<TwoFactorSettings>,
+    body: web::Json<CodigoTotp>,
+) -> Result<HttpResponse, actix_web::Error> {
+
+    usuario.rechazar_suplantacion()?;
+
+    let codigos = confirmar_inscripcion(&pool, &usuario.usuario_id, &two_factor.issuer, &usuario.email, &body.codigo).await
+        .map_err(|e| match e {
+            TotpError::NotEnrolled => e404().with_message("No hay una inscripcion pendiente").into(),
+            e => error_totp(e),
+        })?;
+
+    // Respuesta exitosa
+    let api_response = ApiResponse::<Vec<String>>::new()
+        .with_message("Segundo factor activado, guarda tus codigos de recuperacion")
+        .with_data(codigos)
+        .to_resp();
+
+    Ok(api_response)
+}
+
+
+#[tracing::instrument(
+    name = "Desactivar mi segundo factor",
+    skip_all,
+)]
+pub async fn disable_me(
+    usuario: CurrentUser,
+    pool: web::Data<PgPool>,
+    two_factor: web::Data<TwoFactorSettings>,
+    body: web::Json<CodigoTotp>,
+) -> Result<HttpResponse, actix_web::Error> {
+
+    usuario.rechazar_suplantacion()?;
+
+    if two_factor.require_for_admins && usuario.es_admin() {
+        return Err(e403().with_message("Los administradores deben mantener el segundo factor activo"))?;
+    }
+
+    // Se requiere un codigo valido para desactivarlo
+    verificar_segundo_factor(&pool, &usuario.usuario_id, &two_factor.issuer, &usuario.email, &body.codigo).await
+        .map_err(error_totp)?;
+
+    desactivar_totp(&pool, &usuario.usuario_id).await
+        .map_err(|_| e500())?;
+
+    // Respuesta exitosa
+    let api_response = ApiResponse::<()>::new()
+        .with_message("Segundo factor desactivado")
+        .to_resp();
+
+    Ok(api_response)
+}
+
+
+#[tracing::instrument(
+    name = "Regenerar mis codigos de recuperacion",
+    skip_all,
+)]
+pub async fn regenerate_recovery_codes_me(
+    usuario: CurrentUser,
+    pool: web::Data<PgPool>,
+    two_factor: web::Data<TwoFactorSettings>,
+    body: web::Json<CodigoTotp>,
+) -> Result<HttpResponse, actix_web::Error> {
+
+    usuario.rechazar_suplantacion()?;
+
+    verificar_segundo_factor(&pool, &usuario.usuario_id, &two_factor.issuer, &usuario.email, &body.codigo).await
+        .map_err(error_totp)?;
+
+    let codigos = regenerar_codigos_recuperacion(&pool, &usuario.usuario_id).await
+        .map_err(|_| e500())?;
+
+    // Respuesta exitosa
+    let api_response = ApiResponse::<Vec<String>>::new()
+        .with_message("Codigos de recuperacion generados, los anteriores ya no son validos")
+        .with_data(codigos)
+        .to_resp();
+
+    Ok(api_response)
+}
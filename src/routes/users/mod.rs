@@ -1,7 +1,10 @@
 pub mod me;
 pub mod get;
+pub mod post;
 pub mod patch;
 pub mod delete;
 
 pub mod sqlx;
 pub mod image;
+pub mod approvals;
+pub mod impersonation;
@@ -5,36 +5,34 @@ use uuid::Uuid;
 
 use common::models::user::{Usuario, ActualizaUsuario};
 
-use crate::authentication::jwt_session::JwtSession;
-use crate::api_response::{ApiResponse, e500, e403, e404};
+use crate::authentication::current_user::{CurrentUser, invalidar_cache_usuario};
+use crate::api_response::{ApiResponse, e500, e403, e404, e409};
 //use crate::telemetry::spawn_blocking_with_tracing;
 use crate::upload::image::get_uploads_path;
 
-use super::sqlx::{obtener_usuario_por_id_sqlx, actualizar_usuario_sqlx, actualizar_imagen_usuario_sqlx};
+use super::sqlx::{
+    obtener_usuario_en_alcance_sqlx, actualizar_usuario_sqlx, actualizar_imagen_usuario_sqlx,
+    email_en_uso_sqlx, es_email_duplicado, obtener_departamento_id_sqlx,
+};
 
 
 #[tracing::instrument(
     name = "Actualizar Usuario por id",
-    skip(session, pool)
+    skip(usuario, pool)
 )]
 pub async fn user_patch(
-    session: JwtSession,
+    usuario: CurrentUser,
     pool: web::Data<PgPool>,
     uuid: web::Path<Uuid>,
     body: web::Json<ActualizaUsuario>
 ) -> Result<HttpResponse, actix_web::Error> {
 
-    // Usuario es admin ?
-    let usuario = obtener_usuario_por_id_sqlx(&pool, &session.user_id).await
-        .map_err(|_| e500())?
-        .ok_or(e500())?;
-
-    if !usuario.es_admin() {
+    if !usuario.administra() {
         return Err(e403().with_message("No tienes los permisos requeridos"))?;
     }
 
     // Otro Usuario valido?
-    let mut otro_usuario = obtener_usuario_por_id_sqlx(&pool, &uuid).await
+    let mut otro_usuario = obtener_usuario_en_alcance_sqlx(&pool, &uuid, usuario.departamentos_administrados()).await
         .map_err(|_| e500())?
         .ok_or(e404().with_message("No se encontro el usuario"))?;
 
@@ -46,11 +44,44 @@ pub async fn user_patch(
     let update_body = body.into_inner();
     // Deberia validar actualizacion
     // update_body.validate();
+    let era_admin = otro_usuario.es_admin();
     otro_usuario.actualizar(update_body);
 
+    // Una API key no cambia el rol aunque tenga users:write
+    if otro_usuario.es_admin() != era_admin {
+        usuario.rechazar_api_key()?;
+    }
+
+    // El administrador de departamento no nombra administradores ni saca usuarios de su alcance
+    if !usuario.es_admin() {
+        if otro_usuario.es_admin() {
+            return Err(e403().with_message("Solo un administrador global puede nombrar administradores"))?;
+        }
+        let departamento = obtener_departamento_id_sqlx(&pool, &otro_usuario.departamento).await
+            .map_err(|_| e500())?;
+        if !usuario.administra_departamento(departamento) {
+            return Err(e403().with_message("No administras el departamento destino"))?;
+        }
+    }
+
+    // El email debe seguir siendo unico
+    let email_en_uso = email_en_uso_sqlx(&pool, &otro_usuario.email, &otro_usuario.usuario_id).await
+        .map_err(|_| e500())?;
+    if email_en_uso {
+        return Err(e409().with_message("Ya existe usuario con ese correo electronico"))?;
+    }
+
     // Query Actualizar DB
     let usuario_actualizado = actualizar_usuario_sqlx(&pool, otro_usuario).await
-        .map_err(|_| e500())?;
+        .map_err(|e| {
+            if es_email_duplicado(&e) {
+                e409().with_message("Ya existe usuario con ese correo electronico")
+            } else {
+                e500()
+            }
+        })?;
+
+    invalidar_cache_usuario(&usuario.redis, &usuario_actualizado.usuario_id).await;
 
     // Respuesta exitosa
     let api_response = ApiResponse::<Usuario>::new()
@@ -67,27 +98,22 @@ use crate::upload::image::handle_picture_multipart;
 
 #[tracing::instrument(
     name = "Actualizar imagen de Usuario por id",
-    skip(session, pool, payload, req)
+    skip(usuario, pool, payload, req)
 )]
 pub async fn user_picture_patch(
-    session: JwtSession,
+    usuario: CurrentUser,
     pool: web::Data<PgPool>,
     uuid: web::Path<Uuid>,
     payload: Multipart,
     req: HttpRequest, 
 ) -> Result<HttpResponse, actix_web::Error> {
 
-    // Usuario es admin ?
-    let usuario = obtener_usuario_por_id_sqlx(&pool, &session.user_id).await
-        .map_err(|_| e500())?
-        .ok_or(e500())?;
-
-    if !usuario.es_admin() {
+    if !usuario.administra() {
         return Err(e403().with_message("No tienes los privilegios necesarios"))?;
     }
 
     // Otro Usuario valido?
-    let mut otro_usuario = obtener_usuario_por_id_sqlx(&pool, &uuid).await
+    let mut otro_usuario = obtener_usuario_en_alcance_sqlx(&pool, &uuid, usuario.departamentos_administrados()).await
         .map_err(|_| e500())?
         .ok_or(e404().with_message("No se encontro Usuario"))?;
 
@@ -115,6 +141,8 @@ pub async fn user_picture_patch(
     let usuario_actualizado = actualizar_imagen_usuario_sqlx(&pool, otro_usuario).await
         .map_err(|_| e500())?;
 
+    invalidar_cache_usuario(&usuario.redis, &usuario_actualizado.usuario_id).await;
+
     // Respuesta exitosa
     let api_response = ApiResponse::<Usuario>::new()
         .with_message("Usuario Actualizado")
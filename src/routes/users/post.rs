@@ -0,0 +1,84 @@
+use actix_web::{HttpResponse, web};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::authentication::current_user::{CurrentUser, invalidar_cache_usuario};
+use crate::authentication::login_throttle::desbloquear;
+use crate::api_response::{ApiResponse, e500, e403, e404, e409};
+
+use super::sqlx::{obtener_usuario_en_alcance_sqlx, restaurar_usuario_sqlx};
+
+
+#[tracing::instrument(
+    name = "Desbloquear inicio de sesion de usuario",
+    skip(usuario, pool)
+)]
+pub async fn users_unlock_user_by_id(
+    usuario: CurrentUser,
+    pool: web::Data<PgPool>,
+    uuid: web::Path<Uuid>,
+) -> Result<HttpResponse, actix_web::Error> {
+
+    if !usuario.administra() {
+        return Err(e403().with_message("No tienes los permisos requeridos"))?;
+    }
+
+    // Otro Usuario valido ?
+    let otro_usuario = obtener_usuario_en_alcance_sqlx(&pool, &uuid, usuario.departamentos_administrados()).await
+        .map_err(|_| e500())?
+        .ok_or(e404().with_message("No se encontro el usuario"))?;
+
+    if otro_usuario.es_admin() && usuario.usuario_id != otro_usuario.usuario_id {
+        return Err(e403().with_message("No puedes desbloquear a otro administrador!"))?;
+    }
+
+    // Quitar bloqueo y contadores de intentos
+    let mut redis_con = usuario.redis.get().await
+        .map_err(|_| e500())?;
+
+    desbloquear(&usuario.redis, &mut redis_con, &otro_usuario.email).await
+        .map_err(|_| e500())?;
+
+    // Respuesta exitosa
+    let api_response = ApiResponse::<()>::new()
+        .with_message("Usuario desbloqueado")
+        .to_resp();
+
+    Ok(api_response)
+}
+
+
+#[tracing::instrument(
+    name = "Restaurar usuario desactivado",
+    skip(usuario, pool)
+)]
+pub async fn users_restore_user_by_id(
+    usuario: CurrentUser,
+    pool: web::Data<PgPool>,
+    uuid: web::Path<Uuid>,
+) -> Result<HttpResponse, actix_web::Error> {
+
+    if !usuario.administra() {
+        return Err(e403().with_message("No tienes los permisos requeridos"))?;
+    }
+
+    // Otro Usuario valido ?
+    obtener_usuario_en_alcance_sqlx(&pool, &uuid, usuario.departamentos_administrados()).await
+        .map_err(|_| e500())?
+        .ok_or(e404().with_message("No se encontro el usuario"))?;
+
+    let restaurado = restaurar_usuario_sqlx(&pool, &uuid).await
+        .map_err(|_| e500())?;
+    if !restaurado {
+        return Err(e409().with_message("El usuario no esta desactivado o ya fue anonimizado"))?;
+    }
+
+    invalidar_cache_usuario(&usuario.redis, &uuid).await;
+
+    // Respuesta exitosa
+    let api_response = ApiResponse::<()>::new()
+        .with_message("Usuario restaurado")
+        .to_resp();
+
+    Ok(api_response)
+}
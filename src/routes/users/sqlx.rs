@@ -2,19 +2,199 @@ use anyhow::Context;
 //use crate::models::user::User;
 use common::models::user::{Usuario, UsuarioRol};
 
-use sqlx::PgPool;
+use sqlx::{PgPool, Row};
 use uuid::Uuid;
 
 
+/// Columnas por las que se puede ordenar el directorio
+#[derive(Debug, Clone, Copy, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OrdenUsuarios {
+    Nombres,
+    Apellidos,
+    Email,
+    NumeroEmpleado,
+    CreadoEn,
+}
+
+#[derive(Debug, Clone, Copy, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Direccion {
+    Asc,
+    Desc,
+}
+
+#[derive(Debug, serde::Deserialize)]
+pub struct FiltroUsuarios {
+    // Palabras que deben iniciar el nombre, apellido o email
+    pub busqueda: Option<String>,
+    // Nombre del departamento
+    pub departamento: Option<String>,
+    pub rol: Option<UsuarioRol>,
+    pub activo: Option<bool>,
+    pub verificado: Option<bool>,
+    pub numero_empleado: Option<i16>,
+    pub orden: Option<OrdenUsuarios>,
+    pub direccion: Option<Direccion>,
+    pub pagina: Option<i64>,
+    pub limite: Option<i64>,
+}
+
+pub const USUARIOS_POR_PAGINA: i64 = 20;
+pub const USUARIOS_POR_PAGINA_MAX: i64 = 100;
+// Limita el OFFSET para que no se desborde
+pub const PAGINA_MAX: i64 = 1_000_000;
+
+/// Escapa los comodines de LIKE para buscar el texto tal cual
+fn patron_prefijo(termino: &str) -> String {
+    let escapado = termino.to_lowercase()
+        .replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_");
+    format!("{}%", escapado)
+}
+
+/// Pagina de usuarios que cumplen el filtro y el total sin paginar,
+/// solo de los departamentos dados si no es None
 #[tracing::instrument(
-    name = "Query todos los usuarios",
-    skip_all
+    name = "Query buscar usuarios",
+    skip(pool)
 )]
-pub async fn obtener_usuarios_sqlx(
-    pool: &PgPool
-) -> Result<Vec<Usuario>, anyhow::Error> {
-    match sqlx::query_as!(
-        Usuario, 
+pub async fn buscar_usuarios_sqlx(
+    pool: &PgPool,
+    filtro: FiltroUsuarios,
+    departamentos: Option<&[i32]>,
+) -> Result<(Vec<Usuario>, i64), anyhow::Error> {
+
+    let mut query = sqlx::QueryBuilder::new(
+        r#"SELECT
+            u.usuario_id,
+            u.nombres,
+            u.apellidos,
+            u.email,
+            u.password_hash,
+            u.numero_empleado,
+            u.activo,
+            u.verificado,
+            u.imagen,
+            COALESCE(d.nombre, 'Sin asignar') as departamento,
+            u.rol,
+            u.creado_en,
+            u.modificado_en,
+            COUNT(*) OVER() as total
+        FROM usuarios u LEFT JOIN departamentos d
+        ON u.departamento = d.id
+        WHERE TRUE"#);
+
+    // Busqueda por prefijo, usa los indices sobre lower(nombres),
+    // lower(apellidos) y lower(email)
+    if let Some(busqueda) = &filtro.busqueda {
+        for termino in busqueda.split_whitespace() {
+            let patron = patron_prefijo(termino);
+            query.push(" AND (lower(u.nombres) LIKE ");
+            query.push_bind(patron.clone());
+            query.push(" OR lower(u.apellidos) LIKE ");
+            query.push_bind(patron.clone());
+            query.push(" OR lower(u.email) LIKE ");
+            query.push_bind(patron);
+            query.push(")");
+        }
+    }
+    if let Some(departamentos) = departamentos {
+        query.push(" AND u.departamento = ANY(");
+        query.push_bind(departamentos);
+        query.push(")");
+    }
+    if let Some(departamento) = filtro.departamento {
+        query.push(" AND d.nombre = ");
+        query.push_bind(departamento);
+    }
+    if let Some(rol) = filtro.rol {
+        query.push(" AND u.rol = ");
+        query.push_bind(rol);
+    }
+    if let Some(activo) = filtro.activo {
+        query.push(" AND u.activo = ");
+        query.push_bind(activo);
+    }
+    if let Some(verificado) = filtro.verificado {
+        query.push(" AND u.verificado = ");
+        query.push_bind(verificado);
+    }
+    if let Some(numero_empleado) = filtro.numero_empleado {
+        query.push(" AND u.numero_empleado = ");
+        query.push_bind(numero_empleado);
+    }
+
+    // Las columnas vienen de una lista fija, nunca del query string
+    let columna = match filtro.orden.unwrap_or(OrdenUsuarios::Apellidos) {
+        OrdenUsuarios::Nombres => "u.nombres",
+        OrdenUsuarios::Apellidos => "u.apellidos",
+        OrdenUsuarios::Email => "u.email",
+        OrdenUsuarios::NumeroEmpleado => "u.numero_empleado",
+        OrdenUsuarios::CreadoEn => "u.creado_en",
+    };
+    let direccion = match filtro.direccion.unwrap_or(Direccion::Asc) {
+        Direccion::Asc => "ASC NULLS LAST",
+        Direccion::Desc => "DESC NULLS LAST",
+    };
+    // usuario_id desempata para que las paginas sean estables
+    query.push(format!(" ORDER BY {} {}, u.usuario_id", columna, direccion));
+
+    let pagina: i64 = filtro.pagina.unwrap_or(1).clamp(1, PAGINA_MAX);
+    let usuarios_por_pagina: i64 = filtro.limite
+        .unwrap_or(USUARIOS_POR_PAGINA)
+        .clamp(1, USUARIOS_POR_PAGINA_MAX);
+    query.push(" LIMIT ");
+    query.push_bind(usuarios_por_pagina);
+    query.push(" OFFSET ");
+    query.push_bind((pagina - 1).saturating_mul(usuarios_por_pagina));
+
+    let rows = query.build()
+        .fetch_all(pool)
+        .await
+        .context("Failed to execute query")?;
+
+    // Una pagina fuera de rango no tiene filas para leer el total
+    let total = rows.first()
+        .map(|r| r.try_get::<i64, _>("total"))
+        .transpose()
+        .context("Failed to decode total")?
+        .unwrap_or(0);
+
+    let usuarios = rows.iter().map(|r| {
+        Ok(Usuario {
+            usuario_id: r.try_get("usuario_id")?,
+            nombres: r.try_get("nombres")?,
+            apellidos: r.try_get("apellidos")?,
+            email: r.try_get("email")?,
+            password_hash: r.try_get("password_hash")?,
+            numero_empleado: r.try_get("numero_empleado")?,
+            activo: r.try_get("activo")?,
+            verificado: r.try_get("verificado")?,
+            imagen: r.try_get("imagen")?,
+            departamento: r.try_get("departamento")?,
+            rol: r.try_get("rol")?,
+            creado_en: r.try_get("creado_en")?,
+            modificado_en: r.try_get("modificado_en")?,
+        })
+    }).collect::<Result<Vec<Usuario>, sqlx::Error>>()
+    .context("Failed to decode users")?;
+
+    Ok((usuarios, total))
+}
+
+#[tracing::instrument(
+    name = "Query usuario por id",
+    skip(pool)
+)]
+pub async fn obtener_usuario_por_id_sqlx(
+    pool: &PgPool,
+    usuario_id: &Uuid,
+) -> Result<Option<Usuario>, anyhow::Error>
+{
+    let usuario: Option<Usuario> = sqlx::query_as!(
+        Usuario,
         r#"SELECT 
         usuario_id,
         nombres,
@@ -30,23 +210,28 @@ pub async fn obtener_usuarios_sqlx(
         creado_en,
         modificado_en
         FROM usuarios LEFT JOIN departamentos
-        ON usuarios.departamento = departamentos.id"#
-        )
-        .fetch_all(pool)
-        .await
-    {
-        Ok(users) => Ok(users),
-        Err(e) => Err(e.into()),
-    }
+        ON usuarios.departamento = departamentos.id
+        WHERE usuario_id = $1"#,
+        usuario_id,
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to get query")?;
+
+    Ok(usuario)
 }
 
+
+/// Igual que `obtener_usuario_por_id_sqlx` pero un usuario fuera de los
+/// departamentos dados no se encuentra, None es sin restriccion
 #[tracing::instrument(
-    name = "Query usuario por id",
+    name = "Query usuario por id en alcance",
     skip(pool)
 )]
-pub async fn obtener_usuario_por_id_sqlx(
+pub async fn obtener_usuario_en_alcance_sqlx(
     pool: &PgPool,
     usuario_id: &Uuid,
+    departamentos: Option<&[i32]>,
 ) -> Result<Option<Usuario>, anyhow::Error>
 {
     let usuario: Option<Usuario> = sqlx::query_as!(
@@ -67,8 +252,10 @@ pub async fn obtener_usuario_por_id_sqlx(
         modificado_en
         FROM usuarios LEFT JOIN departamentos
         ON usuarios.departamento = departamentos.id
-        WHERE usuario_id = $1"#,
+        WHERE usuario_id = $1
+            AND ($2::int[] IS NULL OR usuarios.departamento = ANY($2))"#,
         usuario_id,
+        departamentos,
     )
     .fetch_optional(pool)
     .await
@@ -77,6 +264,22 @@ pub async fn obtener_usuario_por_id_sqlx(
     Ok(usuario)
 }
 
+#[tracing::instrument(
+    name = "Query id de departamento por nombre",
+    skip(pool)
+)]
+pub async fn obtener_departamento_id_sqlx(
+    pool: &PgPool,
+    nombre: &str,
+) -> Result<Option<i32>, anyhow::Error> {
+    let row = sqlx::query!("SELECT id FROM departamentos WHERE nombre = $1", nombre)
+        .fetch_optional(pool)
+        .await
+        .context("Failed to execute query")?;
+
+    Ok(row.map(|row| row.id))
+}
+
 
 #[tracing::instrument(
     name = "Query rol del usuario",
@@ -110,20 +313,85 @@ pub async fn obtener_usuario_rol_sqlx(
 }
 
 
+/// Desactiva la cuenta y revoca sus API keys, los datos se conservan
+/// hasta que el worker la anonimiza. Regresa false si ya estaba desactivada.
 #[tracing::instrument(
-    name = "Query SQLX Borrar usuario",
+    name = "Query desactivar usuario",
     skip(pool)
 )]
-pub async fn borrar_usuario_por_id_sqlx(
+pub async fn desactivar_usuario_sqlx(
     pool: &PgPool,
     usuario_id: &Uuid,
+    desactivado_por: &Uuid,
 ) -> Result<bool, anyhow::Error> {
+    let mut transaction = pool.begin()
+        .await
+        .context("Failed to begin transaction")?;
+
     let query = sqlx::query!(
         r#"
-        DELETE FROM usuarios
+        UPDATE usuarios
+        SET activo = false,
+            activo_al_desactivar = activo,
+            desactivado_en = now(),
+            desactivado_por = $2,
+            modificado_en = now()
+        WHERE usuario_id = $1 AND desactivado_en IS NULL
+        "#,
+        usuario_id,
+        desactivado_por,
+        )
+        .execute(&mut transaction)
+        .await
+        .context("Failed to get query")?;
+
+    if query.rows_affected() == 0 {
+        return Ok(false);
+    }
+
+    sqlx::query!(
+        r#"
+        UPDATE api_keys
+        SET revocado_en = now()
+        WHERE usuario_id = $1 AND revocado_en IS NULL
+        "#,
+        usuario_id,
+        )
+        .execute(&mut transaction)
+        .await
+        .context("Failed to revoke api keys")?;
+
+    transaction.commit()
+        .await
+        .context("Failed to commit transaction")?;
+
+    Ok(true)
+}
+
+/// Reactiva una cuenta desactivada que aun no se anonimiza, el activo regresa
+/// al valor que tenia al desactivarla
+#[tracing::instrument(
+    name = "Query restaurar usuario",
+    skip(pool)
+)]
+pub async fn restaurar_usuario_sqlx(
+    pool: &PgPool,
+    usuario_id: &Uuid,
+) -> Result<bool, anyhow::Error> {
+    let query = sqlx::query!(
+        r#"
+        UPDATE usuarios
+        SET activo = COALESCE(activo_al_desactivar, activo),
+            activo_al_desactivar = NULL,
+            desactivado_en = NULL,
+            desactivado_por = NULL,
+            modificado_en = now()
         WHERE usuario_id = $1
+            AND desactivado_en IS NOT NULL
+            AND anonimizado_en IS NULL
         "#,
-        usuario_id)
+        usuario_id,
+        )
         .execute(pool)
         .await
         .context("Failed to get query")?;
@@ -232,3 +500,39 @@ pub async fn actualizar_imagen_usuario_sqlx(
 
     Ok(usuario)
 }
+
+
+#[tracing::instrument(
+    name = "Query email en uso por otro usuario",
+    skip(pool)
+)]
+pub async fn email_en_uso_sqlx(
+    pool: &PgPool,
+    email: &str,
+    usuario_id: &Uuid,
+) -> Result<bool, anyhow::Error> {
+    let row = sqlx::query!(
+        r#"
+        SELECT EXISTS(
+            SELECT usuario_id FROM usuarios
+            WHERE lower(email) = lower($1) AND usuario_id <> $2
+        ) as "existe!"
+        "#,
+        email,
+        usuario_id,
+    )
+    .fetch_one(pool)
+    .await
+    .context("Failed to execute query")?;
+
+    Ok(row.existe)
+}
+
+/// Verdadero si el error es por la restriccion UNIQUE del email,
+/// puede pasar si otra peticion tomo el email despues de verificarlo
+pub fn es_email_duplicado(e: &anyhow::Error) -> bool {
+    e.downcast_ref::<sqlx::Error>()
+        .and_then(|e| e.as_database_error())
+        .map(|e| e.code().as_deref() == Some("23505") && e.constraint() == Some("usuarios_email_key"))
+        .unwrap_or(false)
+}
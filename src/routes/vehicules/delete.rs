@@ -2,10 +2,9 @@ use actix_web::{HttpResponse, web};
 use sqlx::PgPool;
 use uuid::Uuid;
 
-use crate::authentication::jwt_session::JwtSession;
+use crate::authentication::current_user::CurrentUser;
 use crate::api_response::{ApiResponse, e500, e403, e404};
 
-use crate::routes::users::sqlx::obtener_usuario_por_id_sqlx;
 
 
 #[tracing::instrument(
@@ -15,13 +14,16 @@ use crate::routes::users::sqlx::obtener_usuario_por_id_sqlx;
 async fn borrar_vehiculo_sqlx(
     pool: &PgPool,
     uuid: &Uuid,
+    departamentos: Option<&[i32]>,
 ) -> Result<bool, sqlx::Error> {
     let query = sqlx::query!(
         r#"
         DELETE FROM vehiculos
         WHERE vehiculo_id = $1
+            AND ($2::int[] IS NULL OR departamento = ANY($2))
         "#,
-        uuid
+        uuid,
+        departamentos,
     )
     .execute(pool)
     .await?;
@@ -33,25 +35,20 @@ async fn borrar_vehiculo_sqlx(
 
 #[tracing::instrument(
     name = "Borrar vehiculo por id",
-    skip(pool, session)
+    skip(pool, usuario)
 )]
 pub async fn delete_vehicule(
-    session: JwtSession,
+    usuario: CurrentUser,
     pool: web::Data<PgPool>,
     uuid: web::Path<Uuid>,
 ) -> Result<HttpResponse, actix_web::Error> {
 
-    // Usuario es admin ?
-    let usuario = obtener_usuario_por_id_sqlx(&pool, &session.user_id).await
-        .map_err(|_| e500())?
-        .ok_or(e500())?;
-
-    if !usuario.es_admin() {
+    if !usuario.administra() {
         return Err(e403().with_message("No tienes los permisos requeridos"))?;
     }
 
     // Query borrar vehiculo DB
-    match borrar_vehiculo_sqlx(&pool, &uuid).await {
+    match borrar_vehiculo_sqlx(&pool, &uuid, usuario.departamentos_administrados()).await {
         Ok(deleted) => {
             if !deleted {
                return Err(e404().with_message("No se encontro el Vehiculo"))?;
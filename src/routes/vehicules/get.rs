@@ -4,30 +4,24 @@ use common::models::vehicule::vehicule::FilterQueryVehicule;
 use sqlx::{PgPool, Row};
 use uuid::Uuid;
 
-use crate::authentication::jwt_session::JwtSession;
+use crate::authentication::current_user::CurrentUser;
 use crate::api_response::{ApiResponse, e500, e404};
 
 use common::models::vehicule::{Vehiculo, EstadoVehiculo, VehiculoFiltrado};
 
-use crate::routes::users::sqlx::obtener_usuario_por_id_sqlx;
 
 
 
 #[tracing::instrument(
     name = "Get vehicule by id",
-    skip(pool, session)
+    skip(pool, usuario)
 )]
 pub async fn get_vehicule(
-    session: JwtSession,
+    usuario: CurrentUser,
     pool: web::Data<PgPool>,
     uuid: web::Path<Uuid>,
 ) -> Result<HttpResponse, actix_web::Error> {
 
-    // Usuario es admin ?
-    let usuario = obtener_usuario_por_id_sqlx(&pool, &session.user_id).await
-        .map_err(|_| e500())?
-        .ok_or(e500())?;
-
     /*
     if !usuario.es_admin() {
         return Err(e403().with_message("No tienes los permisos requeridos"))?;
@@ -39,10 +33,16 @@ pub async fn get_vehicule(
         .map_err(|_| e500())?
         .ok_or(e404().with_message("No se encontro el Vehiculo"))?;
 
+    // Fuera de su alcance el administrador de departamento lo ve como cualquier usuario
+    let administra = usuario.administra()
+        && obtener_vehiculo_en_alcance_sqlx(&pool, &uuid, usuario.departamentos_administrados()).await
+            .map_err(|_| e500())?
+            .is_some();
+
 
     // Respuesta exitosa
 
-    if usuario.es_admin() {
+    if administra {
         let api_response = ApiResponse::<Vehiculo>::new()
             .with_message("Vehiculo")
             .with_data(vehiculo)
@@ -99,23 +99,56 @@ pub async fn obtener_vehiculo_por_id_sqlx(
     Ok(vehicule)
 }
 
+/// Igual que `obtener_vehiculo_por_id_sqlx` pero un vehiculo fuera de los
+/// departamentos dados no se encuentra, None es sin restriccion
+#[tracing::instrument(
+    name = "Query vehiculo por id en alcance",
+    skip(pool)
+)]
+pub async fn obtener_vehiculo_en_alcance_sqlx(
+    pool: &PgPool,
+    uuid: &Uuid,
+    departamentos: Option<&[i32]>,
+) -> Result<Option<Vehiculo>, anyhow::Error> {
+    let vehicule: Option<Vehiculo> = sqlx::query_as!(
+        Vehiculo,
+        r#"
+        SELECT 
+            vehiculo_id, marca, modelo, año,
+            numero_placa,
+            nombre_economico,
+            numero_tarjeta,
+            estado as "estado!: EstadoVehiculo",
+            activo,
+            imagen,
+            creado_en,
+            modificado_en
+        FROM vehiculos
+        WHERE vehiculo_id = $1
+            AND ($2::int[] IS NULL OR departamento = ANY($2))
+        "#,
+        uuid,
+        departamentos,
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Fallo la ejecucion del query")?;
+
+    Ok(vehicule)
+}
+
 
 
 #[tracing::instrument(
     name = "Get todos los vehiculos",
-    skip(pool, session)
+    skip(pool, usuario)
 )]
 pub async fn get_all_vehicules(
-    session: JwtSession,
+    usuario: CurrentUser,
     pool: web::Data<PgPool>,
     query: web::Query<FilterQueryVehicule>
 ) -> Result<HttpResponse, actix_web::Error> {
 
-    // Usuario es admin ?
-    let usuario = obtener_usuario_por_id_sqlx(&pool, &session.user_id).await
-        .map_err(|_| e500())?
-        .ok_or(e500())?;
-
     /*
     if !usuario.es_admin() {
         return Err(e403().with_message("No tienes los permisos requeridos"))?;
@@ -125,44 +158,16 @@ pub async fn get_all_vehicules(
     // Validar query
     let query = query.into_inner();
 
-    // Query vehiculo DB
-    let vehiculos = obtener_vehiculos_con_filtro_sqlx(&pool, query).await
+    // Query vehiculo DB, los inactivos solo los ve quien los administra
+    let vehiculos = obtener_vehiculos_con_filtro_sqlx(&pool, query, usuario.departamentos_administrados()).await
         .map_err(|_| e500())?;
 
-    if usuario.es_admin() {
-        let api_response = ApiResponse::<Vec<Vehiculo>>::new()
-            .with_message("Lista de vehiculos")
-            .with_data(vehiculos)
-            .to_resp();
-        
-        return Ok(api_response);
-    } else {
-        /*
-        let vehiculos_filtrados: Vec<VehiculoFiltrado> = 
-            vehiculos
-            .into_iter()
-            .filter(|v| v.activo)
-            .map(|v| VehiculoFiltrado::from(v))
-            .collect();
-
-        let api_response = ApiResponse::<Vec<VehiculoFiltrado>>::new()
-            .with_message("Lista de vehiculos")
-            .with_data(vehiculos_filtrados)
-            .to_resp();
-        */
-        let vehiculos_filtrados: Vec<Vehiculo> = 
-            vehiculos
-            .into_iter()
-            .filter(|v| v.activo)
-            .collect();
-
-        let api_response = ApiResponse::<Vec<Vehiculo>>::new()
-            .with_message("Lista de vehiculos")
-            .with_data(vehiculos_filtrados)
-            .to_resp();
-        
-        return Ok(api_response);
-    }
+    let api_response = ApiResponse::<Vec<Vehiculo>>::new()
+        .with_message("Lista de vehiculos")
+        .with_data(vehiculos)
+        .to_resp();
+
+    Ok(api_response)
 }
 
 
@@ -174,6 +179,7 @@ pub async fn obtener_vehiculos_con_filtro_sqlx(
     pool: &PgPool,
     //query: &VehiculesQuery,
     filtro: FilterQueryVehicule,
+    departamentos: Option<&[i32]>,
 ) -> Result<Vec<Vehiculo>, anyhow::Error> {
 
     let mut query = sqlx::QueryBuilder::new(
@@ -187,6 +193,7 @@ pub async fn obtener_vehiculos_con_filtro_sqlx(
                 estado as "estado!: EstadoVehiculo",
                 activo,
                 imagen,
+                departamento,
                 creado_en,
                 modificado_en
             FROM vehiculos
@@ -214,6 +221,11 @@ pub async fn obtener_vehiculos_con_filtro_sqlx(
        query.push(" AND estado = ");
        query.push_bind(filtro.estado.unwrap());
     }
+    if let Some(departamentos) = departamentos {
+        query.push(" AND (activo OR departamento = ANY(");
+        query.push_bind(departamentos);
+        query.push("))");
+    }
     if filtro.activo.is_some() {
        query.push(" AND activo = ");
        query.push_bind(filtro.activo.unwrap());
@@ -4,11 +4,11 @@ use common::models::vehicule::{Vehiculo, EstadoVehiculo, ActualizaVehiculo};
 use sqlx::PgPool;
 use uuid::Uuid;
 
-use crate::authentication::jwt_session::JwtSession;
+use crate::authentication::current_user::CurrentUser;
 use crate::api_response::{ApiResponse, e500, e400, e403, e404};
 
-use crate::routes::users::sqlx::obtener_usuario_por_id_sqlx;
-use super::get::obtener_vehiculo_por_id_sqlx;
+use super::get::obtener_vehiculo_en_alcance_sqlx;
+use crate::routes::licenses::sqlx::es_clase_invalida;
 
 use crate::upload::image::get_uploads_path;
 
@@ -18,23 +18,18 @@ use crate::upload::image::get_uploads_path;
     skip_all
 )]
 pub async fn patch_vehicule(
-    session: JwtSession,
+    usuario: CurrentUser,
     pool: web::Data<PgPool>,
     uuid: web::Path<Uuid>,
     body: web::Json<ActualizaVehiculo>,
 ) -> Result<HttpResponse, actix_web::Error> {
 
-    // Usuario es admin ?
-    let usuario = obtener_usuario_por_id_sqlx(&pool, &session.user_id).await
-        .map_err(|_| e500())?
-        .ok_or(e500())?;
-
-    if !usuario.es_admin() {
+    if !usuario.administra() {
         return Err(e403().with_message("No tienes los permisos requeridos"))?;
     }
 
     // Query vehiculo DB
-    let mut vehiculo = obtener_vehiculo_por_id_sqlx(&pool, &uuid).await
+    let mut vehiculo = obtener_vehiculo_en_alcance_sqlx(&pool, &uuid, usuario.departamentos_administrados()).await
         .map_err(|_| e500())?
         .ok_or(e404().with_message("No se encontro el Vehiculo"))?;
 
@@ -108,28 +103,23 @@ use crate::upload::image::handle_picture_multipart;
 
 #[tracing::instrument(
     name = "Actualizar imagen del vehiculo",
-    skip(session, pool, payload, req)
+    skip(usuario, pool, payload, req)
 )]
 pub async fn patch_vehicule_picture(
-    session: JwtSession,
+    usuario: CurrentUser,
     pool: web::Data<PgPool>,
     uuid: web::Path<Uuid>,
     payload: Multipart,
     req: HttpRequest,
 ) -> Result<HttpResponse, actix_web::Error> {
 
-    // Usuario es admin ?
-    let usuario = obtener_usuario_por_id_sqlx(&pool, &session.user_id).await
-        .map_err(|_| e500())?
-        .ok_or(e500())?;
-
-    if !usuario.es_admin() {
+    if !usuario.administra() {
         return Err(e403().with_message("No tienes los permisos requeridos"))?;
     }
 
 
     // Query vehiculo DB
-    let mut vehiculo = obtener_vehiculo_por_id_sqlx(&pool, &uuid).await
+    let mut vehiculo = obtener_vehiculo_en_alcance_sqlx(&pool, &uuid, usuario.departamentos_administrados()).await
         .map_err(|_| e500())?
         .ok_or(e404().with_message("No se encontro el Vehiculo"))?;
 
@@ -163,3 +153,173 @@ pub async fn patch_vehicule_picture(
 
     Ok(api_response)
 }
+
+
+#[derive(Debug, serde::Deserialize)]
+pub struct ClaseLicenciaVehiculo {
+    pub clase_licencia: String,
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct ClaseLicenciaActualizada {
+    pub vehiculo_id: Uuid,
+    pub clase_licencia: String,
+}
+
+/// Regresa None si el vehiculo no existe
+#[tracing::instrument(
+    name = "Query actualizar clase de licencia del vehiculo",
+    skip(pool)
+)]
+async fn actualizar_clase_licencia_sqlx(
+    pool: &PgPool,
+    vehiculo_id: &Uuid,
+    clase_licencia: &str,
+    departamentos: Option<&[i32]>,
+) -> Result<Option<ClaseLicenciaActualizada>, anyhow::Error> {
+    let vehiculo = sqlx::query_as!(
+        ClaseLicenciaActualizada,
+        r#"
+        UPDATE vehiculos
+        SET clase_licencia = $2, modificado_en = now()
+        WHERE vehiculo_id = $1
+            AND ($3::int[] IS NULL OR departamento = ANY($3))
+        RETURNING vehiculo_id, clase_licencia
+        "#,
+        vehiculo_id,
+        clase_licencia,
+        departamentos,
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Fallo la ejecucion del query")?;
+
+    Ok(vehiculo)
+}
+
+#[tracing::instrument(
+    name = "Actualizar clase de licencia del vehiculo",
+    skip(usuario, pool)
+)]
+pub async fn patch_vehicule_license_class(
+    usuario: CurrentUser,
+    pool: web::Data<PgPool>,
+    uuid: web::Path<Uuid>,
+    body: web::Json<ClaseLicenciaVehiculo>,
+) -> Result<HttpResponse, actix_web::Error> {
+
+    if !usuario.administra() {
+        return Err(e403().with_message("No tienes los permisos requeridos"))?;
+    }
+
+    let clase_licencia = body.into_inner().clase_licencia.trim().to_uppercase();
+
+    let vehiculo = actualizar_clase_licencia_sqlx(&pool, &uuid, &clase_licencia, usuario.departamentos_administrados()).await
+        .map_err(|e| {
+            if es_clase_invalida(&e) {
+                e400().with_message("Clase de licencia invalida")
+            } else {
+                e500()
+            }
+        })?
+        .ok_or(e404().with_message("No se encontro el Vehiculo"))?;
+
+    // Respuesta exitosa
+    let api_response = ApiResponse::<ClaseLicenciaActualizada>::new()
+        .with_message("Clase de licencia del vehiculo actualizada")
+        .with_data(vehiculo)
+        .to_resp();
+
+    Ok(api_response)
+}
+
+
+#[derive(Debug, serde::Deserialize)]
+pub struct DepartamentoVehiculo {
+    // None deja al vehiculo sin departamento
+    pub departamento: Option<i32>,
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct DepartamentoActualizado {
+    pub vehiculo_id: Uuid,
+    pub departamento: Option<i32>,
+}
+
+/// Regresa None si el vehiculo no existe
+#[tracing::instrument(
+    name = "Query actualizar departamento del vehiculo",
+    skip(pool)
+)]
+async fn actualizar_departamento_vehiculo_sqlx(
+    pool: &PgPool,
+    vehiculo_id: &Uuid,
+    departamento: Option<i32>,
+    departamentos: Option<&[i32]>,
+) -> Result<Option<DepartamentoActualizado>, anyhow::Error> {
+    let vehiculo = sqlx::query_as!(
+        DepartamentoActualizado,
+        r#"
+        UPDATE vehiculos
+        SET departamento = $2, modificado_en = now()
+        WHERE vehiculo_id = $1
+            AND ($3::int[] IS NULL OR departamento = ANY($3))
+        RETURNING vehiculo_id, departamento
+        "#,
+        vehiculo_id,
+        departamento,
+        departamentos,
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Fallo la ejecucion del query")?;
+
+    Ok(vehiculo)
+}
+
+#[tracing::instrument(
+    name = "Actualizar departamento del vehiculo",
+    skip(usuario, pool)
+)]
+pub async fn patch_vehicule_department(
+    usuario: CurrentUser,
+    pool: web::Data<PgPool>,
+    uuid: web::Path<Uuid>,
+    body: web::Json<DepartamentoVehiculo>,
+) -> Result<HttpResponse, actix_web::Error> {
+
+    if !usuario.administra() {
+        return Err(e403().with_message("No tienes los permisos requeridos"))?;
+    }
+
+    if !usuario.administra_departamento(body.departamento) {
+        return Err(e403().with_message("No administras el departamento destino"))?;
+    }
+
+    let vehiculo = actualizar_departamento_vehiculo_sqlx(
+        &pool,
+        &uuid,
+        body.departamento,
+        usuario.departamentos_administrados(),
+    ).await
+        .map_err(|e| {
+            let departamento_invalido = e.downcast_ref::<sqlx::Error>()
+                .and_then(|e| e.as_database_error())
+                .map(|e| e.constraint() == Some("vehiculos_departamento_fkey"))
+                .unwrap_or(false);
+            if departamento_invalido {
+                e400().with_message("No se encontro el departamento")
+            } else {
+                e500()
+            }
+        })?
+        .ok_or(e404().with_message("No se encontro el Vehiculo"))?;
+
+    // Respuesta exitosa
+    let api_response = ApiResponse::<DepartamentoActualizado>::new()
+        .with_message("Departamento del vehiculo actualizado")
+        .with_data(vehiculo)
+        .to_resp();
+
+    Ok(api_response)
+}
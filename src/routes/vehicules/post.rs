@@ -3,14 +3,22 @@ use anyhow::Context;
 use sqlx::PgPool;
 use uuid::Uuid;
 
-use crate::authentication::jwt_session::JwtSession;
-use crate::api_response::{ApiResponse, e500, e403};
+use crate::authentication::current_user::CurrentUser;
+use crate::api_response::{ApiResponse, e500, e400, e403};
 
-use crate::routes::users::sqlx::obtener_usuario_por_id_sqlx;
 
 use common::models::vehicule::{NuevoVehiculo, Vehiculo, EstadoVehiculo};
 
 
+#[derive(Debug, serde::Deserialize)]
+pub struct NuevoVehiculoDepartamento {
+    #[serde(flatten)]
+    pub vehiculo: NuevoVehiculo,
+    // Obligatorio para el administrador de departamento
+    pub departamento: Option<i32>,
+}
+
+
 #[tracing::instrument(
     name = "Query insertar nuevo vehiculo",
     skip(pool)
@@ -18,13 +26,14 @@ use common::models::vehicule::{NuevoVehiculo, Vehiculo, EstadoVehiculo};
 async fn insertar_nuevo_vehiculo_sqlx(
     pool: &PgPool,
     vehiculo: NuevoVehiculo,
+    departamento: Option<i32>,
 ) -> Result<Vehiculo, anyhow::Error> {
     let vehiculo: Vehiculo = sqlx::query_as!(
         Vehiculo,
         r#"
         INSERT INTO vehiculos
-        (vehiculo_id, marca, modelo, año, numero_placa, nombre_economico, numero_tarjeta)
-        VALUES ($1, $2, $3, $4, $5, $6, $7)
+        (vehiculo_id, marca, modelo, año, numero_placa, nombre_economico, numero_tarjeta, departamento)
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
         RETURNING 
             vehiculo_id, marca, modelo, año,
             numero_placa,
@@ -43,6 +52,7 @@ async fn insertar_nuevo_vehiculo_sqlx(
         vehiculo.numero_placa,
         vehiculo.nombre_economico,
         vehiculo.numero_tarjeta,
+        departamento,
     )
     .fetch_one(pool)
     .await
@@ -53,27 +63,36 @@ async fn insertar_nuevo_vehiculo_sqlx(
 
 #[tracing::instrument(
     name = "Post nuevo vehiculo",
-    skip(pool, session)
+    skip(pool, usuario)
 )]
 pub async fn post_new_vehicule(
-    session: JwtSession,
+    usuario: CurrentUser,
     pool: web::Data<PgPool>,
-    vehiculo: web::Json<NuevoVehiculo>
+    vehiculo: web::Json<NuevoVehiculoDepartamento>
 ) -> Result<HttpResponse, actix_web::Error> {
 
-    // Usuario es admin ?
-    let usuario = obtener_usuario_por_id_sqlx(&pool, &session.user_id).await
-        .map_err(|_| e500())?
-        .ok_or(e500())?;
-
-    if !usuario.es_admin() {
+    if !usuario.administra() {
         return Err(e403().with_message("No tienes los permisos requeridos"))?;
     }
 
+    let NuevoVehiculoDepartamento { vehiculo, departamento } = vehiculo.into_inner();
+    if !usuario.administra_departamento(departamento) {
+        return Err(e403().with_message("No administras ese departamento"))?;
+    }
+
     // Query insertar nuevo vehiculo DB
-    let vehiculo = vehiculo.into_inner();
-    let nuevo_vehiculo = insertar_nuevo_vehiculo_sqlx(&pool, vehiculo).await
-        .map_err(|_| e500())?;
+    let nuevo_vehiculo = insertar_nuevo_vehiculo_sqlx(&pool, vehiculo, departamento).await
+        .map_err(|e| {
+            let departamento_invalido = e.downcast_ref::<sqlx::Error>()
+                .and_then(|e| e.as_database_error())
+                .map(|e| e.constraint() == Some("vehiculos_departamento_fkey"))
+                .unwrap_or(false);
+            if departamento_invalido {
+                e400().with_message("No se encontro el departamento")
+            } else {
+                e500()
+            }
+        })?;
 
     // Respuesta exitosa
     let api_response = ApiResponse::<Vehiculo>::new()
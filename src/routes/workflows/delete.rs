@@ -0,0 +1,37 @@
+use actix_web::{HttpResponse, web};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::api_response::{ApiResponse, e403, e404, e500};
+use crate::authentication::current_user::CurrentUser;
+
+use super::sqlx::desactivar_flujo_sqlx;
+
+
+#[tracing::instrument(
+    name = "Desactivar flujo de aprobacion",
+    skip(usuario, pool)
+)]
+pub async fn delete_workflow(
+    usuario: CurrentUser,
+    pool: web::Data<PgPool>,
+    uuid: web::Path<Uuid>,
+) -> Result<HttpResponse, actix_web::Error> {
+
+    if !usuario.es_admin() {
+        return Err(e403().with_message("No tienes los permisos requeridos"))?;
+    }
+
+    let desactivado = desactivar_flujo_sqlx(&pool, &uuid).await
+        .map_err(|_| e500())?;
+    if !desactivado {
+        return Err(e404().with_message("No se encontro el flujo"))?;
+    }
+
+    // Respuesta exitosa
+    let api_response = ApiResponse::<()>::new()
+        .with_message("Flujo desactivado, las peticiones en curso lo conservan")
+        .to_resp();
+
+    Ok(api_response)
+}
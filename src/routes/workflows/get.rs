@@ -0,0 +1,40 @@
+use actix_web::{HttpResponse, web};
+use sqlx::PgPool;
+
+use crate::api_response::{ApiResponse, e403, e500};
+use crate::authentication::current_user::CurrentUser;
+
+use super::sqlx::{listar_flujos_sqlx, Flujo};
+
+
+#[derive(Debug, serde::Deserialize)]
+pub struct FiltroFlujos {
+    pub inactivos: Option<bool>,
+}
+
+
+#[tracing::instrument(
+    name = "Obtener flujos de aprobacion",
+    skip(usuario, pool)
+)]
+pub async fn get_workflows(
+    usuario: CurrentUser,
+    pool: web::Data<PgPool>,
+    filtro: web::Query<FiltroFlujos>,
+) -> Result<HttpResponse, actix_web::Error> {
+
+    if !usuario.es_admin() {
+        return Err(e403().with_message("No tienes los permisos requeridos"))?;
+    }
+
+    let flujos = listar_flujos_sqlx(&pool, filtro.inactivos.unwrap_or(false)).await
+        .map_err(|_| e500())?;
+
+    // Respuesta exitosa
+    let api_response = ApiResponse::<Vec<Flujo>>::new()
+        .with_message("Flujos de aprobacion")
+        .with_data(flujos)
+        .to_resp();
+
+    Ok(api_response)
+}
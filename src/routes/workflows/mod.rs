@@ -0,0 +1,4 @@
+pub mod get;
+pub mod post;
+pub mod delete;
+pub mod sqlx;
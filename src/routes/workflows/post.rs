@@ -0,0 +1,79 @@
+use actix_web::{HttpResponse, web};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::api_response::{ApiResponse, e400, e403, e500};
+use crate::authentication::current_user::CurrentUser;
+use crate::routes::users::sqlx::obtener_usuario_por_id_sqlx;
+
+use super::sqlx::{insertar_flujo_sqlx, NuevoFlujo, TipoAprobador};
+
+
+#[derive(Debug, serde::Serialize)]
+pub struct FlujoCreado {
+    pub flujo_id: Uuid,
+}
+
+
+#[tracing::instrument(
+    name = "Crear flujo de aprobacion",
+    skip(usuario, pool)
+)]
+pub async fn post_workflow(
+    usuario: CurrentUser,
+    pool: web::Data<PgPool>,
+    body: web::Json<NuevoFlujo>,
+) -> Result<HttpResponse, actix_web::Error> {
+
+    if !usuario.es_admin() {
+        return Err(e403().with_message("No tienes los permisos requeridos"))?;
+    }
+
+    let flujo = body.into_inner();
+    if flujo.nombre.trim().is_empty() {
+        return Err(e400().with_message("El nombre es requerido"))?;
+    }
+    if flujo.pasos.is_empty() {
+        return Err(e400().with_message("El flujo necesita al menos un paso"))?;
+    }
+    let paso_invalido = flujo.pasos.iter()
+        .any(|paso| (paso.tipo == TipoAprobador::Usuario) != paso.usuario_id.is_some());
+    if paso_invalido {
+        return Err(e400().with_message("Solo los pasos de tipo usuario indican el usuario"))?;
+    }
+    if flujo.duracion_min_horas.unwrap_or(0) < 0 || flujo.distancia_min_km.unwrap_or(0) < 0 {
+        return Err(e400().with_message("Las reglas no pueden ser negativas"))?;
+    }
+
+    // Un paso asignado a un usuario desactivado no lo podria decidir nadie mas que un administrador
+    for usuario_id in flujo.pasos.iter().filter_map(|paso| paso.usuario_id) {
+        let aprobador = obtener_usuario_por_id_sqlx(&pool, &usuario_id).await
+            .map_err(|_| e500())?
+            .ok_or(e400().with_message("No se encontro el usuario del paso"))?;
+        if !aprobador.activo {
+            return Err(e400().with_message(format!("El usuario {} esta desactivado", aprobador.email)))?;
+        }
+    }
+
+    let flujo_id = insertar_flujo_sqlx(&pool, &flujo, &usuario.usuario_id).await
+        .map_err(|e| {
+            // Departamento o usuario del paso que no existe
+            let referencia_invalida = e.downcast_ref::<sqlx::Error>()
+                .and_then(|e| e.as_database_error())
+                .map(|e| e.code().as_deref() == Some("23503"))
+                .unwrap_or(false);
+            if referencia_invalida {
+                e400().with_message("No se encontro el departamento o el usuario del paso")
+            } else {
+                e500()
+            }
+        })?;
+
+    // Respuesta exitosa
+    let api_response = ApiResponse::<FlujoCreado>::new()
+        .with_message("Flujo de aprobacion creado")
+        .with_data(FlujoCreado { flujo_id })
+        .to_resp();
+
+    Ok(api_response)
+}
@@ -0,0 +1,378 @@
+use anyhow::Context;
+use chrono::NaiveDateTime;
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+use crate::routes::department::sqlx::obtener_jefes_para_miembro_sqlx;
+
+
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize, serde::Deserialize, sqlx::Type)]
+#[sqlx(type_name = "tipo_aprobador", rename_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum TipoAprobador {
+    // Jefes del departamento de la peticion, o del mas cercano hacia arriba
+    JefeDepartamento,
+    Usuario,
+    // Administradores globales o del departamento de la peticion, tambien
+    // deciden los pasos de los otros tipos que no tienen aprobadores activos
+    Admin,
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct PasoFlujo {
+    pub orden: i16,
+    pub tipo: TipoAprobador,
+    pub usuario_id: Option<Uuid>,
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct Flujo {
+    pub flujo_id: Uuid,
+    pub nombre: String,
+    pub departamento_id: Option<i32>,
+    pub prioridad: i32,
+    pub duracion_min_horas: Option<i32>,
+    pub distancia_min_km: Option<i32>,
+    pub solo_fin_de_semana: bool,
+    pub activo: bool,
+    pub creado_en: NaiveDateTime,
+    pub pasos: Vec<PasoFlujo>,
+}
+
+#[derive(Debug, serde::Deserialize)]
+pub struct NuevoPaso {
+    pub tipo: TipoAprobador,
+    pub usuario_id: Option<Uuid>,
+}
+
+#[derive(Debug, serde::Deserialize)]
+pub struct NuevoFlujo {
+    pub nombre: String,
+    pub departamento_id: Option<i32>,
+    pub prioridad: Option<i32>,
+    pub duracion_min_horas: Option<i32>,
+    pub distancia_min_km: Option<i32>,
+    pub solo_fin_de_semana: Option<bool>,
+    pub pasos: Vec<NuevoPaso>,
+}
+
+/// El paso de un flujo que sigue, sin flujo solo hay un paso de administrador
+pub fn paso_pendiente(pasos: &[PasoFlujo], aprobados: i64) -> Option<PasoFlujo> {
+    if pasos.is_empty() {
+        return (aprobados == 0).then_some(PasoFlujo { orden: 1, tipo: TipoAprobador::Admin, usuario_id: None });
+    }
+    pasos.iter().find(|paso| i64::from(paso.orden) > aprobados).cloned()
+}
+
+
+#[tracing::instrument(
+    name = "Query listar flujos de aprobacion",
+    skip(pool)
+)]
+pub async fn listar_flujos_sqlx(
+    pool: &PgPool,
+    incluir_inactivos: bool,
+) -> Result<Vec<Flujo>, anyhow::Error> {
+    let flujos = sqlx::query!(
+        r#"
+        SELECT
+            flujo_id, nombre, departamento_id, prioridad,
+            duracion_min_horas, distancia_min_km, solo_fin_de_semana,
+            activo, creado_en
+        FROM flujos_aprobacion
+        WHERE activo OR $1
+        ORDER BY prioridad DESC, creado_en
+        "#,
+        incluir_inactivos,
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to execute query")?;
+
+    let mut resultado = Vec::with_capacity(flujos.len());
+    for flujo in flujos {
+        resultado.push(Flujo {
+            pasos: obtener_pasos_sqlx(pool, &flujo.flujo_id).await?,
+            flujo_id: flujo.flujo_id,
+            nombre: flujo.nombre,
+            departamento_id: flujo.departamento_id,
+            prioridad: flujo.prioridad,
+            duracion_min_horas: flujo.duracion_min_horas,
+            distancia_min_km: flujo.distancia_min_km,
+            solo_fin_de_semana: flujo.solo_fin_de_semana,
+            activo: flujo.activo,
+            creado_en: flujo.creado_en,
+        });
+    }
+
+    Ok(resultado)
+}
+
+#[tracing::instrument(
+    name = "Query pasos de flujo de aprobacion",
+    skip(pool)
+)]
+pub async fn obtener_pasos_sqlx(
+    pool: &PgPool,
+    flujo_id: &Uuid,
+) -> Result<Vec<PasoFlujo>, anyhow::Error> {
+    let pasos = sqlx::query_as!(
+        PasoFlujo,
+        r#"
+        SELECT orden, tipo as "tipo!: TipoAprobador", usuario_id
+        FROM flujos_aprobacion_pasos
+        WHERE flujo_id = $1
+        ORDER BY orden
+        "#,
+        flujo_id,
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to execute query")?;
+
+    Ok(pasos)
+}
+
+#[tracing::instrument(
+    name = "Query insertar flujo de aprobacion",
+    skip(pool)
+)]
+pub async fn insertar_flujo_sqlx(
+    pool: &PgPool,
+    flujo: &NuevoFlujo,
+    admin_id: &Uuid,
+) -> Result<Uuid, anyhow::Error> {
+    let mut transaction = pool.begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+
+    let flujo_id = Uuid::new_v4();
+    sqlx::query!(
+        r#"
+        INSERT INTO flujos_aprobacion
+        (flujo_id, nombre, departamento_id, prioridad, duracion_min_horas,
+         distancia_min_km, solo_fin_de_semana, creado_por)
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+        "#,
+        flujo_id,
+        flujo.nombre.trim(),
+        flujo.departamento_id,
+        flujo.prioridad.unwrap_or(0),
+        flujo.duracion_min_horas,
+        flujo.distancia_min_km,
+        flujo.solo_fin_de_semana.unwrap_or(false),
+        admin_id,
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to insert workflow")?;
+
+    for (i, paso) in flujo.pasos.iter().enumerate() {
+        sqlx::query!(
+            r#"
+            INSERT INTO flujos_aprobacion_pasos (flujo_id, orden, tipo, usuario_id)
+            VALUES ($1, $2, $3, $4)
+            "#,
+            flujo_id,
+            i as i16 + 1,
+            paso.tipo as TipoAprobador,
+            paso.usuario_id,
+        )
+        .execute(&mut transaction)
+        .await
+        .context("Failed to insert workflow step")?;
+    }
+
+    transaction.commit()
+        .await
+        .context("Failed to commit SQL transaction")?;
+
+    Ok(flujo_id)
+}
+
+/// Las peticiones en curso conservan el flujo, solo deja de asignarse
+#[tracing::instrument(
+    name = "Query desactivar flujo de aprobacion",
+    skip(pool)
+)]
+pub async fn desactivar_flujo_sqlx(
+    pool: &PgPool,
+    flujo_id: &Uuid,
+) -> Result<bool, anyhow::Error> {
+    let result = sqlx::query!(
+        "UPDATE flujos_aprobacion SET activo = false WHERE flujo_id = $1 AND activo",
+        flujo_id,
+    )
+    .execute(pool)
+    .await
+    .context("Failed to execute query")?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+/// Elige el flujo de la peticion segun su departamento y las reglas
+#[tracing::instrument(
+    name = "Query asignar flujo de aprobacion",
+    skip(transaction)
+)]
+pub async fn asignar_flujo_sqlx(
+    transaction: &mut Transaction<'_, Postgres>,
+    peticion_id: &Uuid,
+) -> Result<Option<Uuid>, anyhow::Error> {
+    let row = sqlx::query!(
+        r#"
+        WITH RECURSIVE peticion AS (
+            SELECT p.inicio, p.finalizo, p.distancia_estimada_km, p.departamento_id
+            FROM peticiones p
+            WHERE p.peticion_id = $1
+        ), ancestros AS (
+            SELECT d.id, d.padre_id, 0 as nivel
+            FROM departamentos d JOIN peticion p ON d.id = p.departamento_id
+            UNION ALL
+            SELECT d.id, d.padre_id, a.nivel + 1
+            FROM departamentos d JOIN ancestros a ON d.id = a.padre_id
+            WHERE a.nivel < 32
+        ), elegido AS (
+            SELECT f.flujo_id
+            FROM flujos_aprobacion f
+            CROSS JOIN peticion p
+            LEFT JOIN ancestros a ON a.id = f.departamento_id
+            WHERE f.activo
+                AND (f.departamento_id IS NULL OR a.id IS NOT NULL)
+                AND (f.duracion_min_horas IS NULL
+                    OR p.finalizo - p.inicio >= make_interval(hours => f.duracion_min_horas))
+                AND (f.distancia_min_km IS NULL
+                    OR COALESCE(p.distancia_estimada_km >= f.distancia_min_km, false))
+                AND (NOT f.solo_fin_de_semana OR EXISTS (
+                    SELECT 1
+                    FROM generate_series(p.inicio::date, p.finalizo::date, interval '1 day') dia
+                    WHERE extract(isodow FROM dia) >= 6
+                ))
+            ORDER BY f.prioridad DESC, a.nivel ASC NULLS LAST, f.creado_en
+            LIMIT 1
+        )
+        UPDATE peticiones
+        SET flujo_id = (SELECT flujo_id FROM elegido)
+        WHERE peticion_id = $1
+        RETURNING flujo_id
+        "#,
+        peticion_id,
+    )
+    .fetch_one(transaction)
+    .await
+    .context("Failed to execute query")?;
+
+    Ok(row.flujo_id)
+}
+
+/// Administradores globales y los del departamento o de sus ancestros
+#[tracing::instrument(
+    name = "Query administradores para departamento",
+    skip(pool)
+)]
+async fn administradores_para_sqlx(
+    pool: &PgPool,
+    departamento_id: Option<i32>,
+) -> Result<Vec<Uuid>, anyhow::Error> {
+    let administradores = sqlx::query!(
+        r#"
+        WITH RECURSIVE ancestros AS (
+            SELECT d.id, d.padre_id
+            FROM departamentos d
+            WHERE d.id = $1
+            UNION
+            SELECT d.id, d.padre_id
+            FROM departamentos d JOIN ancestros a ON d.id = a.padre_id
+        )
+        SELECT usuario_id as "usuario_id!" FROM usuarios WHERE rol = 'admin' AND activo
+        UNION
+        SELECT u.usuario_id
+        FROM administradores_departamento ad
+        JOIN ancestros a ON a.id = ad.departamento_id
+        JOIN usuarios u ON u.usuario_id = ad.usuario_id
+        WHERE u.activo
+        "#,
+        departamento_id,
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to fetch admins")?;
+
+    Ok(administradores.into_iter().map(|row| row.usuario_id).collect())
+}
+
+/// Usuarios que pueden decidir el paso por si mismos. Se resuelven con el departamento
+/// guardado en la peticion, cambiar al solicitante de departamento no cambia quien aprueba.
+/// Un paso sin aprobadores activos lo deciden los administradores, para que la peticion
+/// no se quede pendiente sin nadie que la pueda decidir
+#[tracing::instrument(
+    name = "Query aprobadores del paso",
+    skip(pool)
+)]
+pub async fn aprobadores_del_paso_sqlx(
+    pool: &PgPool,
+    paso: &PasoFlujo,
+    solicitante_id: &Uuid,
+    departamento_id: Option<i32>,
+) -> Result<Vec<Uuid>, anyhow::Error> {
+    let directos: Vec<Uuid> = match (paso.tipo, departamento_id, paso.usuario_id) {
+        (TipoAprobador::JefeDepartamento, Some(departamento_id), _) =>
+            obtener_jefes_para_miembro_sqlx(pool, departamento_id, solicitante_id).await?
+                .into_iter()
+                .map(|jefe| jefe.usuario_id)
+                .collect(),
+        (TipoAprobador::Usuario, _, Some(usuario_id)) => sqlx::query!(
+            "SELECT usuario_id FROM usuarios WHERE usuario_id = $1 AND activo",
+            usuario_id,
+        )
+        .fetch_optional(pool)
+        .await
+        .context("Failed to fetch step user")?
+        .map(|row| row.usuario_id)
+        .into_iter()
+        .collect(),
+        // Sin departamento no hay jefes que apliquen
+        _ => vec![],
+    };
+
+    // Nadie aprueba su propia peticion
+    let directos: Vec<Uuid> = directos.into_iter().filter(|id| id != solicitante_id).collect();
+    if paso.tipo != TipoAprobador::Admin && !directos.is_empty() {
+        return Ok(directos);
+    }
+
+    Ok(administradores_para_sqlx(pool, departamento_id).await?
+        .into_iter()
+        .filter(|id| id != solicitante_id)
+        .collect())
+}
+
+/// Aprobador que delego en el usuario y sigue ausente, si hay uno
+#[tracing::instrument(
+    name = "Query delegacion vigente",
+    skip(pool)
+)]
+pub async fn delegante_vigente_sqlx(
+    pool: &PgPool,
+    delegado_id: &Uuid,
+    aprobadores: &[Uuid],
+) -> Result<Option<Uuid>, anyhow::Error> {
+    let row = sqlx::query!(
+        r#"
+        SELECT delegante_id
+        FROM delegaciones
+        WHERE delegado_id = $1
+            AND delegante_id = ANY($2)
+            AND now() BETWEEN inicio AND fin
+        ORDER BY creado_en
+        LIMIT 1
+        "#,
+        delegado_id,
+        aprobadores,
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to execute query")?;
+
+    Ok(row.map(|row| row.delegante_id))
+}
@@ -1,8 +1,9 @@
 use std::net::TcpListener;
 
-use crate::authentication::{jwt_session::HmacKey, middleware::reject_anonymous_user};
-use crate::configuration::{Settings, DatabaseSettings};
+use crate::authentication::{key_ring::KeyRing, middleware::reject_anonymous_user, oidc::ProveedoresOidc, password::DummyPasswordHash, password_policy::PasswordPolicy};
+use crate::configuration::{Settings, DatabaseSettings, LdapSettings, LoginThrottleSettings, PasswordHashingSettings, SignupSettings, TwoFactorSettings, UserRetentionSettings};
 use crate::email_client::EmailClient;
+use crate::redis_pool::RedisPool;
 use actix_web::{web, App, HttpServer};
 use actix_web::dev::Server;
 use actix_web_lab::middleware::from_fn;
@@ -22,6 +23,12 @@ use crate::routes::department;
 // Vehicule routes
 use crate::routes::vehicules;
 
+use crate::routes::api_keys;
+use crate::routes::audit;
+use crate::routes::invitations;
+use crate::routes::licenses;
+use crate::routes::workflows;
+
 
 use tracing_actix_web::TracingLogger;
 
@@ -45,7 +52,7 @@ pub fn get_connection_pool(configuration: &DatabaseSettings) -> PgPool {
 //#[derive(Debug)]
 pub struct ApplicationBaseUrl(pub String);
 
-pub struct RedisUri(pub String);
+pub struct UserCacheTtl(pub u64);
 
 pub struct Application {
     port: u16,
@@ -56,9 +63,18 @@ impl Application {
     pub async fn build(configuration: Settings) -> Result<Self, anyhow::Error> {
         let connection_pool = get_connection_pool(&configuration.database);
 
+        // jwt keys, the "legacy" entry falls back to hmca_secret
+        let key_ring = KeyRing::from_settings(&configuration.application.hmca_secret, &configuration.jwt)?;
+
+        // OpenID Connect providers are discovered on first use
+        let proveedores_oidc = ProveedoresOidc::from_settings(&configuration.oidc);
+
+        // password policy with the common passwords list loaded once
+        let password_policy = PasswordPolicy::from_settings(&configuration.password_policy)?;
+
         // redis_client
-        //let redis_client = redis::Client::open(configuration.redis_client.uri.clone())?;
-        let redis_uri = RedisUri(configuration.redis_client.uri);
+        let redis_pool = RedisPool::new(&configuration.redis_client)?;
+        let user_cache_ttl = UserCacheTtl(configuration.redis_client.user_cache_seconds);
 
         // email client
         let email_client = EmailClient::new(
@@ -84,8 +100,17 @@ impl Application {
                          connection_pool,
                          email_client,
                          configuration.application.base_url,
-                         configuration.application.hmca_secret,
-                         redis_uri,
+                         key_ring,
+                         redis_pool,
+                         user_cache_ttl,
+                         configuration.login_throttle,
+                         configuration.two_factor,
+                         configuration.ldap,
+                         proveedores_oidc,
+                         configuration.signup,
+                         password_policy,
+                         configuration.password_hashing,
+                         configuration.user_retention,
                     ).await?;
         Ok( Self { port, server } )
     }
@@ -105,16 +130,34 @@ async fn run(
     db_pool: PgPool,
     email_client: EmailClient,
     base_url: String,
-    hmca_secret: HmacKey,
-    //redis_client: redis::Client,
-    redis_uri: RedisUri,
+    key_ring: KeyRing,
+    redis_pool: RedisPool,
+    user_cache_ttl: UserCacheTtl,
+    login_throttle: LoginThrottleSettings,
+    two_factor: TwoFactorSettings,
+    ldap: LdapSettings,
+    proveedores_oidc: ProveedoresOidc,
+    signup: SignupSettings,
+    password_policy: PasswordPolicy,
+    password_hashing: PasswordHashingSettings,
+    user_retention: UserRetentionSettings,
 ) -> Result<Server, anyhow::Error> {
     // Wrap data into smart pointer actix_web
     let db_pool = web::Data::new(db_pool);
-    let hmca_secret = web::Data::new(hmca_secret);
+    let key_ring = web::Data::new(key_ring);
     let email_client = web::Data::new(email_client);
     let base_url = web::Data::new(ApplicationBaseUrl(base_url));
-    let redis_uri = web::Data::new(redis_uri);
+    let redis_pool = web::Data::new(redis_pool);
+    let user_cache_ttl = web::Data::new(user_cache_ttl);
+    let login_throttle = web::Data::new(login_throttle);
+    let two_factor = web::Data::new(two_factor);
+    let ldap = web::Data::new(ldap);
+    let proveedores_oidc = web::Data::new(proveedores_oidc);
+    let signup = web::Data::new(signup);
+    let password_policy = web::Data::new(password_policy);
+    let dummy_password_hash = web::Data::new(DummyPasswordHash::new(&password_hashing)?);
+    let password_hashing = web::Data::new(password_hashing);
+    let user_retention = web::Data::new(user_retention);
 
 
 
@@ -126,13 +169,17 @@ async fn run(
             .allowed_methods(vec!["GET", "POST", "DELETE", "PATCH"])
             .allowed_headers(vec![actix_web::http::header::AUTHORIZATION,
                              actix_web::http::header::ACCEPT,
+                             actix_web::http::header::HeaderName::from_static("x-api-key"),
             ])
             .allowed_header(actix_web::http::header::CONTENT_TYPE)
+            .expose_headers(vec![actix_web::http::header::HeaderName::from_static("x-total-count")])
             .max_age(3600);
 
         App::new()
             .wrap(TracingLogger::default())
             .wrap(cors)
+            // Public keys for other services
+            .route("/.well-known/jwks.json", web::get().to(auth::jwks::get_jwks))
             // Add API service
             .service(
                 web::scope("/api")
@@ -148,6 +195,15 @@ async fn run(
                             .route("/signup", web::post().to(auth::register::signup_user))
                             .route("/signups/confirm", web::get().to(auth::signup_confirm::confirm))
                             .route("/login", web::post().to(auth::login::login_user))
+                            .route("/login/2fa", web::post().to(auth::two_factor::login_second_factor))
+                            .route("/2fa/setup", web::post().to(auth::two_factor::setup_from_challenge))
+                            .route("/2fa/setup/confirm", web::post().to(auth::two_factor::confirm_setup_from_challenge))
+                            .route("/oidc/{proveedor}/authorize", web::get().to(auth::oidc::oidc_authorize))
+                            .route("/oidc/{proveedor}/callback", web::post().to(auth::oidc::oidc_callback))
+                            .route("/invitations", web::get().to(auth::invitation::get_invitation))
+                            .route("/invitations/accept", web::post().to(auth::invitation::accept_invitation))
+                            .route("/email-change/confirm", web::get().to(users::me::email::confirm_email_change))
+                            .route("/export/download", web::get().to(users::me::export::download_export))
                             .service(
                                 web::resource("/logout")
                                     .wrap(from_fn(reject_anonymous_user))
@@ -162,7 +218,22 @@ async fn run(
                         web::scope("/departments")
                             //.wrap(from_fn(reject_anonymous_user))
                             .route("", web::get().to(department::get::departments_get))
+                            .route("/tree", web::get().to(department::get::departments_tree_get))
+                            .route("/usage", web::get().to(department::quotas::departments_usage_get))
                             .route("/{id}", web::get().to(department::get::department_get))
+                            .route("/{id}/members", web::get().to(department::get::department_members_get))
+                            .route("/{id}/heads", web::get().to(department::get::department_heads_get))
+                            .route("/{id}/heads/{uuid}", web::post().to(department::post::department_head_post))
+                            .route("/{id}/heads/{uuid}", web::delete().to(department::delete::delete_department_head))
+                            .route("/{id}/admins", web::get().to(department::get::department_admins_get))
+                            .route("/{id}/admins/{uuid}", web::post().to(department::post::department_admin_post))
+                            .route("/{id}/admins/{uuid}", web::delete().to(department::delete::delete_department_admin))
+                            .route("/{id}/hierarchy", web::patch().to(department::patch::patch_department_hierarchy))
+                            .route("/{id}/merge", web::post().to(department::post::merge_departments))
+                            .route("/{id}/quota", web::get().to(department::quotas::department_quota_get))
+                            .route("/{id}/quota", web::patch().to(department::quotas::patch_department_quota))
+                            .route("/{id}/quota", web::delete().to(department::quotas::delete_department_quota))
+                            .route("/{id}/usage", web::get().to(department::quotas::department_usage_get))
                             .route("/{name}", web::post().to(department::post::department_post))
                             .route("/{id}", web::delete().to(department::delete::delete_department))
                             .route("/{id}", web::patch().to(department::patch::patch_department))
@@ -178,6 +249,8 @@ async fn run(
                             .route("/{uuid}", web::delete().to(vehicules::delete::delete_vehicule))
                             .route("/{uuid}", web::patch().to(vehicules::patch::patch_vehicule))
                             .route("/picture/{uuid}", web::patch().to(vehicules::patch::patch_vehicule_picture))
+                            .route("/{uuid}/license-class", web::patch().to(vehicules::patch::patch_vehicule_license_class))
+                            .route("/{uuid}/department", web::patch().to(vehicules::patch::patch_vehicule_department))
                             // Get image
                             .route("/picture/{file}", web::get().to(vehicules::image::get_imagen_vehiculo))
 
@@ -191,17 +264,83 @@ async fn run(
                             .route("/me/picture", web::get().to(users::me::image::get_imagen_usuario))
                             .route("/me/picture", web::patch().to(users::me::patch::user_picture_patch_me))
                             .route("/me/change-password", web::post().to(users::me::password::change_user_password))
+                            .route("/me/email", web::post().to(users::me::email::request_email_change_me))
+                            .route("/me/email", web::delete().to(users::me::email::cancel_email_change_me))
+                            .route("/me/2fa", web::post().to(users::me::two_factor::enroll_me))
+                            .route("/me/2fa/confirm", web::post().to(users::me::two_factor::confirm_enroll_me))
+                            .route("/me/2fa", web::delete().to(users::me::two_factor::disable_me))
+                            .route("/me/2fa/recovery-codes", web::post().to(users::me::two_factor::regenerate_recovery_codes_me))
+                            .route("/me/sessions", web::get().to(users::me::sessions::get_my_sessions))
+                            .route("/me/sessions", web::delete().to(users::me::sessions::delete_my_sessions))
+                            .route("/me/sessions/{uuid}", web::delete().to(users::me::sessions::delete_my_session))
+                            .route("/me/identities", web::get().to(users::me::identities::get_my_identities))
+                            .route("/me/identities/{proveedor}", web::post().to(users::me::identities::link_identity))
+                            .route("/me/identities/{proveedor}/callback", web::post().to(users::me::identities::link_identity_callback))
+                            .route("/me/identities/{proveedor}", web::delete().to(users::me::identities::unlink_identity))
+                            .route("/me/export", web::get().to(users::me::export::get_export_me))
+                            .route("/me/export", web::post().to(users::me::export::request_export_me))
+                            .route("/me/delegations", web::get().to(users::me::delegations::get_my_delegations))
+                            .route("/me/delegations", web::post().to(users::me::delegations::post_my_delegation))
+                            .route("/me/delegations/{uuid}", web::delete().to(users::me::delegations::delete_my_delegation))
                             //.route("/me/picture", web::delete().to(users::me::delete::user_picture_delete_me))
                             // Admin routes
+                            .route("/approvals", web::get().to(users::approvals::get_pending_approvals))
                             .route("", web::get().to(users::get::users_get_all))
                             .route("/{uuid}", web::get().to(users::get::users_get_user_by_id))
                             .route("/{uuid}", web::delete().to(users::delete::users_delete_user_by_id))
                             .route("/{uuid}", web::patch().to(users::patch::user_patch))
+                            .route("/{uuid}/unlock", web::post().to(users::post::users_unlock_user_by_id))
+                            .route("/{uuid}/restore", web::post().to(users::post::users_restore_user_by_id))
+                            .route("/{uuid}/sessions", web::delete().to(users::delete::users_delete_sessions_by_id))
+                            .route("/{uuid}/approve", web::post().to(users::approvals::approve_user))
+                            .route("/{uuid}/reject", web::post().to(users::approvals::reject_user))
+                            .route("/{uuid}/impersonate", web::post().to(users::impersonation::impersonate_user))
                             .route("/picture/{uuid}", web::patch().to(users::patch::user_picture_patch))
                             // Get image
                             .route("/picture/{file}", web::get().to(users::image::get_imagen_usuario))
 
                     )
+                    .service(
+                        web::scope("/api-keys")
+                            // Admin routes
+                            .route("", web::get().to(api_keys::get::api_keys_get))
+                            .route("", web::post().to(api_keys::post::api_key_post))
+                            .route("/{uuid}", web::delete().to(api_keys::delete::delete_api_key))
+                    )
+                    .service(
+                        web::scope("/licenses")
+                            // Me routes
+                            .route("/me", web::get().to(licenses::get::get_my_license))
+                            .route("/me", web::post().to(licenses::post::post_my_license))
+                            .route("/me/picture/{lado}", web::get().to(licenses::image::get_my_license_picture))
+                            .route("/me/picture/{lado}", web::patch().to(licenses::patch::patch_my_license_picture))
+                            // Admin routes
+                            .route("", web::get().to(licenses::get::get_licenses))
+                            .route("/{uuid}", web::get().to(licenses::get::get_license_by_user))
+                            .route("/{uuid}/verify", web::post().to(licenses::post::verify_license))
+                            .route("/{uuid}/reject", web::post().to(licenses::post::reject_license))
+                            .route("/{uuid}/picture/{lado}", web::get().to(licenses::image::get_license_picture))
+                    )
+                    .service(
+                        web::scope("/workflows")
+                            // Admin routes
+                            .route("", web::get().to(workflows::get::get_workflows))
+                            .route("", web::post().to(workflows::post::post_workflow))
+                            .route("/{uuid}", web::delete().to(workflows::delete::delete_workflow))
+                    )
+                    .service(
+                        web::scope("/audit")
+                            // Admin routes
+                            .route("", web::get().to(audit::get::audit_get))
+                    )
+                    .service(
+                        web::scope("/invitations")
+                            // Admin routes
+                            .route("", web::get().to(invitations::get::get_invitations))
+                            .route("", web::post().to(invitations::post::post_invitation))
+                            .route("/{uuid}/resend", web::post().to(invitations::post::resend_invitation))
+                            .route("/{uuid}", web::delete().to(invitations::delete::delete_invitation))
+                    )
                     .service(
                         web::scope("/requests")
                             // Admin routes
@@ -214,6 +353,10 @@ async fn run(
                             */
                             // Normal routes
                             .route("/new/{uuid}", web::post().to(crate::routes::requests::post::post_new_request))
+                            .route("/pending-approval", web::get().to(crate::routes::requests::approvals::get_pending_request_approvals))
+                            .route("/{uuid}/approvals", web::get().to(crate::routes::requests::approvals::get_request_approvals))
+                            .route("/{uuid}/approve", web::post().to(crate::routes::requests::approvals::approve_request))
+                            .route("/{uuid}/reject", web::post().to(crate::routes::requests::approvals::reject_request))
                             // Get image
                             //.route("/picture/{file}", web::get().to(users::image::get_imagen_usuario))
 
@@ -221,10 +364,20 @@ async fn run(
             )
             // Add all request extra data
             .app_data(db_pool.clone())
-            .app_data(hmca_secret.clone())
+            .app_data(key_ring.clone())
             .app_data(email_client.clone())
             .app_data(base_url.clone())
-            .app_data(redis_uri.clone())
+            .app_data(redis_pool.clone())
+            .app_data(user_cache_ttl.clone())
+            .app_data(login_throttle.clone())
+            .app_data(two_factor.clone())
+            .app_data(ldap.clone())
+            .app_data(proveedores_oidc.clone())
+            .app_data(signup.clone())
+            .app_data(password_policy.clone())
+            .app_data(password_hashing.clone())
+            .app_data(dummy_password_hash.clone())
+            .app_data(user_retention.clone())
     })
     .listen(listener)?
     .run();
@@ -0,0 +1,201 @@
+use uuid::Uuid;
+
+use crate::helpers::{spawn_app, TestApp, TestUser};
+
+
+async fn post_api_key(app: &TestApp, token: &str, body: &serde_json::Value) -> reqwest::Response {
+    app.api_client
+        .post(format!("{}/api/api-keys", &app.address))
+        .bearer_auth(token)
+        .json(body)
+        .send()
+        .await
+        .expect("Failed to execute request")
+}
+
+/// Creates an api key with the given scopes and returns its id and value
+async fn create_api_key(app: &TestApp, scopes: &[&str]) -> (String, String) {
+    app.test_user.make_admin(&app.db_pool).await;
+    let token = app.test_user.login_token(app).await;
+
+    let response = post_api_key(app, &token, &serde_json::json!({
+        "nombre": "contabilidad",
+        "scopes": scopes,
+    })).await;
+    assert_eq!(200, response.status().as_u16());
+
+    let body: serde_json::Value = response.json().await.unwrap();
+    (
+        body["data"]["api_key_id"].as_str().unwrap().to_string(),
+        body["data"]["key"].as_str().unwrap().to_string(),
+    )
+}
+
+async fn get_users_with_api_key(app: &TestApp, api_key: &str) -> reqwest::Response {
+    app.api_client
+        .get(format!("{}/api/users", &app.address))
+        .header("X-Api-Key", api_key)
+        .send()
+        .await
+        .expect("Failed to execute request")
+}
+
+
+#[tokio::test]
+async fn only_admins_can_create_api_keys() {
+    // Arrange
+    let app = spawn_app().await;
+    let token = app.test_user.login_token(&app).await;
+
+    // Act
+    let response = post_api_key(&app, &token, &serde_json::json!({
+        "nombre": "contabilidad",
+        "scopes": ["users:read"],
+    })).await;
+
+    // Assert
+    assert_eq!(403, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn invalid_scopes_are_rejected_with_400() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.make_admin(&app.db_pool).await;
+    let token = app.test_user.login_token(&app).await;
+
+    let test_cases = vec![
+        (serde_json::json!({"nombre": "rh", "scopes": []}), "no scopes"),
+        (serde_json::json!({"nombre": "rh", "scopes": ["users"]}), "missing permission"),
+        (serde_json::json!({"nombre": "rh", "scopes": ["api-keys:write"]}), "unknown resource"),
+        (serde_json::json!({"nombre": "rh", "scopes": ["users:delete"]}), "unknown permission"),
+    ];
+
+    for (body, description) in test_cases {
+        // Act
+        let response = post_api_key(&app, &token, &body).await;
+
+        // Assert
+        assert_eq!(
+            400,
+            response.status().as_u16(),
+            "The API did not fail with 400 Bad Request when the payload had {}.",
+            description
+        );
+    }
+}
+
+#[tokio::test]
+async fn api_key_with_scope_is_accepted() {
+    // Arrange
+    let app = spawn_app().await;
+    let (_, api_key) = create_api_key(&app, &["users:read"]).await;
+
+    // Act
+    let response = get_users_with_api_key(&app, &api_key).await;
+
+    // Assert
+    assert_eq!(200, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn api_key_without_scope_is_rejected_with_403() {
+    // Arrange
+    let app = spawn_app().await;
+    let (_, api_key) = create_api_key(&app, &["vehicules:read"]).await;
+
+    // Act
+    let response = get_users_with_api_key(&app, &api_key).await;
+
+    // Assert
+    assert_eq!(403, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn api_key_can_not_manage_api_keys() {
+    // Arrange
+    let app = spawn_app().await;
+    let (_, api_key) = create_api_key(&app, &["users:read", "users:write"]).await;
+
+    // Act
+    let response = app.api_client
+        .get(format!("{}/api/api-keys", &app.address))
+        .header("X-Api-Key", &api_key)
+        .send()
+        .await
+        .expect("Failed to execute request");
+
+    // Assert
+    assert_eq!(403, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn api_key_can_not_change_user_roles() {
+    // Arrange
+    let app = spawn_app().await;
+    let (_, api_key) = create_api_key(&app, &["users:read", "users:write"]).await;
+    let other_user = TestUser::generate();
+    other_user.store(&app.db_pool).await;
+
+    // Act
+    let response = app.api_client
+        .patch(format!("{}/api/users/{}", &app.address, other_user.user_id))
+        .header("X-Api-Key", &api_key)
+        .json(&serde_json::json!({ "rol": "admin" }))
+        .send()
+        .await
+        .expect("Failed to execute request");
+
+    // Assert
+    assert_eq!(403, response.status().as_u16());
+    let row = sqlx::query!(
+        "SELECT rol::text as \"rol!\" FROM usuarios WHERE usuario_id = $1",
+        other_user.user_id,
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!("normal", row.rol);
+}
+
+#[tokio::test]
+async fn revoked_api_key_is_rejected_with_401() {
+    // Arrange
+    let app = spawn_app().await;
+    let (api_key_id, api_key) = create_api_key(&app, &["users:read"]).await;
+    let token = app.test_user.login_token(&app).await;
+
+    // Act
+    let response = app.api_client
+        .delete(format!("{}/api/api-keys/{}", &app.address, api_key_id))
+        .bearer_auth(&token)
+        .send()
+        .await
+        .expect("Failed to execute request");
+    assert_eq!(200, response.status().as_u16());
+
+    // Assert
+    assert_eq!(401, get_users_with_api_key(&app, &api_key).await.status().as_u16());
+}
+
+#[tokio::test]
+async fn api_key_is_stored_hashed_and_tracks_last_use() {
+    // Arrange
+    let app = spawn_app().await;
+    let (api_key_id, api_key) = create_api_key(&app, &["users:read"]).await;
+
+    // Act
+    get_users_with_api_key(&app, &api_key).await;
+
+    // Assert
+    let row = sqlx::query!(
+        "SELECT key_hash, ultimo_uso FROM api_keys WHERE api_key_id = $1",
+        Uuid::parse_str(&api_key_id).unwrap()
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .expect("Failed to fetch api key");
+
+    assert_ne!(api_key, row.key_hash);
+    assert!(row.ultimo_uso.is_some());
+}
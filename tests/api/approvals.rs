@@ -0,0 +1,111 @@
+use crate::helpers::{spawn_app_with, TestApp};
+
+
+fn signup_body(email: &str) -> serde_json::Value {
+    serde_json::json!({
+        "first_name": "Ursula",
+        "last_name": "Le guin",
+        "email": email,
+        "password": "caballo-bateria-grapa-7",
+        "re_password": "caballo-bateria-grapa-7",
+    })
+}
+
+async fn login(app: &TestApp, email: &str) -> reqwest::Response {
+    app.post_login(&serde_json::json!({
+        "email": email,
+        "password": "caballo-bateria-grapa-7",
+    }))
+    .await
+}
+
+async fn pending_user_id(app: &TestApp, email: &str) -> uuid::Uuid {
+    sqlx::query!("SELECT usuario_id FROM usuarios WHERE email = $1", email)
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch the new user")
+        .usuario_id
+}
+
+
+#[tokio::test]
+async fn signup_from_a_domain_not_allowed_is_rejected_with_403() {
+    // Arrange
+    let app = spawn_app_with(|c| c.signup.allowed_domains = vec!["cpv.local".to_string()]).await;
+
+    // Act
+    let response = app.post_register(&signup_body("ursula_le_guin@gmail.com")).await;
+
+    // Assert
+    assert_eq!(403, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn pending_user_can_not_login_until_approved() {
+    // Arrange
+    let app = spawn_app_with(|c| c.signup.require_approval = true).await;
+    app.test_user.make_admin(&app.db_pool).await;
+    let token = app.test_user.login_token(&app).await;
+
+    let response = app.post_register(&signup_body("ursula@cpv.local")).await;
+    assert_eq!(201, response.status().as_u16());
+
+    // Act - Part 1 - Pending
+    assert_eq!(403, login(&app, "ursula@cpv.local").await.status().as_u16());
+
+    let queue: serde_json::Value = app.api_client
+        .get(format!("{}/api/users/approvals", &app.address))
+        .bearer_auth(&token)
+        .send()
+        .await
+        .expect("Failed to execute request")
+        .json()
+        .await
+        .unwrap();
+    assert_eq!("ursula@cpv.local", queue["data"][0]["email"]);
+
+    // Act - Part 2 - Approve
+    let usuario_id = pending_user_id(&app, "ursula@cpv.local").await;
+    let response = app.api_client
+        .post(format!("{}/api/users/{}/approve", &app.address, usuario_id))
+        .bearer_auth(&token)
+        .send()
+        .await
+        .expect("Failed to execute request");
+    assert_eq!(200, response.status().as_u16());
+
+    // Assert
+    assert_eq!(200, login(&app, "ursula@cpv.local").await.status().as_u16());
+}
+
+#[tokio::test]
+async fn rejected_user_can_not_login() {
+    // Arrange
+    let app = spawn_app_with(|c| c.signup.require_approval = true).await;
+    app.test_user.make_admin(&app.db_pool).await;
+    let token = app.test_user.login_token(&app).await;
+
+    app.post_register(&signup_body("ursula@cpv.local")).await;
+    let usuario_id = pending_user_id(&app, "ursula@cpv.local").await;
+
+    // Act
+    let response = app.api_client
+        .post(format!("{}/api/users/{}/reject", &app.address, usuario_id))
+        .bearer_auth(&token)
+        .json(&serde_json::json!({ "motivo": "No pertenece a la institucion" }))
+        .send()
+        .await
+        .expect("Failed to execute request");
+    assert_eq!(200, response.status().as_u16());
+
+    // Assert
+    assert_eq!(403, login(&app, "ursula@cpv.local").await.status().as_u16());
+
+    let response = app.api_client
+        .post(format!("{}/api/users/{}/approve", &app.address, usuario_id))
+        .bearer_auth(&token)
+        .send()
+        .await
+        .expect("Failed to execute request");
+    assert_eq!(404, response.status().as_u16());
+}
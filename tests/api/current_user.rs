@@ -0,0 +1,67 @@
+use crate::helpers::spawn_app;
+
+#[tokio::test]
+async fn valid_token_of_a_deleted_user_is_rejected_with_401() {
+    // Arrange
+    let app = spawn_app().await;
+    let token = app.test_user.login_token(&app).await;
+
+    sqlx::query!("DELETE FROM usuarios WHERE usuario_id = $1", app.test_user.user_id)
+        .execute(&app.db_pool)
+        .await
+        .expect("Failed to delete test user");
+
+    // Act
+    let response = app.api_client
+        .get(format!("{}/api/users/me", &app.address))
+        .bearer_auth(token)
+        .send()
+        .await
+        .expect("Failed to execute request");
+
+    // Assert
+    assert_eq!(401, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn valid_token_of_a_deactivated_user_is_rejected_with_401() {
+    // Arrange
+    let app = spawn_app().await;
+    let token = app.test_user.login_token(&app).await;
+
+    sqlx::query!("UPDATE usuarios SET activo = false WHERE usuario_id = $1", app.test_user.user_id)
+        .execute(&app.db_pool)
+        .await
+        .expect("Failed to deactivate test user");
+
+    // Act
+    let response = app.api_client
+        .get(format!("{}/api/users/me", &app.address))
+        .bearer_auth(token)
+        .send()
+        .await
+        .expect("Failed to execute request");
+
+    // Assert
+    assert_eq!(401, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn valid_token_of_an_active_user_returns_its_profile() {
+    // Arrange
+    let app = spawn_app().await;
+    let token = app.test_user.login_token(&app).await;
+
+    // Act
+    let response = app.api_client
+        .get(format!("{}/api/users/me", &app.address))
+        .bearer_auth(token)
+        .send()
+        .await
+        .expect("Failed to execute request");
+
+    // Assert
+    assert_eq!(200, response.status().as_u16());
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["data"]["email"], app.test_user.email);
+}
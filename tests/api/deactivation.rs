@@ -0,0 +1,194 @@
+use uuid::Uuid;
+use control_parque_vehicular::anonymization_worker::anonimizar_usuarios_vencidos;
+
+use crate::helpers::{spawn_app, TestApp, TestUser};
+
+
+async fn delete_user(app: &TestApp, token: &str, user: &TestUser) -> reqwest::Response {
+    app.api_client
+        .delete(format!("{}/api/users/{}", &app.address, user.user_id))
+        .bearer_auth(token)
+        .send()
+        .await
+        .expect("Failed to execute request")
+}
+
+async fn restore_user(app: &TestApp, token: &str, user: &TestUser) -> reqwest::Response {
+    app.api_client
+        .post(format!("{}/api/users/{}/restore", &app.address, user.user_id))
+        .bearer_auth(token)
+        .send()
+        .await
+        .expect("Failed to execute request")
+}
+
+async fn store_user(app: &TestApp) -> TestUser {
+    let user = TestUser::generate();
+    user.store(&app.db_pool).await;
+    user
+}
+
+
+#[tokio::test]
+async fn deleted_user_is_deactivated_and_can_not_login() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.make_admin(&app.db_pool).await;
+    let token = app.test_user.login_token(&app).await;
+    let user = store_user(&app).await;
+
+    // Act
+    let response = delete_user(&app, &token, &user).await;
+
+    // Assert
+    assert_eq!(200, response.status().as_u16());
+    let row = sqlx::query!(
+        "SELECT activo, desactivado_en, desactivado_por FROM usuarios WHERE usuario_id = $1",
+        user.user_id,
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .expect("The user row was erased");
+    assert!(!row.activo);
+    assert!(row.desactivado_en.is_some());
+    assert_eq!(Some(app.test_user.user_id), row.desactivado_por);
+    assert_eq!(403, user.login(&app).await.status().as_u16());
+}
+
+#[tokio::test]
+async fn deactivated_user_can_be_restored() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.make_admin(&app.db_pool).await;
+    let token = app.test_user.login_token(&app).await;
+    let user = store_user(&app).await;
+    assert_eq!(200, delete_user(&app, &token, &user).await.status().as_u16());
+
+    // Act
+    let response = restore_user(&app, &token, &user).await;
+
+    // Assert
+    assert_eq!(200, response.status().as_u16());
+    assert_eq!(200, user.login(&app).await.status().as_u16());
+}
+
+#[tokio::test]
+async fn restoring_a_suspended_user_keeps_it_inactive() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.make_admin(&app.db_pool).await;
+    let token = app.test_user.login_token(&app).await;
+    let user = store_user(&app).await;
+    sqlx::query!("UPDATE usuarios SET activo = false WHERE usuario_id = $1", user.user_id)
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(200, delete_user(&app, &token, &user).await.status().as_u16());
+
+    // Act
+    let response = restore_user(&app, &token, &user).await;
+
+    // Assert
+    assert_eq!(200, response.status().as_u16());
+    let row = sqlx::query!(
+        "SELECT activo, desactivado_en FROM usuarios WHERE usuario_id = $1",
+        user.user_id,
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    assert!(!row.activo);
+    assert!(row.desactivado_en.is_none());
+}
+
+#[tokio::test]
+async fn restoring_an_active_user_returns_409() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.make_admin(&app.db_pool).await;
+    let token = app.test_user.login_token(&app).await;
+    let user = store_user(&app).await;
+
+    // Act
+    let response = restore_user(&app, &token, &user).await;
+
+    // Assert
+    assert_eq!(409, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn users_past_the_retention_window_are_anonymized() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.make_admin(&app.db_pool).await;
+    let token = app.test_user.login_token(&app).await;
+    let user = store_user(&app).await;
+    assert_eq!(200, delete_user(&app, &token, &user).await.status().as_u16());
+    sqlx::query!(
+        "UPDATE usuarios SET desactivado_en = now() - interval '31 days' WHERE usuario_id = $1",
+        user.user_id,
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+
+    // Act
+    let anonimizados = anonimizar_usuarios_vencidos(&app.db_pool, 30).await.unwrap();
+
+    // Assert
+    assert_eq!(1, anonimizados);
+    let row = sqlx::query!(
+        "SELECT nombres, email, anonimizado_en FROM usuarios WHERE usuario_id = $1",
+        user.user_id,
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .expect("The anonymized user must keep its row");
+    assert_ne!(user.first_name, row.nombres);
+    assert_ne!(user.email, row.email);
+    assert!(row.anonimizado_en.is_some());
+    // Ya no se puede restaurar
+    assert_eq!(409, restore_user(&app, &token, &user).await.status().as_u16());
+}
+
+#[tokio::test]
+async fn anonymizing_a_user_blanks_the_license_image_of_its_requests() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.make_admin(&app.db_pool).await;
+    let token = app.test_user.login_token(&app).await;
+    let user = store_user(&app).await;
+    let peticion_id = Uuid::new_v4();
+    sqlx::query!(
+        r#"
+        INSERT INTO peticiones (peticion_id, vehiculo_id, usuario_id, inicio, finalizo, kilometraje_inicial, kilometraje_final, usuario_licencia_imagen)
+        VALUES ($1, 'fefa3ab9-2ad0-4c01-9959-c18bce2f5aed', $2, now(), now(), 100, 200, 'licencia.png')
+        "#,
+        peticion_id,
+        user.user_id,
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(200, delete_user(&app, &token, &user).await.status().as_u16());
+    sqlx::query!(
+        "UPDATE usuarios SET desactivado_en = now() - interval '31 days' WHERE usuario_id = $1",
+        user.user_id,
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+
+    // Act
+    anonimizar_usuarios_vencidos(&app.db_pool, 30).await.unwrap();
+
+    // Assert
+    let row = sqlx::query!(
+        "SELECT usuario_licencia_imagen FROM peticiones WHERE peticion_id = $1",
+        peticion_id,
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .expect("The request must be kept");
+    assert_eq!("", row.usuario_licencia_imagen);
+}
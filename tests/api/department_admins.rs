@@ -0,0 +1,269 @@
+use uuid::Uuid;
+
+use crate::helpers::{spawn_app, TestApp, TestUser};
+
+
+const VEHICULO_ID: &str = "fefa3ab9-2ad0-4c01-9959-c18bce2f5aed";
+
+async fn member_of(app: &TestApp, id: i32) -> TestUser {
+    let user = TestUser::generate();
+    user.store(&app.db_pool).await;
+    sqlx::query!("UPDATE usuarios SET departamento = $1 WHERE usuario_id = $2", id, user.user_id)
+        .execute(&app.db_pool)
+        .await
+        .expect("Failed to set the department");
+    user
+}
+
+/// Miembro del departamento que ademas lo administra
+async fn admin_of(app: &TestApp, id: i32) -> (TestUser, String) {
+    let user = member_of(app, id).await;
+    sqlx::query!(
+        "INSERT INTO administradores_departamento (departamento_id, usuario_id) VALUES ($1, $2)",
+        id,
+        user.user_id,
+    )
+    .execute(&app.db_pool)
+    .await
+    .expect("Failed to make the department admin");
+    let token = user.login_token(app).await;
+    (user, token)
+}
+
+async fn get_user(app: &TestApp, token: &str, user: &TestUser) -> reqwest::Response {
+    app.api_client
+        .get(format!("{}/api/users/{}", &app.address, user.user_id))
+        .bearer_auth(token)
+        .send()
+        .await
+        .expect("Failed to execute request")
+}
+
+
+#[tokio::test]
+async fn department_admin_only_lists_users_of_their_department() {
+    // Arrange
+    let app = spawn_app().await;
+    let (_, token) = admin_of(&app, 1).await;
+    let propio = member_of(&app, 1).await;
+    let ajeno = member_of(&app, 2).await;
+
+    // Act
+    let response = app.api_client
+        .get(format!("{}/api/users?limite=100", &app.address))
+        .bearer_auth(&token)
+        .send()
+        .await
+        .expect("Failed to execute request");
+
+    // Assert
+    assert_eq!(200, response.status().as_u16());
+    let body: serde_json::Value = response.json().await.unwrap();
+    let emails: Vec<&str> = body["data"].as_array().unwrap()
+        .iter()
+        .map(|u| u["email"].as_str().unwrap())
+        .collect();
+    assert!(emails.contains(&propio.email.as_str()));
+    assert!(!emails.contains(&ajeno.email.as_str()));
+}
+
+#[tokio::test]
+async fn department_admin_cannot_see_or_delete_users_of_other_departments() {
+    // Arrange
+    let app = spawn_app().await;
+    let (_, token) = admin_of(&app, 1).await;
+    let propio = member_of(&app, 1).await;
+    let ajeno = member_of(&app, 2).await;
+
+    // Act
+    let response_propio = get_user(&app, &token, &propio).await;
+    let response_ajeno = get_user(&app, &token, &ajeno).await;
+    let response_borrar = app.api_client
+        .delete(format!("{}/api/users/{}", &app.address, ajeno.user_id))
+        .bearer_auth(&token)
+        .send()
+        .await
+        .expect("Failed to execute request");
+
+    // Assert
+    assert_eq!(200, response_propio.status().as_u16());
+    assert_eq!(404, response_ajeno.status().as_u16());
+    assert_eq!(404, response_borrar.status().as_u16());
+    let activo = sqlx::query!("SELECT activo FROM usuarios WHERE usuario_id = $1", ajeno.user_id)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .activo;
+    assert!(activo);
+}
+
+#[tokio::test]
+async fn department_admin_cannot_move_users_out_of_their_scope() {
+    // Arrange
+    let app = spawn_app().await;
+    let (_, token) = admin_of(&app, 1).await;
+    let propio = member_of(&app, 1).await;
+    let destino = sqlx::query!("SELECT nombre FROM departamentos WHERE id = 2")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .nombre;
+
+    // Act
+    let response = app.api_client
+        .patch(format!("{}/api/users/{}", &app.address, propio.user_id))
+        .bearer_auth(&token)
+        .json(&serde_json::json!({ "departamento": destino }))
+        .send()
+        .await
+        .expect("Failed to execute request");
+
+    // Assert
+    assert_eq!(403, response.status().as_u16());
+    let departamento = sqlx::query!("SELECT departamento FROM usuarios WHERE usuario_id = $1", propio.user_id)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .departamento;
+    assert_eq!(Some(1), departamento);
+}
+
+#[tokio::test]
+async fn department_admin_only_manages_vehicles_of_their_department() {
+    // Arrange
+    let app = spawn_app().await;
+    let (_, token) = admin_of(&app, 1).await;
+    let vehiculo_id: Uuid = VEHICULO_ID.parse().unwrap();
+    sqlx::query!("UPDATE vehiculos SET departamento = 2 WHERE vehiculo_id = $1", vehiculo_id)
+        .execute(&app.db_pool)
+        .await
+        .expect("Failed to set the vehicle department");
+
+    // Act
+    let response_borrar = app.api_client
+        .delete(format!("{}/api/vehicules/{}", &app.address, vehiculo_id))
+        .bearer_auth(&token)
+        .send()
+        .await
+        .expect("Failed to execute request");
+    let response_mover = app.api_client
+        .patch(format!("{}/api/vehicules/{}/department", &app.address, vehiculo_id))
+        .bearer_auth(&token)
+        .json(&serde_json::json!({ "departamento": 1 }))
+        .send()
+        .await
+        .expect("Failed to execute request");
+
+    // Assert
+    assert_eq!(404, response_borrar.status().as_u16());
+    assert_eq!(404, response_mover.status().as_u16());
+    let departamento = sqlx::query!("SELECT departamento FROM vehiculos WHERE vehiculo_id = $1", vehiculo_id)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .departamento;
+    assert_eq!(Some(2), departamento);
+}
+
+#[tokio::test]
+async fn department_admin_only_approves_requests_of_their_department() {
+    // Arrange
+    let app = spawn_app().await;
+    let (_, token_ajeno) = admin_of(&app, 2).await;
+    let (_, token_propio) = admin_of(&app, 1).await;
+    let solicitante = member_of(&app, 1).await;
+    let peticion_id = Uuid::new_v4();
+    let inicio = chrono::Utc::now().naive_utc() + chrono::Duration::days(1);
+    sqlx::query!(
+        r#"
+        INSERT INTO peticiones
+        (peticion_id, usuario_id, vehiculo_id, departamento_id, inicio, finalizo,
+         kilometraje_inicial, kilometraje_final, usuario_licencia_imagen)
+        VALUES ($1, $2, $3, 1, $4, $5, 120000, 120000, 'licencia.jpeg')
+        "#,
+        peticion_id,
+        solicitante.user_id,
+        VEHICULO_ID.parse::<Uuid>().unwrap(),
+        inicio,
+        inicio + chrono::Duration::hours(4),
+    )
+    .execute(&app.db_pool)
+    .await
+    .expect("Failed to store the request");
+
+    // Act
+    let response_ver = app.api_client
+        .get(format!("{}/api/requests/{}/approvals", &app.address, peticion_id))
+        .bearer_auth(&token_ajeno)
+        .send()
+        .await
+        .expect("Failed to execute request");
+    let response_ajeno = app.api_client
+        .post(format!("{}/api/requests/{}/approve", &app.address, peticion_id))
+        .bearer_auth(&token_ajeno)
+        .send()
+        .await
+        .expect("Failed to execute request");
+    let response_propio = app.api_client
+        .post(format!("{}/api/requests/{}/approve", &app.address, peticion_id))
+        .bearer_auth(&token_propio)
+        .send()
+        .await
+        .expect("Failed to execute request");
+
+    // Assert
+    assert_eq!(403, response_ver.status().as_u16());
+    assert_eq!(403, response_ajeno.status().as_u16());
+    assert_eq!(200, response_propio.status().as_u16());
+}
+
+#[tokio::test]
+async fn department_admin_cannot_unlock_or_log_out_a_global_admin() {
+    // Arrange
+    let app = spawn_app().await;
+    let (_, token) = admin_of(&app, 1).await;
+    let global = member_of(&app, 1).await;
+    global.make_admin(&app.db_pool).await;
+
+    // Act
+    let response_unlock = app.api_client
+        .post(format!("{}/api/users/{}/unlock", &app.address, global.user_id))
+        .bearer_auth(&token)
+        .send()
+        .await
+        .expect("Failed to execute request");
+    let response_sessions = app.api_client
+        .delete(format!("{}/api/users/{}/sessions", &app.address, global.user_id))
+        .bearer_auth(&token)
+        .send()
+        .await
+        .expect("Failed to execute request");
+
+    // Assert
+    assert_eq!(403, response_unlock.status().as_u16());
+    assert_eq!(403, response_sessions.status().as_u16());
+}
+
+#[tokio::test]
+async fn removing_a_department_admin_drops_the_cached_scope() {
+    // Arrange
+    let app = spawn_app().await;
+    let (admin, token) = admin_of(&app, 1).await;
+    let propio = member_of(&app, 1).await;
+    app.test_user.make_admin(&app.db_pool).await;
+    let global_token = app.test_user.login_token(&app).await;
+    // Deja el alcance en el cache
+    assert_eq!(200, get_user(&app, &token, &propio).await.status().as_u16());
+
+    // Act
+    let response = app.api_client
+        .delete(format!("{}/api/departments/1/admins/{}", &app.address, admin.user_id))
+        .bearer_auth(&global_token)
+        .send()
+        .await
+        .expect("Failed to execute request");
+
+    // Assert
+    assert_eq!(200, response.status().as_u16());
+    assert_eq!(403, get_user(&app, &token, &propio).await.status().as_u16());
+}
@@ -0,0 +1,161 @@
+use uuid::Uuid;
+
+use crate::helpers::{spawn_app, TestApp, TestUser};
+
+
+/// Usuario del departamento 1 con licencia verificada
+async fn licensed_member(app: &TestApp) -> TestUser {
+    let user = TestUser::generate();
+    user.store(&app.db_pool).await;
+    sqlx::query!("UPDATE usuarios SET departamento = 1 WHERE usuario_id = $1", user.user_id)
+        .execute(&app.db_pool)
+        .await
+        .expect("Failed to set the department");
+    sqlx::query!(
+        r#"
+        INSERT INTO licencias
+        (licencia_id, usuario_id, numero, clase, estado_emisor, expira_en,
+         imagen_frente, imagen_reverso, verificada_en)
+        VALUES ($1, $2, 'A1', 'B', 'Jalisco', now() + interval '1 year', 'f.jpeg', 'r.jpeg', now())
+        "#,
+        Uuid::new_v4(),
+        user.user_id,
+    )
+    .execute(&app.db_pool)
+    .await
+    .expect("Failed to store the license");
+
+    user
+}
+
+async fn set_quota(app: &TestApp, quota: serde_json::Value) {
+    let admin = TestUser::generate();
+    admin.store(&app.db_pool).await;
+    admin.make_admin(&app.db_pool).await;
+    let token = admin.login_token(app).await;
+
+    let response = app.api_client
+        .patch(format!("{}/api/departments/1/quota", &app.address))
+        .bearer_auth(&token)
+        .json(&quota)
+        .send()
+        .await
+        .expect("Failed to execute request");
+    assert_eq!(200, response.status().as_u16());
+}
+
+async fn new_request(app: &TestApp, token: &str, distancia_km: i32) -> reqwest::Response {
+    let inicio = chrono::Utc::now().naive_utc() + chrono::Duration::hours(1);
+    app.api_client
+        .post(format!("{}/api/requests/new/fefa3ab9-2ad0-4c01-9959-c18bce2f5aed", &app.address))
+        .bearer_auth(token)
+        .json(&serde_json::json!({
+            "inicio": inicio,
+            "finalizo": inicio + chrono::Duration::hours(2),
+            "kilometraje_inicial": 120000,
+            "usuario_licencia_imagen": "licencia.jpeg",
+            "distancia_estimada_km": distancia_km,
+        }))
+        .send()
+        .await
+        .expect("Failed to execute request")
+}
+
+
+#[tokio::test]
+async fn blocking_quota_rejects_requests_over_the_monthly_km() {
+    // Arrange
+    let app = spawn_app().await;
+    let user = licensed_member(&app).await;
+    set_quota(&app, serde_json::json!({ "km_max": 100, "modo": "bloquear" })).await;
+    let token = user.login_token(&app).await;
+
+    // Act
+    let response = new_request(&app, &token, 150).await;
+
+    // Assert
+    assert_eq!(409, response.status().as_u16());
+    let count = sqlx::query!(r#"SELECT count(*) as "count!" FROM peticiones WHERE usuario_id = $1"#, user.user_id)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(0, count);
+}
+
+#[tokio::test]
+async fn reversed_interval_is_rejected_before_the_quota() {
+    // Arrange
+    let app = spawn_app().await;
+    let user = licensed_member(&app).await;
+    set_quota(&app, serde_json::json!({ "horas_max": 1, "modo": "bloquear" })).await;
+    let token = user.login_token(&app).await;
+    let inicio = chrono::Utc::now().naive_utc() + chrono::Duration::hours(10);
+
+    // Act
+    let response = app.api_client
+        .post(format!("{}/api/requests/new/fefa3ab9-2ad0-4c01-9959-c18bce2f5aed", &app.address))
+        .bearer_auth(&token)
+        .json(&serde_json::json!({
+            "inicio": inicio,
+            "finalizo": inicio - chrono::Duration::hours(8),
+            "kilometraje_inicial": 120000,
+            "usuario_licencia_imagen": "licencia.jpeg",
+            "distancia_estimada_km": 10,
+        }))
+        .send()
+        .await
+        .expect("Failed to execute request");
+
+    // Assert
+    assert_eq!(400, response.status().as_u16());
+    let count = sqlx::query!(r#"SELECT count(*) as "count!" FROM peticiones WHERE usuario_id = $1"#, user.user_id)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(0, count);
+}
+
+#[tokio::test]
+async fn warning_quota_creates_the_request_with_a_warning() {
+    // Arrange
+    let app = spawn_app().await;
+    let user = licensed_member(&app).await;
+    set_quota(&app, serde_json::json!({ "km_max": 100, "modo": "advertir" })).await;
+    let token = user.login_token(&app).await;
+
+    // Act
+    let response = new_request(&app, &token, 150).await;
+
+    // Assert
+    assert_eq!(200, response.status().as_u16());
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert!(body["message"].as_str().unwrap().contains("150 de 100 km"));
+    let peticion_id: Uuid = body["data"]["peticion_id"].as_str().unwrap().parse().unwrap();
+    let departamento_id = sqlx::query!("SELECT departamento_id FROM peticiones WHERE peticion_id = $1", peticion_id)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .departamento_id;
+    assert_eq!(Some(1), departamento_id);
+}
+
+#[tokio::test]
+async fn department_usage_is_only_visible_to_admins_and_heads() {
+    // Arrange
+    let app = spawn_app().await;
+    let user = licensed_member(&app).await;
+    let token = user.login_token(&app).await;
+
+    // Act
+    let response = app.api_client
+        .get(format!("{}/api/departments/1/usage", &app.address))
+        .bearer_auth(&token)
+        .send()
+        .await
+        .expect("Failed to execute request");
+
+    // Assert
+    assert_eq!(403, response.status().as_u16());
+}
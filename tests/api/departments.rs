@@ -0,0 +1,268 @@
+use crate::helpers::{spawn_app, TestApp, TestUser};
+
+
+async fn patch_hierarchy(app: &TestApp, token: &str, id: i32, body: serde_json::Value) -> reqwest::Response {
+    app.api_client
+        .patch(format!("{}/api/departments/{}/hierarchy", &app.address, id))
+        .bearer_auth(token)
+        .json(&body)
+        .send()
+        .await
+        .expect("Failed to execute request")
+}
+
+async fn set_department(app: &TestApp, user: &TestUser, id: i32) {
+    sqlx::query!("UPDATE usuarios SET departamento = $1 WHERE usuario_id = $2", id, user.user_id)
+        .execute(&app.db_pool)
+        .await
+        .expect("Failed to set the department");
+}
+
+
+#[tokio::test]
+async fn tree_nests_children_under_their_parent() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.make_admin(&app.db_pool).await;
+    let token = app.test_user.login_token(&app).await;
+    let response = patch_hierarchy(&app, &token, 2, serde_json::json!({
+        "padre_id": 1,
+        "centro_costos": "CC-200",
+    })).await;
+    assert_eq!(200, response.status().as_u16());
+
+    // Act
+    let response = app.api_client
+        .get(format!("{}/api/departments/tree", &app.address))
+        .bearer_auth(&token)
+        .send()
+        .await
+        .expect("Failed to execute request");
+
+    // Assert
+    assert_eq!(200, response.status().as_u16());
+    let body: serde_json::Value = response.json().await.unwrap();
+    let raiz = body["data"].as_array().unwrap()
+        .iter()
+        .find(|nodo| nodo["id"] == 1)
+        .unwrap();
+    assert_eq!(2, raiz["hijos"][0]["id"]);
+    assert_eq!("CC-200", raiz["hijos"][0]["centro_costos"]);
+    assert!(body["data"].as_array().unwrap().iter().all(|nodo| nodo["id"] != 2));
+}
+
+#[tokio::test]
+async fn department_can_not_depend_on_its_descendants() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.make_admin(&app.db_pool).await;
+    let token = app.test_user.login_token(&app).await;
+    let response = patch_hierarchy(&app, &token, 2, serde_json::json!({ "padre_id": 1 })).await;
+    assert_eq!(200, response.status().as_u16());
+
+    // Act
+    let response = patch_hierarchy(&app, &token, 1, serde_json::json!({ "padre_id": 2 })).await;
+
+    // Assert
+    assert_eq!(409, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn heads_see_the_members_of_child_departments() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.make_admin(&app.db_pool).await;
+    let admin_token = app.test_user.login_token(&app).await;
+    patch_hierarchy(&app, &admin_token, 2, serde_json::json!({ "padre_id": 1 })).await;
+
+    let jefe = TestUser::generate();
+    jefe.store(&app.db_pool).await;
+    set_department(&app, &jefe, 1).await;
+    let response = app.api_client
+        .post(format!("{}/api/departments/1/heads/{}", &app.address, jefe.user_id))
+        .bearer_auth(&admin_token)
+        .send()
+        .await
+        .expect("Failed to execute request");
+    assert_eq!(200, response.status().as_u16());
+
+    let miembro = TestUser::generate();
+    miembro.store(&app.db_pool).await;
+    set_department(&app, &miembro, 2).await;
+    let token = jefe.login_token(&app).await;
+
+    // Act
+    let response = app.api_client
+        .get(format!("{}/api/departments/1/members?subdepartamentos=true", &app.address))
+        .bearer_auth(&token)
+        .send()
+        .await
+        .expect("Failed to execute request");
+
+    // Assert
+    assert_eq!(200, response.status().as_u16());
+    let body: serde_json::Value = response.json().await.unwrap();
+    let miembros = body["data"].as_array().unwrap();
+    assert_eq!(2, miembros.len());
+    assert!(miembros.iter().any(|m| m["email"] == jefe.email && m["es_jefe"] == true));
+    assert!(miembros.iter().any(|m| m["email"] == miembro.email && m["departamento_id"] == 2));
+}
+
+#[tokio::test]
+async fn members_are_hidden_from_users_who_are_not_heads() {
+    // Arrange
+    let app = spawn_app().await;
+    let token = app.test_user.login_token(&app).await;
+
+    // Act
+    let response = app.api_client
+        .get(format!("{}/api/departments/1/members", &app.address))
+        .bearer_auth(&token)
+        .send()
+        .await
+        .expect("Failed to execute request");
+
+    // Assert
+    assert_eq!(403, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn department_with_members_is_not_deleted_without_a_target() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.make_admin(&app.db_pool).await;
+    let token = app.test_user.login_token(&app).await;
+    let miembro = TestUser::generate();
+    miembro.store(&app.db_pool).await;
+    set_department(&app, &miembro, 3).await;
+
+    // Act
+    let response = app.api_client
+        .delete(format!("{}/api/departments/3", &app.address))
+        .bearer_auth(&token)
+        .send()
+        .await
+        .expect("Failed to execute request");
+
+    // Assert
+    assert_eq!(409, response.status().as_u16());
+    let departamento = sqlx::query!("SELECT departamento FROM usuarios WHERE usuario_id = $1", miembro.user_id)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .departamento;
+    assert_eq!(Some(3), departamento);
+}
+
+#[tokio::test]
+async fn deleting_a_department_moves_its_members_to_the_target() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.make_admin(&app.db_pool).await;
+    let token = app.test_user.login_token(&app).await;
+    let miembro = TestUser::generate();
+    miembro.store(&app.db_pool).await;
+    set_department(&app, &miembro, 3).await;
+
+    // Act
+    let response = app.api_client
+        .delete(format!("{}/api/departments/3?destino=2", &app.address))
+        .bearer_auth(&token)
+        .send()
+        .await
+        .expect("Failed to execute request");
+
+    // Assert
+    assert_eq!(200, response.status().as_u16());
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(1, body["data"]["miembros"]);
+    let departamento = sqlx::query!("SELECT departamento FROM usuarios WHERE usuario_id = $1", miembro.user_id)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .departamento;
+    assert_eq!(Some(2), departamento);
+}
+
+#[tokio::test]
+async fn merge_moves_heads_and_children_to_the_target() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.make_admin(&app.db_pool).await;
+    let token = app.test_user.login_token(&app).await;
+    patch_hierarchy(&app, &token, 3, serde_json::json!({ "padre_id": 2 })).await;
+    let jefe = TestUser::generate();
+    jefe.store(&app.db_pool).await;
+    app.api_client
+        .post(format!("{}/api/departments/2/heads/{}", &app.address, jefe.user_id))
+        .bearer_auth(&token)
+        .send()
+        .await
+        .expect("Failed to execute request");
+
+    // Act
+    let response = app.api_client
+        .post(format!("{}/api/departments/2/merge", &app.address))
+        .bearer_auth(&token)
+        .json(&serde_json::json!({ "destino": 1 }))
+        .send()
+        .await
+        .expect("Failed to execute request");
+
+    // Assert
+    assert_eq!(200, response.status().as_u16());
+    let response = app.api_client
+        .get(format!("{}/api/departments/1", &app.address))
+        .bearer_auth(&token)
+        .send()
+        .await
+        .expect("Failed to execute request");
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(jefe.user_id.to_string(), body["data"]["jefes"][0]);
+    let padre_id = sqlx::query!("SELECT padre_id FROM departamentos WHERE id = 3")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .padre_id;
+    assert_eq!(Some(1), padre_id);
+}
+
+#[tokio::test]
+async fn deleting_a_department_moves_its_workflows_to_the_target() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.make_admin(&app.db_pool).await;
+    let token = app.test_user.login_token(&app).await;
+    let response = app.api_client
+        .post(format!("{}/api/workflows", &app.address))
+        .bearer_auth(&token)
+        .json(&serde_json::json!({
+            "nombre": "Flujo del 3",
+            "departamento_id": 3,
+            "pasos": [{ "tipo": "admin" }],
+        }))
+        .send()
+        .await
+        .expect("Failed to execute request");
+    assert_eq!(200, response.status().as_u16());
+    let body: serde_json::Value = response.json().await.unwrap();
+    let flujo_id: uuid::Uuid = body["data"]["flujo_id"].as_str().unwrap().parse().unwrap();
+    let delete = |path: &'static str| app.api_client
+        .delete(format!("{}/api/departments/{}", &app.address, path))
+        .bearer_auth(&token)
+        .send();
+
+    // Act
+    let without_target = delete("3").await.expect("Failed to execute request");
+    let response = delete("3?destino=2").await.expect("Failed to execute request");
+
+    // Assert
+    assert_eq!(409, without_target.status().as_u16());
+    assert_eq!(200, response.status().as_u16());
+    let departamento_id = sqlx::query!("SELECT departamento_id FROM flujos_aprobacion WHERE flujo_id = $1", flujo_id)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .departamento_id;
+    assert_eq!(Some(2), departamento_id);
+}
@@ -0,0 +1,165 @@
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
+
+use crate::helpers::{spawn_app, TestApp, TestUser};
+
+
+async fn post_email_change(app: &TestApp, token: &str, body: &serde_json::Value) -> reqwest::Response {
+    app.api_client
+        .post(format!("{}/api/users/me/email", &app.address))
+        .bearer_auth(token)
+        .json(body)
+        .send()
+        .await
+        .expect("Failed to execute request")
+}
+
+async fn confirm_email_change(app: &TestApp, change_token: &str) -> reqwest::Response {
+    app.api_client
+        .get(format!("{}/api/auth/email-change/confirm?token={}", &app.address, change_token))
+        .send()
+        .await
+        .expect("Failed to execute request")
+}
+
+/// Requests a change to `email` and returns a known confirmation token
+async fn request_change(app: &TestApp, email: &str) -> String {
+    let token = app.test_user.login_token(app).await;
+
+    let response = post_email_change(app, &token, &serde_json::json!({
+        "email": email,
+        "password": &app.test_user.password,
+    })).await;
+    assert_eq!(202, response.status().as_u16());
+
+    // The real token only travels by email, replace it with a known one
+    let change_token = Uuid::new_v4().to_string();
+    sqlx::query!(
+        "UPDATE cambios_email SET token_hash = $2 WHERE usuario_id = $1 AND confirmado_en IS NULL",
+        app.test_user.user_id,
+        format!("{:x}", Sha256::digest(change_token.as_bytes())),
+    )
+    .execute(&app.db_pool)
+    .await
+    .expect("Failed to update email change token");
+
+    change_token
+}
+
+
+#[tokio::test]
+async fn email_change_requires_the_current_password() {
+    // Arrange
+    let app = spawn_app().await;
+    let token = app.test_user.login_token(&app).await;
+
+    // Act
+    let response = post_email_change(&app, &token, &serde_json::json!({
+        "email": "nuevo@example.com",
+        "password": "not-my-password",
+    })).await;
+
+    // Assert
+    assert_eq!(400, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn email_change_to_an_existing_email_returns_409() {
+    // Arrange
+    let app = spawn_app().await;
+    let other_user = TestUser::generate();
+    other_user.store(&app.db_pool).await;
+    let token = app.test_user.login_token(&app).await;
+
+    // Act
+    let response = post_email_change(&app, &token, &serde_json::json!({
+        "email": other_user.email.to_uppercase(),
+        "password": &app.test_user.password,
+    })).await;
+
+    // Assert
+    assert_eq!(409, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn old_email_works_until_the_change_is_confirmed() {
+    // Arrange
+    let app = spawn_app().await;
+    let change_token = request_change(&app, "nuevo@example.com").await;
+
+    // Act - Part 1 - Pending change
+    let response = app.test_user.login(&app).await;
+
+    // Assert - Part 1
+    assert_eq!(200, response.status().as_u16());
+
+    // Act - Part 2 - Confirm
+    let response = confirm_email_change(&app, &change_token).await;
+    assert_eq!(200, response.status().as_u16());
+
+    // Assert - Part 2
+    let old_login = app.test_user.login(&app).await;
+    assert_eq!(401, old_login.status().as_u16());
+
+    let new_login = app.post_login(&serde_json::json!({
+        "email": "nuevo@example.com",
+        "password": &app.test_user.password,
+    })).await;
+    assert_eq!(200, new_login.status().as_u16());
+}
+
+#[tokio::test]
+async fn email_change_link_is_single_use() {
+    // Arrange
+    let app = spawn_app().await;
+    let change_token = request_change(&app, "nuevo@example.com").await;
+    assert_eq!(200, confirm_email_change(&app, &change_token).await.status().as_u16());
+
+    // Act
+    let response = confirm_email_change(&app, &change_token).await;
+
+    // Assert
+    assert_eq!(400, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn confirming_an_email_taken_in_the_meantime_returns_409() {
+    // Arrange
+    let app = spawn_app().await;
+    let change_token = request_change(&app, "nuevo@example.com").await;
+
+    let other_user = TestUser {
+        email: "nuevo@example.com".to_string(),
+        ..TestUser::generate()
+    };
+    other_user.store(&app.db_pool).await;
+
+    // Act
+    let response = confirm_email_change(&app, &change_token).await;
+
+    // Assert
+    assert_eq!(409, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn admin_patch_to_an_existing_email_returns_409() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.make_admin(&app.db_pool).await;
+    let token = app.test_user.login_token(&app).await;
+
+    let other_user = TestUser::generate();
+    other_user.store(&app.db_pool).await;
+
+    // Act
+    let response = app.api_client
+        .patch(format!("{}/api/users/{}", &app.address, other_user.user_id))
+        .bearer_auth(&token)
+        .json(&serde_json::json!({ "email": &app.test_user.email }))
+        .send()
+        .await
+        .expect("Failed to execute request");
+
+    // Assert
+    assert_eq!(409, response.status().as_u16());
+}
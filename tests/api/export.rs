@@ -0,0 +1,135 @@
+use std::io::Cursor;
+
+use control_parque_vehicular::configuration::UserExportSettings;
+use control_parque_vehicular::export_worker::{
+    expirar_exportaciones, hash_token, try_execute_task, ExecutionOutcome,
+};
+
+use crate::helpers::{spawn_app, TestApp};
+
+
+async fn post_export(app: &TestApp, token: &str) -> reqwest::Response {
+    app.api_client
+        .post(format!("{}/api/users/me/export", &app.address))
+        .bearer_auth(token)
+        .send()
+        .await
+        .expect("Failed to execute request")
+}
+
+async fn get_download(app: &TestApp, token: &str) -> reqwest::Response {
+    app.api_client
+        .get(format!("{}/api/auth/export/download?token={}", &app.address, token))
+        .send()
+        .await
+        .expect("Failed to execute request")
+}
+
+async fn process_all_pending_exports(app: &TestApp) {
+    let settings = UserExportSettings { link_expiration_hours: 48, worker_interval_seconds: 10 };
+    loop {
+        if let ExecutionOutcome::EmptyQueue =
+            try_execute_task(&app.db_pool, &app.email_client, &app.address, &settings)
+                .await
+                .unwrap()
+        {
+            break;
+        }
+    }
+}
+
+/// El token real solo llega por correo, se reemplaza por uno conocido
+async fn set_download_token(app: &TestApp, token: &str) {
+    sqlx::query!(
+        "UPDATE exportaciones SET token_hash = $1 WHERE usuario_id = $2",
+        hash_token(token),
+        app.test_user.user_id,
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+}
+
+
+#[tokio::test]
+async fn only_one_export_can_be_pending_at_a_time() {
+    // Arrange
+    let app = spawn_app().await;
+    let token = app.test_user.login_token(&app).await;
+    assert_eq!(202, post_export(&app, &token).await.status().as_u16());
+
+    // Act
+    let response = post_export(&app, &token).await;
+
+    // Assert
+    assert_eq!(409, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn ready_export_is_a_zip_with_the_user_data() {
+    // Arrange
+    let app = spawn_app().await;
+    let token = app.test_user.login_token(&app).await;
+    assert_eq!(202, post_export(&app, &token).await.status().as_u16());
+    process_all_pending_exports(&app).await;
+    set_download_token(&app, "token-de-prueba").await;
+
+    // Act
+    let response = get_download(&app, "token-de-prueba").await;
+
+    // Assert
+    assert_eq!(200, response.status().as_u16());
+    let bytes = response.bytes().await.unwrap();
+    let mut zip = zip::ZipArchive::new(Cursor::new(bytes)).expect("The download is not a zip");
+    for nombre in ["perfil.json", "peticiones.json", "inicios_sesion.json"] {
+        assert!(zip.by_name(nombre).is_ok(), "The export is missing {}", nombre);
+    }
+    let perfil: serde_json::Value = serde_json::from_reader(zip.by_name("perfil.json").unwrap()).unwrap();
+    assert_eq!(app.test_user.email, perfil["email"]);
+}
+
+#[tokio::test]
+async fn export_status_is_ready_after_the_worker_runs() {
+    // Arrange
+    let app = spawn_app().await;
+    let token = app.test_user.login_token(&app).await;
+    assert_eq!(202, post_export(&app, &token).await.status().as_u16());
+
+    // Act
+    process_all_pending_exports(&app).await;
+
+    // Assert
+    let response = app.api_client
+        .get(format!("{}/api/users/me/export", &app.address))
+        .bearer_auth(&token)
+        .send()
+        .await
+        .expect("Failed to execute request");
+    assert_eq!(200, response.status().as_u16());
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!("lista", body["data"]["estado"]);
+}
+
+#[tokio::test]
+async fn expired_download_link_is_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    let token = app.test_user.login_token(&app).await;
+    assert_eq!(202, post_export(&app, &token).await.status().as_u16());
+    process_all_pending_exports(&app).await;
+    set_download_token(&app, "token-de-prueba").await;
+    sqlx::query!(
+        "UPDATE exportaciones SET expira_en = now() - interval '1 hour' WHERE usuario_id = $1",
+        app.test_user.user_id,
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+
+    // Act
+    let response = get_download(&app, "token-de-prueba").await;
+
+    // Assert
+    assert_eq!(400, response.status().as_u16());
+    assert_eq!(1, expirar_exportaciones(&app.db_pool).await.unwrap());
+}
@@ -2,7 +2,7 @@ use sqlx::{Connection, Executor, PgConnection, PgPool};
 use uuid::Uuid;
 use once_cell::sync::Lazy;
 //use wiremock::MockServer;
-use control_parque_vehicular::configuration::{get_configuration, DatabaseSettings};
+use control_parque_vehicular::configuration::{get_configuration, DatabaseSettings, Settings};
 use control_parque_vehicular::email_client::EmailClient;
 use control_parque_vehicular::startup::{Application, get_connection_pool};
 use control_parque_vehicular::telemetry::{init_subscriber, get_subscriber};
@@ -220,6 +220,14 @@ impl TestApp {
 
 // Launch our application in the background -somehow-
 pub async fn spawn_app() -> TestApp {
+    spawn_app_with(|_| {}).await
+}
+
+/// Same as `spawn_app`, but lets the test adjust the configuration first
+pub async fn spawn_app_with<F>(configure: F) -> TestApp
+where
+    F: FnOnce(&mut Settings),
+{
     // The first time `initialize` is invoked the code `TRACING` is executed
     // All other invocations will instead skip execution.
     Lazy::force(&TRACING);
@@ -236,6 +244,7 @@ pub async fn spawn_app() -> TestApp {
         c.application.port = 0;
         // Use mock server as email API
         //c.email_client.base_url = email_server.uri();
+        configure(&mut c);
         c
     };
 
@@ -262,7 +271,13 @@ pub async fn spawn_app() -> TestApp {
         port: application_port,
         test_user: TestUser::generate(),
         api_client: client,
-        email_client: configuration.email_client.client()
+        email_client: EmailClient::new(
+            configuration.email_client.smtp_host,
+            configuration.email_client.smtp_name,
+            configuration.email_client.smtp_username,
+            configuration.email_client.smtp_password,
+            configuration.email_client.smtp_port,
+        ).expect("Failed to build email client"),
     };
     test_app.test_user.store(&test_app.db_pool).await;
     test_app
@@ -326,8 +341,8 @@ impl TestUser {
         //dbg!(&password_hash);
 
         sqlx::query!(
-            "INSERT INTO users (user_id, first_name, last_name, email, password_hash)
-            VALUES ($1, $2, $3, $4, $5)",
+            "INSERT INTO usuarios (usuario_id, nombres, apellidos, email, password_hash, verificado)
+            VALUES ($1, $2, $3, $4, $5, true)",
             self.user_id,
             self.first_name,
             self.last_name,
@@ -339,6 +354,13 @@ impl TestUser {
             .expect("Failed to create test users.");
     }
 
+    pub async fn make_admin(&self, pool: &PgPool) {
+        sqlx::query!("UPDATE usuarios SET rol = 'admin' WHERE usuario_id = $1", self.user_id)
+            .execute(pool)
+            .await
+            .expect("Failed to make the test user an admin.");
+    }
+
     pub async fn login(&self, app: &TestApp) -> reqwest::Response {
         app.post_login(&serde_json::json!({
             "email": &self.email,
@@ -346,6 +368,13 @@ impl TestUser {
         }))
         .await
     }
+
+    /// Login and return the JWT from the response data
+    pub async fn login_token(&self, app: &TestApp) -> String {
+        let response = self.login(app).await;
+        let body: serde_json::Value = response.json().await.unwrap();
+        body["data"].as_str().expect("Login did not return a token").to_string()
+    }
 }
 
 pub fn assert_is_a_redirect_to(response: &reqwest::Response, location: &str) {
@@ -0,0 +1,143 @@
+use crate::helpers::{spawn_app, TestApp, TestUser};
+
+
+async fn post_impersonate(app: &TestApp, token: &str, user: &TestUser) -> reqwest::Response {
+    app.api_client
+        .post(format!("{}/api/users/{}/impersonate", &app.address, user.user_id))
+        .bearer_auth(token)
+        .json(&serde_json::json!({ "motivo": "No ve su reservacion" }))
+        .send()
+        .await
+        .expect("Failed to execute request")
+}
+
+/// Token del administrador de prueba actuando como un usuario nuevo
+async fn impersonation_token(app: &TestApp) -> (TestUser, String) {
+    app.test_user.make_admin(&app.db_pool).await;
+    let admin_token = app.test_user.login_token(app).await;
+    let user = TestUser::generate();
+    user.store(&app.db_pool).await;
+
+    let response = post_impersonate(app, &admin_token, &user).await;
+    assert_eq!(200, response.status().as_u16());
+    let body: serde_json::Value = response.json().await.unwrap();
+    let token = body["data"]["token"].as_str().unwrap().to_string();
+
+    (user, token)
+}
+
+
+#[tokio::test]
+async fn only_admins_can_impersonate() {
+    // Arrange
+    let app = spawn_app().await;
+    let token = app.test_user.login_token(&app).await;
+    let user = TestUser::generate();
+    user.store(&app.db_pool).await;
+
+    // Act
+    let response = post_impersonate(&app, &token, &user).await;
+
+    // Assert
+    assert_eq!(403, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn impersonation_token_acts_as_the_user_with_an_act_claim() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let (user, token) = impersonation_token(&app).await;
+
+    // Assert
+    let payload = token.split('.').nth(1).unwrap();
+    let claims: serde_json::Value = serde_json::from_slice(
+        &base64::decode_config(payload, base64::URL_SAFE_NO_PAD).unwrap()
+    ).unwrap();
+    assert_eq!(user.user_id.to_string(), claims["sub"]);
+    assert_eq!(app.test_user.user_id.to_string(), claims["act"]["sub"]);
+
+    let response = app.api_client
+        .get(format!("{}/api/users/me", &app.address))
+        .bearer_auth(&token)
+        .send()
+        .await
+        .expect("Failed to execute request");
+    assert_eq!(200, response.status().as_u16());
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(user.email, body["data"]["email"]);
+}
+
+#[tokio::test]
+async fn impersonation_token_can_not_change_the_password() {
+    // Arrange
+    let app = spawn_app().await;
+    let (user, token) = impersonation_token(&app).await;
+
+    // Act
+    let response = app.api_client
+        .post(format!("{}/api/users/me/change-password", &app.address))
+        .bearer_auth(&token)
+        .json(&serde_json::json!({
+            "password_actual": &user.password,
+            "password_nuevo": "caballo-bateria-grapa-7",
+        }))
+        .send()
+        .await
+        .expect("Failed to execute request");
+
+    // Assert
+    assert_eq!(403, response.status().as_u16());
+    assert_eq!(200, user.login(&app).await.status().as_u16());
+}
+
+#[tokio::test]
+async fn impersonation_and_its_requests_are_audited() {
+    // Arrange
+    let app = spawn_app().await;
+    let (user, token) = impersonation_token(&app).await;
+
+    // Act
+    app.api_client
+        .get(format!("{}/api/users/me", &app.address))
+        .bearer_auth(&token)
+        .send()
+        .await
+        .expect("Failed to execute request");
+
+    // Assert
+    let acciones: Vec<String> = sqlx::query!(
+        r#"
+        SELECT accion FROM auditoria
+        WHERE actor_id = $1 AND usuario_id = $2
+        ORDER BY creado_en
+        "#,
+        app.test_user.user_id,
+        user.user_id,
+    )
+    .fetch_all(&app.db_pool)
+    .await
+    .unwrap()
+    .into_iter()
+    .map(|row| row.accion)
+    .collect();
+    assert_eq!(vec!["suplantacion_iniciada", "peticion_suplantada"], acciones);
+}
+
+#[tokio::test]
+async fn admins_can_not_be_impersonated() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.make_admin(&app.db_pool).await;
+    let token = app.test_user.login_token(&app).await;
+    let admin = TestUser::generate();
+    admin.store(&app.db_pool).await;
+    admin.make_admin(&app.db_pool).await;
+
+    // Act
+    let response = post_impersonate(&app, &token, &admin).await;
+
+    // Assert
+    assert_eq!(403, response.status().as_u16());
+}
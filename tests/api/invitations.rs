@@ -0,0 +1,191 @@
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
+
+use crate::helpers::{spawn_app, TestApp};
+
+
+async fn post_invitation(app: &TestApp, token: &str, body: &serde_json::Value) -> reqwest::Response {
+    app.api_client
+        .post(format!("{}/api/invitations", &app.address))
+        .bearer_auth(token)
+        .json(body)
+        .send()
+        .await
+        .expect("Failed to execute request")
+}
+
+/// Invites `email` as an admin and returns the invitation id and a known token
+async fn invite(app: &TestApp, email: &str) -> (Uuid, String) {
+    app.test_user.make_admin(&app.db_pool).await;
+    let token = app.test_user.login_token(app).await;
+
+    let response = post_invitation(app, &token, &serde_json::json!({
+        "email": email,
+        "departamento": "Becas",
+        "rol": "admin",
+        "numero_empleado": 42,
+    })).await;
+    assert_eq!(201, response.status().as_u16());
+
+    let body: serde_json::Value = response.json().await.unwrap();
+    let invitacion_id = Uuid::parse_str(body["data"]["invitacion_id"].as_str().unwrap()).unwrap();
+
+    // The real token only travels by email, replace it with a known one
+    let invitation_token = Uuid::new_v4().to_string();
+    sqlx::query!(
+        "UPDATE invitaciones SET token_hash = $2 WHERE invitacion_id = $1",
+        invitacion_id,
+        format!("{:x}", Sha256::digest(invitation_token.as_bytes())),
+    )
+    .execute(&app.db_pool)
+    .await
+    .expect("Failed to update invitation token");
+
+    (invitacion_id, invitation_token)
+}
+
+async fn accept(app: &TestApp, invitation_token: &str) -> reqwest::Response {
+    app.api_client
+        .post(format!("{}/api/auth/invitations/accept", &app.address))
+        .json(&serde_json::json!({
+            "token": invitation_token,
+            "nombres": "Invitado",
+            "apellidos": "Perez",
+            "password": "una-contraseña-larga",
+        }))
+        .send()
+        .await
+        .expect("Failed to execute request")
+}
+
+
+#[tokio::test]
+async fn only_admins_can_invite() {
+    // Arrange
+    let app = spawn_app().await;
+    let token = app.test_user.login_token(&app).await;
+
+    // Act
+    let response = post_invitation(&app, &token, &serde_json::json!({
+        "email": "invitado@example.com",
+    })).await;
+
+    // Assert
+    assert_eq!(403, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn inviting_an_existing_email_returns_409() {
+    // Arrange
+    let app = spawn_app().await;
+    let (_, _) = invite(&app, "invitado@example.com").await;
+    let token = app.test_user.login_token(&app).await;
+
+    let test_cases = vec![
+        (app.test_user.email.clone(), "an existing user"),
+        ("Invitado@Example.com".to_string(), "a pending invitation"),
+    ];
+
+    for (email, description) in test_cases {
+        // Act
+        let response = post_invitation(&app, &token, &serde_json::json!({ "email": email })).await;
+
+        // Assert
+        assert_eq!(
+            409,
+            response.status().as_u16(),
+            "The API did not fail with 409 Conflict when the email was {}.",
+            description
+        );
+    }
+}
+
+#[tokio::test]
+async fn accepted_invitation_creates_a_verified_user() {
+    // Arrange
+    let app = spawn_app().await;
+    let (_, invitation_token) = invite(&app, "invitado@example.com").await;
+
+    // Act
+    let response = accept(&app, &invitation_token).await;
+
+    // Assert
+    assert_eq!(201, response.status().as_u16());
+
+    let row = sqlx::query!(
+        r#"
+        SELECT u.verificado, u.rol::text as "rol!", u.numero_empleado, d.nombre as "departamento?"
+        FROM usuarios u
+        LEFT JOIN departamentos d ON d.id = u.departamento
+        WHERE u.email = 'invitado@example.com'
+        "#
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .expect("Failed to fetch the invited user");
+
+    assert!(row.verificado);
+    assert_eq!("admin", row.rol);
+    assert_eq!(Some(42), row.numero_empleado);
+    assert_eq!(Some("Becas".to_string()), row.departamento);
+
+    let login = app.post_login(&serde_json::json!({
+        "email": "invitado@example.com",
+        "password": "una-contraseña-larga",
+    })).await;
+    assert_eq!(200, login.status().as_u16());
+}
+
+#[tokio::test]
+async fn invitation_link_is_single_use() {
+    // Arrange
+    let app = spawn_app().await;
+    let (_, invitation_token) = invite(&app, "invitado@example.com").await;
+    assert_eq!(201, accept(&app, &invitation_token).await.status().as_u16());
+
+    // Act
+    let response = accept(&app, &invitation_token).await;
+
+    // Assert
+    assert_eq!(404, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn revoked_invitation_can_not_be_accepted() {
+    // Arrange
+    let app = spawn_app().await;
+    let (invitacion_id, invitation_token) = invite(&app, "invitado@example.com").await;
+    let token = app.test_user.login_token(&app).await;
+
+    // Act
+    let response = app.api_client
+        .delete(format!("{}/api/invitations/{}", &app.address, invitacion_id))
+        .bearer_auth(&token)
+        .send()
+        .await
+        .expect("Failed to execute request");
+    assert_eq!(200, response.status().as_u16());
+
+    // Assert
+    assert_eq!(404, accept(&app, &invitation_token).await.status().as_u16());
+}
+
+#[tokio::test]
+async fn resending_an_invitation_invalidates_the_previous_link() {
+    // Arrange
+    let app = spawn_app().await;
+    let (invitacion_id, invitation_token) = invite(&app, "invitado@example.com").await;
+    let token = app.test_user.login_token(&app).await;
+
+    // Act
+    let response = app.api_client
+        .post(format!("{}/api/invitations/{}/resend", &app.address, invitacion_id))
+        .bearer_auth(&token)
+        .send()
+        .await
+        .expect("Failed to execute request");
+    assert_eq!(200, response.status().as_u16());
+
+    // Assert
+    assert_eq!(404, accept(&app, &invitation_token).await.status().as_u16());
+}
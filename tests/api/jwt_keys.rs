@@ -0,0 +1,84 @@
+use control_parque_vehicular::authentication::jwt_session::TokenClaims;
+use control_parque_vehicular::authentication::key_ring::KeyRing;
+use control_parque_vehicular::configuration::{get_configuration, JwtKeySettings};
+use jsonwebtoken::Algorithm;
+use secrecy::Secret;
+use uuid::Uuid;
+
+use crate::helpers::spawn_app;
+
+
+#[tokio::test]
+async fn jwks_does_not_expose_hmac_keys() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app.api_client
+        .get(format!("{}/.well-known/jwks.json", &app.address))
+        .send()
+        .await
+        .expect("Failed to execute request");
+
+    // Assert
+    assert_eq!(200, response.status().as_u16());
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(0, body["keys"].as_array().unwrap().len());
+}
+
+#[tokio::test]
+async fn token_for_another_audience_is_rejected_with_401() {
+    // Arrange
+    let app = spawn_app().await;
+    let configuration = get_configuration().expect("Failed to read configuration");
+    let key_ring = KeyRing::from_settings(&configuration.application.hmca_secret, &configuration.jwt)
+        .expect("Failed to build key ring");
+
+    let mut claims = TokenClaims::new(&app.test_user.user_id, &Uuid::new_v4(), &key_ring);
+    claims.aud = "another-service".to_string();
+    let token = key_ring.encode(&claims).unwrap();
+
+    // Act
+    let response = app.api_client
+        .get(format!("{}/api/users/me", &app.address))
+        .bearer_auth(token)
+        .send()
+        .await
+        .expect("Failed to execute request");
+
+    // Assert
+    assert_eq!(401, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn retired_legacy_key_no_longer_verifies_its_tokens() {
+    // Arrange
+    let configuration = get_configuration().expect("Failed to read configuration");
+    let legacy_ring = KeyRing::from_settings(&configuration.application.hmca_secret, &configuration.jwt)
+        .expect("Failed to build key ring");
+    let claims = TokenClaims::new(&Uuid::new_v4(), &Uuid::new_v4(), &legacy_ring);
+    let token = legacy_ring.encode(&claims).unwrap();
+
+    let mut settings = configuration.jwt.clone();
+    for key in settings.keys.iter_mut().filter(|key| key.kid == "legacy") {
+        key.retired = true;
+    }
+    settings.keys.push(JwtKeySettings {
+        kid: "rotated".to_string(),
+        algorithm: Algorithm::HS256,
+        secret: Some(Secret::new(Uuid::new_v4().to_string())),
+        private_key: None,
+        public_key: None,
+        verify_only: false,
+        retired: false,
+    });
+    settings.signing_kid = Some("rotated".to_string());
+
+    // Act
+    let rotated_ring = KeyRing::from_settings(&configuration.application.hmca_secret, &settings)
+        .expect("Failed to build key ring");
+
+    // Assert
+    assert!(legacy_ring.decode(&token).is_ok());
+    assert!(rotated_ring.decode(&token).is_err());
+}
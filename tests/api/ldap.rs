@@ -0,0 +1,99 @@
+use crate::helpers::{spawn_app_with, TestApp};
+
+// These tests require the directory seeded by scripts/init_ldap.sh
+
+
+async fn spawn_app_with_ldap() -> TestApp {
+    spawn_app_with(|c| c.ldap.domains = vec!["cpv.local".to_string()]).await
+}
+
+async fn ldap_login(app: &TestApp, email: &str, password: &str) -> reqwest::Response {
+    app.post_login(&serde_json::json!({
+        "email": email,
+        "password": password,
+    }))
+    .await
+}
+
+
+#[tokio::test]
+#[ignore = "requires scripts/init_ldap.sh"]
+async fn first_directory_login_creates_the_user() {
+    // Arrange
+    let app = spawn_app_with_ldap().await;
+
+    // Act
+    let response = ldap_login(&app, "luis@cpv.local", "password").await;
+
+    // Assert
+    assert_eq!(200, response.status().as_u16());
+
+    let row = sqlx::query!(
+        r#"
+        SELECT u.nombres, u.apellidos, u.verificado, u.rol::text as "rol!", d.nombre as "departamento?"
+        FROM usuarios u
+        LEFT JOIN departamentos d ON d.id = u.departamento
+        WHERE u.email = 'luis@cpv.local'
+        "#
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .expect("Failed to fetch the directory user");
+
+    assert_eq!("Luis", row.nombres);
+    assert_eq!("Medina", row.apellidos);
+    assert!(row.verificado);
+    assert_eq!("normal", row.rol);
+    assert_eq!(Some("Recursos Humanos".to_string()), row.departamento);
+}
+
+#[tokio::test]
+#[ignore = "requires scripts/init_ldap.sh"]
+async fn directory_admin_group_maps_to_admin_role() {
+    // Arrange
+    let app = spawn_app_with_ldap().await;
+
+    // Act
+    let response = ldap_login(&app, "ana@cpv.local", "password").await;
+
+    // Assert
+    assert_eq!(200, response.status().as_u16());
+
+    let row = sqlx::query!(r#"SELECT rol::text as "rol!" FROM usuarios WHERE email = 'ana@cpv.local'"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch the directory user");
+    assert_eq!("admin", row.rol);
+}
+
+#[tokio::test]
+#[ignore = "requires scripts/init_ldap.sh"]
+async fn wrong_directory_password_is_rejected_with_401() {
+    // Arrange
+    let app = spawn_app_with_ldap().await;
+
+    // Act
+    let response = ldap_login(&app, "luis@cpv.local", "not-the-password").await;
+
+    // Assert
+    assert_eq!(401, response.status().as_u16());
+
+    let row = sqlx::query!("SELECT count(*) as \"count!\" FROM usuarios WHERE email = 'luis@cpv.local'")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to count users");
+    assert_eq!(0, row.count);
+}
+
+#[tokio::test]
+#[ignore = "requires scripts/init_ldap.sh"]
+async fn empty_password_is_not_an_anonymous_bind() {
+    // Arrange
+    let app = spawn_app_with_ldap().await;
+
+    // Act
+    let response = ldap_login(&app, "luis@cpv.local", "").await;
+
+    // Assert
+    assert_eq!(401, response.status().as_u16());
+}
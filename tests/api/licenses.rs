@@ -0,0 +1,129 @@
+use crate::helpers::{spawn_app, TestApp, TestUser};
+
+
+async fn post_my_license(app: &TestApp, token: &str, clase: &str) -> reqwest::Response {
+    app.api_client
+        .post(format!("{}/api/licenses/me", &app.address))
+        .bearer_auth(token)
+        .json(&serde_json::json!({
+            "numero": "A12345678",
+            "clase": clase,
+            "estado_emisor": "Jalisco",
+            "expira_en": (chrono::Utc::now() + chrono::Duration::days(365)).date_naive(),
+        }))
+        .send()
+        .await
+        .expect("Failed to execute request")
+}
+
+
+#[tokio::test]
+async fn license_with_an_unknown_class_is_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    let token = app.test_user.login_token(&app).await;
+
+    // Act
+    let response = post_my_license(&app, &token, "Z").await;
+
+    // Assert
+    assert_eq!(400, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn only_admins_can_verify_licenses() {
+    // Arrange
+    let app = spawn_app().await;
+    let token = app.test_user.login_token(&app).await;
+    assert_eq!(200, post_my_license(&app, &token, "B").await.status().as_u16());
+
+    // Act
+    let response = app.api_client
+        .post(format!("{}/api/licenses/{}/verify", &app.address, app.test_user.user_id))
+        .bearer_auth(&token)
+        .send()
+        .await
+        .expect("Failed to execute request");
+
+    // Assert
+    assert_eq!(403, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn admins_can_not_verify_or_reject_their_own_license() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.make_admin(&app.db_pool).await;
+    let token = app.test_user.login_token(&app).await;
+    assert_eq!(200, post_my_license(&app, &token, "B").await.status().as_u16());
+
+    // Act
+    let verify = app.api_client
+        .post(format!("{}/api/licenses/{}/verify", &app.address, app.test_user.user_id))
+        .bearer_auth(&token)
+        .send()
+        .await
+        .expect("Failed to execute request");
+    let reject = app.api_client
+        .post(format!("{}/api/licenses/{}/reject", &app.address, app.test_user.user_id))
+        .bearer_auth(&token)
+        .json(&serde_json::json!({ "motivo": "Foto borrosa" }))
+        .send()
+        .await
+        .expect("Failed to execute request");
+
+    // Assert
+    assert_eq!(403, verify.status().as_u16());
+    assert_eq!(403, reject.status().as_u16());
+}
+
+#[tokio::test]
+async fn license_without_pictures_can_not_be_verified() {
+    // Arrange
+    let app = spawn_app().await;
+    let user = TestUser::generate();
+    user.store(&app.db_pool).await;
+    let user_token = user.login_token(&app).await;
+    assert_eq!(200, post_my_license(&app, &user_token, "B").await.status().as_u16());
+    app.test_user.make_admin(&app.db_pool).await;
+    let admin_token = app.test_user.login_token(&app).await;
+
+    // Act
+    let response = app.api_client
+        .post(format!("{}/api/licenses/{}/verify", &app.address, user.user_id))
+        .bearer_auth(&admin_token)
+        .send()
+        .await
+        .expect("Failed to execute request");
+
+    // Assert
+    assert_eq!(409, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn request_without_a_verified_license_is_forbidden() {
+    // Arrange
+    let app = spawn_app().await;
+    let token = app.test_user.login_token(&app).await;
+    assert_eq!(200, post_my_license(&app, &token, "B").await.status().as_u16());
+    let inicio = chrono::Utc::now().naive_utc() + chrono::Duration::days(1);
+
+    // Act
+    let response = app.api_client
+        .post(format!("{}/api/requests/new/fefa3ab9-2ad0-4c01-9959-c18bce2f5aed", &app.address))
+        .bearer_auth(&token)
+        .json(&serde_json::json!({
+            "inicio": inicio,
+            "finalizo": inicio + chrono::Duration::hours(4),
+            "kilometraje_inicial": 120000,
+            "usuario_licencia_imagen": "licencia.jpeg",
+        }))
+        .send()
+        .await
+        .expect("Failed to execute request");
+
+    // Assert
+    assert_eq!(403, response.status().as_u16());
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!("Tu licencia de conducir no ha sido verificada", body["message"]);
+}
@@ -1,4 +1,4 @@
-use crate::helpers::spawn_app;
+use crate::helpers::{spawn_app, spawn_app_with};
 
 
 #[tokio::test]
@@ -43,3 +43,53 @@ async fn error_response_on_invalid_credentials() {
     // Asert
     assert_eq!(json_response.status, "failed".to_string());
 }
+
+
+#[tokio::test]
+async fn account_is_locked_after_repeated_failed_logins() {
+    // Arrange
+    let app = spawn_app().await;
+    let wrong_login_body = serde_json::json!({
+        "email": app.test_user.email,
+        "password": "wrong-password",
+    });
+
+    // Act - Part 1 - Fail until the lockout kicks in
+    for _ in 0..5 {
+        let response = app.post_login(&wrong_login_body).await;
+        assert_eq!(401, response.status().as_u16());
+    }
+
+    // Act - Part 2 - Valid credentials are rejected while locked
+    let response = app.test_user.login(&app).await;
+
+    // Assert
+    assert_eq!(429, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn login_upgrades_an_outdated_password_hash() {
+    // Arrange - the test user is stored with m=15000,t=2,p=1
+    let app = spawn_app_with(|c| {
+        c.password_hashing.memory_kib = 19456;
+        c.password_hashing.iterations = 3;
+    }).await;
+
+    // Act
+    let response = app.test_user.login(&app).await;
+
+    // Assert
+    assert_eq!(200, response.status().as_u16());
+
+    let stored = sqlx::query!(
+        "SELECT password_hash FROM usuarios WHERE usuario_id = $1",
+        app.test_user.user_id,
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .expect("Failed to fetch the test user");
+    assert!(stored.password_hash.contains("m=19456,t=3,p=1"));
+
+    // The upgraded hash still validates the same password
+    assert_eq!(200, app.test_user.login(&app).await.status().as_u16());
+}
@@ -83,3 +83,38 @@ async fn error_response_when_anonymous_user_with_invalid_token_tries_to_logout()
     assert_eq!(json_response.status, "failed".to_string());
     assert_eq!(json_response.message, "Invalid token".to_string());
 }
+
+#[tokio::test]
+async fn token_is_rejected_after_logout_but_other_tokens_still_work() {
+    // Arrange
+    let app = spawn_app().await;
+    let token = app.test_user.login_token(&app).await;
+    let other_token = app.test_user.login_token(&app).await;
+
+    // Act - Part 1 - Logout
+    let response = app.api_client
+        .get(format!("{}/api/auth/logout", &app.address))
+        .bearer_auth(&token)
+        .send()
+        .await
+        .expect("Failed to execute request");
+    assert_eq!(200, response.status().as_u16());
+
+    // Act - Part 2 - Use both tokens
+    let revoked = app.api_client
+        .get(format!("{}/api/users/me", &app.address))
+        .bearer_auth(&token)
+        .send()
+        .await
+        .expect("Failed to execute request");
+    let still_valid = app.api_client
+        .get(format!("{}/api/users/me", &app.address))
+        .bearer_auth(&other_token)
+        .send()
+        .await
+        .expect("Failed to execute request");
+
+    // Asert
+    assert_eq!(401, revoked.status().as_u16());
+    assert_eq!(200, still_valid.status().as_u16());
+}
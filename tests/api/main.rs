@@ -1,5 +1,25 @@
+mod api_keys;
+mod approvals;
+mod current_user;
+mod deactivation;
+mod department_admins;
+mod department_quotas;
+mod departments;
+mod email_change;
+mod export;
 mod health_check;
 mod helpers;
+mod impersonation;
+mod invitations;
+mod jwt_keys;
+mod ldap;
+mod licenses;
 mod login;
 mod logout;
+mod oidc;
+mod password_policy;
 mod register;
+mod request_approvals;
+mod sessions;
+mod two_factor;
+mod users;
@@ -0,0 +1,113 @@
+use control_parque_vehicular::configuration::OidcProviderSettings;
+
+use crate::helpers::{spawn_app_with, TestApp};
+
+
+// Nothing listens on this port, discovery always fails
+async fn spawn_app_with_unreachable_provider() -> TestApp {
+    spawn_app_with(|c| c.oidc.providers = vec![OidcProviderSettings {
+        name: "mock".to_string(),
+        issuer_url: "http://127.0.0.1:1/realms/cpv".to_string(),
+        client_id: "cpv".to_string(),
+        client_secret: None,
+        redirect_url: "http://127.0.0.1:3000/oidc/callback".to_string(),
+        scopes: vec![],
+    }])
+    .await
+}
+
+
+#[tokio::test]
+async fn unknown_provider_is_rejected_with_404() {
+    // Arrange
+    let app = spawn_app_with_unreachable_provider().await;
+
+    // Act
+    let response = app.api_client
+        .get(format!("{}/api/auth/oidc/unknown/authorize", &app.address))
+        .send()
+        .await
+        .expect("Failed to execute request");
+
+    // Assert
+    assert_eq!(404, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn unavailable_provider_returns_503() {
+    // Arrange
+    let app = spawn_app_with_unreachable_provider().await;
+
+    // Act
+    let response = app.api_client
+        .get(format!("{}/api/auth/oidc/mock/authorize", &app.address))
+        .send()
+        .await
+        .expect("Failed to execute request");
+
+    // Assert
+    assert_eq!(503, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn callback_with_unknown_state_is_rejected_with_401() {
+    // Arrange
+    let app = spawn_app_with_unreachable_provider().await;
+
+    // Act
+    let response = app.api_client
+        .post(format!("{}/api/auth/oidc/mock/callback", &app.address))
+        .json(&serde_json::json!({
+            "code": "code",
+            "state": "not-a-state",
+        }))
+        .send()
+        .await
+        .expect("Failed to execute request");
+
+    // Assert
+    assert_eq!(401, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn identities_can_be_listed_and_unlinked() {
+    // Arrange
+    let app = spawn_app_with_unreachable_provider().await;
+    let token = app.test_user.login_token(&app).await;
+
+    sqlx::query!(
+        "INSERT INTO identidades_externas (identidad_id, usuario_id, proveedor, sujeto)
+        VALUES ($1, $2, 'mock', 'subject')",
+        uuid::Uuid::new_v4(),
+        app.test_user.user_id,
+    )
+    .execute(&app.db_pool)
+    .await
+    .expect("Failed to link identity");
+
+    // Act - Part 1 - List
+    let response = app.api_client
+        .get(format!("{}/api/users/me/identities", &app.address))
+        .bearer_auth(&token)
+        .send()
+        .await
+        .expect("Failed to execute request");
+    assert_eq!(200, response.status().as_u16());
+
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!("subject", body["data"][0]["sujeto"]);
+
+    // Act - Part 2 - Unlink
+    let unlink = || async {
+        app.api_client
+            .delete(format!("{}/api/users/me/identities/mock", &app.address))
+            .bearer_auth(&token)
+            .send()
+            .await
+            .expect("Failed to execute request")
+    };
+
+    // Assert
+    assert_eq!(200, unlink().await.status().as_u16());
+    assert_eq!(404, unlink().await.status().as_u16());
+}
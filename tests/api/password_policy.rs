@@ -0,0 +1,112 @@
+use crate::helpers::{spawn_app, TestApp};
+
+
+async fn post_change_password(app: &TestApp, token: &str, actual: &str, nuevo: &str) -> reqwest::Response {
+    app.api_client
+        .post(format!("{}/api/users/me/change-password", &app.address))
+        .bearer_auth(token)
+        .json(&serde_json::json!({
+            "password_actual": actual,
+            "password_nuevo": nuevo,
+        }))
+        .send()
+        .await
+        .expect("Failed to execute request")
+}
+
+/// Reglas que regresa una respuesta 400 de la politica de contraseñas
+async fn violated_rules(response: reqwest::Response) -> Vec<String> {
+    assert_eq!(400, response.status().as_u16());
+    let body: serde_json::Value = response.json().await.unwrap();
+    body["data"].as_array()
+        .expect("The response did not list the violated rules")
+        .iter()
+        .map(|violacion| violacion["regla"].as_str().unwrap().to_string())
+        .collect()
+}
+
+
+#[tokio::test]
+async fn signup_with_a_common_password_is_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app.api_client
+        .post(format!("{}/api/auth/signup", &app.address))
+        .json(&serde_json::json!({
+            "nombres": "Ursula",
+            "apellidos": "Le guin",
+            "email": "ursula_le_guin@gmail.com",
+            "password": "Password1234",
+            "re_password": "Password1234",
+        }))
+        .send()
+        .await
+        .expect("Failed to execute request");
+
+    // Assert
+    assert!(violated_rules(response).await.contains(&"comun".to_string()));
+}
+
+#[tokio::test]
+async fn weak_new_password_is_rejected_with_the_violated_rules() {
+    // Arrange
+    let app = spawn_app().await;
+    let token = app.test_user.login_token(&app).await;
+
+    let test_cases = vec![
+        ("corta", "longitud"),
+        ("aaaaaaaaaaaaaaaa", "entropia"),
+        ("qwertyuiop", "comun"),
+    ];
+
+    for (password, regla) in test_cases {
+        // Act
+        let response = post_change_password(&app, &token, &app.test_user.password, password).await;
+
+        // Assert
+        assert!(
+            violated_rules(response).await.contains(&regla.to_string()),
+            "The API did not report the rule {} for {}.",
+            regla,
+            password
+        );
+    }
+}
+
+#[tokio::test]
+async fn recent_passwords_can_not_be_reused() {
+    // Arrange
+    let app = spawn_app().await;
+    let token = app.test_user.login_token(&app).await;
+    let nuevo = "caballo-bateria-grapa-7";
+    assert_eq!(200, post_change_password(&app, &token, &app.test_user.password, nuevo).await.status().as_u16());
+
+    // Act
+    let response = post_change_password(&app, &token, nuevo, &app.test_user.password).await;
+
+    // Assert
+    assert!(violated_rules(response).await.contains(&"reutilizada".to_string()));
+}
+
+#[tokio::test]
+async fn changed_password_replaces_the_old_one() {
+    // Arrange
+    let app = spawn_app().await;
+    let token = app.test_user.login_token(&app).await;
+    let nuevo = "caballo-bateria-grapa-7";
+
+    // Act
+    let response = post_change_password(&app, &token, &app.test_user.password, nuevo).await;
+
+    // Assert
+    assert_eq!(200, response.status().as_u16());
+    assert_eq!(401, app.test_user.login(&app).await.status().as_u16());
+
+    let login = app.post_login(&serde_json::json!({
+        "email": &app.test_user.email,
+        "password": nuevo,
+    })).await;
+    assert_eq!(200, login.status().as_u16());
+}
@@ -58,8 +58,8 @@ async fn register_persists_the_new_user() {
         "first_name": "Ursula",
         "last_name": "Le guin",
         "email": "ursula_le_guin@gmail.com",
-        "password": "password1234",
-        "re_password": "password1234",
+        "password": "caballo-bateria-grapa-7",
+        "re_password": "caballo-bateria-grapa-7",
     });
 
     /*
@@ -94,8 +94,8 @@ async fn register_returns_a_201_for_valid_json_data() {
         "first_name": "Ursula",
         "last_name": "Le guin",
         "email": "ursula_le_guin@gmail.com",
-        "password": "password1234",
-        "re_password": "password1234",
+        "password": "caballo-bateria-grapa-7",
+        "re_password": "caballo-bateria-grapa-7",
     });
 
     /*
@@ -0,0 +1,317 @@
+use uuid::Uuid;
+
+use crate::helpers::{spawn_app, TestApp, TestUser};
+
+
+/// Usuario del departamento 1 con licencia verificada y una peticion pendiente
+async fn pending_request(app: &TestApp) -> (TestUser, Uuid) {
+    let user = TestUser::generate();
+    user.store(&app.db_pool).await;
+    sqlx::query!("UPDATE usuarios SET departamento = 1 WHERE usuario_id = $1", user.user_id)
+        .execute(&app.db_pool)
+        .await
+        .expect("Failed to set the department");
+    sqlx::query!(
+        r#"
+        INSERT INTO licencias
+        (licencia_id, usuario_id, numero, clase, estado_emisor, expira_en,
+         imagen_frente, imagen_reverso, verificada_en)
+        VALUES ($1, $2, 'A1', 'B', 'Jalisco', now() + interval '1 year', 'f.jpeg', 'r.jpeg', now())
+        "#,
+        Uuid::new_v4(),
+        user.user_id,
+    )
+    .execute(&app.db_pool)
+    .await
+    .expect("Failed to store the license");
+
+    let token = user.login_token(app).await;
+    let inicio = chrono::Utc::now().naive_utc() + chrono::Duration::days(1);
+    let response = app.api_client
+        .post(format!("{}/api/requests/new/fefa3ab9-2ad0-4c01-9959-c18bce2f5aed", &app.address))
+        .bearer_auth(&token)
+        .json(&serde_json::json!({
+            "inicio": inicio,
+            "finalizo": inicio + chrono::Duration::hours(4),
+            "kilometraje_inicial": 120000,
+            "usuario_licencia_imagen": "licencia.jpeg",
+            "distancia_estimada_km": 80,
+        }))
+        .send()
+        .await
+        .expect("Failed to execute request");
+    assert_eq!(200, response.status().as_u16());
+    let body: serde_json::Value = response.json().await.unwrap();
+    let peticion_id = body["data"]["peticion_id"].as_str().unwrap().parse().unwrap();
+
+    (user, peticion_id)
+}
+
+async fn approve(app: &TestApp, token: &str, peticion_id: &Uuid) -> reqwest::Response {
+    app.api_client
+        .post(format!("{}/api/requests/{}/approve", &app.address, peticion_id))
+        .bearer_auth(token)
+        .send()
+        .await
+        .expect("Failed to execute request")
+}
+
+async fn request_state(app: &TestApp, peticion_id: &Uuid) -> String {
+    sqlx::query!(r#"SELECT estado::text as "estado!" FROM peticiones WHERE peticion_id = $1"#, peticion_id)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .estado
+}
+
+/// Flujo del departamento 1: primero el jefe y despues el encargado de flota
+async fn head_then_fleet_manager(app: &TestApp) -> (TestUser, TestUser) {
+    app.test_user.make_admin(&app.db_pool).await;
+    let admin_token = app.test_user.login_token(app).await;
+    let jefe = TestUser::generate();
+    jefe.store(&app.db_pool).await;
+    let encargado = TestUser::generate();
+    encargado.store(&app.db_pool).await;
+
+    let response = app.api_client
+        .post(format!("{}/api/departments/1/heads/{}", &app.address, jefe.user_id))
+        .bearer_auth(&admin_token)
+        .send()
+        .await
+        .expect("Failed to execute request");
+    assert_eq!(200, response.status().as_u16());
+    let response = app.api_client
+        .post(format!("{}/api/workflows", &app.address))
+        .bearer_auth(&admin_token)
+        .json(&serde_json::json!({
+            "nombre": "Jefe y flota",
+            "departamento_id": 1,
+            "pasos": [
+                { "tipo": "jefe_departamento" },
+                { "tipo": "usuario", "usuario_id": encargado.user_id },
+            ],
+        }))
+        .send()
+        .await
+        .expect("Failed to execute request");
+    assert_eq!(200, response.status().as_u16());
+
+    (jefe, encargado)
+}
+
+
+#[tokio::test]
+async fn without_a_workflow_an_admin_accepts_the_request() {
+    // Arrange
+    let app = spawn_app().await;
+    let (user, peticion_id) = pending_request(&app).await;
+    app.test_user.make_admin(&app.db_pool).await;
+    let admin_token = app.test_user.login_token(&app).await;
+
+    // Act
+    let own = approve(&app, &user.login_token(&app).await, &peticion_id).await;
+    let response = approve(&app, &admin_token, &peticion_id).await;
+
+    // Assert
+    assert_eq!(403, own.status().as_u16());
+    assert_eq!(200, response.status().as_u16());
+    assert_eq!("aceptada", request_state(&app, &peticion_id).await);
+}
+
+#[tokio::test]
+async fn workflow_steps_are_approved_in_order() {
+    // Arrange
+    let app = spawn_app().await;
+    let (jefe, encargado) = head_then_fleet_manager(&app).await;
+    let (_, peticion_id) = pending_request(&app).await;
+    let encargado_token = encargado.login_token(&app).await;
+
+    // Act
+    let early = approve(&app, &encargado_token, &peticion_id).await;
+    let first = approve(&app, &jefe.login_token(&app).await, &peticion_id).await;
+    let pending_after_first = request_state(&app, &peticion_id).await;
+    let second = approve(&app, &encargado_token, &peticion_id).await;
+
+    // Assert
+    assert_eq!(403, early.status().as_u16());
+    assert_eq!(200, first.status().as_u16());
+    assert_eq!("pendiente", pending_after_first);
+    assert_eq!(200, second.status().as_u16());
+    assert_eq!("aceptada", request_state(&app, &peticion_id).await);
+
+    let aprobadores: Vec<Option<Uuid>> = sqlx::query!(
+        "SELECT aprobador_id FROM peticiones_aprobaciones WHERE peticion_id = $1 ORDER BY orden",
+        peticion_id,
+    )
+    .fetch_all(&app.db_pool)
+    .await
+    .unwrap()
+    .into_iter()
+    .map(|row| row.aprobador_id)
+    .collect();
+    assert_eq!(vec![Some(jefe.user_id), Some(encargado.user_id)], aprobadores);
+}
+
+#[tokio::test]
+async fn delegate_approves_on_behalf_of_an_absent_head() {
+    // Arrange
+    let app = spawn_app().await;
+    let (jefe, _) = head_then_fleet_manager(&app).await;
+    let (_, peticion_id) = pending_request(&app).await;
+    let delegado = TestUser::generate();
+    delegado.store(&app.db_pool).await;
+    let now = chrono::Utc::now().naive_utc();
+    let response = app.api_client
+        .post(format!("{}/api/users/me/delegations", &app.address))
+        .bearer_auth(jefe.login_token(&app).await)
+        .json(&serde_json::json!({
+            "delegado_id": delegado.user_id,
+            "inicio": now - chrono::Duration::hours(1),
+            "fin": now + chrono::Duration::days(7),
+            "motivo": "Vacaciones",
+        }))
+        .send()
+        .await
+        .expect("Failed to execute request");
+    assert_eq!(200, response.status().as_u16());
+
+    // Act
+    let response = approve(&app, &delegado.login_token(&app).await, &peticion_id).await;
+
+    // Assert
+    assert_eq!(200, response.status().as_u16());
+    let row = sqlx::query!(
+        "SELECT aprobador_id, en_nombre_de FROM peticiones_aprobaciones WHERE peticion_id = $1",
+        peticion_id,
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(Some(delegado.user_id), row.aprobador_id);
+    assert_eq!(Some(jefe.user_id), row.en_nombre_de);
+}
+
+#[tokio::test]
+async fn rejection_requires_a_comment_and_ends_the_request() {
+    // Arrange
+    let app = spawn_app().await;
+    let (jefe, _) = head_then_fleet_manager(&app).await;
+    let (_, peticion_id) = pending_request(&app).await;
+    let token = jefe.login_token(&app).await;
+    let reject = |body: serde_json::Value| app.api_client
+        .post(format!("{}/api/requests/{}/reject", &app.address, peticion_id))
+        .bearer_auth(&token)
+        .json(&body)
+        .send();
+
+    // Act
+    let without_comment = reject(serde_json::json!({})).await.expect("Failed to execute request");
+    let response = reject(serde_json::json!({ "comentario": "No hay presupuesto" })).await
+        .expect("Failed to execute request");
+
+    // Assert
+    assert_eq!(400, without_comment.status().as_u16());
+    assert_eq!(200, response.status().as_u16());
+    assert_eq!("rechazada", request_state(&app, &peticion_id).await);
+}
+
+#[tokio::test]
+async fn approvers_come_from_the_department_stored_on_the_request() {
+    // Arrange
+    let app = spawn_app().await;
+    let (jefe, encargado) = head_then_fleet_manager(&app).await;
+    let (user, peticion_id) = pending_request(&app).await;
+    sqlx::query!("UPDATE usuarios SET departamento = NULL WHERE usuario_id = $1", user.user_id)
+        .execute(&app.db_pool)
+        .await
+        .expect("Failed to clear the department");
+    let jefe_token = jefe.login_token(&app).await;
+    let pending = |token: String| app.api_client
+        .get(format!("{}/api/requests/pending-approval", &app.address))
+        .bearer_auth(token)
+        .send();
+
+    // Act
+    let jefe_pending = pending(jefe_token.clone()).await.expect("Failed to execute request");
+    let encargado_pending = pending(encargado.login_token(&app).await).await
+        .expect("Failed to execute request");
+    let response = approve(&app, &jefe_token, &peticion_id).await;
+
+    // Assert
+    assert_eq!(200, jefe_pending.status().as_u16());
+    let body: serde_json::Value = jefe_pending.json().await.unwrap();
+    let ids: Vec<&str> = body["data"].as_array().unwrap().iter()
+        .map(|peticion| peticion["peticion_id"].as_str().unwrap())
+        .collect();
+    assert_eq!(vec![peticion_id.to_string().as_str()], ids);
+    // El encargado decide hasta el segundo paso
+    let body: serde_json::Value = encargado_pending.json().await.unwrap();
+    assert!(body["data"].as_array().unwrap().is_empty());
+    assert_eq!(200, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn head_step_without_heads_falls_back_to_admins() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.make_admin(&app.db_pool).await;
+    let admin_token = app.test_user.login_token(&app).await;
+    let response = app.api_client
+        .post(format!("{}/api/workflows", &app.address))
+        .bearer_auth(&admin_token)
+        .json(&serde_json::json!({
+            "nombre": "Solo jefe",
+            "departamento_id": 1,
+            "pasos": [{ "tipo": "jefe_departamento" }],
+        }))
+        .send()
+        .await
+        .expect("Failed to execute request");
+    assert_eq!(200, response.status().as_u16());
+    let (_, peticion_id) = pending_request(&app).await;
+
+    // Act
+    let pending = app.api_client
+        .get(format!("{}/api/requests/pending-approval", &app.address))
+        .bearer_auth(&admin_token)
+        .send()
+        .await
+        .expect("Failed to execute request");
+    let response = approve(&app, &admin_token, &peticion_id).await;
+
+    // Assert
+    let body: serde_json::Value = pending.json().await.unwrap();
+    assert!(body["data"].as_array().unwrap().iter()
+        .any(|peticion| peticion["peticion_id"] == peticion_id.to_string()));
+    assert_eq!(200, response.status().as_u16());
+    assert_eq!("aceptada", request_state(&app, &peticion_id).await);
+}
+
+#[tokio::test]
+async fn workflow_step_cannot_name_an_inactive_user() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.make_admin(&app.db_pool).await;
+    let admin_token = app.test_user.login_token(&app).await;
+    let inactivo = TestUser::generate();
+    inactivo.store(&app.db_pool).await;
+    sqlx::query!("UPDATE usuarios SET activo = false WHERE usuario_id = $1", inactivo.user_id)
+        .execute(&app.db_pool)
+        .await
+        .expect("Failed to deactivate the user");
+
+    // Act
+    let response = app.api_client
+        .post(format!("{}/api/workflows", &app.address))
+        .bearer_auth(&admin_token)
+        .json(&serde_json::json!({
+            "nombre": "Usuario inactivo",
+            "pasos": [{ "tipo": "usuario", "usuario_id": inactivo.user_id }],
+        }))
+        .send()
+        .await
+        .expect("Failed to execute request");
+
+    // Assert
+    assert_eq!(400, response.status().as_u16());
+}
@@ -0,0 +1,121 @@
+use crate::helpers::{spawn_app, TestApp};
+
+
+async fn get_me(app: &TestApp, token: &str) -> reqwest::Response {
+    app.api_client
+        .get(format!("{}/api/users/me", &app.address))
+        .bearer_auth(token)
+        .send()
+        .await
+        .expect("Failed to execute request")
+}
+
+async fn get_sessions(app: &TestApp, token: &str) -> serde_json::Value {
+    let response = app.api_client
+        .get(format!("{}/api/users/me/sessions", &app.address))
+        .bearer_auth(token)
+        .send()
+        .await
+        .expect("Failed to execute request");
+    assert_eq!(200, response.status().as_u16());
+
+    let body: serde_json::Value = response.json().await.unwrap();
+    body["data"].clone()
+}
+
+
+#[tokio::test]
+async fn each_login_creates_a_session() {
+    // Arrange
+    let app = spawn_app().await;
+    let _ = app.test_user.login_token(&app).await;
+    let token = app.test_user.login_token(&app).await;
+
+    // Act
+    let sessions = get_sessions(&app, &token).await;
+
+    // Assert
+    let sessions = sessions.as_array().unwrap();
+    assert_eq!(2, sessions.len());
+    assert_eq!(1, sessions.iter().filter(|s| s["actual"] == true).count());
+}
+
+#[tokio::test]
+async fn revoked_session_is_rejected_with_401() {
+    // Arrange
+    let app = spawn_app().await;
+    let other_token = app.test_user.login_token(&app).await;
+    let token = app.test_user.login_token(&app).await;
+
+    let sessions = get_sessions(&app, &token).await;
+    let other_session_id = sessions.as_array().unwrap()
+        .iter()
+        .find(|s| s["actual"] == false)
+        .unwrap()["sesion_id"]
+        .as_str()
+        .unwrap()
+        .to_string();
+
+    // Act
+    let response = app.api_client
+        .delete(format!("{}/api/users/me/sessions/{}", &app.address, other_session_id))
+        .bearer_auth(&token)
+        .send()
+        .await
+        .expect("Failed to execute request");
+    assert_eq!(200, response.status().as_u16());
+
+    // Assert
+    assert_eq!(401, get_me(&app, &other_token).await.status().as_u16());
+    assert_eq!(200, get_me(&app, &token).await.status().as_u16());
+}
+
+#[tokio::test]
+async fn logout_everywhere_revokes_all_sessions() {
+    // Arrange
+    let app = spawn_app().await;
+    let other_token = app.test_user.login_token(&app).await;
+    let token = app.test_user.login_token(&app).await;
+
+    // Act
+    let response = app.api_client
+        .delete(format!("{}/api/users/me/sessions", &app.address))
+        .bearer_auth(&token)
+        .send()
+        .await
+        .expect("Failed to execute request");
+    assert_eq!(200, response.status().as_u16());
+
+    // Assert
+    assert_eq!(401, get_me(&app, &other_token).await.status().as_u16());
+    assert_eq!(401, get_me(&app, &token).await.status().as_u16());
+}
+
+#[tokio::test]
+async fn revoking_a_revoked_session_again_succeeds() {
+    // Arrange
+    let app = spawn_app().await;
+    let other_token = app.test_user.login_token(&app).await;
+    let token = app.test_user.login_token(&app).await;
+    let sessions = get_sessions(&app, &token).await;
+    let other_session_id = sessions.as_array().unwrap()
+        .iter()
+        .find(|s| s["actual"] == false)
+        .unwrap()["sesion_id"]
+        .as_str()
+        .unwrap()
+        .to_string();
+    let revoke = || app.api_client
+        .delete(format!("{}/api/users/me/sessions/{}", &app.address, other_session_id))
+        .bearer_auth(&token)
+        .send();
+
+    // Act
+    let first = revoke().await.expect("Failed to execute request");
+    let second = revoke().await.expect("Failed to execute request");
+
+    // Assert
+    assert_eq!(200, first.status().as_u16());
+    assert_eq!(200, second.status().as_u16());
+    assert_eq!(401, get_me(&app, &other_token).await.status().as_u16());
+}
@@ -0,0 +1,119 @@
+use chrono::Utc;
+use totp_rs::{Algorithm, Secret, TOTP};
+
+use crate::helpers::{spawn_app, TestApp};
+
+
+fn totp_code(secret: &str, seconds_offset: i64) -> String {
+    let totp = TOTP::new(
+        Algorithm::SHA1,
+        6,
+        1,
+        30,
+        Secret::Encoded(secret.to_string()).to_bytes().unwrap(),
+        None,
+        "test".to_string(),
+    ).unwrap();
+
+    totp.generate((Utc::now().timestamp() + seconds_offset) as u64)
+}
+
+/// Enrolls the test user and returns the secret and the recovery codes
+async fn enroll_test_user(app: &TestApp) -> (String, Vec<String>) {
+    let token = app.test_user.login_token(app).await;
+
+    let response = app.api_client
+        .post(format!("{}/api/users/me/2fa", &app.address))
+        .bearer_auth(&token)
+        .send()
+        .await
+        .expect("Failed to execute request");
+    assert_eq!(200, response.status().as_u16());
+    let body: serde_json::Value = response.json().await.unwrap();
+    let secret = body["data"]["secreto"].as_str().unwrap().to_string();
+    assert!(body["data"]["otpauth_uri"].as_str().unwrap().starts_with("otpauth://totp/"));
+
+    let response = app.api_client
+        .post(format!("{}/api/users/me/2fa/confirm", &app.address))
+        .bearer_auth(&token)
+        .json(&serde_json::json!({ "codigo": totp_code(&secret, 0) }))
+        .send()
+        .await
+        .expect("Failed to execute request");
+    assert_eq!(200, response.status().as_u16());
+    let body: serde_json::Value = response.json().await.unwrap();
+    let recovery_codes = serde_json::from_value(body["data"].clone()).unwrap();
+
+    (secret, recovery_codes)
+}
+
+async fn login_challenge(app: &TestApp) -> String {
+    let response = app.test_user.login(app).await;
+    assert_eq!(202, response.status().as_u16());
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["data"]["requiere_inscripcion"], false);
+
+    body["data"]["desafio"].as_str().unwrap().to_string()
+}
+
+async fn post_second_factor(app: &TestApp, challenge: &str, code: &str) -> reqwest::Response {
+    app.api_client
+        .post(format!("{}/api/auth/login/2fa", &app.address))
+        .json(&serde_json::json!({ "desafio": challenge, "codigo": code }))
+        .send()
+        .await
+        .expect("Failed to execute request")
+}
+
+
+#[tokio::test]
+async fn login_returns_token_only_after_a_valid_totp_code() {
+    // Arrange
+    let app = spawn_app().await;
+    let (secret, _) = enroll_test_user(&app).await;
+
+    // Act - Part 1 - Password alone yields a challenge
+    let challenge = login_challenge(&app).await;
+
+    // Act - Part 2 - The code used to confirm the enrollment can not be replayed,
+    // the next one is accepted
+    let response = post_second_factor(&app, &challenge, &totp_code(&secret, 30)).await;
+
+    // Assert
+    assert_eq!(200, response.status().as_u16());
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert!(body["data"].as_str().is_some());
+}
+
+#[tokio::test]
+async fn totp_code_can_not_be_reused() {
+    // Arrange
+    let app = spawn_app().await;
+    let (secret, _) = enroll_test_user(&app).await;
+
+    // Act
+    let challenge = login_challenge(&app).await;
+    let response = post_second_factor(&app, &challenge, &totp_code(&secret, 0)).await;
+
+    // Assert
+    assert_eq!(401, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn recovery_code_is_accepted_only_once() {
+    // Arrange
+    let app = spawn_app().await;
+    let (_, recovery_codes) = enroll_test_user(&app).await;
+
+    // Act - Part 1
+    let challenge = login_challenge(&app).await;
+    let response = post_second_factor(&app, &challenge, &recovery_codes[0]).await;
+    assert_eq!(200, response.status().as_u16());
+
+    // Act - Part 2
+    let challenge = login_challenge(&app).await;
+    let response = post_second_factor(&app, &challenge, &recovery_codes[0]).await;
+
+    // Assert
+    assert_eq!(401, response.status().as_u16());
+}
@@ -0,0 +1,126 @@
+use crate::helpers::{spawn_app, TestApp, TestUser};
+
+
+async fn get_users(app: &TestApp, token: &str, query: &str) -> reqwest::Response {
+    app.api_client
+        .get(format!("{}/api/users?{}", &app.address, query))
+        .bearer_auth(token)
+        .send()
+        .await
+        .expect("Failed to execute request")
+}
+
+async fn store_user(app: &TestApp, first_name: &str, last_name: &str) -> TestUser {
+    let user = TestUser {
+        first_name: first_name.to_string(),
+        last_name: last_name.to_string(),
+        ..TestUser::generate()
+    };
+    user.store(&app.db_pool).await;
+    user
+}
+
+fn emails(body: &serde_json::Value) -> Vec<String> {
+    body["data"].as_array()
+        .unwrap()
+        .iter()
+        .map(|usuario| usuario["email"].as_str().unwrap().to_string())
+        .collect()
+}
+
+
+#[tokio::test]
+async fn only_admins_can_list_users() {
+    // Arrange
+    let app = spawn_app().await;
+    let token = app.test_user.login_token(&app).await;
+
+    // Act
+    let response = get_users(&app, &token, "").await;
+
+    // Assert
+    assert_eq!(403, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn search_matches_name_prefixes_ignoring_case() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.make_admin(&app.db_pool).await;
+    let token = app.test_user.login_token(&app).await;
+    let zacarias = store_user(&app, "Zacarias", "Quiroga").await;
+    let _ = store_user(&app, "Zoe", "Quintero").await;
+
+    // Act
+    let response = get_users(&app, &token, "busqueda=zac%20quir").await;
+
+    // Assert
+    assert_eq!(200, response.status().as_u16());
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(vec![zacarias.email], emails(&body));
+}
+
+#[tokio::test]
+async fn users_are_paginated_with_the_total_in_a_header() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.make_admin(&app.db_pool).await;
+    let token = app.test_user.login_token(&app).await;
+    for apellido in ["Quintana", "Quiroz", "Quiñones"] {
+        store_user(&app, "Paginado", apellido).await;
+    }
+
+    // Act
+    let response = get_users(&app, &token, "busqueda=paginado&orden=apellidos&limite=2&pagina=2").await;
+
+    // Assert
+    assert_eq!(200, response.status().as_u16());
+    assert_eq!("3", response.headers().get("x-total-count").unwrap());
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(1, body["data"].as_array().unwrap().len());
+}
+
+#[tokio::test]
+async fn huge_page_is_rejected_with_400() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.make_admin(&app.db_pool).await;
+    let token = app.test_user.login_token(&app).await;
+
+    // Act
+    let response = get_users(&app, &token, &format!("pagina={}&limite=100", i64::MAX)).await;
+
+    // Assert
+    assert_eq!(400, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn users_can_be_filtered_by_role() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.make_admin(&app.db_pool).await;
+    let token = app.test_user.login_token(&app).await;
+    let _ = store_user(&app, "Filtrado", "Normal").await;
+
+    // Act
+    let response = get_users(&app, &token, "busqueda=filtrado&rol=admin").await;
+
+    // Assert
+    assert_eq!(200, response.status().as_u16());
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert!(emails(&body).is_empty());
+}
+
+#[tokio::test]
+async fn invalid_sort_column_is_rejected_with_400() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.make_admin(&app.db_pool).await;
+    let token = app.test_user.login_token(&app).await;
+
+    // Act
+    let response = get_users(&app, &token, "orden=password_hash").await;
+
+    // Assert
+    assert_eq!(400, response.status().as_u16());
+}